@@ -0,0 +1,45 @@
+//! Пример плагина модели цены: цена колеблется по синусоиде вокруг середины диапазона.
+//! Сборка: `cargo build --example price_model_plugin`,
+//! затем путь к библиотеке указывается в поле `price_model_plugin` конфигурации генератора
+
+use std::ffi::{c_char, c_void};
+
+const ABI_VERSION: u32 = 1;
+const PHASE_STEP: f64 = 0.1;
+
+struct SineModel {
+    middle: f64,
+    amplitude: f64,
+    phase: f64,
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sq_model_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sq_model_create(_ticker: *const c_char, upper_bound_price: f64) -> *mut c_void {
+    let model = SineModel {
+        middle: upper_bound_price / 2.0,
+        amplitude: upper_bound_price / 4.0,
+        phase: 0.0,
+    };
+    Box::into_raw(Box::new(model)) as *mut c_void
+}
+
+/// # Safety
+/// `state` должен быть получен из `sq_model_create`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sq_model_next_price(state: *mut c_void, _current_price: f64) -> f64 {
+    let model = unsafe { &mut *(state as *mut SineModel) };
+    model.phase += PHASE_STEP;
+    model.middle + model.amplitude * model.phase.sin()
+}
+
+/// # Safety
+/// `state` должен быть получен из `sq_model_create` и не использоваться после вызова
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sq_model_destroy(state: *mut c_void) {
+    drop(unsafe { Box::from_raw(state as *mut SineModel) });
+}
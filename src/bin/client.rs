@@ -1,33 +1,606 @@
+use anyhow::{Result, bail};
 use clap::Parser;
-use std::path::Path;
-use streaming_quotes::client::quotes_client::{ClientCmd, QuotesClient};
-use streaming_quotes::init_log;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use streaming_quotes::client::aggregate::AggregatingHandler;
+use streaming_quotes::client::alert::{AlertingHandler, PriceAlerts};
+use streaming_quotes::client::discovery::discover_servers;
+use streaming_quotes::client::filter::QuoteFilter;
+use streaming_quotes::client::handler::{ConsoleFormat, ConsolePrinter, QuoteHandler};
+use streaming_quotes::client::health::{HealthOutput, HealthReporter};
+use streaming_quotes::client::queue::OverflowPolicy;
+use streaming_quotes::client::quotes_client::{
+    ClientCmd, ClientControl, PingTimeouts, QuotesClient, QuotesClientBuilder, ReconnectPolicy,
+    read_tickers,
+};
+use streaming_quotes::client::record::{self, RecordReader};
+use streaming_quotes::client::recovery::RecoveryPolicy;
+use streaming_quotes::client::session::SessionState;
+use streaming_quotes::client::sink::{SinkHandler, open_sink};
+use streaming_quotes::client::validate::{QuoteValidation, ValidationPolicy};
+use streaming_quotes::protocol::{DeltaFilter, SubscriptionMode};
+use streaming_quotes::{init_file_log, init_log};
+
+const TUI_CHANNEL_CAPACITY: usize = 1024;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Server addr
+    /// Server addr, ip address or host name with port. If not set, the server is discovered on the local network via mDNS
     #[arg(short, long)]
-    server: String,
+    server: Option<String>,
 
-    /// Port for receive quotes
-    #[arg(short, long)]
+    /// Backup servers addrs, comma separated. The client fails over to them when the connection is lost
+    #[arg(long, value_delimiter = ',')]
+    backup_servers: Vec<String>,
+
+    /// Time to discover the server via mDNS in milliseconds
+    #[arg(long, default_value_t = 3000)]
+    discover_millis: u64,
+
+    /// Port for receive quotes, 0 - any free port
+    #[arg(short, long, default_value_t = 0)]
     port: u16,
 
     /// Path to file with tickers names
+    #[arg(short, long, required_unless_present = "replay")]
+    tickers_path: Option<String>,
+
+    /// Additional ports for striped quotes receiving
+    #[arg(long, value_delimiter = ',')]
+    stripe_ports: Vec<u16>,
+
+    /// Access token for the server
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Address to bind sockets receiving quotes. Loopback by default
+    #[arg(long)]
+    bind_ip: Option<IpAddr>,
+
+    /// Period of polling sockets receiving quotes in milliseconds
+    #[arg(long, default_value_t = 100)]
+    poll_millis: u64,
+
+    /// Poll sockets receiving quotes continuously instead of every --poll-millis.
+    /// Lowest latency, but the receive thread keeps a CPU core busy
+    #[arg(long)]
+    busy_poll: bool,
+
+    /// Receive buffer size of sockets receiving quotes in bytes (SO_RCVBUF)
+    #[arg(long)]
+    recv_buffer_bytes: Option<usize>,
+
+    /// Time to connect to the server in milliseconds
+    #[arg(long, default_value_t = 5000)]
+    connect_timeout_millis: u64,
+
+    /// Resend the ticker request if no data arrives for this many milliseconds
+    /// while the connection is alive
+    #[arg(long)]
+    no_data_timeout_millis: Option<u64>,
+
+    /// Ping period in milliseconds
+    #[arg(long, default_value_t = PingTimeouts::default().ping_period_millis)]
+    ping_period_millis: u64,
+
+    /// Time to wait for pong in milliseconds
+    #[arg(long, default_value_t = PingTimeouts::default().wait_pong_millis)]
+    wait_pong_millis: u64,
+
+    /// CA certificates in PEM format to connect to the server over TLS
+    #[arg(long)]
+    tls_ca: Option<String>,
+
+    /// Server name in its certificate. Server ip address by default
+    #[arg(long)]
+    tls_server_name: Option<String>,
+
+    /// Session token of a previous connection to resume its subscription
+    #[arg(long)]
+    session: Option<String>,
+
+    /// File with the client session state: loaded at start to resume the previous session
+    /// with its server, port and tickers, and saved at exit
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Receive a quote only if its price moved by this percent since the last received quote
+    #[arg(long)]
+    min_price_change_percent: Option<f64>,
+
+    /// Receive a quote only if the volume accumulated since the last received quote is at least this value
+    #[arg(long)]
+    min_volume: Option<u32>,
+
+    /// Pass only quotes of these tickers to the output, comma separated. Filtered locally
+    #[arg(long, value_delimiter = ',')]
+    local_tickers: Vec<String>,
+
+    /// Pass a quote to the output only if its price moved by this percent since the last passed quote.
+    /// Filtered locally
+    #[arg(long)]
+    local_min_price_change_percent: Option<f64>,
+
+    /// Pass a quote to the output only if the volume accumulated since the last passed quote
+    /// is at least this value. Filtered locally
+    #[arg(long)]
+    local_min_volume: Option<u32>,
+
+    /// Receive OHLCV bars instead of quotes
+    #[arg(long)]
+    bars: bool,
+
+    /// Print this number of the most recent quotes of each ticker before live quotes
+    #[arg(long, default_value_t = 0)]
+    history: u32,
+
+    /// Write quotes to this file instead of the console. Format is chosen by extension:
+    /// .csv, .jsonl/.ndjson, .db/.sqlite (requires "sqlite" feature). Quotes are appended
+    /// to an existing file
     #[arg(short, long)]
-    tickers_path: String,
+    output: Option<String>,
+
+    /// Console output format: plain, table (aligned columns with price change), csv or json
+    #[arg(long, value_parser = parse_console_format, default_value = "plain")]
+    format: ConsoleFormat,
+
+    /// Color console quotes green when the price goes up and red when it goes down
+    #[arg(long)]
+    color: bool,
+
+    /// Check quotes for duplicates, out of order timestamps and staleness.
+    /// What to do with invalid quotes: drop, warn or flag
+    #[arg(long, value_parser = parse_validation_policy)]
+    validate: Option<ValidationPolicy>,
+
+    /// Maximum age of a quote by the local clock in milliseconds. Requires wall clock
+    /// timestamps on the server. Checked only with --validate
+    #[arg(long, requires = "validate")]
+    max_age_millis: Option<u64>,
+
+    /// Suppress duplicate deliveries of a quote among this many recent quotes of its ticker
+    #[arg(long)]
+    dedup_window: Option<usize>,
+
+    /// Print only the latest quote of each ticker once per this interval in milliseconds
+    #[arg(long)]
+    conflation_millis: Option<u64>,
+
+    /// Hand quotes to the output through a queue of this many entries on a separate thread,
+    /// so a slow output does not hold up receiving
+    #[arg(long)]
+    handler_queue: Option<usize>,
+
+    /// What to do when the handler queue or the quote buffer is full:
+    /// drop-oldest, drop-newest or block
+    #[arg(long, value_parser = parse_overflow_policy, default_value = "drop-oldest")]
+    overflow_policy: OverflowPolicy,
+
+    /// Build OHLCV bars of this length in milliseconds from received quotes and print them
+    #[arg(long)]
+    local_bar_interval_millis: Option<u64>,
+
+    /// Print an alert when the ticker price rises above the level, as TICKER=LEVEL. Can be repeated
+    #[arg(long, value_parser = parse_ticker_value)]
+    alert_above: Vec<(String, f64)>,
+
+    /// Print an alert when the ticker price falls below the level, as TICKER=LEVEL. Can be repeated
+    #[arg(long, value_parser = parse_ticker_value)]
+    alert_below: Vec<(String, f64)>,
+
+    /// Print an alert when the ticker price moves by the percent, as TICKER=PERCENT. Can be repeated
+    #[arg(long, value_parser = parse_ticker_value)]
+    alert_move_percent: Vec<(String, f64)>,
+
+    /// Rewrite this file every second while quotes are flowing, for supervisors to check its modification time
+    #[arg(long, conflicts_with = "replay")]
+    health_file: Option<String>,
+
+    /// Answer HTTP health checks at this address: 200 while quotes are flowing, otherwise 503
+    #[arg(long, conflicts_with_all = ["replay", "health_file"])]
+    health_addr: Option<SocketAddr>,
+
+    /// The client is unhealthy when no quote is received for this number of milliseconds
+    #[arg(long, default_value_t = 10000)]
+    health_max_quote_age_millis: u64,
+
+    /// Record received datagrams to this file for offline replay
+    #[arg(long)]
+    record: Option<String>,
+
+    /// Replay datagrams recorded with --record through the output without connecting to the server
+    #[arg(long, conflicts_with_all = ["record", "tui"])]
+    replay: Option<String>,
+
+    /// Replay recorded datagrams with the original pauses between them
+    #[arg(long, requires = "replay")]
+    replay_realtime: bool,
+
+    /// Show a live quote table in the terminal instead of printing quotes (requires "tui" feature)
+    #[arg(long, conflicts_with = "output")]
+    tui: bool,
+
+    /// Reconnect to the server when the connection is lost
+    #[arg(long)]
+    reconnect: bool,
+
+    /// Maximum number of reconnect attempts in a row. Unlimited by default
+    #[arg(long)]
+    reconnect_max_retries: Option<u32>,
+
+    /// Maximum delay between reconnect attempts in milliseconds
+    #[arg(long, default_value_t = ReconnectPolicy::default().max_delay_millis)]
+    reconnect_max_delay_millis: u64,
+
+    /// Number of transient socket errors in a row (e.g. connection refused on UDP)
+    /// after which the connection is considered lost. 0 treats every error as fatal
+    #[arg(long, default_value_t = RecoveryPolicy::default().max_retries)]
+    socket_error_retries: u32,
+}
+
+fn parse_ticker_value(value: &str) -> Result<(String, f64)> {
+    let Some((ticker, number)) = value.split_once('=') else {
+        bail!("Expected TICKER=VALUE, got {value}");
+    };
+    Ok((ticker.trim().to_string(), number.trim().parse()?))
+}
+
+fn parse_validation_policy(value: &str) -> Result<ValidationPolicy> {
+    match value {
+        "drop" => Ok(ValidationPolicy::Drop),
+        "warn" => Ok(ValidationPolicy::Warn),
+        "flag" => Ok(ValidationPolicy::Flag),
+        _ => bail!("Expected drop, warn or flag, got {value}"),
+    }
+}
+
+fn parse_overflow_policy(value: &str) -> Result<OverflowPolicy> {
+    match value {
+        "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+        "drop-newest" => Ok(OverflowPolicy::DropNewest),
+        _ => bail!("Expected drop-oldest or drop-newest, got {value}"),
+    }
+}
+
+fn parse_console_format(value: &str) -> Result<ConsoleFormat> {
+    match value {
+        "plain" => Ok(ConsoleFormat::Plain),
+        "table" => Ok(ConsoleFormat::Table),
+        "csv" => Ok(ConsoleFormat::Csv),
+        "json" => Ok(ConsoleFormat::Json),
+        _ => bail!("Expected plain, table, csv or json, got {value}"),
+    }
+}
+
+fn price_alerts(args: &Args) -> PriceAlerts {
+    let print = |alert: &_| println!("alert: {alert}");
+    let mut alerts = PriceAlerts::default();
+    for (ticker, level) in args.alert_above.iter() {
+        alerts = alerts.on_price_above(ticker, *level, print);
+    }
+    for (ticker, level) in args.alert_below.iter() {
+        alerts = alerts.on_price_below(ticker, *level, print);
+    }
+    for (ticker, percent) in args.alert_move_percent.iter() {
+        alerts = alerts.on_move_pct(ticker, *percent, print);
+    }
+    alerts
+}
+
+fn server_addr(args: &Args) -> Result<String> {
+    if let Some(server) = args.server.as_ref() {
+        return Ok(server.clone());
+    }
+    let servers = discover_servers(Duration::from_millis(args.discover_millis))?;
+    let Some(server) = servers.first() else {
+        bail!("Server address is not set and no server is discovered via mDNS");
+    };
+    log::info!("Discovered server {} at {}", server.name, server.addr);
+    Ok(server.addr.to_string())
+}
+
+fn create_builder(args: &Args) -> Result<(QuotesClientBuilder, String)> {
+    if let Some(path) = args.state_file.as_deref().filter(|path| path.exists()) {
+        let state = SessionState::load(path)?;
+        log::info!("Resume session {} from {}", state.session, path.display());
+        return Ok((QuotesClientBuilder::resume(&state), state.server_addr));
+    }
+    let Some(tickers_path) = args.tickers_path.as_ref() else {
+        bail!("Path to file with tickers names is not set");
+    };
+    let tickers = read_tickers(tickers_path)?;
+    let server = server_addr(args)?;
+    Ok((
+        QuotesClientBuilder::new(&server, args.port, tickers),
+        server,
+    ))
+}
+
+fn create_client(args: Args) -> Result<QuotesClient> {
+    let (builder, server) = create_builder(&args)?;
+    let mut builder = builder
+        .with_ping_period_millis(args.ping_period_millis)
+        .with_wait_pong_millis(args.wait_pong_millis)
+        .with_poll_millis(args.poll_millis)
+        .with_connect_timeout_millis(args.connect_timeout_millis);
+    if let Some(ip) = args.bind_ip {
+        builder = builder.with_bind_ip(ip);
+    }
+    if let Some(millis) = args.no_data_timeout_millis {
+        builder = builder.with_no_data_timeout_millis(millis);
+    }
+    if args.busy_poll {
+        builder = builder.with_busy_poll();
+    }
+    if let Some(bytes) = args.recv_buffer_bytes {
+        builder = builder.with_recv_buffer_bytes(bytes);
+    }
+    let delta = (args.local_min_price_change_percent.is_some() || args.local_min_volume.is_some())
+        .then_some(DeltaFilter {
+            min_price_change_percent: args.local_min_price_change_percent,
+            min_volume: args.local_min_volume,
+        });
+    if !args.local_tickers.is_empty() || delta.is_some() {
+        builder = builder.with_local_filter(QuoteFilter {
+            tickers: (!args.local_tickers.is_empty()).then_some(args.local_tickers),
+            delta,
+        });
+    }
+    if let Some(millis) = args.conflation_millis {
+        builder = builder.with_conflation_millis(millis);
+    }
+    if let Some(capacity) = args.handler_queue {
+        builder = builder.with_handler_queue(capacity);
+    }
+    builder = builder.with_overflow_policy(args.overflow_policy);
+    if let Some(policy) = args.validate {
+        builder = builder.with_validation(QuoteValidation {
+            policy,
+            max_age_millis: args.max_age_millis,
+        });
+    }
+    if let Some(window) = args.dedup_window {
+        builder = builder.with_dedup(window);
+    }
+    let mut client = builder.build()?.with_stripe_ports(args.stripe_ports);
+    if let Some(token) = args.token {
+        client = client.with_token(token);
+    }
+    if let Some(session) = args.session {
+        client = client.with_session(session);
+    }
+    if args.min_price_change_percent.is_some() || args.min_volume.is_some() {
+        client = client.with_delta_filter(DeltaFilter {
+            min_price_change_percent: args.min_price_change_percent,
+            min_volume: args.min_volume,
+        });
+    }
+    if args.bars {
+        client = client.with_bars();
+    }
+    if args.history > 0 {
+        client = client.with_history(args.history);
+    }
+    if let Some(path) = args.record.as_ref() {
+        client = client.with_recording(Path::new(path))?;
+    }
+    if !args.backup_servers.is_empty() {
+        let backup_servers: Vec<&str> = args.backup_servers.iter().map(String::as_str).collect();
+        client = client.with_backup_servers(&backup_servers)?;
+    }
+    if args.reconnect {
+        client = client.with_reconnect(ReconnectPolicy {
+            max_delay_millis: args.reconnect_max_delay_millis,
+            max_retries: args.reconnect_max_retries,
+            ..Default::default()
+        });
+    }
+    client = client.with_recovery(RecoveryPolicy {
+        max_retries: args.socket_error_retries,
+        ..Default::default()
+    });
+    if let Some(ca_path) = args.tls_ca {
+        let server_name = match args.tls_server_name {
+            Some(val) => val,
+            None => match server.parse::<SocketAddr>() {
+                Ok(addr) => addr.ip().to_string(),
+                Err(_) => server
+                    .rsplit_once(':')
+                    .map_or(server.as_str(), |(host, _)| host)
+                    .to_string(),
+            },
+        };
+        client = client.with_tls(&ca_path, &server_name)?;
+    }
+    Ok(client)
+}
+
+fn parse_subscribe_cmd(cmd: &str) -> Option<ClientCmd> {
+    let (mode, tickers) = cmd.split_once(' ')?;
+    let mode = match mode {
+        "add" | "subscribe" => SubscriptionMode::Add,
+        "remove" | "unsubscribe" => SubscriptionMode::Remove,
+        "replace" => SubscriptionMode::Replace,
+        _ => return None,
+    };
+    let tickers = tickers
+        .split(',')
+        .map(|ticker| ticker.trim().to_string())
+        .filter(|ticker| !ticker.is_empty())
+        .collect();
+    Some(ClientCmd::Subscribe { mode, tickers })
+}
+
+fn create_handler(
+    output: Option<&str>,
+    console: ConsolePrinter,
+    bar_interval_millis: Option<u64>,
+    alerts: PriceAlerts,
+) -> Result<Box<dyn QuoteHandler>> {
+    let mut handler: Box<dyn QuoteHandler> = match output {
+        Some(path) => Box::new(SinkHandler::new(open_sink(Path::new(path))?)),
+        None => Box::new(console),
+    };
+    if let Some(interval_millis) = bar_interval_millis {
+        handler = Box::new(AggregatingHandler::new(handler, interval_millis).0);
+    }
+    if !alerts.is_empty() {
+        handler = Box::new(AlertingHandler::new(handler, alerts));
+    }
+    Ok(handler)
+}
+
+fn start_client(
+    client: QuotesClient,
+    output: Option<&str>,
+    console: ConsolePrinter,
+    tui: bool,
+    bar_interval_millis: Option<u64>,
+    alerts: PriceAlerts,
+) -> Result<ClientControl> {
+    if tui {
+        return Ok(client.start_receive_channel(TUI_CHANNEL_CAPACITY)?);
+    }
+    let handler = create_handler(output, console, bar_interval_millis, alerts)?;
+    Ok(client.start_receive_handler(handler)?)
+}
+
+fn replay_record(
+    path: &str,
+    realtime: bool,
+    output: Option<&str>,
+    console: ConsolePrinter,
+    bar_interval_millis: Option<u64>,
+    alerts: PriceAlerts,
+) -> Result<u64> {
+    let mut handler = create_handler(output, console, bar_interval_millis, alerts)?;
+    let mut reader = RecordReader::open(Path::new(path))?;
+    record::replay(&mut reader, &mut handler, realtime)
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(control: &ClientControl) -> Result<()> {
+    streaming_quotes::client::tui::run(control)
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_control: &ClientControl) -> Result<()> {
+    bail!("Client is built without \"tui\" feature")
+}
+
+fn run_commands(control: &ClientControl) {
+    let mut cmd_buf = String::new();
+    let stdin = std::io::stdin();
+    loop {
+        println!(
+            "To stop client type \"exit\", to show client stats and quote loss type \"stats\", to show price summary of tickers type \"summary\", to change subscription type \"add|remove|replace <tickers>\", to pause or resume receiving type \"pause|resume\""
+        );
+        if let Err(e) = stdin.read_line(&mut cmd_buf) {
+            log::error!("Can't read new command: {e}");
+            break;
+        }
+        let cmd = cmd_buf.trim().to_lowercase();
+        if cmd == "exit" {
+            break;
+        }
+        if cmd == "pause" || cmd == "resume" {
+            let client_cmd = if cmd == "pause" {
+                ClientCmd::Pause
+            } else {
+                ClientCmd::Resume
+            };
+            if let Err(e) = control.tx.send(client_cmd) {
+                log::error!("Can't {cmd} receiving: {e}");
+            }
+            cmd_buf.clear();
+            continue;
+        }
+        if cmd == "summary" {
+            for (ticker, summary) in control.summary.snapshot() {
+                println!("{ticker}: {summary}");
+            }
+            cmd_buf.clear();
+            continue;
+        }
+        if cmd == "stats" {
+            println!("State: {}", control.state.get());
+            println!("Tickers: {:?}", control.acked_tickers.lock().unwrap());
+            println!("{}", control.client_stats());
+            if let Some(queue) = control.handler_queue.as_ref() {
+                println!(
+                    "Handler queue: {} queued, {} dropped",
+                    queue.len(),
+                    queue.overflowed()
+                );
+            }
+            if let Some(validator) = control.validator.as_ref() {
+                println!("Invalid quotes: {}", validator.stats());
+            }
+            if let Some(dedup) = control.dedup.as_ref() {
+                println!("Suppressed duplicates: {}", dedup.suppressed());
+            }
+            for (ticker, loss) in control.loss.snapshot() {
+                println!("{ticker}: {loss}");
+            }
+            cmd_buf.clear();
+            continue;
+        }
+        if let Some(subscribe_cmd) = parse_subscribe_cmd(cmd_buf.trim()) {
+            if let Err(e) = control.tx.send(subscribe_cmd) {
+                log::error!("Can't change subscription: {e}");
+            }
+        } else if !cmd.is_empty() {
+            println!("Unknown command: {cmd}");
+        }
+        cmd_buf.clear();
+    }
 }
 
 fn main() {
-    if let Err(e) = init_log(Path::new("logs"), "client.log") {
+    let args = Args::parse();
+    let tui = args.tui;
+    let log_res = if tui {
+        init_file_log(Path::new("logs"), "client.log")
+    } else {
+        init_log(Path::new("logs"), "client.log")
+    };
+    if let Err(e) = log_res {
         println!("Can't init logger: {e}");
         return;
     }
 
-    let args = Args::parse();
+    let output = args.output.clone();
+    let console = ConsolePrinter::new(args.format).with_color(args.color);
+    let bar_interval_millis = args.local_bar_interval_millis;
+    let alerts = price_alerts(&args);
+
+    if let Some(path) = args.replay.as_deref() {
+        let res = replay_record(
+            path,
+            args.replay_realtime,
+            output.as_deref(),
+            console,
+            bar_interval_millis,
+            alerts,
+        );
+        match res {
+            Ok(count) => log::info!("Replayed {count} datagrams from {path}"),
+            Err(e) => log::error!("Can't replay record {path}: {e}"),
+        }
+        return;
+    }
 
-    let client = match QuotesClient::new(&args.server, args.port, &args.tickers_path) {
+    let health_output = match (args.health_file.as_deref(), args.health_addr) {
+        (Some(path), _) => Some(HealthOutput::File(PathBuf::from(path))),
+        (None, Some(addr)) => Some(HealthOutput::Tcp(addr)),
+        (None, None) => None,
+    };
+    let health_max_quote_age_millis = args.health_max_quote_age_millis;
+    let state_file = args.state_file.clone();
+
+    let client = match create_client(args) {
         Ok(val) => val,
         Err(e) => {
             log::error!("Can't create client application: {e}");
@@ -37,29 +610,56 @@ fn main() {
 
     log::info!("Client: {}", client);
 
-    let control = match client.start_receive_quotes() {
+    let mut control = match start_client(
+        client,
+        output.as_deref(),
+        console,
+        tui,
+        bar_interval_millis,
+        alerts,
+    ) {
         Ok(val) => val,
         Err(e) => {
             log::error!("Can't start client application: {e}");
             return;
         }
     };
+    log::info!("Receive quotes port: {}", control.recv_quote_port);
+    log::info!("Session: {}", control.session);
 
-    let mut cmd_buf = String::new();
-    let stdin = std::io::stdin();
-    loop {
-        println!("To stop client type \"exit\"");
-        if let Err(e) = stdin.read_line(&mut cmd_buf) {
-            log::error!("Can't read new command: {e}");
-            break;
+    let health = health_output.and_then(|output| {
+        match HealthReporter::new(&control, output, health_max_quote_age_millis) {
+            Ok(reporter) => Some(reporter.start()),
+            Err(e) => {
+                log::error!("Can't start health reporter: {e}");
+                None
+            }
         }
-        if cmd_buf.trim().to_lowercase() == "exit" {
-            break;
-        } else {
-            cmd_buf.clear();
+    });
+
+    if tui {
+        if let Err(e) = run_tui(&control) {
+            log::error!("Terminal UI error: {e}");
         }
+    } else {
+        run_commands(&control);
     }
 
+    log::info!("Client stats: {}", control.client_stats());
+    if let Some(validator) = control.validator.as_ref() {
+        log::info!("Invalid quotes: {}", validator.stats());
+    }
+    if let Some(dedup) = control.dedup.as_ref() {
+        log::info!("Suppressed duplicates: {}", dedup.suppressed());
+    }
+    log::info!("Quote loss: {}", control.loss.total());
+    if let Some(path) = state_file.as_deref() {
+        match control.save_state().save(path) {
+            Ok(()) => log::info!("Session state is saved to {}", path.display()),
+            Err(e) => log::error!("Can't save session state to {}: {e}", path.display()),
+        }
+    }
+    control.quotes = None;
     if let Err(e) = control.tx.send(ClientCmd::Stop) {
         log::error!("Stop error: {e}");
     }
@@ -67,5 +667,11 @@ fn main() {
     if control.thread_handle.join().is_err() {
         log::error!("Can't join thread");
     }
+    if let Some(health) = health {
+        let _ = health.tx.send(ClientCmd::Stop);
+        if health.thread_handle.join().is_err() {
+            log::error!("Can't join health reporter thread");
+        }
+    }
     log::info!("Exit");
 }
@@ -1,14 +1,25 @@
 use clap::Parser;
 use std::path::Path;
+use std::thread;
 use streaming_quotes::init_log;
+use streaming_quotes::server::config::ServerConfig;
+use streaming_quotes::server::error::ServerError;
 use streaming_quotes::server::quotes_server::{ControlCmd, QuotesServer};
+use streaming_quotes::server::relay::UpstreamRelay;
+#[cfg(target_os = "linux")]
+use streaming_quotes::utils::systemd_listener;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Server config path
+    /// Generator config paths, comma separated. Tickers of all configs are merged.
+    /// Not needed if the server relays quotes of an upstream server
+    #[arg(short, long, value_delimiter = ',')]
+    config_path: Vec<String>,
+
+    /// Server settings path (json)
     #[arg(short, long)]
-    config_path: String,
+    server_config: Option<String>,
 }
 
 fn main() {
@@ -19,7 +30,29 @@ fn main() {
 
     let args = Args::parse();
 
-    let quotes_server = match QuotesServer::new(&args.config_path) {
+    let server_config = match args.server_config {
+        Some(path) => match ServerConfig::from_file(&path) {
+            Ok(val) => val,
+            Err(e) => {
+                log::error!("Can't read server config: {e}");
+                return;
+            }
+        },
+        None => ServerConfig::default(),
+    };
+
+    let config_paths: Vec<&str> = args.config_path.iter().map(String::as_str).collect();
+    let quotes_server = match server_config.upstream.clone() {
+        Some(upstream) => UpstreamRelay::connect(&upstream)
+            .map_err(ServerError::from)
+            .and_then(|relay| QuotesServer::with_source(relay, server_config)),
+        None if config_paths.is_empty() => {
+            log::error!("Generator config path or upstream server is required");
+            return;
+        }
+        None => QuotesServer::with_config(&config_paths, server_config),
+    };
+    let mut quotes_server = match quotes_server {
         Ok(val) => val,
         Err(e) => {
             log::error!("Can't create server: {e}");
@@ -27,6 +60,19 @@ fn main() {
         }
     };
 
+    #[cfg(target_os = "linux")]
+    match systemd_listener() {
+        Ok(Some(listener)) => {
+            log::info!("Use listener socket passed by systemd");
+            quotes_server = quotes_server.with_listener(listener);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("Can't use listener socket passed by systemd: {e}");
+            return;
+        }
+    }
+
     let server_control = match quotes_server.start() {
         Ok(val) => val,
         Err(e) => {
@@ -35,27 +81,46 @@ fn main() {
         }
     };
 
-    let mut cmd_buf = String::new();
-    let stdin = std::io::stdin();
-    loop {
-        println!("To stop server type \"exit\"");
-        if let Err(e) = stdin.read_line(&mut cmd_buf) {
-            log::error!("Can't read new command: {e}");
-            break;
-        }
-        if cmd_buf.trim().to_lowercase() == "exit" {
-            break;
-        } else {
+    if let Err(e) = server_control.stop_on_signal() {
+        log::error!("Can't set stop signal handler: {e}");
+    }
+
+    let tx = server_control.tx.clone();
+    let stats = server_control.stats.clone();
+    thread::spawn(move || {
+        let mut cmd_buf = String::new();
+        let stdin = std::io::stdin();
+        loop {
+            println!("To stop server type \"exit\", to show thread statistics type \"stats\"");
+            match stdin.read_line(&mut cmd_buf) {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("Can't read new command: {e}");
+                    break;
+                }
+            }
+            match cmd_buf.trim().to_lowercase().as_str() {
+                "exit" => break,
+                "stats" => {
+                    for (subsystem, stats) in stats.snapshot() {
+                        println!("{subsystem}: {stats}");
+                    }
+                }
+                _ => {}
+            }
             cmd_buf.clear();
         }
-    }
 
-    if let Err(e) = server_control.tx.send(ControlCmd::Stop) {
-        log::error!("Stop error: {e}");
-    }
+        if let Err(e) = tx.send(ControlCmd::Stop) {
+            log::error!("Stop error: {e}");
+        }
+    });
 
-    if server_control.thread_handle.join().is_err() {
-        log::error!("Can't join thread");
+    match server_control.thread_handle.join() {
+        Ok(Err(e)) => log::error!("Server is stopped with error: {e}"),
+        Err(_) => log::error!("Can't join thread"),
+        Ok(Ok(())) => {}
     }
     log::info!("Exit");
 }
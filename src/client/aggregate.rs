@@ -0,0 +1,191 @@
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::validate::QuoteIssue;
+use crate::quote::{Bar, StockQuote};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Средневзвешенная по объему цена тикера с начала приема котировок
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vwap {
+    /// Средневзвешенная по объему цена
+    pub price: f64,
+    /// Суммарный объем котировок
+    pub volume: u64,
+}
+
+#[derive(Default)]
+struct TickerAggregate {
+    open: Option<Bar>,
+    last_closed: Option<Bar>,
+    turnover: f64,
+    volume: u64,
+}
+
+/// Собирает по котировкам тикеров свечи OHLCV и VWAP на стороне клиента.
+/// Свеча тикера закрывается первой котировкой этого тикера после конца ее интервала
+pub struct QuoteAggregator {
+    interval_millis: u64,
+    tickers: Mutex<HashMap<String, TickerAggregate>>,
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl QuoteAggregator {
+    /// Агрегатор свечей длиной interval_millis
+    pub fn new(interval_millis: u64) -> Self {
+        Self {
+            interval_millis: interval_millis.max(1),
+            tickers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Учитывает котировку, принятую в now_millis. Возвращает закрытую свечу тикера
+    pub fn update(&self, quote: &StockQuote, now_millis: u64) -> Option<Bar> {
+        let start_millis = now_millis - now_millis % self.interval_millis;
+        let mut tickers = self.tickers.lock().unwrap();
+        let state = tickers.entry(quote.ticker.clone()).or_default();
+        state.turnover += quote.price * f64::from(quote.volume);
+        state.volume += u64::from(quote.volume);
+
+        let mut closed = None;
+        match state.open.as_mut() {
+            Some(bar) if bar.start_millis >= start_millis => bar.update(quote),
+            _ => {
+                closed = state
+                    .open
+                    .replace(Bar::open(quote, start_millis, self.interval_millis));
+            }
+        }
+        if let Some(bar) = closed.as_ref() {
+            state.last_closed = Some(bar.clone());
+        }
+        closed
+    }
+
+    /// Текущая незакрытая свеча тикера
+    pub fn open_bar(&self, ticker: &str) -> Option<Bar> {
+        self.tickers.lock().unwrap().get(ticker)?.open.clone()
+    }
+
+    /// Последняя закрытая свеча тикера
+    pub fn last_bar(&self, ticker: &str) -> Option<Bar> {
+        self.tickers
+            .lock()
+            .unwrap()
+            .get(ticker)?
+            .last_closed
+            .clone()
+    }
+
+    /// VWAP тикера. None, если по тикеру не было котировок с ненулевым объемом
+    pub fn vwap(&self, ticker: &str) -> Option<Vwap> {
+        let tickers = self.tickers.lock().unwrap();
+        let state = tickers.get(ticker).filter(|state| state.volume > 0)?;
+        Some(Vwap {
+            price: state.turnover / state.volume as f64,
+            volume: state.volume,
+        })
+    }
+}
+
+/// Обработчик, который передает котировки внутреннему обработчику и собирает по ним
+/// свечи и VWAP. Закрытые свечи передаются внутреннему обработчику через `on_bar`
+pub struct AggregatingHandler<H: QuoteHandler> {
+    inner: H,
+    aggregator: Arc<QuoteAggregator>,
+}
+
+impl<H: QuoteHandler> AggregatingHandler<H> {
+    /// Создает обработчик со свечами длиной interval_millis и агрегатор для запросов
+    /// текущих свечей и VWAP
+    pub fn new(inner: H, interval_millis: u64) -> (Self, Arc<QuoteAggregator>) {
+        let aggregator = Arc::new(QuoteAggregator::new(interval_millis));
+        (
+            Self {
+                inner,
+                aggregator: aggregator.clone(),
+            },
+            aggregator,
+        )
+    }
+}
+
+impl<H: QuoteHandler> QuoteHandler for AggregatingHandler<H> {
+    fn on_quote(&mut self, quote: StockQuote) {
+        let closed = self.aggregator.update(&quote, unix_millis());
+        self.inner.on_quote(quote);
+        if let Some(bar) = closed {
+            self.inner.on_bar(bar);
+        }
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        self.inner.on_replay(quote);
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        self.inner.on_flagged(quote, issue);
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        self.inner.on_bar(bar);
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        self.inner.on_connection(event);
+    }
+
+    fn on_tick(&mut self) {
+        self.inner.on_tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_aggregator() {
+        let quote = |ticker: &str, price: f64, volume: u32| StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume,
+            timestamp: 0,
+        };
+        let aggregator = QuoteAggregator::new(1000);
+        assert!(aggregator.update(&quote("AMD", 10.0, 1), 5100).is_none());
+        assert!(aggregator.update(&quote("AMD", 12.0, 2), 5400).is_none());
+        assert!(aggregator.update(&quote("INT", 5.0, 1), 6100).is_none());
+        assert!(aggregator.update(&quote("AMD", 9.0, 3), 5700).is_none());
+        assert_eq!(aggregator.open_bar("AMD").unwrap().high, 12.0);
+
+        let bar = aggregator.update(&quote("AMD", 11.0, 4), 6200).unwrap();
+        assert_eq!(
+            bar,
+            Bar {
+                ticker: "AMD".to_string(),
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 9.0,
+                volume: 6,
+                start_millis: 5000,
+                interval_millis: 1000,
+            }
+        );
+        assert_eq!(aggregator.last_bar("AMD"), Some(bar));
+        assert_eq!(aggregator.open_bar("AMD").unwrap().start_millis, 6000);
+        assert!(aggregator.last_bar("INT").is_none());
+
+        let vwap = aggregator.vwap("AMD").unwrap();
+        assert_eq!(vwap.volume, 10);
+        assert!((vwap.price - 105.0 / 10.0).abs() < 1e-9);
+        assert!(aggregator.vwap("MSFT").is_none());
+    }
+}
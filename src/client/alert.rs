@@ -0,0 +1,251 @@
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::validate::QuoteIssue;
+use crate::quote::{Bar, StockQuote};
+use std::fmt::Display;
+
+/// Условие срабатывания оповещения
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertCondition {
+    /// Цена поднялась выше уровня
+    Above(f64),
+    /// Цена опустилась ниже уровня
+    Below(f64),
+    /// Цена изменилась не меньше чем на заданный процент от опорной цены.
+    /// Опорная цена - первая котировка тикера, затем цена последнего срабатывания
+    MovePercent(f64),
+}
+
+/// Сработавшее оповещение
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceAlert {
+    /// Тикер
+    pub ticker: String,
+    /// Условие оповещения
+    pub condition: AlertCondition,
+    /// Цена котировки, на которой сработало оповещение
+    pub price: f64,
+    /// Опорная цена для `AlertCondition::MovePercent`, иначе уровень
+    pub reference: f64,
+}
+
+impl Display for PriceAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.condition {
+            AlertCondition::Above(level) => {
+                write!(
+                    f,
+                    "{} price {:.4} is above {level}",
+                    self.ticker, self.price
+                )
+            }
+            AlertCondition::Below(level) => {
+                write!(
+                    f,
+                    "{} price {:.4} is below {level}",
+                    self.ticker, self.price
+                )
+            }
+            AlertCondition::MovePercent(percent) => write!(
+                f,
+                "{} price {:.4} moved at least {percent}% from {:.4}",
+                self.ticker, self.price, self.reference
+            ),
+        }
+    }
+}
+
+type AlertCallback = Box<dyn FnMut(&PriceAlert) + Send>;
+
+struct Alert {
+    ticker: String,
+    condition: AlertCondition,
+    callback: AlertCallback,
+    triggered: bool,
+    reference: Option<f64>,
+}
+
+impl Alert {
+    fn crossed(&mut self, hit: bool) -> bool {
+        let crossed = hit && !self.triggered;
+        self.triggered = hit;
+        crossed
+    }
+
+    fn moved(&mut self, price: f64, percent: f64) -> Option<f64> {
+        let reference = self.reference.replace(price)?;
+        if reference != 0.0 && ((price - reference) / reference * 100.0).abs() >= percent {
+            return Some(reference);
+        }
+        self.reference = Some(reference);
+        None
+    }
+
+    fn check(&mut self, quote: &StockQuote) -> Option<PriceAlert> {
+        if quote.ticker != self.ticker {
+            return None;
+        }
+        let reference = match self.condition {
+            AlertCondition::Above(level) => self.crossed(quote.price > level).then_some(level)?,
+            AlertCondition::Below(level) => self.crossed(quote.price < level).then_some(level)?,
+            AlertCondition::MovePercent(percent) => self.moved(quote.price, percent)?,
+        };
+        Some(PriceAlert {
+            ticker: quote.ticker.clone(),
+            condition: self.condition,
+            price: quote.price,
+            reference,
+        })
+    }
+}
+
+/// Набор ценовых оповещений. Оповещения о пересечении уровня срабатывают один раз,
+/// пока цена не вернется за уровень
+#[derive(Default)]
+pub struct PriceAlerts {
+    alerts: Vec<Alert>,
+}
+
+impl PriceAlerts {
+    /// Добавляет оповещение с условием condition по тикеру
+    pub fn on_condition<F>(mut self, ticker: &str, condition: AlertCondition, callback: F) -> Self
+    where
+        F: FnMut(&PriceAlert) + Send + 'static,
+    {
+        self.alerts.push(Alert {
+            ticker: ticker.to_string(),
+            condition,
+            callback: Box::new(callback),
+            triggered: false,
+            reference: None,
+        });
+        self
+    }
+
+    /// Оповещение о подъеме цены тикера выше level
+    pub fn on_price_above<F>(self, ticker: &str, level: f64, callback: F) -> Self
+    where
+        F: FnMut(&PriceAlert) + Send + 'static,
+    {
+        self.on_condition(ticker, AlertCondition::Above(level), callback)
+    }
+
+    /// Оповещение о снижении цены тикера ниже level
+    pub fn on_price_below<F>(self, ticker: &str, level: f64, callback: F) -> Self
+    where
+        F: FnMut(&PriceAlert) + Send + 'static,
+    {
+        self.on_condition(ticker, AlertCondition::Below(level), callback)
+    }
+
+    /// Оповещение об изменении цены тикера не меньше чем на percent процентов
+    pub fn on_move_pct<F>(self, ticker: &str, percent: f64, callback: F) -> Self
+    where
+        F: FnMut(&PriceAlert) + Send + 'static,
+    {
+        self.on_condition(ticker, AlertCondition::MovePercent(percent), callback)
+    }
+
+    /// Проверяет котировку и вызывает сработавшие оповещения
+    pub fn check(&mut self, quote: &StockQuote) {
+        for alert in self.alerts.iter_mut() {
+            if let Some(event) = alert.check(quote) {
+                (alert.callback)(&event);
+            }
+        }
+    }
+
+    /// Оповещений нет
+    pub fn is_empty(&self) -> bool {
+        self.alerts.is_empty()
+    }
+}
+
+/// Обработчик, который проверяет оповещения по каждой котировке в потоке приема
+/// и передает котировки внутреннему обработчику
+pub struct AlertingHandler<H: QuoteHandler> {
+    inner: H,
+    alerts: PriceAlerts,
+}
+
+impl<H: QuoteHandler> AlertingHandler<H> {
+    /// Проверяет alerts по котировкам, которые получает inner
+    pub fn new(inner: H, alerts: PriceAlerts) -> Self {
+        Self { inner, alerts }
+    }
+}
+
+impl<H: QuoteHandler> QuoteHandler for AlertingHandler<H> {
+    fn on_quote(&mut self, quote: StockQuote) {
+        self.alerts.check(&quote);
+        self.inner.on_quote(quote);
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        self.inner.on_replay(quote);
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        self.inner.on_flagged(quote, issue);
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        self.inner.on_bar(bar);
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        self.inner.on_connection(event);
+    }
+
+    fn on_tick(&mut self) {
+        self.inner.on_tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_price_alerts() {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let collect = |fired: &Arc<Mutex<Vec<PriceAlert>>>| {
+            let fired = fired.clone();
+            move |alert: &PriceAlert| fired.lock().unwrap().push(alert.clone())
+        };
+        let mut alerts = PriceAlerts::default()
+            .on_price_above("AMD", 100.0, collect(&fired))
+            .on_price_below("AMD", 90.0, collect(&fired))
+            .on_move_pct("INT", 10.0, collect(&fired));
+
+        let quote = |ticker: &str, price: f64| StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume: 1,
+            timestamp: 0,
+        };
+        for price in [95.0, 101.0, 105.0, 99.0, 102.0, 89.0] {
+            alerts.check(&quote("AMD", price));
+        }
+        for price in [50.0, 54.0, 56.0, 60.0, 50.0] {
+            alerts.check(&quote("INT", price));
+        }
+
+        let fired: Vec<(AlertCondition, f64, f64)> = fired
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|alert| (alert.condition, alert.price, alert.reference))
+            .collect();
+        assert_eq!(
+            fired,
+            vec![
+                (AlertCondition::Above(100.0), 101.0, 100.0),
+                (AlertCondition::Above(100.0), 102.0, 100.0),
+                (AlertCondition::Below(90.0), 89.0, 90.0),
+                (AlertCondition::MovePercent(10.0), 56.0, 50.0),
+                (AlertCondition::MovePercent(10.0), 50.0, 56.0),
+            ]
+        );
+    }
+}
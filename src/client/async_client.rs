@@ -0,0 +1,330 @@
+use super::quotes_client::{PingTimeouts, loopback_for, resolve_server_addr};
+use crate::protocol::*;
+use crate::quote::StockQuote;
+use anyhow::{Result, anyhow, bail};
+use futures_core::Stream;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+const WAIT_SUBSCRIBED_MILLIS: u64 = 5000;
+const CONNECT_TIMEOUT_MILLIS: u64 = 5000;
+
+/// Асинхронный клиент приёма котировок. Не создает потоков: котировки принимаются
+/// в задаче, которая опрашивает QuoteStream
+#[derive(Debug, Clone)]
+pub struct AsyncQuotesClient {
+    server_addr: SocketAddr,
+    recv_quote_port: u16,
+    tickers: Vec<String>,
+    token: Option<String>,
+    ping_timeouts: PingTimeouts,
+}
+
+/// Поток котировок подписки. Заканчивается, когда сервер останавливается
+/// или перестает отвечать на пинг. Поврежденные датаграммы пропускаются
+pub struct QuoteStream {
+    socket: UdpSocket,
+    _control: TcpStream,
+    server_udp: Option<SocketAddr>,
+    ping: Interval,
+    ping_timeouts: PingTimeouts,
+    last_datagram: Instant,
+    session: String,
+    bad_datagrams: u64,
+    finished: bool,
+}
+
+async fn recv_message(stream: &mut TcpStream) -> Result<Message> {
+    let read = async {
+        let mut bin_len = [0u8; 4];
+        stream.read_exact(&mut bin_len).await?;
+        let mut bin_msg = vec![0u8; u32::from_be_bytes(bin_len) as usize];
+        stream.read_exact(&mut bin_msg).await?;
+        Ok::<_, anyhow::Error>(postcard::from_bytes(&bin_msg)?)
+    };
+    match tokio::time::timeout(Duration::from_millis(WAIT_SUBSCRIBED_MILLIS), read).await {
+        Ok(msg) => msg,
+        Err(_) => bail!("Server doesn't respond during {WAIT_SUBSCRIBED_MILLIS} ms"),
+    }
+}
+
+impl AsyncQuotesClient {
+    /// Создаёт новый асинхронный клиент котировок:
+    /// server_addr - ip-адрес или имя хоста сервера с портом для подключения по tcp
+    /// recv_quote_port - порт для приема котировок, 0 - любой свободный порт
+    /// tickers - тикеры подписки
+    pub fn new(server_addr: &str, recv_quote_port: u16, tickers: Vec<String>) -> Result<Self> {
+        Ok(Self {
+            server_addr: resolve_server_addr(server_addr)?,
+            recv_quote_port,
+            tickers,
+            token: None,
+            ping_timeouts: PingTimeouts::default(),
+        })
+    }
+
+    /// Токен доступа, который передается серверу вместе с запросом котировок
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Таймауты проверки связи с сервером. Период пинга должен быть меньше
+    /// времени ожидания пинга на сервере
+    pub fn with_ping_timeouts(mut self, ping_timeouts: PingTimeouts) -> Self {
+        self.ping_timeouts = ping_timeouts;
+        self
+    }
+
+    /// Подписывается на котировки. Многоадресная рассылка и TLS не поддерживаются
+    pub async fn subscribe(self) -> Result<QuoteStream> {
+        let udp_addr = SocketAddr::new(loopback_for(self.server_addr), self.recv_quote_port);
+        let socket = UdpSocket::bind(udp_addr).await?;
+        log::info!("Start receive quotes at addr: {}", socket.local_addr()?);
+
+        let connect = TcpStream::connect(self.server_addr);
+        let mut control = match tokio::time::timeout(
+            Duration::from_millis(CONNECT_TIMEOUT_MILLIS),
+            connect,
+        )
+        .await
+        {
+            Ok(Ok(val)) => val,
+            Ok(Err(e)) => bail!("Can't establish connection to {}: {e}", self.server_addr),
+            Err(_) => bail!(
+                "Server {} doesn't accept connection during {CONNECT_TIMEOUT_MILLIS} ms",
+                self.server_addr
+            ),
+        };
+        let req = Message::Tickers(TickerReqMessage {
+            port: socket.local_addr()?.port(),
+            stripe_ports: Vec::new(),
+            tickers: self.tickers.clone(),
+            token: self.token.clone(),
+            session: None,
+            filter: None,
+            bars: false,
+        });
+        log::debug!("Request tickers: {:?}", req);
+        control.write_all(&pack_message_with_len(&req)?).await?;
+        control.flush().await?;
+
+        let (unknown_tickers, tickers, session) = match recv_message(&mut control).await? {
+            Message::Subscribed {
+                multicast_group: Some(group),
+                ..
+            } => bail!("Server publishes quotes to multicast group {group}"),
+            Message::Subscribed {
+                unknown_tickers,
+                tickers,
+                session,
+                ..
+            } => (unknown_tickers, tickers, session),
+            Message::Error { code } => bail!("Server rejected connection: {code:?}"),
+            msg => bail!("Unexpected response: {msg:?}"),
+        };
+        if tickers.is_empty() {
+            bail!("Server doesn't know any of requested tickers: {unknown_tickers:?}");
+        }
+        if !unknown_tickers.is_empty() {
+            log::warn!("Unknown tickers are ignored by server: {unknown_tickers:?}");
+        }
+
+        let period = Duration::from_millis(self.ping_timeouts.ping_period_millis);
+        let mut ping = tokio::time::interval_at(Instant::now() + period, period);
+        ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Ok(QuoteStream {
+            socket,
+            _control: control,
+            server_udp: None,
+            ping,
+            ping_timeouts: self.ping_timeouts,
+            last_datagram: Instant::now(),
+            session,
+            bad_datagrams: 0,
+            finished: false,
+        })
+    }
+}
+
+impl QuoteStream {
+    /// Токен сессии, выданный сервером при подписке
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Количество пропущенных датаграмм, которые не удалось декодировать
+    pub fn bad_datagrams(&self) -> u64 {
+        self.bad_datagrams
+    }
+
+    fn poll_datagram(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<StockQuote>>> {
+        let mut recv_buf = [0u8; MAX_SIZE_DATAGRAM];
+        loop {
+            let mut buf = ReadBuf::new(&mut recv_buf);
+            let addr = match self.socket.poll_recv_from(cx, &mut buf) {
+                Poll::Ready(Ok(addr)) => addr,
+                Poll::Ready(Err(e)) if e.kind() == ErrorKind::ConnectionReset => continue,
+                Poll::Ready(Err(e)) => {
+                    return Poll::Ready(Err(anyhow!("Can't receive quotes: {e}")));
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            self.last_datagram = Instant::now();
+            let msg = match postcard::from_bytes::<Message>(buf.filled()) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    log::warn!("Skip bad datagram from {addr}: {e}");
+                    self.bad_datagrams += 1;
+                    continue;
+                }
+            };
+            match msg {
+                Message::Quote(resp) | Message::ReplayQuote(resp) => {
+                    self.server_udp = Some(addr);
+                    return Poll::Ready(Ok(Some(resp.quote)));
+                }
+                Message::Pong => log::info!("PONG"),
+                Message::Shutdown => {
+                    log::info!("Server is shutting down");
+                    return Poll::Ready(Ok(None));
+                }
+                msg => log::warn!("Unexpected datagram: {msg:?}"),
+            }
+        }
+    }
+
+    fn poll_ping(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        while self.ping.poll_tick(cx).is_ready() {
+            let timeout = Duration::from_millis(
+                self.ping_timeouts.ping_period_millis + self.ping_timeouts.wait_pong_millis,
+            );
+            if self.last_datagram.elapsed() > timeout {
+                bail!("Server doesn't respond during {} ms", timeout.as_millis());
+            }
+            let Some(addr) = self.server_udp else {
+                continue;
+            };
+            match self
+                .socket
+                .try_send_to(&postcard::to_stdvec(&Message::Ping)?, addr)
+            {
+                Ok(_) => log::info!("PING"),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => bail!("Can't send ping: {e}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Stream for QuoteStream {
+    type Item = StockQuote;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<StockQuote>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
+        }
+        let res = match this.poll_ping(cx) {
+            Ok(()) => match this.poll_datagram(cx) {
+                Poll::Ready(res) => res,
+                Poll::Pending => return Poll::Pending,
+            },
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(Some(quote)) => Poll::Ready(Some(quote)),
+            Ok(None) => {
+                this.finished = true;
+                Poll::Ready(None)
+            }
+            Err(e) => {
+                log::error!("Quote stream is finished: {e}");
+                this.finished = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServerBuilder;
+    use futures_core::Stream;
+
+    fn quote(timestamp: u64) -> StockQuote {
+        StockQuote {
+            ticker: "AMD".to_string(),
+            price: 1.0,
+            volume: 1,
+            timestamp,
+        }
+    }
+
+    async fn next(stream: &mut QuoteStream) -> Option<StockQuote> {
+        let next = std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx));
+        tokio::time::timeout(Duration::from_secs(5), next)
+            .await
+            .unwrap()
+    }
+
+    fn free_udp_port() -> u16 {
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[tokio::test]
+    async fn test_stream_skips_bad_datagrams() {
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .with_quotes((1..=3).map(quote).collect())
+            .with_interval_millis(50)
+            .start()
+            .unwrap();
+        let port = free_udp_port();
+        let mut stream =
+            AsyncQuotesClient::new(&server.addr().to_string(), port, vec!["AMD".to_string()])
+                .unwrap()
+                .subscribe()
+                .await
+                .unwrap();
+        assert!(stream.session().starts_with("mock-"));
+
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(&[u8::MAX; 8], ("127.0.0.1", port)).unwrap();
+        let mut timestamps = Vec::new();
+        for _ in 0..3 {
+            timestamps.push(next(&mut stream).await.unwrap().timestamp);
+        }
+        assert_eq!(timestamps, vec![1, 2, 3]);
+        assert_eq!(stream.bad_datagrams(), 1);
+
+        drop(server);
+        assert!(next(&mut stream).await.is_none());
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_rejected() {
+        let server = MockServerBuilder::default()
+            .with_rejection(ErrorCode::Unauthorized)
+            .start()
+            .unwrap();
+        let res = AsyncQuotesClient::new(&server.addr().to_string(), 0, vec!["AMD".to_string()])
+            .unwrap()
+            .subscribe()
+            .await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::validate::QuoteIssue;
+use crate::quote::{Bar, StockQuote};
+use std::time::{Duration, Instant};
+
+/// Обработчик, который копит котировки и раз в интервал передает внутреннему обработчику
+/// только последнюю котировку каждого тикера. Защищает медленных получателей,
+/// например интерфейс, от всплесков котировок. Котировки истории, помеченные котировки,
+/// свечи и события соединения передаются сразу
+pub struct ConflatingHandler<H: QuoteHandler> {
+    inner: H,
+    interval: Duration,
+    pending: Vec<StockQuote>,
+    last_flush: Instant,
+}
+
+impl<H: QuoteHandler> ConflatingHandler<H> {
+    /// Передает inner последние котировки тикеров раз в interval_millis
+    pub fn new(inner: H, interval_millis: u64) -> Self {
+        Self {
+            inner,
+            interval: Duration::from_millis(interval_millis),
+            pending: Vec::new(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn flush_if_due(&mut self, now: Instant) {
+        if now.duration_since(self.last_flush) < self.interval {
+            return;
+        }
+        self.last_flush = now;
+        for quote in self.pending.drain(..) {
+            self.inner.on_quote(quote);
+        }
+    }
+}
+
+impl<H: QuoteHandler> QuoteHandler for ConflatingHandler<H> {
+    fn on_quote(&mut self, quote: StockQuote) {
+        match self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.ticker == quote.ticker)
+        {
+            Some(pending) => *pending = quote,
+            None => self.pending.push(quote),
+        }
+        self.flush_if_due(Instant::now());
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        self.inner.on_replay(quote);
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        self.inner.on_flagged(quote, issue);
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        self.inner.on_bar(bar);
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        self.inner.on_connection(event);
+    }
+
+    fn on_tick(&mut self) {
+        self.flush_if_due(Instant::now());
+        self.inner.on_tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collector {
+        quotes: Vec<(String, f64)>,
+    }
+
+    impl QuoteHandler for Collector {
+        fn on_quote(&mut self, quote: StockQuote) {
+            self.quotes.push((quote.ticker, quote.price));
+        }
+
+        fn on_bar(&mut self, _bar: Bar) {}
+    }
+
+    #[test]
+    fn test_conflating_handler() {
+        let quote = |ticker: &str, price: f64| StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            ..Default::default()
+        };
+        let mut handler = ConflatingHandler::new(Collector::default(), 1000);
+        let start = handler.last_flush;
+        handler.on_quote(quote("AMD", 1.0));
+        handler.on_quote(quote("INT", 5.0));
+        handler.on_quote(quote("AMD", 2.0));
+        handler.flush_if_due(start + Duration::from_millis(500));
+        assert!(handler.inner.quotes.is_empty());
+
+        handler.flush_if_due(start + Duration::from_millis(1000));
+        assert_eq!(
+            handler.inner.quotes,
+            vec![("AMD".to_string(), 2.0), ("INT".to_string(), 5.0)]
+        );
+        handler.flush_if_due(start + Duration::from_millis(2500));
+        assert_eq!(handler.inner.quotes.len(), 2);
+    }
+}
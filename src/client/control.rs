@@ -0,0 +1,151 @@
+use super::error::{ClientError, ClientResult};
+use crate::protocol::{MAX_CONTROL_MESSAGE_LEN, Message};
+use anyhow::anyhow;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read};
+
+const READ_CHUNK_LEN: usize = 4096;
+
+/// Ответы сервера на изменения подписки и запросы истории. Поток приема читает их
+/// без ожидания между опросами сокетов, поэтому изменение подписки не задерживает прием
+#[derive(Default)]
+pub(super) struct ControlReplies {
+    buf: Vec<u8>,
+    changes: VecDeque<bool>,
+    history: usize,
+}
+
+impl ControlReplies {
+    /// Отправлено изменение подписки. backfill - запросить историю тикеров из подтверждения
+    pub(super) fn change_sent(&mut self, backfill: bool) {
+        self.changes.push_back(backfill);
+    }
+
+    /// Принято подтверждение изменения подписки. Возвращает признак backfill
+    pub(super) fn change_acked(&mut self) -> bool {
+        self.changes.pop_front().unwrap_or_default()
+    }
+
+    /// Отправлен запрос истории тикера
+    pub(super) fn history_sent(&mut self) {
+        self.history += 1;
+    }
+
+    /// Принята история тикера
+    pub(super) fn history_received(&mut self) {
+        self.history = self.history.saturating_sub(1);
+    }
+
+    /// Сервер ответил еще не на все запросы
+    pub(super) fn is_waiting(&self) -> bool {
+        !self.changes.is_empty() || self.history > 0
+    }
+
+    /// Читает все данные, доступные в неблокирующем потоке
+    pub(super) fn read_available<R: Read>(&mut self, reader: &mut R) -> ClientResult<()> {
+        let mut chunk = [0u8; READ_CHUNK_LEN];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(ClientError::Io(std::io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "server closed connection",
+                    )));
+                }
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Следующее полностью принятое сообщение
+    pub(super) fn next_message(&mut self) -> ClientResult<Option<Message>> {
+        let [b0, b1, b2, b3, ..] = self.buf[..] else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes([b0, b1, b2, b3]);
+        if len > MAX_CONTROL_MESSAGE_LEN {
+            return Err(ClientError::Decode(anyhow!(
+                "control message of {len} bytes is too long"
+            )));
+        }
+        let end = 4 + len as usize;
+        if self.buf.len() < end {
+            return Ok(None);
+        }
+        let msg = postcard::from_bytes(&self.buf[4..end]);
+        self.buf.drain(..end);
+        msg.map(Some).map_err(|e| ClientError::Decode(e.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{SubscriptionMode, pack_message_with_len};
+    use std::io::Cursor;
+
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn test_control_replies() {
+        let mut replies = ControlReplies::default();
+        assert!(!replies.is_waiting());
+        replies.change_sent(true);
+        replies.change_sent(false);
+        assert!(replies.is_waiting());
+
+        let packed = pack_message_with_len(&Message::SubscriptionChanged {
+            mode: SubscriptionMode::Add,
+            unknown_tickers: Vec::new(),
+            tickers: vec!["AMD".to_string()],
+        })
+        .unwrap();
+        let (head, tail) = packed.split_at(3);
+        let mut reader = Chunks(vec![head.to_vec()]);
+        replies.read_available(&mut reader).unwrap();
+        assert!(replies.next_message().unwrap().is_none());
+
+        let mut reader = Chunks(vec![
+            tail.to_vec(),
+            pack_message_with_len(&Message::Pong).unwrap(),
+        ]);
+        replies.read_available(&mut reader).unwrap();
+        assert!(matches!(
+            replies.next_message().unwrap(),
+            Some(Message::SubscriptionChanged { .. })
+        ));
+        assert!(replies.change_acked());
+        assert!(matches!(
+            replies.next_message().unwrap(),
+            Some(Message::Pong)
+        ));
+        assert!(replies.next_message().unwrap().is_none());
+        assert!(!replies.change_acked());
+        assert!(!replies.is_waiting());
+
+        assert!(matches!(
+            replies.read_available(&mut Cursor::new(Vec::new())),
+            Err(ClientError::Io(_))
+        ));
+        let mut reader = Chunks(vec![u32::MAX.to_be_bytes().to_vec()]);
+        replies.read_available(&mut reader).unwrap();
+        assert!(matches!(
+            replies.next_message(),
+            Err(ClientError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::quote::StockQuote;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DedupKey {
+    Seq(u64),
+    Timestamp(u64),
+}
+
+#[derive(Default)]
+struct RecentKeys {
+    order: VecDeque<DedupKey>,
+    keys: HashSet<DedupKey>,
+}
+
+/// Подавляет повторно доставленные живые котировки до передачи обработчику.
+/// Котировка считается повтором, если среди последних window котировок тикера уже была
+/// котировка с тем же номером последовательности, а без номера - с той же временной меткой.
+/// Разделяется между потоками приема котировок клиента
+pub struct QuoteDeduplicator {
+    window: usize,
+    tickers: Mutex<HashMap<String, RecentKeys>>,
+    suppressed: AtomicU64,
+}
+
+impl QuoteDeduplicator {
+    /// Помнит ключи последних window котировок каждого тикера
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            tickers: Mutex::new(HashMap::new()),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    /// Котировка с номером seq, 0 - без номера, уже была передана обработчику
+    pub(super) fn is_duplicate(&self, quote: &StockQuote, seq: u64) -> bool {
+        let key = match seq {
+            0 => DedupKey::Timestamp(quote.timestamp),
+            seq => DedupKey::Seq(seq),
+        };
+        let mut tickers = self.tickers.lock().unwrap();
+        let recent = tickers.entry(quote.ticker.clone()).or_default();
+        if !recent.keys.insert(key) {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        recent.order.push_back(key);
+        while recent.order.len() > self.window {
+            if let Some(oldest) = recent.order.pop_front() {
+                recent.keys.remove(&oldest);
+            }
+        }
+        false
+    }
+
+    /// Новый поток котировок после переподключения: номера начинаются заново
+    pub(super) fn restart(&self) {
+        self.tickers.lock().unwrap().clear();
+    }
+
+    /// Количество подавленных повторов
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_deduplicator() {
+        let dedup = QuoteDeduplicator::new(2);
+        let quote = |ticker: &str, timestamp: u64| StockQuote {
+            ticker: ticker.to_string(),
+            timestamp,
+            ..Default::default()
+        };
+        assert!(!dedup.is_duplicate(&quote("AMD", 1), 1));
+        assert!(dedup.is_duplicate(&quote("AMD", 1), 1));
+        assert!(!dedup.is_duplicate(&quote("INT", 1), 1));
+        assert!(!dedup.is_duplicate(&quote("AMD", 5), 0));
+        assert!(dedup.is_duplicate(&quote("AMD", 5), 0));
+        assert!(!dedup.is_duplicate(&quote("AMD", 6), 3));
+        assert!(!dedup.is_duplicate(&quote("AMD", 1), 1));
+        assert_eq!(dedup.suppressed(), 2);
+
+        dedup.restart();
+        assert!(!dedup.is_duplicate(&quote("AMD", 6), 3));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::protocol::{MDNS_SERVICE_TYPE, MDNS_TLS_PROPERTY, MDNS_WEBSOCKET_PROPERTY};
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Сервер котировок, найденный в локальной сети
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    /// Полное имя сервиса mDNS
+    pub name: String,
+    /// Адрес управляющего канала
+    pub addr: SocketAddr,
+    /// Управляющий канал защищен TLS
+    pub tls: bool,
+    /// Адрес точки подключения клиентов WebSocket, если она есть
+    pub websocket_addr: Option<SocketAddr>,
+}
+
+/// Сервер по описанию сервиса mDNS. None, если у сервиса нет адресов
+fn discovered_server(info: &ServiceInfo) -> Option<DiscoveredServer> {
+    let mut ips: Vec<_> = info.get_addresses().iter().copied().collect();
+    ips.sort_by_key(|ip| ip.is_ipv6());
+    let ip = ips.first().copied()?;
+    let websocket_addr = info
+        .get_property_val_str(MDNS_WEBSOCKET_PROPERTY)
+        .and_then(|port| port.parse().ok())
+        .map(|port| SocketAddr::new(ip, port));
+    Some(DiscoveredServer {
+        name: info.get_fullname().to_string(),
+        addr: SocketAddr::new(ip, info.get_port()),
+        tls: info.get_property_val_str(MDNS_TLS_PROPERTY) == Some("1"),
+        websocket_addr,
+    })
+}
+
+/// Ищет серверы котировок в локальной сети через mDNS в течение timeout.
+/// Адреса IPv4 идут раньше IPv6
+pub fn discover_servers(timeout: Duration) -> Result<Vec<DiscoveredServer>> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(MDNS_SERVICE_TYPE)?;
+    let deadline = Instant::now() + timeout;
+    let mut servers = Vec::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = events.recv_timeout(left) else {
+            break;
+        };
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        let Some(server) = discovered_server(&info) else {
+            continue;
+        };
+        log::debug!("Discovered server: {server:?}");
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    let _ = daemon.stop_browse(MDNS_SERVICE_TYPE);
+    let _ = daemon.shutdown();
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_discovered_server() {
+        let properties = HashMap::from([
+            (MDNS_TLS_PROPERTY.to_string(), "1".to_string()),
+            (MDNS_WEBSOCKET_PROPERTY.to_string(), "8091".to_string()),
+        ]);
+        let ips: [IpAddr; 2] = ["fe80::1".parse().unwrap(), "192.168.1.10".parse().unwrap()];
+        let info = ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            "quotes",
+            "quotes.local.",
+            &ips[..],
+            8090,
+            properties,
+        )
+        .unwrap();
+        assert_eq!(
+            discovered_server(&info),
+            Some(DiscoveredServer {
+                name: "quotes._quotes._tcp.local.".to_string(),
+                addr: "192.168.1.10:8090".parse().unwrap(),
+                tls: true,
+                websocket_addr: Some("192.168.1.10:8091".parse().unwrap()),
+            })
+        );
+
+        let info =
+            ServiceInfo::new(MDNS_SERVICE_TYPE, "quotes", "quotes.local.", (), 8090, None).unwrap();
+        assert_eq!(discovered_server(&info), None);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::protocol::ErrorCode;
+use std::net::SocketAddr;
+use thiserror::Error;
+
+/// Ошибки клиента котировок, по которым приложение может решать,
+/// повторять ли попытку и с какой задержкой
+#[derive(Error, Debug)]
+pub enum ClientError {
+    /// Некорректные настройки клиента
+    #[error("Invalid client config: {0}")]
+    Config(String),
+    /// Не удалось разрешить адрес сервера
+    #[error("Can't resolve server address {addr}: {reason}")]
+    Resolve {
+        /// Адрес сервера
+        addr: String,
+        /// Причина
+        reason: String,
+    },
+    /// Не удалось открыть сокет приема котировок
+    #[error("Can't bind {addr}: {source}")]
+    SocketBind {
+        /// Адрес сокета
+        addr: SocketAddr,
+        /// Причина
+        source: std::io::Error,
+    },
+    /// Не удалось подключиться к серверу
+    #[error("Can't establish connection to {addr}: {source}")]
+    Connect {
+        /// Адрес сервера
+        addr: SocketAddr,
+        /// Причина
+        source: std::io::Error,
+    },
+    /// Сервер не принял подключение за время ожидания
+    #[error("Server {addr} doesn't accept connection during {millis} ms")]
+    ConnectTimeout {
+        /// Адрес сервера
+        addr: SocketAddr,
+        /// Время ожидания
+        millis: u64,
+    },
+    /// Не удалось загрузить сертификаты или установить соединение TLS
+    #[error("TLS error: {0}")]
+    Tls(anyhow::Error),
+    /// Сервер отклонил запрос с кодом ошибки
+    #[error("Server rejected request: {0:?}")]
+    Rejected(ErrorCode),
+    /// Подписка не состоялась
+    #[error("Can't subscribe: {0}")]
+    Subscribe(String),
+    /// Не удалось прочитать или разобрать ответ сервера
+    #[error("Can't decode server response: {0}")]
+    Decode(anyhow::Error),
+    /// Сервер прислал сообщение, которое клиент не ожидает
+    #[error("Unexpected response: {0}")]
+    UnexpectedMessage(String),
+    /// Сервер не ответил на пинг
+    #[error("Server at address {0} doesn't response")]
+    PingTimeout(SocketAddr),
+    /// Не удалось присоединиться к группе multicast
+    #[error("Can't join multicast group {group}: {source}")]
+    Multicast {
+        /// Адрес группы
+        group: SocketAddr,
+        /// Причина
+        source: std::io::Error,
+    },
+    /// Попытки переподключения исчерпаны
+    #[error("Can't reconnect to server after {0} attempts")]
+    ReconnectExhausted(u32),
+    /// Поток клиента остановлен
+    #[error("Client thread is stopped")]
+    Stopped,
+    /// Не удалось закодировать сообщение серверу или состояние клиента
+    #[error("Can't encode: {0}")]
+    Encode(anyhow::Error),
+    /// Внутренняя ошибка таймера потока приема
+    #[error("Timer error: {0}")]
+    Timer(anyhow::Error),
+    /// Ошибка ввода-вывода
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl ClientError {
+    /// Сбой может пройти сам: имеет смысл повторить попытку позже.
+    /// Ошибки настроек, отказ сервера и несовместимые ответы повторять бесполезно
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Connect { .. }
+            | Self::ConnectTimeout { .. }
+            | Self::PingTimeout(_)
+            | Self::Resolve { .. }
+            | Self::Io(_) => true,
+            Self::Rejected(code) => {
+                matches!(code, ErrorCode::TooManyClients | ErrorCode::Overloaded)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Результат операций клиента котировок
+pub type ClientResult<T> = Result<T, ClientError>;
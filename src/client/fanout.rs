@@ -0,0 +1,155 @@
+use super::handler::QuoteHandler;
+use crate::quote::{Bar, StockQuote};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+
+struct Subscriber {
+    tickers: Option<Vec<String>>,
+    tx: mpsc::SyncSender<Arc<StockQuote>>,
+}
+
+/// Раздача котировок одной подписки клиента нескольким получателям приложения.
+/// Каждый получатель читает свой канал ограниченной емкости; котировка не копируется,
+/// а передается через `Arc`. Если канал получателя заполнен, котировка для него
+/// отбрасывается и учитывается, чтобы медленный получатель не задерживал остальных
+#[derive(Default)]
+pub struct QuoteBroadcast {
+    subscribers: Mutex<Vec<Subscriber>>,
+    dropped: AtomicU64,
+}
+
+impl QuoteBroadcast {
+    /// Новый получатель всех котировок с каналом емкостью capacity
+    pub fn subscribe(&self, capacity: usize) -> mpsc::Receiver<Arc<StockQuote>> {
+        self.add_subscriber(None, capacity)
+    }
+
+    /// Новый получатель котировок тикеров tickers с каналом емкостью capacity
+    pub fn subscribe_tickers<I>(
+        &self,
+        tickers: I,
+        capacity: usize,
+    ) -> mpsc::Receiver<Arc<StockQuote>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let tickers = tickers
+            .into_iter()
+            .map(|ticker| ticker.as_ref().to_string())
+            .collect();
+        self.add_subscriber(Some(tickers), capacity)
+    }
+
+    fn add_subscriber(
+        &self,
+        tickers: Option<Vec<String>>,
+        capacity: usize,
+    ) -> mpsc::Receiver<Arc<StockQuote>> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { tickers, tx });
+        rx
+    }
+
+    /// Количество получателей. Получатель удаляется, когда приложение удаляет его канал
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Количество котировок, отброшенных из-за заполненных каналов получателей
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Передает котировку всем получателям ее тикера
+    pub(super) fn publish(&self, quote: StockQuote) {
+        let quote = Arc::new(quote);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| {
+            let wanted = subscriber
+                .tickers
+                .as_ref()
+                .is_none_or(|tickers| tickers.contains(&quote.ticker));
+            if !wanted {
+                return true;
+            }
+            match subscriber.tx.try_send(quote.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+}
+
+/// Передает котировки в `QuoteBroadcast`. Свечи не передаются
+pub struct BroadcastHandler {
+    broadcast: Arc<QuoteBroadcast>,
+    bars_skipped: bool,
+}
+
+impl BroadcastHandler {
+    /// Создает обработчик и раздачу, у которой приложение берет каналы получателей
+    pub fn new() -> (Self, Arc<QuoteBroadcast>) {
+        let broadcast = Arc::new(QuoteBroadcast::default());
+        (
+            Self {
+                broadcast: broadcast.clone(),
+                bars_skipped: false,
+            },
+            broadcast,
+        )
+    }
+}
+
+impl QuoteHandler for BroadcastHandler {
+    fn on_quote(&mut self, quote: StockQuote) {
+        self.broadcast.publish(quote);
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        if !self.bars_skipped {
+            log::warn!(
+                "Bars are not passed to quote subscribers, skip bar of {}",
+                bar.ticker
+            );
+            self.bars_skipped = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_broadcast() {
+        let (mut handler, broadcast) = BroadcastHandler::new();
+        let all = broadcast.subscribe(10);
+        let amd = broadcast.subscribe_tickers(["AMD"], 10);
+        let slow = broadcast.subscribe(1);
+        let gone = broadcast.subscribe(10);
+        drop(gone);
+
+        for ticker in ["AMD", "INT", "AMD"] {
+            handler.on_quote(StockQuote {
+                ticker: ticker.to_string(),
+                ..Default::default()
+            });
+        }
+        assert_eq!(broadcast.subscribers(), 3);
+        assert_eq!(all.try_iter().count(), 3);
+        let amd: Vec<Arc<StockQuote>> = amd.try_iter().collect();
+        assert_eq!(amd.len(), 2);
+        assert!(amd.iter().all(|quote| quote.ticker == "AMD"));
+        assert_eq!(slow.try_iter().count(), 1);
+        assert_eq!(broadcast.dropped(), 2);
+    }
+}
@@ -0,0 +1,132 @@
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::validate::QuoteIssue;
+use crate::protocol::{DeltaFilter, DeltaState, ticker_matches};
+use crate::quote::{Bar, StockQuote};
+use std::collections::HashMap;
+
+/// Фильтр котировок на стороне клиента. Применяется до передачи котировок обработчику,
+/// даже если сервер присылает все котировки
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuoteFilter {
+    /// Тикеры или шаблоны тикеров, например "BANK_*", котировки и свечи которых
+    /// передаются обработчику. None - все тикеры
+    pub tickers: Option<Vec<String>>,
+    /// Передавать котировку, только если цена изменилась достаточно с последней
+    /// переданной котировки тикера или объем, накопленный с нее, достаточно велик
+    pub delta: Option<DeltaFilter>,
+}
+
+impl QuoteFilter {
+    fn allows(&self, ticker: &str) -> bool {
+        self.tickers.as_ref().is_none_or(|tickers| {
+            tickers
+                .iter()
+                .any(|pattern| ticker_matches(pattern, ticker))
+        })
+    }
+}
+
+/// Обработчик, который передает внутреннему обработчику только котировки, прошедшие фильтр
+pub(super) struct FilteredHandler<H: QuoteHandler> {
+    inner: H,
+    filter: QuoteFilter,
+    delta_states: HashMap<String, DeltaState>,
+}
+
+impl<H: QuoteHandler> FilteredHandler<H> {
+    pub(super) fn new(inner: H, filter: QuoteFilter) -> Self {
+        Self {
+            inner,
+            filter,
+            delta_states: HashMap::new(),
+        }
+    }
+}
+
+impl<H: QuoteHandler> QuoteHandler for FilteredHandler<H> {
+    fn on_quote(&mut self, quote: StockQuote) {
+        if !self.filter.allows(&quote.ticker) {
+            return;
+        }
+        if let Some(delta) = self.filter.delta.as_ref() {
+            let state = self.delta_states.entry(quote.ticker.clone()).or_default();
+            if !delta.passes(state, &quote) {
+                return;
+            }
+            state.sent(quote.price);
+        }
+        self.inner.on_quote(quote);
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        if self.filter.allows(&quote.ticker) {
+            self.inner.on_replay(quote);
+        }
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        if self.filter.allows(&quote.ticker) {
+            self.inner.on_flagged(quote, issue);
+        }
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        if self.filter.allows(&bar.ticker) {
+            self.inner.on_bar(bar);
+        }
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        self.inner.on_connection(event);
+    }
+
+    fn on_tick(&mut self) {
+        self.inner.on_tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collector {
+        quotes: Vec<StockQuote>,
+    }
+
+    impl QuoteHandler for Collector {
+        fn on_quote(&mut self, quote: StockQuote) {
+            self.quotes.push(quote);
+        }
+
+        fn on_bar(&mut self, _bar: Bar) {}
+    }
+
+    #[test]
+    fn test_filtered_handler() {
+        let quote = |ticker: &str, price: f64, volume: u32| StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume,
+            timestamp: 0,
+        };
+        let filter = QuoteFilter {
+            tickers: Some(vec!["AMD".to_string()]),
+            delta: Some(DeltaFilter {
+                min_price_change_percent: Some(10.0),
+                min_volume: Some(1000),
+            }),
+        };
+        let mut handler = FilteredHandler::new(Collector::default(), filter);
+        handler.on_quote(quote("AMD", 100.0, 1));
+        handler.on_quote(quote("INT", 100.0, 1));
+        handler.on_quote(quote("AMD", 105.0, 1));
+        handler.on_quote(quote("AMD", 111.0, 1));
+        handler.on_quote(quote("AMD", 112.0, 5000));
+        handler.on_quote(quote("AMD", 113.0, 600));
+        handler.on_quote(quote("AMD", 114.0, 600));
+
+        let prices: Vec<f64> = handler.inner.quotes.iter().map(|q| q.price).collect();
+        assert_eq!(prices, vec![100.0, 111.0, 112.0, 114.0]);
+    }
+}
@@ -0,0 +1,278 @@
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::validate::QuoteIssue;
+use crate::protocol::ticker_matches;
+use crate::quote::{Bar, StockQuote};
+use std::sync::{Arc, Mutex};
+
+type SharedGroupHandler = Arc<Mutex<dyn QuoteHandler>>;
+
+struct TickerGroup {
+    name: String,
+    tickers: Vec<String>,
+    handler: SharedGroupHandler,
+}
+
+impl TickerGroup {
+    fn contains(&self, ticker: &str) -> bool {
+        self.tickers
+            .iter()
+            .any(|pattern| ticker_matches(pattern, ticker))
+    }
+}
+
+fn union(groups: &[TickerGroup]) -> Vec<String> {
+    let mut tickers: Vec<String> = Vec::new();
+    for group in groups.iter() {
+        for ticker in group.tickers.iter() {
+            if !tickers.contains(ticker) {
+                tickers.push(ticker.clone());
+            }
+        }
+    }
+    tickers
+}
+
+fn difference(from: &[String], without: &[String]) -> Vec<String> {
+    from.iter()
+        .filter(|ticker| !without.contains(ticker))
+        .cloned()
+        .collect()
+}
+
+/// Изменение подписки после изменения групп или списка тикеров
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GroupsChange {
+    /// Тикеры, на которые нужно подписаться
+    pub added: Vec<String>,
+    /// Тикеры, которые больше не нужны
+    pub removed: Vec<String>,
+}
+
+impl GroupsChange {
+    /// Изменение подписки с тикеров before на тикеры after
+    pub fn between(before: &[String], after: &[String]) -> Self {
+        Self {
+            added: difference(after, before),
+            removed: difference(before, after),
+        }
+    }
+}
+
+/// Именованные группы тикеров, у каждой свой обработчик. Клиент подписывается
+/// на объединение тикеров всех групп по одному подключению, а котировки и свечи
+/// тикера передаются обработчикам всех групп, в которые он входит
+#[derive(Default)]
+pub struct TickerGroups {
+    groups: Mutex<Vec<TickerGroup>>,
+}
+
+impl TickerGroups {
+    /// Добавляет группу name с тикерами tickers и обработчиком handler
+    pub fn with_group<I, H>(self, name: &str, tickers: I, handler: H) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        H: QuoteHandler + 'static,
+    {
+        self.set_group(name, tickers, handler);
+        self
+    }
+
+    /// Тикеры всех групп без повторов
+    pub fn tickers(&self) -> Vec<String> {
+        union(&self.groups.lock().unwrap())
+    }
+
+    /// Названия групп
+    pub fn names(&self) -> Vec<String> {
+        let groups = self.groups.lock().unwrap();
+        groups.iter().map(|group| group.name.clone()).collect()
+    }
+
+    /// Тикеры группы name
+    pub fn group_tickers(&self, name: &str) -> Option<Vec<String>> {
+        let groups = self.groups.lock().unwrap();
+        let group = groups.iter().find(|group| group.name == name)?;
+        Some(group.tickers.clone())
+    }
+
+    /// Добавляет группу или заменяет группу с тем же названием
+    pub fn set_group<I, H>(&self, name: &str, tickers: I, handler: H) -> GroupsChange
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        H: QuoteHandler + 'static,
+    {
+        let group = TickerGroup {
+            name: name.to_string(),
+            tickers: tickers
+                .into_iter()
+                .map(|ticker| ticker.as_ref().to_string())
+                .collect(),
+            handler: Arc::new(Mutex::new(handler)),
+        };
+        self.change(|groups| {
+            groups.retain(|group| group.name != name);
+            groups.push(group);
+        })
+    }
+
+    /// Удаляет группу name
+    pub fn remove_group(&self, name: &str) -> GroupsChange {
+        self.change(|groups| groups.retain(|group| group.name != name))
+    }
+
+    fn handlers(&self, ticker: Option<&str>) -> Vec<SharedGroupHandler> {
+        let groups = self.groups.lock().unwrap();
+        groups
+            .iter()
+            .filter(|group| ticker.is_none_or(|ticker| group.contains(ticker)))
+            .map(|group| group.handler.clone())
+            .collect()
+    }
+
+    fn change(&self, f: impl FnOnce(&mut Vec<TickerGroup>)) -> GroupsChange {
+        let mut groups = self.groups.lock().unwrap();
+        let before = union(&groups);
+        f(&mut groups);
+        GroupsChange::between(&before, &union(&groups))
+    }
+}
+
+/// Обработчик, который передает котировки и свечи обработчикам групп тикеров.
+/// Обработчики групп вызываются без блокировки списка групп, поэтому могут сами
+/// менять группы, а приложение может менять группы, пока обработчик занят
+pub(super) struct GroupHandler {
+    groups: Arc<TickerGroups>,
+}
+
+impl GroupHandler {
+    pub(super) fn new(groups: Arc<TickerGroups>) -> Self {
+        Self { groups }
+    }
+}
+
+impl QuoteHandler for GroupHandler {
+    fn on_quote(&mut self, quote: StockQuote) {
+        for handler in self.groups.handlers(Some(&quote.ticker)) {
+            handler.lock().unwrap().on_quote(quote.clone());
+        }
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        for handler in self.groups.handlers(Some(&quote.ticker)) {
+            handler.lock().unwrap().on_replay(quote.clone());
+        }
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        for handler in self.groups.handlers(Some(&quote.ticker)) {
+            handler.lock().unwrap().on_flagged(quote.clone(), issue);
+        }
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        for handler in self.groups.handlers(Some(&bar.ticker)) {
+            handler.lock().unwrap().on_bar(bar.clone());
+        }
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        for handler in self.groups.handlers(None) {
+            handler.lock().unwrap().on_connection(event.clone());
+        }
+    }
+
+    fn on_tick(&mut self) {
+        for handler in self.groups.handlers(None) {
+            handler.lock().unwrap().on_tick();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collector {
+        quotes: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl QuoteHandler for Collector {
+        fn on_quote(&mut self, quote: StockQuote) {
+            self.quotes.lock().unwrap().push(quote.ticker);
+        }
+
+        fn on_bar(&mut self, _bar: Bar) {}
+    }
+
+    #[test]
+    fn test_ticker_groups() {
+        let tech = Arc::new(Mutex::new(Vec::new()));
+        let fx = Arc::new(Mutex::new(Vec::new()));
+        let groups = Arc::new(
+            TickerGroups::default()
+                .with_group(
+                    "tech",
+                    &["AMD", "INT"],
+                    Collector {
+                        quotes: tech.clone(),
+                    },
+                )
+                .with_group("fx", &["EUR", "AMD"], Collector { quotes: fx.clone() }),
+        );
+        assert_eq!(groups.tickers(), vec!["AMD", "INT", "EUR"]);
+
+        let mut handler = GroupHandler::new(groups.clone());
+        let quote = |ticker: &str| StockQuote {
+            ticker: ticker.to_string(),
+            ..Default::default()
+        };
+        for ticker in ["AMD", "INT", "EUR", "MSFT"] {
+            handler.on_quote(quote(ticker));
+        }
+        assert_eq!(*tech.lock().unwrap(), vec!["AMD", "INT"]);
+        assert_eq!(*fx.lock().unwrap(), vec!["AMD", "EUR"]);
+
+        let change = groups.remove_group("tech");
+        assert_eq!(change.added, Vec::<String>::new());
+        assert_eq!(change.removed, vec!["INT"]);
+        let change = groups.set_group("fx", &["GBP"], Collector { quotes: fx.clone() });
+        assert_eq!(change.added, vec!["GBP"]);
+        assert_eq!(change.removed, vec!["EUR", "AMD"]);
+        assert_eq!(groups.names(), vec!["fx"]);
+        assert_eq!(groups.group_tickers("fx"), Some(vec!["GBP".to_string()]));
+    }
+
+    struct Regrouper {
+        groups: Arc<TickerGroups>,
+    }
+
+    impl QuoteHandler for Regrouper {
+        fn on_quote(&mut self, quote: StockQuote) {
+            let quotes = Arc::new(Mutex::new(Vec::new()));
+            self.groups
+                .set_group(&quote.ticker, [&quote.ticker], Collector { quotes });
+            self.groups.remove_group("regroup");
+        }
+
+        fn on_bar(&mut self, _bar: Bar) {}
+    }
+
+    #[test]
+    fn test_handler_changes_groups() {
+        let groups = Arc::new(TickerGroups::default());
+        groups.set_group(
+            "regroup",
+            ["AMD"],
+            Regrouper {
+                groups: groups.clone(),
+            },
+        );
+        GroupHandler::new(groups.clone()).on_quote(StockQuote {
+            ticker: "AMD".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(groups.names(), vec!["AMD"]);
+    }
+}
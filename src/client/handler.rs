@@ -0,0 +1,464 @@
+use super::queue::{BoundedQueue, OverflowPolicy};
+use super::validate::QuoteIssue;
+use crate::quote::{Bar, StockQuote};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::TrySendError;
+use std::sync::{Arc, mpsc};
+
+/// Изменение состояния соединения клиента с сервером
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// Клиент подписался на котировки сервера
+    Connected {
+        /// Адрес сервера
+        server: SocketAddr,
+        /// Сервер отличается от прежнего
+        failover: bool,
+    },
+    /// Связь с сервером потеряна
+    Lost {
+        /// Адрес сервера
+        server: SocketAddr,
+        /// Причина
+        reason: String,
+    },
+    /// Переподключиться не удалось, прием котировок остановлен
+    Failed {
+        /// Причина
+        reason: String,
+    },
+}
+
+impl Display for ConnectionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connected {
+                server,
+                failover: true,
+            } => write!(f, "failed over to server {server}"),
+            Self::Connected { server, .. } => write!(f, "connected to server {server}"),
+            Self::Lost { server, reason } => write!(f, "lost server {server}: {reason}"),
+            Self::Failed { reason } => write!(f, "can't reconnect: {reason}"),
+        }
+    }
+}
+
+/// Получатель данных, принятых клиентом котировок.
+/// Вызывается из потоков приема, поэтому должен быть Send
+pub trait QuoteHandler: Send {
+    /// Новая котировка
+    fn on_quote(&mut self, quote: StockQuote);
+
+    /// Котировка из истории, полученная при подписке или по запросу истории.
+    /// По умолчанию обрабатывается как новая котировка
+    fn on_replay(&mut self, quote: StockQuote) {
+        self.on_quote(quote);
+    }
+
+    /// Котировка, не прошедшая проверку, при политике `ValidationPolicy::Flag`.
+    /// По умолчанию обрабатывается как новая котировка
+    fn on_flagged(&mut self, quote: StockQuote, _issue: QuoteIssue) {
+        self.on_quote(quote);
+    }
+
+    /// Закрытая свеча OHLCV. По умолчанию игнорируется
+    fn on_bar(&mut self, _bar: Bar) {}
+
+    /// Изменение состояния соединения с сервером. По умолчанию игнорируется
+    fn on_connection(&mut self, _event: ConnectionEvent) {}
+
+    /// Периодический вызов из потока клиента, даже если котировок нет.
+    /// По умолчанию ничего не делает
+    fn on_tick(&mut self) {}
+}
+
+impl QuoteHandler for Box<dyn QuoteHandler> {
+    fn on_quote(&mut self, quote: StockQuote) {
+        (**self).on_quote(quote)
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        (**self).on_replay(quote)
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        (**self).on_flagged(quote, issue)
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        (**self).on_bar(bar)
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        (**self).on_connection(event)
+    }
+
+    fn on_tick(&mut self) {
+        (**self).on_tick()
+    }
+}
+
+const ANSI_GREEN: &str = "\x1b[32m";
+const ANSI_RED: &str = "\x1b[31m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Формат вывода `ConsolePrinter`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ConsoleFormat {
+    /// Текстовое представление котировки и свечи
+    #[default]
+    Plain,
+    /// Выровненные столбцы с изменением цены относительно предыдущей котировки тикера
+    Table,
+    /// Строка CSV, первое поле - вид записи: quote, replay, flagged или bar
+    Csv,
+    /// Объект JSON в одну строку с полем kind - видом записи
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a, T: Serialize> {
+    kind: &'a str,
+    #[serde(flatten)]
+    record: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issue: Option<String>,
+}
+
+fn json_line<T: Serialize>(kind: &str, record: &T, issue: Option<QuoteIssue>) -> String {
+    let line = JsonLine {
+        kind,
+        record,
+        issue: issue.map(|issue| issue.to_string()),
+    };
+    serde_json::to_string(&line).unwrap_or_else(|e| format!("{{\"error\":\"{e}\"}}"))
+}
+
+/// Печатает котировки и свечи в стандартный вывод в формате `ConsoleFormat`.
+/// В форматах CSV и JSON события соединения пишутся в лог, чтобы вывод оставался разбираемым
+#[derive(Debug, Default, Clone)]
+pub struct ConsolePrinter {
+    format: ConsoleFormat,
+    color: bool,
+    last_prices: HashMap<String, f64>,
+}
+
+impl ConsolePrinter {
+    /// Печать в формате format
+    pub fn new(format: ConsoleFormat) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+
+    /// Выделять цветом рост (зеленый) и снижение (красный) цены относительно
+    /// предыдущей котировки тикера. Действует в форматах Plain и Table
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn price_change(&mut self, quote: &StockQuote) -> f64 {
+        self.last_prices
+            .insert(quote.ticker.clone(), quote.price)
+            .map(|last| quote.price - last)
+            .unwrap_or_default()
+    }
+
+    fn colorize(&self, line: String, change: f64) -> String {
+        if !self.color || change == 0.0 {
+            return line;
+        }
+        let color = if change > 0.0 { ANSI_GREEN } else { ANSI_RED };
+        format!("{color}{line}{ANSI_RESET}")
+    }
+
+    fn format_quote(
+        &mut self,
+        kind: &str,
+        quote: &StockQuote,
+        issue: Option<QuoteIssue>,
+    ) -> String {
+        let change = self.price_change(quote);
+        let line = match self.format {
+            ConsoleFormat::Plain => match (kind, issue) {
+                (_, Some(issue)) => format!("{issue}: {quote}"),
+                ("replay", None) => format!("replay: {quote}"),
+                _ => quote.to_string(),
+            },
+            ConsoleFormat::Table => {
+                let mut line = format!(
+                    "{:<7} {:<8} {:>12.4} {:>+10.4} {:>10} {:>16}",
+                    kind, quote.ticker, quote.price, change, quote.volume, quote.timestamp
+                );
+                if let Some(issue) = issue {
+                    line.push_str(&format!("  {issue}"));
+                }
+                line
+            }
+            ConsoleFormat::Csv => {
+                return format!(
+                    "{kind},{},{},{},{}",
+                    quote.ticker, quote.price, quote.volume, quote.timestamp
+                );
+            }
+            ConsoleFormat::Json => return json_line(kind, quote, issue),
+        };
+        self.colorize(line, change)
+    }
+
+    fn format_bar(&self, bar: &Bar) -> String {
+        match self.format {
+            ConsoleFormat::Plain => bar.to_string(),
+            ConsoleFormat::Table => format!(
+                "{:<7} {:<8} O {:.4} H {:.4} L {:.4} C {:.4} V {} START {} INTERVAL {}",
+                "bar",
+                bar.ticker,
+                bar.open,
+                bar.high,
+                bar.low,
+                bar.close,
+                bar.volume,
+                bar.start_millis,
+                bar.interval_millis
+            ),
+            ConsoleFormat::Csv => format!(
+                "bar,{},{},{},{},{},{},{},{}",
+                bar.ticker,
+                bar.open,
+                bar.high,
+                bar.low,
+                bar.close,
+                bar.volume,
+                bar.start_millis,
+                bar.interval_millis
+            ),
+            ConsoleFormat::Json => json_line("bar", bar, None),
+        }
+    }
+}
+
+impl QuoteHandler for ConsolePrinter {
+    fn on_quote(&mut self, quote: StockQuote) {
+        println!("{}", self.format_quote("quote", &quote, None));
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        println!("{}", self.format_quote("replay", &quote, None));
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        println!("{}", self.format_quote("flagged", &quote, Some(issue)));
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        println!("{}", self.format_bar(&bar));
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        match self.format {
+            ConsoleFormat::Plain | ConsoleFormat::Table => println!("connection: {event}"),
+            ConsoleFormat::Csv | ConsoleFormat::Json => log::info!("Connection: {event}"),
+        }
+    }
+}
+
+/// Передает котировки в канал ограниченной емкости. Прием котировок не ждет приложение:
+/// если канал заполнен, котировка отбрасывается и учитывается в `dropped`.
+/// Свечи в канал не передаются
+pub struct ChannelHandler {
+    tx: mpsc::SyncSender<StockQuote>,
+    dropped: Arc<AtomicU64>,
+    bars_skipped: bool,
+}
+
+impl ChannelHandler {
+    /// Создает обработчик и канал, из которого приложение читает котировки
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<StockQuote>) {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        (
+            Self {
+                tx,
+                dropped: Arc::new(AtomicU64::new(0)),
+                bars_skipped: false,
+            },
+            rx,
+        )
+    }
+
+    /// Счетчик котировок, отброшенных из-за переполнения канала
+    pub fn dropped(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+}
+
+impl QuoteHandler for ChannelHandler {
+    fn on_quote(&mut self, quote: StockQuote) {
+        match self.tx.try_send(quote) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Disconnected(_)) => log::debug!("Quotes receiver is dropped"),
+        }
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        if !self.bars_skipped {
+            log::warn!(
+                "Bars are not passed to quotes channel, skip bar of {}",
+                bar.ticker
+            );
+            self.bars_skipped = true;
+        }
+    }
+}
+
+/// Буфер котировок ограниченной емкости, из которого приложение забирает котировки
+/// без ожидания. По умолчанию при переполнении вытесняется самая старая котировка
+pub type QuoteBuffer = BoundedQueue<StockQuote>;
+
+/// Складывает котировки в `QuoteBuffer`, не останавливая прием.
+/// Свечи в буфер не передаются
+pub struct BufferHandler {
+    buffer: Arc<QuoteBuffer>,
+    bars_skipped: bool,
+}
+
+impl BufferHandler {
+    /// Создает обработчик и буфер емкостью capacity
+    pub fn new(capacity: usize) -> (Self, Arc<QuoteBuffer>) {
+        Self::with_policy(capacity, OverflowPolicy::DropOldest)
+    }
+
+    /// Создает обработчик и буфер емкостью capacity с политикой переполнения policy
+    pub fn with_policy(capacity: usize, policy: OverflowPolicy) -> (Self, Arc<QuoteBuffer>) {
+        let buffer = Arc::new(QuoteBuffer::with_policy(capacity, policy));
+        (
+            Self {
+                buffer: buffer.clone(),
+                bars_skipped: false,
+            },
+            buffer,
+        )
+    }
+}
+
+impl QuoteHandler for BufferHandler {
+    fn on_quote(&mut self, quote: StockQuote) {
+        self.buffer.push(quote);
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        if !self.bars_skipped {
+            log::warn!(
+                "Bars are not passed to quotes buffer, skip bar of {}",
+                bar.ticker
+            );
+            self.bars_skipped = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_buffer() {
+        let (mut handler, buffer) = BufferHandler::new(3);
+        for price in 1..=5 {
+            handler.on_quote(StockQuote {
+                ticker: "AMD".to_string(),
+                price: price as f64,
+                volume: 1,
+                timestamp: 0,
+            });
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.overflowed(), 2);
+
+        let prices: Vec<f64> = buffer.pop_up_to(2).iter().map(|q| q.price).collect();
+        assert_eq!(prices, vec![3.0, 4.0]);
+        assert_eq!(buffer.pop_up_to(10).len(), 1);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_channel_handler_drops_when_full() {
+        let (mut handler, quotes) = ChannelHandler::new(2);
+        let dropped = handler.dropped();
+        for price in 1..=5 {
+            handler.on_quote(StockQuote {
+                ticker: "AMD".to_string(),
+                price: price as f64,
+                volume: 1,
+                timestamp: 0,
+            });
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        let prices: Vec<f64> = quotes.try_iter().map(|q| q.price).collect();
+        assert_eq!(prices, vec![1.0, 2.0]);
+
+        drop(quotes);
+        handler.on_quote(StockQuote {
+            ticker: "AMD".to_string(),
+            price: 6.0,
+            volume: 1,
+            timestamp: 0,
+        });
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_console_printer_formats() {
+        let quote = |price: f64| StockQuote {
+            ticker: "AMD".to_string(),
+            price,
+            volume: 7,
+            timestamp: 100,
+        };
+        let mut printer = ConsolePrinter::new(ConsoleFormat::Plain).with_color(true);
+        assert_eq!(
+            printer.format_quote("quote", &quote(10.0), None),
+            "T: AMD, P: 10.0000, V: 7, TIME: 100"
+        );
+        assert_eq!(
+            printer.format_quote("quote", &quote(11.0), None),
+            "\x1b[32mT: AMD, P: 11.0000, V: 7, TIME: 100\x1b[0m"
+        );
+        assert!(
+            printer
+                .format_quote("quote", &quote(9.5), None)
+                .starts_with(ANSI_RED)
+        );
+
+        let mut printer = ConsolePrinter::new(ConsoleFormat::Table);
+        printer.format_quote("quote", &quote(10.0), None);
+        assert_eq!(
+            printer.format_quote("replay", &quote(9.5), None),
+            "replay  AMD            9.5000    -0.5000          7              100"
+        );
+
+        let mut printer = ConsolePrinter::new(ConsoleFormat::Csv);
+        assert_eq!(
+            printer.format_quote("quote", &quote(10.5), None),
+            "quote,AMD,10.5,7,100"
+        );
+
+        let mut printer = ConsolePrinter::new(ConsoleFormat::Json);
+        assert_eq!(
+            printer.format_quote(
+                "flagged",
+                &quote(10.5),
+                Some(QuoteIssue::Duplicate { seq: 3 })
+            ),
+            r#"{"kind":"flagged","ticker":"AMD","price":10.5,"volume":7,"timestamp":100,"issue":"duplicate seq 3"}"#
+        );
+    }
+}
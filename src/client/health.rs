@@ -0,0 +1,345 @@
+use super::metrics::ClientMetrics;
+use super::quotes_client::{ClientCmd, ClientControl, is_stop_cmd};
+use super::state::{ClientState, StateWatch};
+use crate::timer::Timer;
+use anyhow::Result;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const HEARTBEAT_MILLIS: u64 = 1000;
+const ACCEPT_MILLIS: u64 = 100;
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const READ_REQUEST_TIMEOUT_MILLIS: u64 = 1000;
+const MAX_REQUEST_LEN: usize = 1024;
+
+const HEARTBEAT_EVENT: &str = "heartbeat";
+const ACCEPT_EVENT: &str = "accept";
+const WAIT_CMD_EVENT: &str = "cmd";
+
+/// Куда клиент сообщает, что котировки поступают
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthOutput {
+    /// Раз в секунду перезаписывать файл временем Unix в секундах, пока клиент жив.
+    /// Супервизор проверяет время изменения файла
+    File(PathBuf),
+    /// Отвечать на запросы HTTP по этому адресу: 200 ok, пока клиент жив, иначе 503
+    Tcp(SocketAddr),
+}
+
+fn problem(
+    state: &ClientState,
+    last_quote_age: Option<Duration>,
+    since_start: Duration,
+    max_quote_age: Duration,
+) -> Option<String> {
+    if let ClientState::Dead { reason } = state {
+        return Some(format!("dead: {reason}"));
+    }
+    match last_quote_age {
+        Some(age) if age > max_quote_age => Some(format!("no quotes for {} ms", age.as_millis())),
+        None if since_start > max_quote_age => Some("no quotes received".to_string()),
+        _ => None,
+    }
+}
+
+/// Управление потоком сообщений о живости клиента
+pub struct HealthReporterControl {
+    /// Отправка команды остановки
+    pub tx: mpsc::Sender<ClientCmd>,
+    /// Дескриптор потока
+    pub thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+/// Сообщает супервизору (systemd, k8s), что клиент жив: котировки поступали
+/// не позже max_quote_age назад и прием не остановлен. До первой котировки
+/// клиент считается живым в течение max_quote_age с запуска
+pub struct HealthReporter {
+    output: HealthOutput,
+    listener: Option<TcpListener>,
+    metrics: Arc<ClientMetrics>,
+    state: Arc<StateWatch>,
+    max_quote_age: Duration,
+    started: Instant,
+}
+
+impl HealthReporter {
+    /// Сообщает о живости клиента control в output
+    pub fn new(
+        control: &ClientControl,
+        output: HealthOutput,
+        max_quote_age_millis: u64,
+    ) -> Result<Self> {
+        let listener = match &output {
+            HealthOutput::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                listener.set_nonblocking(true)?;
+                log::info!("Health endpoint is listening at {}", listener.local_addr()?);
+                Some(listener)
+            }
+            HealthOutput::File(path) => {
+                log::info!("Heartbeat file: {}", path.display());
+                None
+            }
+        };
+        Ok(Self {
+            output,
+            listener,
+            metrics: control.metrics.clone(),
+            state: control.state.clone(),
+            max_quote_age: Duration::from_millis(max_quote_age_millis),
+            started: Instant::now(),
+        })
+    }
+
+    /// Адрес, на котором принимаются запросы, если output - `HealthOutput::Tcp`
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.as_ref()?.local_addr().ok()
+    }
+
+    fn problem(&self) -> Option<String> {
+        problem(
+            &self.state.get(),
+            self.metrics.last_quote_age(),
+            self.started.elapsed(),
+            self.max_quote_age,
+        )
+    }
+
+    fn touch(&self) -> Result<()> {
+        let HealthOutput::File(path) = &self.output else {
+            return Ok(());
+        };
+        if let Some(problem) = self.problem() {
+            log::debug!("Skip heartbeat: {problem}");
+            return Ok(());
+        }
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|val| val.as_secs())
+            .unwrap_or_default();
+        std::fs::write(path, format!("{secs}\n"))?;
+        Ok(())
+    }
+
+    fn handle_request(&self, mut conn: TcpStream) -> Result<()> {
+        conn.set_nonblocking(false)?;
+        conn.set_read_timeout(Some(Duration::from_millis(READ_REQUEST_TIMEOUT_MILLIS)))?;
+        let mut buf = [0u8; MAX_REQUEST_LEN];
+        let _ = conn.read(&mut buf)?;
+
+        let (status, body) = match self.problem() {
+            None => ("200 OK", "ok\n".to_string()),
+            Some(problem) => ("503 Service Unavailable", format!("{problem}\n")),
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        conn.write_all(response.as_bytes())?;
+        Ok(())
+    }
+
+    /// Запускает поток сообщений о живости
+    pub fn start(self) -> HealthReporterControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::default();
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            match self.listener {
+                Some(_) => timer.add_event(ACCEPT_EVENT, ACCEPT_MILLIS),
+                None => timer.add_event(HEARTBEAT_EVENT, HEARTBEAT_MILLIS),
+            }
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    if is_stop_cmd(&rx) {
+                        break;
+                    }
+                }
+
+                if let Some(listener) = self.listener.as_ref() {
+                    if !timer.is_expired_event(ACCEPT_EVENT)? {
+                        continue;
+                    }
+                    timer.reset_event(ACCEPT_EVENT)?;
+                    match listener.accept() {
+                        Ok((conn, addr)) => {
+                            if let Err(e) = self.handle_request(conn) {
+                                log::warn!("Can't handle health request from {addr}: {e}");
+                            }
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                        Err(e) => {
+                            log::error!("Can't accept health connection: {e}");
+                            break;
+                        }
+                    }
+                } else if timer.is_expired_event(HEARTBEAT_EVENT)? {
+                    timer.reset_event(HEARTBEAT_EVENT)?;
+                    if let Err(e) = self.touch() {
+                        log::error!("Can't write heartbeat file: {e}");
+                    }
+                }
+            }
+
+            log::info!("Health reporter is stopped");
+            Ok(())
+        });
+        HealthReporterControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::quotes_client::QuotesClientBuilder;
+    use crate::quote::StockQuote;
+    use crate::testing::{MockQuoteServer, MockServerBuilder};
+    use tempfile::tempdir;
+
+    fn start_client() -> (MockQuoteServer, ClientControl) {
+        let quotes = (1..=1000)
+            .map(|timestamp| StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 1,
+                timestamp,
+            })
+            .collect();
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .with_quotes(quotes)
+            .with_interval_millis(10)
+            .start()
+            .unwrap();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_channel(1000)
+            .unwrap();
+        (server, control)
+    }
+
+    fn stop_client(control: ClientControl) {
+        control.tx.send(ClientCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+    }
+
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    fn http_get(addr: SocketAddr) -> String {
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        conn.write_all(b"GET /health HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        conn.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_health_endpoint() {
+        let (_server, control) = start_client();
+        let reporter = HealthReporter::new(
+            &control,
+            HealthOutput::Tcp("127.0.0.1:0".parse().unwrap()),
+            1000,
+        )
+        .unwrap();
+        let addr = reporter.local_addr().unwrap();
+        let health = reporter.start();
+
+        assert!(wait_until(|| control.metrics.last_quote_age().is_some()));
+        let response = http_get(addr);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\nok\n"));
+
+        stop_client(control);
+        let response = http_get(addr);
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable"));
+        assert!(response.contains("dead: "));
+
+        health.tx.send(ClientCmd::Stop).unwrap();
+        health.thread_handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_heartbeat_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("heartbeat");
+        let (_server, control) = start_client();
+        let reporter =
+            HealthReporter::new(&control, HealthOutput::File(path.clone()), 1000).unwrap();
+        assert!(reporter.local_addr().is_none());
+        let health = reporter.start();
+
+        assert!(wait_until(|| path.exists()));
+        let secs: u64 = std::fs::read_to_string(&path)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert!(now.as_secs() - secs <= 1);
+
+        stop_client(control);
+        std::fs::remove_file(&path).unwrap();
+        thread::sleep(Duration::from_millis(2 * HEARTBEAT_MILLIS));
+        assert!(!path.exists());
+
+        health.tx.send(ClientCmd::Stop).unwrap();
+        health.thread_handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_health_problem() {
+        let secs = Duration::from_secs;
+        let max_age = secs(10);
+        assert_eq!(
+            problem(&ClientState::Connecting, None, secs(5), max_age),
+            None
+        );
+        assert_eq!(
+            problem(&ClientState::Subscribed, None, secs(11), max_age),
+            Some("no quotes received".to_string())
+        );
+        assert_eq!(
+            problem(&ClientState::Streaming, Some(secs(3)), secs(100), max_age),
+            None
+        );
+        assert_eq!(
+            problem(
+                &ClientState::Reconnecting,
+                Some(secs(12)),
+                secs(100),
+                max_age
+            ),
+            Some("no quotes for 12000 ms".to_string())
+        );
+        let dead = ClientState::Dead {
+            reason: "stopped".to_string(),
+        };
+        assert_eq!(
+            problem(&dead, Some(secs(1)), secs(100), max_age),
+            Some("dead: stopped".to_string())
+        );
+    }
+}
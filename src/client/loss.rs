@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::sync::Mutex;
+
+const MAX_TRACKED_MISSING: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+/// Снимок статистики потерь котировок
+pub struct LossSnapshot {
+    /// Количество принятых котировок с номером
+    pub received: u64,
+    /// Количество пропущенных номеров, которые так и не пришли
+    pub lost: u64,
+    /// Количество котировок, пришедших позже следующих за ними
+    pub reordered: u64,
+}
+
+impl LossSnapshot {
+    /// Доля потерянных котировок среди отправленных сервером
+    pub fn loss_ratio(&self) -> f64 {
+        let total = self.received + self.lost;
+        if total == 0 {
+            return 0.0;
+        }
+        self.lost as f64 / total as f64
+    }
+
+    fn add(&mut self, other: &LossSnapshot) {
+        self.received += other.received;
+        self.lost += other.lost;
+        self.reordered += other.reordered;
+    }
+}
+
+impl Display for LossSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "received: {}, lost: {}, reordered: {}, loss: {:.2}%",
+            self.received,
+            self.lost,
+            self.reordered,
+            self.loss_ratio() * 100.0
+        )
+    }
+}
+
+/// Положение потока котировок тикера: статистика потерь, которую продолжает
+/// перезапущенный клиент. Сервер нумерует котировки заново в каждом подключении,
+/// поэтому номер последней котировки не сохраняется
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeqPosition {
+    /// Тикер
+    pub ticker: String,
+    /// Статистика потерь
+    pub loss: LossSnapshot,
+}
+
+struct TickerSeq {
+    expected: u64,
+    missing: BTreeSet<u64>,
+    stats: LossSnapshot,
+}
+
+impl Default for TickerSeq {
+    fn default() -> Self {
+        Self {
+            expected: 1,
+            missing: BTreeSet::new(),
+            stats: LossSnapshot::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+/// Потери котировок по тикерам, посчитанные по пропускам номеров котировок.
+/// Разделяется между потоками приема котировок клиента
+pub struct LossStats {
+    tickers: Mutex<BTreeMap<String, TickerSeq>>,
+}
+
+impl LossStats {
+    /// Учитывает котировку тикера с номером seq. Котировки без номера и повторы не учитываются.
+    /// Опоздавшей считается котировка из последних MAX_TRACKED_MISSING пропусков
+    pub(super) fn record(&self, ticker: &str, seq: u64) {
+        if seq == 0 {
+            return;
+        }
+        let mut tickers = self.tickers.lock().unwrap();
+        let state = tickers.entry(ticker.to_string()).or_default();
+        if seq >= state.expected {
+            state.stats.lost += seq - state.expected;
+            state.stats.received += 1;
+            let tracked_from = state
+                .expected
+                .max(seq.saturating_sub(MAX_TRACKED_MISSING as u64));
+            state.missing.extend(tracked_from..seq);
+            while state.missing.len() > MAX_TRACKED_MISSING {
+                state.missing.pop_first();
+            }
+            state.expected = seq + 1;
+        } else if state.missing.remove(&seq) {
+            state.stats.lost -= 1;
+            state.stats.reordered += 1;
+            state.stats.received += 1;
+        }
+    }
+
+    /// Новый поток котировок после переподключения: номера начинаются заново
+    pub(super) fn restart(&self) {
+        for state in self.tickers.lock().unwrap().values_mut() {
+            state.expected = 1;
+            state.missing.clear();
+        }
+    }
+
+    /// Снимки статистики всех тикеров
+    pub fn snapshot(&self) -> Vec<(String, LossSnapshot)> {
+        self.tickers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(ticker, state)| (ticker.clone(), state.stats))
+            .collect()
+    }
+
+    /// Положения потоков котировок всех тикеров
+    pub fn positions(&self) -> Vec<SeqPosition> {
+        self.tickers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(ticker, state)| SeqPosition {
+                ticker: ticker.clone(),
+                loss: state.stats,
+            })
+            .collect()
+    }
+
+    /// Продолжает статистику потерь сохраненной сессии. Сервер нумерует котировки
+    /// заново в каждом подключении, поэтому номера ожидаются с начала
+    pub(super) fn restore(&self, positions: &[SeqPosition]) {
+        let mut tickers = self.tickers.lock().unwrap();
+        for position in positions {
+            tickers.entry(position.ticker.clone()).or_default().stats = position.loss;
+        }
+    }
+
+    /// Статистика по всем тикерам вместе
+    pub fn total(&self) -> LossSnapshot {
+        let mut total = LossSnapshot::default();
+        for state in self.tickers.lock().unwrap().values() {
+            total.add(&state.stats);
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_stats() {
+        let stats = LossStats::default();
+        for seq in [1, 2, 5, 3, 6, 6, 0] {
+            stats.record("AMD", seq);
+        }
+        stats.record("INT", 2);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot[0].0, "AMD");
+        assert_eq!(
+            snapshot[0].1,
+            LossSnapshot {
+                received: 5,
+                lost: 1,
+                reordered: 1,
+            }
+        );
+        assert_eq!(snapshot[1].1.lost, 1);
+        assert_eq!(stats.total().lost, 2);
+        assert!((stats.total().loss_ratio() - 2.0 / 8.0).abs() < 1e-9);
+
+        let positions = stats.positions();
+        assert_eq!(positions[0].loss.received, 5);
+        assert_eq!(positions[1].loss.lost, 1);
+
+        stats.restart();
+        stats.record("AMD", 1);
+        assert_eq!(stats.snapshot()[0].1.lost, 1);
+
+        let restored = LossStats::default();
+        restored.restore(&positions);
+        restored.record("INT", 1);
+        assert_eq!(restored.total().received, 7);
+        assert_eq!(restored.total().lost, 2);
+        assert_eq!(restored.positions()[0], positions[0]);
+    }
+}
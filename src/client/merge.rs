@@ -0,0 +1,318 @@
+use super::error::{ClientError, ClientResult};
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::quotes_client::{ClientCmd, ClientControl, QuotesClient};
+use crate::quote::{Bar, StockQuote};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+
+/// Как выбирать котировки тикера, который приходит от нескольких серверов
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Arbitration {
+    /// Передавать котировки всех серверов
+    #[default]
+    All,
+    /// Передавать котировку, только если ее временная метка больше метки последней
+    /// переданной котировки тикера. Повторы одной котировки с разных серверов отбрасываются.
+    /// Годится, только если метки серверов сравнимы, например серверы ретранслируют
+    /// один источник. Когда сервер теряет связь, отсчет меток его тикеров начинается заново
+    Newest,
+    /// Тикер закрепляется за сервером, первым приславшим его котировку.
+    /// Котировки других серверов отбрасываются, пока этот сервер не потеряет связь
+    FirstSource,
+}
+
+/// Котировка объединенного потока с названием сервера-источника
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcedQuote {
+    /// Название источника
+    pub source: String,
+    /// Котировка
+    pub quote: StockQuote,
+}
+
+#[derive(Default)]
+struct ArbiterState {
+    timestamps: HashMap<String, (String, u64)>,
+    owners: HashMap<String, String>,
+}
+
+/// Выбор котировок из нескольких источников по `Arbitration`
+pub struct Arbiter {
+    arbitration: Arbitration,
+    state: Mutex<ArbiterState>,
+    rejected: AtomicU64,
+}
+
+impl Arbiter {
+    /// Выбор котировок по arbitration
+    pub fn new(arbitration: Arbitration) -> Self {
+        Self {
+            arbitration,
+            state: Mutex::new(ArbiterState::default()),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Передавать ли котировку источника source в объединенный поток
+    pub(super) fn accept(&self, source: &str, quote: &StockQuote) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let accepted = match self.arbitration {
+            Arbitration::All => true,
+            Arbitration::Newest => {
+                let last = state.timestamps.get(&quote.ticker);
+                let newer = last.is_none_or(|(_, last)| quote.timestamp > *last);
+                if newer {
+                    state
+                        .timestamps
+                        .insert(quote.ticker.clone(), (source.to_string(), quote.timestamp));
+                }
+                newer
+            }
+            Arbitration::FirstSource => {
+                let owner = state
+                    .owners
+                    .entry(quote.ticker.clone())
+                    .or_insert_with(|| source.to_string());
+                owner == source
+            }
+        };
+        if !accepted {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        accepted
+    }
+
+    /// Источник source потерял связь: его тикеры достаются следующему приславшему,
+    /// а метки его последних котировок забываются
+    pub(super) fn release(&self, source: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.owners.retain(|_, owner| owner != source);
+        state
+            .timestamps
+            .retain(|_, (last_source, _)| last_source != source);
+    }
+
+    /// Количество котировок, отброшенных при выборе
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+struct SourceHandler {
+    source: String,
+    arbiter: Arc<Arbiter>,
+    tx: mpsc::SyncSender<SourcedQuote>,
+}
+
+impl QuoteHandler for SourceHandler {
+    fn on_quote(&mut self, quote: StockQuote) {
+        if !self.arbiter.accept(&self.source, &quote) {
+            return;
+        }
+        let sourced = SourcedQuote {
+            source: self.source.clone(),
+            quote,
+        };
+        if self.tx.send(sourced).is_err() {
+            log::debug!("Merged quotes receiver is dropped");
+        }
+    }
+
+    fn on_bar(&mut self, _bar: Bar) {}
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        if matches!(
+            event,
+            ConnectionEvent::Lost { .. } | ConnectionEvent::Failed { .. }
+        ) {
+            log::warn!("Source {} is unavailable: {event}", self.source);
+            self.arbiter.release(&self.source);
+        }
+    }
+}
+
+/// Клиент нескольких серверов котировок. Котировки всех серверов сливаются в один
+/// канал с названием источника, а котировки одного тикера с разных серверов
+/// выбираются по `Arbitration`
+#[derive(Default)]
+pub struct MergedClient {
+    sources: Vec<(String, QuotesClient)>,
+    arbitration: Arbitration,
+}
+
+impl MergedClient {
+    /// Добавляет сервер с названием source
+    pub fn with_source(mut self, source: &str, client: QuotesClient) -> Self {
+        self.sources.push((source.to_string(), client));
+        self
+    }
+
+    /// Как выбирать котировки тикера, который приходит от нескольких серверов
+    pub fn with_arbitration(mut self, arbitration: Arbitration) -> Self {
+        self.arbitration = arbitration;
+        self
+    }
+
+    /// Запускает клиенты всех серверов с общим каналом емкостью capacity.
+    /// Если канал заполнен, прием ждет, пока приложение заберет котировки.
+    /// Если какой-то клиент не запустился, уже запущенные останавливаются
+    pub fn start(self, capacity: usize) -> ClientResult<MergedControl> {
+        if self.sources.is_empty() {
+            return Err(ClientError::Config("no sources to merge".to_string()));
+        }
+        let arbiter = Arc::new(Arbiter::new(self.arbitration));
+        let (tx, quotes) = mpsc::sync_channel(capacity);
+        let mut control = MergedControl {
+            quotes,
+            sources: Vec::new(),
+            arbiter: arbiter.clone(),
+        };
+        for (source, client) in self.sources {
+            let handler = SourceHandler {
+                source: source.clone(),
+                arbiter: arbiter.clone(),
+                tx: tx.clone(),
+            };
+            match client.start_receive_handler(handler) {
+                Ok(source_control) => control.sources.push((source, source_control)),
+                Err(e) => {
+                    log::error!("Can't start source {source}: {e}");
+                    control.stop();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(control)
+    }
+}
+
+/// Управление клиентами объединенного потока
+pub struct MergedControl {
+    /// Объединенный канал котировок всех серверов
+    pub quotes: mpsc::Receiver<SourcedQuote>,
+    /// Управление клиентом каждого сервера по названию источника
+    pub sources: Vec<(String, ClientControl)>,
+    /// Выбор котировок из нескольких источников
+    pub arbiter: Arc<Arbiter>,
+}
+
+impl MergedControl {
+    /// Управление клиентом источника source
+    pub fn source(&self, source: &str) -> Option<&ClientControl> {
+        self.sources
+            .iter()
+            .find(|(name, _)| name == source)
+            .map(|(_, control)| control)
+    }
+
+    /// Останавливает клиенты всех серверов и ждет завершения их потоков.
+    /// Непрочитанные котировки канала отбрасываются
+    pub fn stop(self) {
+        for (source, control) in self.sources.iter() {
+            if control.tx.send(ClientCmd::Stop).is_err() {
+                log::debug!("Source {source} is already stopped");
+            }
+        }
+        drop(self.quotes);
+        for (source, control) in self.sources {
+            match control.thread_handle.join() {
+                Ok(Err(e)) => log::error!("Source {source} error: {e}"),
+                Err(_) => log::error!("Can't join thread of source {source}"),
+                Ok(Ok(())) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::quotes_client::QuotesClientBuilder;
+    use crate::testing::{MockQuoteServer, MockServerBuilder};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_arbiter() {
+        let quote = |ticker: &str, timestamp: u64| StockQuote {
+            ticker: ticker.to_string(),
+            timestamp,
+            ..Default::default()
+        };
+
+        let newest = Arbiter::new(Arbitration::Newest);
+        assert!(newest.accept("a", &quote("AMD", 1)));
+        assert!(!newest.accept("b", &quote("AMD", 1)));
+        assert!(newest.accept("b", &quote("AMD", 2)));
+        assert!(newest.accept("a", &quote("INT", 1)));
+        assert_eq!(newest.rejected(), 1);
+        assert!(newest.accept("a", &quote("AMD", 100)));
+        assert!(!newest.accept("b", &quote("AMD", 3)));
+        newest.release("a");
+        assert!(newest.accept("b", &quote("AMD", 3)));
+        assert!(newest.accept("b", &quote("INT", 1)));
+
+        let first = Arbiter::new(Arbitration::FirstSource);
+        assert!(first.accept("a", &quote("AMD", 1)));
+        assert!(!first.accept("b", &quote("AMD", 2)));
+        assert!(first.accept("b", &quote("INT", 2)));
+        first.release("a");
+        assert!(first.accept("b", &quote("AMD", 3)));
+        assert!(!first.accept("a", &quote("AMD", 4)));
+
+        let all = Arbiter::new(Arbitration::default());
+        assert!(all.accept("a", &quote("AMD", 1)));
+        assert!(all.accept("b", &quote("AMD", 1)));
+    }
+
+    #[test]
+    fn test_merged_client() {
+        let quotes: Vec<StockQuote> = (1..=3)
+            .map(|timestamp| StockQuote {
+                ticker: "AMD".to_string(),
+                timestamp,
+                ..Default::default()
+            })
+            .collect();
+        let servers: Vec<MockQuoteServer> = (0..2)
+            .map(|_| {
+                MockServerBuilder::default()
+                    .with_quotes(quotes.clone())
+                    .start()
+                    .unwrap()
+            })
+            .collect();
+        let client = |server: &MockQuoteServer| {
+            QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+                .with_poll_millis(10)
+                .build()
+                .unwrap()
+        };
+        let control = MergedClient::default()
+            .with_source("a", client(&servers[0]))
+            .with_source("b", client(&servers[1]))
+            .with_arbitration(Arbitration::Newest)
+            .start(10)
+            .unwrap();
+
+        let timestamps: Vec<u64> = (0..3)
+            .map(|_| {
+                control
+                    .quotes
+                    .recv_timeout(Duration::from_secs(5))
+                    .unwrap()
+                    .quote
+                    .timestamp
+            })
+            .collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while control.arbiter.rejected() < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(control.arbiter.rejected(), 3);
+        assert!(control.quotes.try_recv().is_err());
+        assert!(control.source("b").is_some());
+        control.stop();
+    }
+}
@@ -0,0 +1,284 @@
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Длительность окна, за которое считаются скорости приема и задержка
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Временные метки меньше этой считаются номерами котировок, а не временем:
+/// по ним задержку не посчитать
+const MIN_WALL_CLOCK_MILLIS: u64 = 1_000_000_000_000;
+
+/// Счетчики на начало окна и скорости, посчитанные по последнему завершенному окну.
+/// До завершения первого окна скорости считаются с запуска клиента
+#[derive(Default)]
+struct RateWindow {
+    started: Option<Instant>,
+    quotes: u64,
+    bytes: u64,
+    latency_micros: u64,
+    latency_quotes: u64,
+    quotes_per_sec: f64,
+    bytes_per_sec: f64,
+    latency: Option<Duration>,
+}
+
+/// Счетчики приема котировок клиентом. Разделяются между потоками приема
+pub struct ClientMetrics {
+    started: Instant,
+    quotes: AtomicU64,
+    bytes: AtomicU64,
+    decode_errors: AtomicU64,
+    socket_errors: AtomicU64,
+    resubscribes: AtomicU64,
+    last_quote_micros: AtomicU64,
+    last_data_micros: AtomicU64,
+    latency_micros: AtomicU64,
+    latency_quotes: AtomicU64,
+    window: Mutex<RateWindow>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+/// Снимок метрик клиента
+pub struct ClientStats {
+    /// Количество принятых котировок
+    pub quotes: u64,
+    /// Количество принятых байт во всех датаграммах
+    pub bytes: u64,
+    /// Количество котировок в секунду за последнее окно
+    pub quotes_per_sec: f64,
+    /// Количество байт в секунду за последнее окно
+    pub bytes_per_sec: f64,
+    /// Средняя задержка от временной метки котировки до приема за последнее окно.
+    /// None, если временные метки сервера не являются временем (`TimestampMode::Counter`)
+    pub latency: Option<Duration>,
+    /// Количество датаграмм, которые не удалось декодировать
+    pub decode_errors: u64,
+    /// Количество временных ошибок сокетов, после которых прием продолжился
+    pub socket_errors: u64,
+    /// Количество повторных подписок после того, как данные перестали приходить
+    pub resubscribes: u64,
+    /// Оценка доли потерянных котировок по пропускам номеров
+    pub loss_ratio: f64,
+    /// Время с последней принятой котировки или None, если котировок еще не было
+    pub last_quote_age: Option<Duration>,
+}
+
+impl Display for ClientStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "quotes: {} ({:.1}/s), bytes: {} ({:.1}/s), decode errors: {}, socket errors: {}, \
+             resubscribes: {}, loss: {:.2}%",
+            self.quotes,
+            self.quotes_per_sec,
+            self.bytes,
+            self.bytes_per_sec,
+            self.decode_errors,
+            self.socket_errors,
+            self.resubscribes,
+            self.loss_ratio * 100.0
+        )?;
+        if let Some(latency) = self.latency {
+            write!(f, ", latency: {} ms", latency.as_millis())?;
+        }
+        if let Some(age) = self.last_quote_age {
+            write!(f, ", last quote: {} ms ago", age.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+impl ClientMetrics {
+    pub(super) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            quotes: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+            socket_errors: AtomicU64::new(0),
+            resubscribes: AtomicU64::new(0),
+            last_quote_micros: AtomicU64::new(0),
+            last_data_micros: AtomicU64::new(0),
+            latency_micros: AtomicU64::new(0),
+            latency_quotes: AtomicU64::new(0),
+            window: Mutex::new(RateWindow::default()),
+        }
+    }
+
+    fn micros_since_start(&self) -> u64 {
+        self.started.elapsed().as_micros() as u64
+    }
+
+    /// Принята датаграмма длиной len
+    pub(super) fn datagram(&self, len: usize) {
+        self.bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Принята котировка с временной меткой timestamp
+    pub(super) fn quote(&self, timestamp: u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.quote_at(timestamp, now);
+    }
+
+    fn quote_at(&self, timestamp: u64, since_epoch: Duration) {
+        self.quotes.fetch_add(1, Ordering::Relaxed);
+        self.last_quote_micros
+            .store(self.micros_since_start().max(1), Ordering::Relaxed);
+        if timestamp >= MIN_WALL_CLOCK_MILLIS {
+            let latency = since_epoch.saturating_sub(Duration::from_millis(timestamp));
+            self.latency_micros
+                .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+            self.latency_quotes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Приняты котировка, котировка истории или свеча
+    pub(super) fn data(&self) {
+        self.last_data_micros
+            .store(self.micros_since_start().max(1), Ordering::Relaxed);
+    }
+
+    /// Датаграмму не удалось декодировать
+    pub(super) fn decode_error(&self) {
+        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Временная ошибка сокета, прием продолжится
+    pub(super) fn socket_error(&self) {
+        self.socket_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Подписка отправлена заново, потому что данные перестали приходить
+    pub(super) fn resubscribe(&self) {
+        self.resubscribes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn age(&self, micros: &AtomicU64) -> Option<Duration> {
+        let micros = micros.load(Ordering::Relaxed);
+        (micros > 0)
+            .then(|| Duration::from_micros(self.micros_since_start().saturating_sub(micros)))
+    }
+
+    /// Время с последней принятой котировки или None, если котировок еще не было
+    pub fn last_quote_age(&self) -> Option<Duration> {
+        self.age(&self.last_quote_micros)
+    }
+
+    /// Время с последних принятых данных любого вида или None, если данных еще не было
+    pub fn last_data_age(&self) -> Option<Duration> {
+        self.age(&self.last_data_micros)
+    }
+
+    /// Текущий снимок метрик. loss_ratio считается отдельно по номерам котировок
+    pub fn snapshot(&self, loss_ratio: f64) -> ClientStats {
+        self.snapshot_at(loss_ratio, Instant::now())
+    }
+
+    fn snapshot_at(&self, loss_ratio: f64, now: Instant) -> ClientStats {
+        let quotes = self.quotes.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let latency_micros = self.latency_micros.load(Ordering::Relaxed);
+        let latency_quotes = self.latency_quotes.load(Ordering::Relaxed);
+
+        let mut window = self.window.lock().unwrap();
+        let started = *window.started.get_or_insert(self.started);
+        let elapsed = now.saturating_duration_since(started);
+        if elapsed >= RATE_WINDOW || started == self.started {
+            let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+            window.quotes_per_sec = (quotes - window.quotes) as f64 / secs;
+            window.bytes_per_sec = (bytes - window.bytes) as f64 / secs;
+            let window_quotes = latency_quotes - window.latency_quotes;
+            window.latency = (window_quotes > 0).then(|| {
+                Duration::from_micros((latency_micros - window.latency_micros) / window_quotes)
+            });
+        }
+        if elapsed >= RATE_WINDOW {
+            window.started = Some(now);
+            window.quotes = quotes;
+            window.bytes = bytes;
+            window.latency_micros = latency_micros;
+            window.latency_quotes = latency_quotes;
+        }
+
+        ClientStats {
+            quotes,
+            bytes,
+            quotes_per_sec: window.quotes_per_sec,
+            bytes_per_sec: window.bytes_per_sec,
+            latency: window.latency,
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            socket_errors: self.socket_errors.load(Ordering::Relaxed),
+            resubscribes: self.resubscribes.load(Ordering::Relaxed),
+            loss_ratio,
+            last_quote_age: self.last_quote_age(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_metrics() {
+        let metrics = ClientMetrics::new();
+        assert_eq!(metrics.snapshot(0.0).last_quote_age, None);
+
+        metrics.datagram(20);
+        metrics.quote(1);
+        metrics.datagram(30);
+        metrics.quote(2);
+        metrics.datagram(5);
+        metrics.decode_error();
+        metrics.socket_error();
+        metrics.resubscribe();
+        assert_eq!(metrics.last_data_age(), None);
+        metrics.data();
+
+        let stats = metrics.snapshot(0.5);
+        assert_eq!(stats.quotes, 2);
+        assert_eq!(stats.bytes, 55);
+        assert_eq!(stats.decode_errors, 1);
+        assert_eq!(stats.socket_errors, 1);
+        assert_eq!(stats.resubscribes, 1);
+        assert!(metrics.last_data_age().unwrap() < Duration::from_secs(1));
+        assert_eq!(stats.loss_ratio, 0.5);
+        assert!(stats.quotes_per_sec > 0.0);
+        assert_eq!(stats.latency, None);
+        assert!(stats.last_quote_age.unwrap() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_windowed_rates() {
+        let metrics = ClientMetrics::new();
+        let epoch = Duration::from_millis(MIN_WALL_CLOCK_MILLIS + 1000);
+        for _ in 0..10 {
+            metrics.datagram(100);
+            metrics.quote_at(MIN_WALL_CLOCK_MILLIS + 990, epoch);
+        }
+        let first = metrics.snapshot_at(0.0, metrics.started + RATE_WINDOW);
+        assert!((first.quotes_per_sec - 10.0).abs() < 1e-6);
+        assert!((first.bytes_per_sec - 1000.0).abs() < 1e-6);
+        assert_eq!(first.latency, Some(Duration::from_millis(10)));
+
+        metrics.datagram(100);
+        metrics.quote_at(MIN_WALL_CLOCK_MILLIS + 970, epoch);
+        let within = metrics.snapshot_at(0.0, metrics.started + RATE_WINDOW * 3 / 2);
+        assert_eq!(within.quotes_per_sec, first.quotes_per_sec);
+
+        let second = metrics.snapshot_at(0.0, metrics.started + RATE_WINDOW * 3);
+        assert_eq!(second.quotes, 11);
+        assert!((second.quotes_per_sec - 0.5).abs() < 1e-6);
+        assert!((second.bytes_per_sec - 50.0).abs() < 1e-6);
+        assert_eq!(second.latency, Some(Duration::from_millis(30)));
+
+        let idle = metrics.snapshot_at(0.0, metrics.started + RATE_WINDOW * 5);
+        assert_eq!(idle.bytes_per_sec, 0.0);
+        assert_eq!(idle.latency, None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::protocol::Message;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Что сделать с принятым сообщением после промежуточного обработчика
+#[derive(Debug)]
+pub enum Action {
+    /// Передать сообщение дальше по цепочке
+    Continue,
+    /// Отбросить сообщение: следующие обработчики и клиент его не получат
+    Drop,
+    /// Заменить сообщение и передать замену дальше по цепочке
+    Replace(Message),
+}
+
+/// Промежуточный обработчик датаграмм, принятых клиентом. Вызывается в потоках приема
+/// до учета потерь, проверки и передачи `QuoteHandler`, поэтому должен быть быстрым.
+/// Через него можно вести лог и метрики, менять или отбрасывать сообщения.
+/// Отброшенный понг клиент не увидит и сочтет сервер недоступным
+pub trait MessageMiddleware: Send {
+    /// Вызывается для каждого декодированного сообщения
+    fn on_message(&mut self, msg: &Message) -> Action;
+}
+
+impl<F> MessageMiddleware for F
+where
+    F: FnMut(&Message) -> Action + Send,
+{
+    fn on_message(&mut self, msg: &Message) -> Action {
+        self(msg)
+    }
+}
+
+/// Цепочка промежуточных обработчиков, которые вызываются в порядке добавления.
+/// Разделяется между потоками приема котировок клиента
+#[derive(Clone, Default)]
+pub(super) struct MiddlewareChain {
+    middlewares: Arc<Mutex<Vec<Box<dyn MessageMiddleware>>>>,
+}
+
+impl Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiddlewareChain")
+            .field("len", &self.middlewares.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl MiddlewareChain {
+    /// Добавляет обработчик в конец цепочки
+    pub(super) fn push<M: MessageMiddleware + 'static>(&self, middleware: M) {
+        self.middlewares.lock().unwrap().push(Box::new(middleware));
+    }
+
+    /// Пропускает сообщение через цепочку. None, если сообщение отброшено
+    pub(super) fn apply(&self, mut msg: Message) -> Option<Message> {
+        let mut middlewares = self.middlewares.lock().unwrap();
+        for middleware in middlewares.iter_mut() {
+            match middleware.on_message(&msg) {
+                Action::Continue => {}
+                Action::Drop => return None,
+                Action::Replace(replaced) => msg = replaced,
+            }
+        }
+        Some(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::QuoteRespMessage;
+    use crate::quote::StockQuote;
+
+    #[test]
+    fn test_middleware_chain() {
+        let chain = MiddlewareChain::default();
+        let seen = Arc::new(Mutex::new(0));
+        let counter = seen.clone();
+        chain.push(move |_msg: &Message| {
+            *counter.lock().unwrap() += 1;
+            Action::Continue
+        });
+        chain.push(|msg: &Message| match msg {
+            Message::Quote(resp) if resp.quote.ticker == "AMD" => Action::Drop,
+            Message::Quote(resp) => Action::Replace(Message::Quote(QuoteRespMessage {
+                quote: StockQuote {
+                    price: resp.quote.price * 2.0,
+                    ..resp.quote.clone()
+                },
+                seq: resp.seq,
+            })),
+            _ => Action::Continue,
+        });
+
+        let quote = |ticker: &str| {
+            Message::Quote(QuoteRespMessage {
+                quote: StockQuote {
+                    ticker: ticker.to_string(),
+                    price: 1.5,
+                    ..Default::default()
+                },
+                seq: 1,
+            })
+        };
+        assert!(chain.apply(quote("AMD")).is_none());
+        let Some(Message::Quote(resp)) = chain.apply(quote("INT")) else {
+            panic!("quote is dropped");
+        };
+        assert_eq!(resp.quote.price, 3.0);
+        assert!(matches!(chain.apply(Message::Pong), Some(Message::Pong)));
+        assert_eq!(*seen.lock().unwrap(), 3);
+    }
+}
@@ -1,2 +1,83 @@
 /// Клиент приема котировок
 pub mod quotes_client;
+
+/// Ошибки клиента котировок
+pub mod error;
+
+/// Наблюдаемое состояние соединения клиента
+pub mod state;
+
+/// Обработка принятых котировок
+pub mod handler;
+
+/// Очереди ограниченной емкости между приемом котировок и обработчиками
+pub mod queue;
+
+/// Промежуточные обработчики принятых сообщений
+pub mod middleware;
+
+/// Раздача котировок одной подписки нескольким получателям приложения
+pub mod fanout;
+
+/// Фильтрация котировок на стороне клиента
+pub mod filter;
+
+/// Группы тикеров с отдельными обработчиками поверх одного подключения
+pub mod group;
+
+/// Объединенный поток котировок нескольких серверов
+pub mod merge;
+
+/// Сжатие потока котировок до последнего значения тикера за интервал
+pub mod conflate;
+
+/// Ценовые оповещения по принятым котировкам
+pub mod alert;
+
+/// Свечи OHLCV и VWAP по принятым котировкам
+pub mod aggregate;
+
+/// Запись принятых котировок в файлы и базы данных
+pub mod sink;
+
+/// Запись принятых датаграмм и воспроизведение записи без сервера
+pub mod record;
+
+/// Подавление повторно доставленных котировок
+pub mod dedup;
+
+/// Проверка принятых котировок на повторы, порядок и устаревание
+pub mod validate;
+
+/// Учет потерь котировок по номерам последовательности
+pub mod loss;
+
+/// Сохранение и восстановление состояния сессии клиента
+pub mod session;
+
+/// Сводка по тикерам за сессию клиента
+pub mod summary;
+
+/// Повтор приема котировок после временных ошибок сокетов
+pub mod recovery;
+
+mod control;
+
+mod watchdog;
+
+/// Метрики приема котировок клиентом
+pub mod metrics;
+
+/// Сообщения супервизору о живости клиента: файл-пульс или проверка по tcp
+pub mod health;
+
+/// Таблица котировок в терминале
+#[cfg(feature = "tui")]
+pub mod tui;
+
+/// Поиск серверов котировок в локальной сети через mDNS
+pub mod discovery;
+
+/// Асинхронный клиент котировок на tokio
+#[cfg(feature = "async")]
+pub mod async_client;
@@ -0,0 +1,305 @@
+use super::handler::{ConnectionEvent, QuoteHandler};
+use super::validate::QuoteIssue;
+use crate::quote::{Bar, StockQuote};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Что делать с новым элементом, если очередь заполнена
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OverflowPolicy {
+    /// Вытеснить самый старый элемент
+    #[default]
+    DropOldest,
+    /// Отбросить новый элемент
+    DropNewest,
+    /// Ждать, пока получатель освободит место. Отправитель при этом останавливается,
+    /// поэтому политика не подходит для очередей потока приема клиента: остановленный
+    /// прием не отвечает на пинг, и сервер отключает клиента
+    Block,
+}
+
+impl Display for OverflowPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DropOldest => write!(f, "drop-oldest"),
+            Self::DropNewest => write!(f, "drop-newest"),
+            Self::Block => write!(f, "block"),
+        }
+    }
+}
+
+/// Очередь ограниченной емкости между потоком приема и получателем.
+/// Заполненная очередь поступает по `OverflowPolicy`, отброшенные элементы учитываются
+/// как переполнение
+pub struct BoundedQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    overflowed: AtomicU64,
+    closed: AtomicBool,
+    changed: Condvar,
+}
+
+impl<T> BoundedQueue<T> {
+    /// Создает очередь на capacity элементов, вытесняющую самые старые
+    pub fn new(capacity: usize) -> Self {
+        Self::with_policy(capacity, OverflowPolicy::DropOldest)
+    }
+
+    /// Создает очередь на capacity элементов с политикой переполнения policy
+    pub fn with_policy(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            policy,
+            overflowed: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Добавляет элемент. Если очередь заполнена, поступает по политике переполнения
+    pub fn push(&self, item: T) {
+        let mut items = self.items.lock().unwrap();
+        if items.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    items.pop_front();
+                    self.overflowed.fetch_add(1, Ordering::Relaxed);
+                }
+                OverflowPolicy::DropNewest => {
+                    self.overflowed.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                OverflowPolicy::Block => {
+                    items = self
+                        .changed
+                        .wait_while(items, |items| {
+                            items.len() >= self.capacity && !self.closed.load(Ordering::Relaxed)
+                        })
+                        .unwrap();
+                    if items.len() >= self.capacity {
+                        self.overflowed.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                }
+            }
+        }
+        items.push_back(item);
+        self.changed.notify_all();
+    }
+
+    /// Забирает до max самых старых элементов
+    pub fn pop_up_to(&self, max: usize) -> Vec<T> {
+        let mut items = self.items.lock().unwrap();
+        let count = max.min(items.len());
+        let popped = items.drain(..count).collect();
+        self.changed.notify_all();
+        popped
+    }
+
+    /// Ждет и забирает самый старый элемент. None, если очередь закрыта и пуста
+    pub(super) fn pop_wait(&self) -> Option<T> {
+        let items = self.items.lock().unwrap();
+        let mut items = self
+            .changed
+            .wait_while(items, |items| {
+                items.is_empty() && !self.closed.load(Ordering::Relaxed)
+            })
+            .unwrap();
+        let item = items.pop_front();
+        self.changed.notify_all();
+        item
+    }
+
+    /// Закрывает очередь: ожидающие получатели забирают оставшиеся элементы и завершаются,
+    /// ожидающие отправители больше не ждут
+    pub(super) fn close(&self) {
+        let _items = self.items.lock().unwrap();
+        self.closed.store(true, Ordering::Relaxed);
+        self.changed.notify_all();
+    }
+
+    /// Количество элементов в очереди
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    /// Очередь пуста
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Количество элементов, отброшенных из-за переполнения
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+/// Вызов обработчика, ожидающий в очереди `QueuedHandler`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerEvent {
+    /// Котировка
+    Quote(StockQuote),
+    /// Котировка истории
+    Replay(StockQuote),
+    /// Котировка, не прошедшая проверку
+    Flagged(StockQuote, QuoteIssue),
+    /// Свеча
+    Bar(Bar),
+    /// Изменение состояния соединения
+    Connection(ConnectionEvent),
+    /// Периодический вызов без котировок
+    Tick,
+}
+
+impl HandlerEvent {
+    fn deliver<H: QuoteHandler>(self, handler: &mut H) {
+        match self {
+            Self::Quote(quote) => handler.on_quote(quote),
+            Self::Replay(quote) => handler.on_replay(quote),
+            Self::Flagged(quote, issue) => handler.on_flagged(quote, issue),
+            Self::Bar(bar) => handler.on_bar(bar),
+            Self::Connection(event) => handler.on_connection(event),
+            Self::Tick => handler.on_tick(),
+        }
+    }
+}
+
+/// Обработчик, который передает вызовы внутреннему обработчику в отдельном потоке
+/// через очередь ограниченной емкости. Медленный обработчик, например запись в базу,
+/// не задерживает прием и не расходует память без предела.
+/// При удалении ждет, пока внутренний обработчик разберет очередь
+pub struct QueuedHandler {
+    queue: Arc<BoundedQueue<HandlerEvent>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl QueuedHandler {
+    /// Создает обработчик с очередью емкостью capacity и политикой переполнения policy
+    pub fn new<H>(
+        mut inner: H,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> (Self, Arc<BoundedQueue<HandlerEvent>>)
+    where
+        H: QuoteHandler + 'static,
+    {
+        let queue: Arc<BoundedQueue<HandlerEvent>> =
+            Arc::new(BoundedQueue::with_policy(capacity, policy));
+        let worker_queue = queue.clone();
+        let worker = thread::spawn(move || {
+            while let Some(event) = worker_queue.pop_wait() {
+                event.deliver(&mut inner);
+            }
+        });
+        (
+            Self {
+                queue: queue.clone(),
+                worker: Some(worker),
+            },
+            queue,
+        )
+    }
+}
+
+impl QuoteHandler for QueuedHandler {
+    fn on_quote(&mut self, quote: StockQuote) {
+        self.queue.push(HandlerEvent::Quote(quote));
+    }
+
+    fn on_replay(&mut self, quote: StockQuote) {
+        self.queue.push(HandlerEvent::Replay(quote));
+    }
+
+    fn on_flagged(&mut self, quote: StockQuote, issue: QuoteIssue) {
+        self.queue.push(HandlerEvent::Flagged(quote, issue));
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        self.queue.push(HandlerEvent::Bar(bar));
+    }
+
+    fn on_connection(&mut self, event: ConnectionEvent) {
+        self.queue.push(HandlerEvent::Connection(event));
+    }
+
+    fn on_tick(&mut self) {
+        if self.queue.is_empty() {
+            self.queue.push(HandlerEvent::Tick);
+        }
+    }
+}
+
+impl Drop for QueuedHandler {
+    fn drop(&mut self) {
+        self.queue.close();
+        if let Some(Err(_)) = self.worker.take().map(|worker| worker.join()) {
+            log::error!("Queued handler thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_bounded_queue_policies() {
+        let newest = BoundedQueue::with_policy(2, OverflowPolicy::DropNewest);
+        for item in 1..=4 {
+            newest.push(item);
+        }
+        assert_eq!(newest.pop_up_to(10), vec![1, 2]);
+        assert_eq!(newest.overflowed(), 2);
+
+        let blocking = Arc::new(BoundedQueue::with_policy(1, OverflowPolicy::Block));
+        let producer = {
+            let blocking = blocking.clone();
+            thread::spawn(move || (1..=5).for_each(|item| blocking.push(item)))
+        };
+        let received: Vec<i32> = (0..5).filter_map(|_| blocking.pop_wait()).collect();
+        producer.join().unwrap();
+        assert_eq!(received, vec![1, 2, 3, 4, 5]);
+        assert_eq!(blocking.overflowed(), 0);
+
+        blocking.close();
+        assert_eq!(blocking.pop_wait(), None);
+    }
+
+    struct Slow {
+        tx: mpsc::Sender<f64>,
+    }
+
+    impl QuoteHandler for Slow {
+        fn on_quote(&mut self, quote: StockQuote) {
+            thread::sleep(Duration::from_millis(20));
+            self.tx.send(quote.price).unwrap();
+        }
+
+        fn on_bar(&mut self, _bar: Bar) {}
+    }
+
+    #[test]
+    fn test_queued_handler() {
+        let (tx, rx) = mpsc::channel();
+        let (mut handler, queue) = QueuedHandler::new(Slow { tx }, 2, OverflowPolicy::DropNewest);
+        for price in 1..=10 {
+            handler.on_quote(StockQuote {
+                ticker: "AMD".to_string(),
+                price: price as f64,
+                ..Default::default()
+            });
+        }
+        drop(handler);
+        let delivered: Vec<f64> = rx.iter().collect();
+        assert!(delivered.len() <= 3);
+        assert_eq!(delivered.first(), Some(&1.0));
+        assert_eq!(queue.overflowed() as usize + delivered.len(), 10);
+    }
+}
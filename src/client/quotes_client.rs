@@ -1,34 +1,100 @@
+use super::conflate::ConflatingHandler;
+use super::control::ControlReplies;
+use super::dedup::QuoteDeduplicator;
+use super::error::{ClientError, ClientResult};
+use super::fanout::{BroadcastHandler, QuoteBroadcast};
+use super::filter::{FilteredHandler, QuoteFilter};
+use super::group::{GroupHandler, GroupsChange, TickerGroups};
+use super::handler::{
+    BufferHandler, ChannelHandler, ConnectionEvent, ConsolePrinter, QuoteBuffer, QuoteHandler,
+};
+use super::loss::{LossStats, SeqPosition};
+use super::metrics::{ClientMetrics, ClientStats};
+use super::middleware::{MessageMiddleware, MiddlewareChain};
+use super::queue::{BoundedQueue, HandlerEvent, OverflowPolicy, QueuedHandler};
+use super::record::DatagramRecorder;
+use super::recovery::{Recovery, RecoveryPolicy};
+use super::session::{CurrentSession, SessionState};
+use super::state::{ClientState, StateWatch};
+use super::summary::SessionSummary;
+use super::validate::{QuoteValidation, QuoteValidator, ValidationPolicy};
+use super::watchdog::NoDataWatchdog;
 use crate::protocol::*;
+use crate::quote::{Bar, StockQuote};
+use crate::stats::{LoopStats, ThreadStats};
 use crate::timer::Timer;
-use anyhow::{Result, bail};
+use crate::tls::{self, ControlStream};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 use std::fmt::Display;
 use std::io::BufReader;
 use std::io::{BufRead, ErrorKind, Write};
-use std::net::{SocketAddr, TcpStream, UdpSocket};
-use std::sync::mpsc;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
+use std::time::{Duration, Instant};
 
-const PING_PERIOD_MILLIS: u64 = 30000;
-const WAIT_PONG_MILLIS: u64 = 5000;
+const DEFAULT_PING_PERIOD_MILLIS: u64 = 30000;
+const DEFAULT_WAIT_PONG_MILLIS: u64 = 5000;
+const DEFAULT_RECONNECT_INITIAL_MILLIS: u64 = 500;
+const DEFAULT_RECONNECT_MAX_MILLIS: u64 = 30000;
 const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
-const WAIT_QUOTES_MILLIS: u64 = 100;
+const DEFAULT_POLL_MILLIS: u64 = 100;
+const DEFAULT_CONNECT_TIMEOUT_MILLIS: u64 = 5000;
+const WAIT_SUBSCRIBED_MILLIS: u64 = 5000;
 
 const WAIT_PING_EVENT: &str = "ping";
 const WAIT_PONG_EVENT: &str = "pong";
 const WAIT_CMD_EVENT: &str = "cmd";
 const WAIT_QUOTES_EVENT: &str = "quotes";
 
+const CLIENT_SUBSYSTEM: &str = "client";
+const STRIPE_SUBSYSTEM: &str = "stripe";
+
 /// Команды управления клиентом
 pub enum ClientCmd {
     /// Остановить клиент
     Stop,
+    /// Изменить подписку на котировки
+    Subscribe {
+        /// Как тикеры меняют текущую подписку
+        mode: SubscriptionMode,
+        /// Тикеры
+        tickers: Vec<String>,
+    },
+    /// Приостановить прием: сервер перестает присылать котировки,
+    /// а уже отправленные не передаются обработчику. Соединение сохраняется.
+    /// Котировки группы multicast сервер продолжает публиковать, клиент их только не передает
+    Pause,
+    /// Возобновить прием после `Pause`
+    Resume,
+}
+
+impl ClientCmd {
+    /// Добавить тикеры к текущей подписке
+    pub fn subscribe(tickers: Vec<String>) -> Self {
+        Self::Subscribe {
+            mode: SubscriptionMode::Add,
+            tickers,
+        }
+    }
+
+    /// Убрать тикеры из текущей подписки
+    pub fn unsubscribe(tickers: Vec<String>) -> Self {
+        Self::Subscribe {
+            mode: SubscriptionMode::Remove,
+            tickers,
+        }
+    }
 }
 
-fn is_stop_cmd(rx: &mpsc::Receiver<ClientCmd>) -> bool {
+pub(super) fn is_stop_cmd(rx: &mpsc::Receiver<ClientCmd>) -> bool {
     match rx.try_recv() {
         Ok(cmd) => match cmd {
             ClientCmd::Stop => return true,
+            ClientCmd::Subscribe { .. } | ClientCmd::Pause | ClientCmd::Resume => false,
         },
         Err(e) => match e {
             TryRecvError::Disconnected => {
@@ -40,107 +106,399 @@ fn is_stop_cmd(rx: &mpsc::Receiver<ClientCmd>) -> bool {
     }
 }
 
-struct PingControl {
-    thread_handle: thread::JoinHandle<Result<()>>,
-    tx: mpsc::Sender<ClientCmd>,
-}
-
 enum PingState {
     WaitPing,
     WaitPong,
 }
 
+pub(super) fn loopback_for(server_addr: SocketAddr) -> IpAddr {
+    match server_addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    }
+}
+
+fn bind_udp(addr: SocketAddr) -> ClientResult<UdpSocket> {
+    UdpSocket::bind(addr).map_err(|source| ClientError::SocketBind { addr, source })
+}
+
+fn not_started_with_groups() -> ClientError {
+    ClientError::Config("client is not started with ticker groups".to_string())
+}
+
+fn not_started_with_broadcast() -> ClientError {
+    ClientError::Config("client is not started with quote broadcast".to_string())
+}
+
+/// Разрешает адрес сервера: ip-адрес или имя хоста с портом, например
+/// "127.0.0.1:8090" или "quotes.example.com:8090". Берется первый найденный адрес
+pub fn resolve_server_addr(server_addr: &str) -> ClientResult<SocketAddr> {
+    if let Ok(addr) = server_addr.parse() {
+        return Ok(addr);
+    }
+    let resolve_error = |reason: String| ClientError::Resolve {
+        addr: server_addr.to_string(),
+        reason,
+    };
+    let mut addrs = server_addr
+        .to_socket_addrs()
+        .map_err(|e| resolve_error(e.to_string()))?;
+    addrs
+        .next()
+        .ok_or_else(|| resolve_error("resolved to nothing".to_string()))
+}
+
+/// Читает тикеры из файла, по одному тикеру в строке
+pub fn read_tickers(tickers_path: &str) -> ClientResult<Vec<String>> {
+    let file = std::fs::File::open(tickers_path)?;
+    let read_buf = BufReader::new(file);
+    let mut tickers = Vec::new();
+    for line in read_buf.lines() {
+        tickers.push(line?);
+    }
+    Ok(tickers)
+}
+
+/// Таймауты проверки связи с сервером
+#[derive(Debug, Clone, Copy)]
+pub struct PingTimeouts {
+    /// Период отправки пинга серверу
+    pub ping_period_millis: u64,
+    /// Время ожидания понга, после которого сервер считается недоступным
+    pub wait_pong_millis: u64,
+}
+
+impl Default for PingTimeouts {
+    fn default() -> Self {
+        Self {
+            ping_period_millis: DEFAULT_PING_PERIOD_MILLIS,
+            wait_pong_millis: DEFAULT_WAIT_PONG_MILLIS,
+        }
+    }
+}
+
+/// Повторное подключение к серверу при потере связи. Задержка перед очередной
+/// попыткой удваивается, начиная с initial_delay_millis, но не превышает max_delay_millis
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Задержка перед первой попыткой
+    pub initial_delay_millis: u64,
+    /// Наибольшая задержка между попытками
+    pub max_delay_millis: u64,
+    /// Наибольшее число попыток подряд, None - без ограничения
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay_millis: DEFAULT_RECONNECT_INITIAL_MILLIS,
+            max_delay_millis: DEFAULT_RECONNECT_MAX_MILLIS,
+            max_retries: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let millis = self
+            .initial_delay_millis
+            .saturating_mul(1u64 << attempt.min(32))
+            .min(self.max_delay_millis);
+        Duration::from_millis(millis)
+    }
+}
+
 struct PingPong {
     server_addr: SocketAddr,
+    timeouts: PingTimeouts,
+    state: PingState,
 }
 
 impl PingPong {
-    fn new(server_addr: SocketAddr) -> Self {
-        Self { server_addr }
+    fn new(server_addr: SocketAddr, timeouts: PingTimeouts, timer: &mut Timer) -> Self {
+        timer.add_event(WAIT_PING_EVENT, timeouts.ping_period_millis);
+        log::info!("Ping pong start to server: {server_addr}");
+        Self {
+            server_addr,
+            timeouts,
+            state: PingState::WaitPing,
+        }
+    }
+
+    fn check(&mut self, sock: &UdpSocket, timer: &mut Timer) -> ClientResult<()> {
+        match self.state {
+            PingState::WaitPing => {
+                if timer
+                    .is_expired_event(WAIT_PING_EVENT)
+                    .map_err(ClientError::Timer)?
+                {
+                    let bin_ping = postcard::to_stdvec(&Message::Ping)
+                        .map_err(|e| ClientError::Encode(e.into()))?;
+                    sock.send_to(&bin_ping, self.server_addr)?;
+                    log::info!("PING");
+                    timer
+                        .remove_event(WAIT_PING_EVENT)
+                        .map_err(ClientError::Timer)?;
+                    timer.add_event(WAIT_PONG_EVENT, self.timeouts.wait_pong_millis);
+                    self.state = PingState::WaitPong;
+                }
+            }
+            PingState::WaitPong => {
+                if timer
+                    .is_expired_event(WAIT_PONG_EVENT)
+                    .map_err(ClientError::Timer)?
+                {
+                    return Err(ClientError::PingTimeout(self.server_addr));
+                }
+            }
+        }
+        Ok(())
     }
 
-    fn ping(sock: &UdpSocket) -> Result<()> {
-        let bin_ping = postcard::to_stdvec(&Message::Ping)?;
-        sock.send(&bin_ping)?;
-        log::info!("PING");
+    fn pong(&mut self, timer: &mut Timer) -> ClientResult<()> {
+        log::info!("PONG");
+        if let PingState::WaitPong = self.state {
+            timer
+                .remove_event(WAIT_PONG_EVENT)
+                .map_err(ClientError::Timer)?;
+            timer.add_event(WAIT_PING_EVENT, self.timeouts.ping_period_millis);
+            self.state = PingState::WaitPing;
+        }
         Ok(())
     }
 
-    fn is_pong_received(sock: &UdpSocket) -> bool {
-        let mut recv_buf = [0u8; MAX_SIZE_DATAGRAM];
-        let pack_len = match sock.recv(&mut recv_buf) {
-            Ok(len) => len,
-            Err(_) => return false,
+    fn stop(self, timer: &mut Timer) {
+        let event = match self.state {
+            PingState::WaitPing => WAIT_PING_EVENT,
+            PingState::WaitPong => WAIT_PONG_EVENT,
         };
+        if let Err(e) = timer.remove_event(event) {
+            log::warn!("Can't stop ping pong: {e}");
+        }
+    }
+}
 
-        let msg = match postcard::from_bytes::<Message>(&recv_buf[..pack_len]) {
-            Ok(msg) => msg,
-            Err(_) => return false,
-        };
-        match msg {
-            Message::Pong => {
-                log::info!("PONG");
-                return true;
+type SharedHandler = Arc<Mutex<dyn QuoteHandler>>;
+
+fn notify(handler: &SharedHandler, event: ConnectionEvent) {
+    handler.lock().unwrap().on_connection(event);
+}
+
+enum Datagram {
+    Quote(QuoteRespMessage, SocketAddr),
+    Replay(StockQuote, SocketAddr),
+    Bar(Bar, SocketAddr),
+    Pong,
+    Shutdown,
+}
+
+/// Результат опроса сокета котировок
+enum Received {
+    Nothing,
+    Datagram,
+    Shutdown,
+}
+
+impl Datagram {
+    fn source(&self) -> Option<(&str, SocketAddr)> {
+        match self {
+            Self::Quote(resp, addr) => Some((&resp.quote.ticker, *addr)),
+            Self::Replay(quote, addr) => Some((&quote.ticker, *addr)),
+            Self::Bar(bar, addr) => Some((&bar.ticker, *addr)),
+            Self::Pong | Self::Shutdown => None,
+        }
+    }
+
+    fn deliver(self, handler: &SharedHandler, recv_context: &RecvContext) {
+        recv_context.state.set_streaming();
+        recv_context.metrics.data();
+        let mut issue = None;
+        if let Self::Quote(resp, _) = &self {
+            recv_context.loss.record(&resp.quote.ticker, resp.seq);
+            recv_context.metrics.quote(resp.quote.timestamp);
+            let dedup = recv_context.dedup.as_ref();
+            if dedup.is_some_and(|dedup| dedup.is_duplicate(&resp.quote, resp.seq)) {
+                log::debug!("Suppress duplicate quote {}", resp.quote);
+                return;
             }
-            _ => {
-                log::warn!("Wrong response");
-                return false;
+            if let Some(validator) = recv_context.validator.as_ref() {
+                issue = validator
+                    .check(&resp.quote, resp.seq)
+                    .map(|issue| (issue, validator.policy()));
             }
         }
+        if recv_context.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        if let (
+            Self::Quote(resp, _),
+            None | Some((_, ValidationPolicy::Warn | ValidationPolicy::Flag)),
+        ) = (&self, issue)
+        {
+            recv_context.summary.record(&resp.quote);
+        }
+        let mut handler = handler.lock().unwrap();
+        match self {
+            Self::Quote(resp, _) => match issue {
+                None => handler.on_quote(resp.quote),
+                Some((issue, ValidationPolicy::Drop)) => {
+                    log::debug!("Drop quote {}: {issue}", resp.quote);
+                }
+                Some((issue, ValidationPolicy::Warn)) => {
+                    log::warn!("Invalid quote {}: {issue}", resp.quote);
+                    handler.on_quote(resp.quote);
+                }
+                Some((issue, ValidationPolicy::Flag)) => handler.on_flagged(resp.quote, issue),
+            },
+            Self::Replay(quote, _) => handler.on_replay(quote),
+            Self::Bar(bar, _) => handler.on_bar(bar),
+            Self::Pong | Self::Shutdown => {}
+        }
+    }
+}
+
+#[derive(Clone)]
+struct RecvContext {
+    loss: Arc<LossStats>,
+    metrics: Arc<ClientMetrics>,
+    recorder: Option<Arc<DatagramRecorder>>,
+    validator: Option<Arc<QuoteValidator>>,
+    dedup: Option<Arc<QuoteDeduplicator>>,
+    paused: Arc<AtomicBool>,
+    state: Arc<StateWatch>,
+    summary: Arc<SessionSummary>,
+    recovery: RecoveryPolicy,
+    busy_poll: bool,
+    recv_buffer_bytes: Option<usize>,
+    middleware: MiddlewareChain,
+}
+
+impl RecvContext {
+    fn wait_poll(&self, timer: &mut Timer) {
+        if self.busy_poll {
+            std::hint::spin_loop();
+            timer.advance();
+        } else {
+            timer.sleep();
+        }
+    }
+
+    fn bind(&self, addr: SocketAddr) -> ClientResult<UdpSocket> {
+        let sock = bind_udp(addr)?;
+        self.set_recv_buffer(&sock)?;
+        sock.set_nonblocking(true)?;
+        Ok(sock)
+    }
+
+    fn set_recv_buffer(&self, sock: &UdpSocket) -> ClientResult<()> {
+        if let Some(bytes) = self.recv_buffer_bytes {
+            let sock = SockRef::from(sock);
+            sock.set_recv_buffer_size(bytes)?;
+            log::debug!("Receive buffer size: {} bytes", sock.recv_buffer_size()?);
+        }
+        Ok(())
+    }
+}
+
+struct StripeReceiverControl {
+    thread_handle: thread::JoinHandle<ClientResult<()>>,
+    tx: mpsc::Sender<ClientCmd>,
+}
+
+struct StripeReceiver {
+    sock: UdpSocket,
+    poll_millis: u64,
+    loop_stats: Arc<LoopStats>,
+    handler: SharedHandler,
+    recv_context: RecvContext,
+}
+
+impl StripeReceiver {
+    fn new(
+        udp_addr: SocketAddr,
+        poll_millis: u64,
+        loop_stats: Arc<LoopStats>,
+        handler: SharedHandler,
+        recv_context: RecvContext,
+    ) -> ClientResult<Self> {
+        let sock = recv_context.bind(udp_addr)?;
+        log::info!(
+            "Start receive striped quotes at addr: {}",
+            sock.local_addr()?
+        );
+        Ok(Self {
+            sock,
+            poll_millis,
+            loop_stats,
+            handler,
+            recv_context,
+        })
     }
 
-    fn start(self) -> Result<PingControl> {
-        let udp_sock = UdpSocket::bind("127.0.0.1:5433")?;
-        udp_sock.set_nonblocking(true)?;
-        udp_sock.connect(self.server_addr)?;
-        log::info!("Ping pong start to server: {}", self.server_addr);
+    fn start(self) -> StripeReceiverControl {
         let (tx, rx) = mpsc::channel();
         let handle = thread::spawn(move || {
-            let mut state = PingState::WaitPing;
-            let mut timer = Timer::default();
-            timer.add_event(WAIT_PING_EVENT, PING_PERIOD_MILLIS);
+            let mut timer = Timer::with_stats(self.loop_stats.clone());
+            let mut recovery = Recovery::new(self.recv_context.recovery);
+            timer.add_event(WAIT_QUOTES_EVENT, self.poll_millis);
             timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
-
             loop {
-                timer.sleep();
-                if timer.is_expired_event(WAIT_CMD_EVENT)? {
-                    timer.reset_event(WAIT_CMD_EVENT)?;
+                self.recv_context.wait_poll(&mut timer);
+                if timer
+                    .is_expired_event(WAIT_CMD_EVENT)
+                    .map_err(ClientError::Timer)?
+                {
+                    timer
+                        .reset_event(WAIT_CMD_EVENT)
+                        .map_err(ClientError::Timer)?;
                     if is_stop_cmd(&rx) {
-                        log::debug!("Stop ping from stop cmd");
                         break;
                     }
                 }
 
-                match state {
-                    PingState::WaitPing => {
-                        if timer.is_expired_event(WAIT_PING_EVENT)? {
-                            Self::ping(&udp_sock)?;
-                            timer.remove_event(WAIT_PING_EVENT)?;
-                            timer.add_event(WAIT_PONG_EVENT, WAIT_PONG_MILLIS);
-                            state = PingState::WaitPong;
+                let poll_due = self.recv_context.busy_poll
+                    || timer
+                        .is_expired_event(WAIT_QUOTES_EVENT)
+                        .map_err(ClientError::Timer)?;
+                if poll_due && recovery.is_ready(Instant::now()) {
+                    timer
+                        .reset_event(WAIT_QUOTES_EVENT)
+                        .map_err(ClientError::Timer)?;
+                    match QuotesClient::recv_datagram(&self.sock, &self.recv_context) {
+                        Ok(Some(Datagram::Shutdown)) => break,
+                        Ok(Some(datagram)) => {
+                            recovery.succeeded();
+                            datagram.deliver(&self.handler, &self.recv_context)
                         }
-                    }
-                    PingState::WaitPong => {
-                        if timer.is_expired_event(WAIT_PONG_EVENT)? {
-                            if !Self::is_pong_received(&udp_sock) {
-                                log::info!("Pong doesn't received");
-                                break;
+                        Ok(None) => {}
+                        Err(e) => {
+                            let reason = e.to_string();
+                            match recovery.failed(e, Instant::now()) {
+                                Ok(delay) => {
+                                    self.recv_context.metrics.socket_error();
+                                    log::warn!(
+                                        "Transient error of striped quotes: {reason}, retry in {} ms",
+                                        delay.as_millis()
+                                    );
+                                }
+                                Err(_) => {
+                                    log::error!("Can't receive striped quotes: {reason}");
+                                    break;
+                                }
                             }
-                            timer.remove_event(WAIT_PONG_EVENT)?;
-                            timer.add_event(WAIT_PING_EVENT, PING_PERIOD_MILLIS);
-                            state = PingState::WaitPing;
                         }
                     }
                 }
             }
 
-            log::info!("Ping pong finish");
+            log::info!("Stop receive striped quotes");
             Ok(())
         });
-        Ok(PingControl {
+        StripeReceiverControl {
             thread_handle: handle,
             tx,
-        })
+        }
     }
 }
 
@@ -149,15 +507,431 @@ pub struct ClientControl {
     /// Отправка команды потоку-клиента
     pub tx: mpsc::Sender<ClientCmd>,
     /// Дескриптор потока-клиента
-    pub thread_handle: thread::JoinHandle<Result<()>>,
+    pub thread_handle: thread::JoinHandle<ClientResult<()>>,
+    /// Статистика циклов опроса фоновых потоков клиента
+    pub stats: Arc<ThreadStats>,
+    /// Потери котировок по тикерам
+    pub loss: Arc<LossStats>,
+    /// Счетчики принятых котировок, байт и ошибок декодирования
+    pub metrics: Arc<ClientMetrics>,
+    /// Порт приема котировок, переданный серверу. Если клиент создан с портом 0,
+    /// это порт, назначенный системой
+    pub recv_quote_port: u16,
+    /// Токен сессии для восстановления подписки при повторном подключении
+    pub session: String,
+    /// Сервер и токен сессии текущего подключения, в том числе после переподключения
+    pub current_session: Arc<CurrentSession>,
+    /// Канал котировок, если клиент запущен через `start_receive_channel`.
+    /// Если канал заполнен, котировки отбрасываются и учитываются в `overflowed_quotes`
+    pub quotes: Option<mpsc::Receiver<StockQuote>>,
+    channel_dropped: Option<Arc<AtomicU64>>,
+    /// Буфер котировок, если клиент запущен через `start_receive_buffered`
+    pub buffer: Option<Arc<QuoteBuffer>>,
+    /// Раздача котировок получателям, если клиент запущен через `start_receive_broadcast`
+    pub broadcast: Option<Arc<QuoteBroadcast>>,
+    /// Очередь вызовов обработчика, если она задана при сборке клиента
+    pub handler_queue: Option<Arc<BoundedQueue<HandlerEvent>>>,
+    /// Группы тикеров, если клиент запущен через `start_receive_groups`
+    pub groups: Option<Arc<TickerGroups>>,
+    /// Проверка котировок, если она задана при сборке клиента
+    pub validator: Option<Arc<QuoteValidator>>,
+    /// Подавление повторов котировок, если оно задано при сборке клиента
+    pub dedup: Option<Arc<QuoteDeduplicator>>,
+    /// Состояние соединения с сервером. Изменения можно ждать через `StateWatch::subscribe`
+    pub state: Arc<StateWatch>,
+    /// Цены и объемы котировок по тикерам за сессию
+    pub summary: Arc<SessionSummary>,
+    /// Тикеры подписки, подтвержденные сервером. Шаблоны в них раскрыты
+    pub acked_tickers: Arc<Mutex<Vec<String>>>,
+}
+
+impl ClientControl {
+    /// Текущий снимок метрик клиента вместе с оценкой потерь котировок
+    pub fn client_stats(&self) -> ClientStats {
+        self.metrics.snapshot(self.loss.total().loss_ratio())
+    }
+
+    /// Забирает без ожидания до max принятых котировок из буфера или канала котировок.
+    /// Подходит для вызова из цикла отрисовки. Если клиент запущен с обработчиком,
+    /// возвращает пустой список
+    pub fn try_recv_quotes(&self, max: usize) -> Vec<StockQuote> {
+        if let Some(buffer) = self.buffer.as_ref() {
+            return buffer.pop_up_to(max);
+        }
+        let Some(quotes) = self.quotes.as_ref() else {
+            return Vec::new();
+        };
+        quotes.try_iter().take(max).collect()
+    }
+
+    /// Количество котировок и вызовов обработчика, отброшенных из-за переполнения
+    /// канала, буфера и очереди обработчика
+    pub fn overflowed_quotes(&self) -> u64 {
+        let channel = self
+            .channel_dropped
+            .as_ref()
+            .map(|dropped| dropped.load(Ordering::Relaxed));
+        let buffered = self.buffer.as_ref().map(|buffer| buffer.overflowed());
+        let queued = self.handler_queue.as_ref().map(|queue| queue.overflowed());
+        channel.unwrap_or_default() + buffered.unwrap_or_default() + queued.unwrap_or_default()
+    }
+
+    /// Новый получатель котировок с каналом емкостью capacity. Если указаны tickers,
+    /// получатель видит только котировки этих тикеров. Котировки, не поместившиеся
+    /// в канал, отбрасываются для этого получателя и учитываются в `QuoteBroadcast::dropped`
+    pub fn subscribe_quotes(
+        &self,
+        tickers: Option<&[String]>,
+        capacity: usize,
+    ) -> ClientResult<mpsc::Receiver<Arc<StockQuote>>> {
+        let Some(broadcast) = self.broadcast.as_ref() else {
+            return Err(not_started_with_broadcast());
+        };
+        Ok(match tickers {
+            Some(tickers) => broadcast.subscribe_tickers(tickers, capacity),
+            None => broadcast.subscribe(capacity),
+        })
+    }
+
+    /// Добавляет группу тикеров или заменяет группу с тем же названием
+    /// и подписывается на тикеры, которых не было в других группах
+    pub fn set_group<I, H>(&self, name: &str, tickers: I, handler: H) -> ClientResult<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        H: QuoteHandler + 'static,
+    {
+        let Some(groups) = self.groups.as_ref() else {
+            return Err(not_started_with_groups());
+        };
+        self.apply_groups_change(groups.set_group(name, tickers, handler))
+    }
+
+    /// Удаляет группу тикеров и отписывается от тикеров, которые не нужны другим группам
+    pub fn remove_group(&self, name: &str) -> ClientResult<()> {
+        let Some(groups) = self.groups.as_ref() else {
+            return Err(not_started_with_groups());
+        };
+        self.apply_groups_change(groups.remove_group(name))
+    }
+
+    /// Тикеры подписки, подтвержденные сервером. Шаблоны в них раскрыты
+    pub fn subscribed_tickers(&self) -> Vec<String> {
+        self.acked_tickers.lock().unwrap().clone()
+    }
+
+    /// Текущее состояние сессии для `QuotesClient::resume` после перезапуска процесса
+    pub fn save_state(&self) -> SessionState {
+        SessionState {
+            server_addr: self.current_session.server_addr().to_string(),
+            recv_quote_port: self.recv_quote_port,
+            tickers: self.subscribed_tickers(),
+            session: self.current_session.session(),
+            positions: self.loss.positions(),
+        }
+    }
+
+    /// Заменяет подписку тикерами tickers: подписывается только на тикеры, которых
+    /// нет в подписке, и отписывается только от лишних. Тикеры сравниваются по названиям,
+    /// поэтому шаблон всегда считается новым тикером. Возвращает изменение подписки
+    pub fn set_tickers(&self, tickers: Vec<String>) -> ClientResult<GroupsChange> {
+        let change = GroupsChange::between(&self.subscribed_tickers(), &tickers);
+        self.apply_groups_change(change.clone())?;
+        Ok(change)
+    }
+
+    fn apply_groups_change(&self, change: GroupsChange) -> ClientResult<()> {
+        if !change.added.is_empty() {
+            self.tx
+                .send(ClientCmd::subscribe(change.added))
+                .map_err(|_| ClientError::Stopped)?;
+        }
+        if !change.removed.is_empty() {
+            self.tx
+                .send(ClientCmd::unsubscribe(change.removed))
+                .map_err(|_| ClientError::Stopped)?;
+        }
+        Ok(())
+    }
 }
 
 /// Клиент приёма котировок
 #[derive(Debug)]
 pub struct QuotesClient {
     server_addr: SocketAddr,
+    servers: Vec<SocketAddr>,
+    recv_quote_port: u16,
+    stripe_ports: Vec<u16>,
+    tickers: Vec<String>,
+    token: Option<String>,
+    ping_timeouts: PingTimeouts,
+    tls: Option<(Arc<rustls::ClientConfig>, String)>,
+    session: Option<String>,
+    filter: Option<DeltaFilter>,
+    bars: bool,
+    history: u32,
+    reconnect: Option<ReconnectPolicy>,
+    poll_millis: u64,
+    busy_poll: bool,
+    recv_buffer_bytes: Option<usize>,
+    bind_ip: IpAddr,
+    connect_timeout: Duration,
+    no_data_timeout: Option<Duration>,
+    local_filter: Option<QuoteFilter>,
+    conflation_millis: Option<u64>,
+    validation: Option<QuoteValidation>,
+    dedup_window: Option<usize>,
+    handler_queue: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    recovery: RecoveryPolicy,
+    middleware: MiddlewareChain,
+    recorder: Option<Arc<DatagramRecorder>>,
+    restored_positions: Vec<SeqPosition>,
+    paused: Arc<AtomicBool>,
+    state: Arc<StateWatch>,
+    acked_tickers: Arc<Mutex<Vec<String>>>,
+}
+
+/// Построитель клиента котировок с параметрами соединения, проверки связи и опроса.
+/// Остальные настройки задаются методами with_* собранного клиента
+#[derive(Debug, Clone)]
+pub struct QuotesClientBuilder {
+    server_addr: String,
     recv_quote_port: u16,
     tickers: Vec<String>,
+    ping_timeouts: PingTimeouts,
+    poll_millis: u64,
+    busy_poll: bool,
+    recv_buffer_bytes: Option<usize>,
+    bind_ip: Option<IpAddr>,
+    connect_timeout_millis: u64,
+    no_data_millis: Option<u64>,
+    local_filter: Option<QuoteFilter>,
+    conflation_millis: Option<u64>,
+    validation: Option<QuoteValidation>,
+    dedup_window: Option<usize>,
+    handler_queue: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    session_state: Option<SessionState>,
+}
+
+impl QuotesClientBuilder {
+    /// server_addr - ip-адрес или имя хоста сервера с портом для подключения по tcp,
+    /// recv_quote_port - порт для приема котировок, 0 - любой свободный порт,
+    /// tickers - тикеры подписки или шаблоны тикеров, например "BANK_*"
+    pub fn new<I>(server_addr: &str, recv_quote_port: u16, tickers: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        Self {
+            server_addr: server_addr.to_string(),
+            recv_quote_port,
+            tickers: tickers
+                .into_iter()
+                .map(|ticker| ticker.as_ref().to_string())
+                .collect(),
+            ping_timeouts: PingTimeouts::default(),
+            poll_millis: DEFAULT_POLL_MILLIS,
+            busy_poll: false,
+            recv_buffer_bytes: None,
+            bind_ip: None,
+            connect_timeout_millis: DEFAULT_CONNECT_TIMEOUT_MILLIS,
+            no_data_millis: None,
+            local_filter: None,
+            conflation_millis: None,
+            validation: None,
+            dedup_window: None,
+            handler_queue: None,
+            overflow_policy: OverflowPolicy::default(),
+            session_state: None,
+        }
+    }
+
+    /// Построитель клиента, который продолжает сессию, сохраненную через
+    /// `ClientControl::save_state`: сервер, порт приема и тикеры берутся из state.
+    /// Собранный клиент продолжает сессию, как после `QuotesClient::with_session_state`
+    pub fn resume(state: &SessionState) -> Self {
+        let mut builder = Self::new(&state.server_addr, state.recv_quote_port, &state.tickers);
+        builder.session_state = Some(state.clone());
+        builder
+    }
+
+    /// Период отправки пинга серверу
+    pub fn with_ping_period_millis(mut self, millis: u64) -> Self {
+        self.ping_timeouts.ping_period_millis = millis;
+        self
+    }
+
+    /// Время ожидания понга, после которого сервер считается недоступным
+    pub fn with_wait_pong_millis(mut self, millis: u64) -> Self {
+        self.ping_timeouts.wait_pong_millis = millis;
+        self
+    }
+
+    /// Период опроса сокетов приема котировок
+    pub fn with_poll_millis(mut self, millis: u64) -> Self {
+        self.poll_millis = millis;
+        self
+    }
+
+    /// Опрашивать сокеты приема непрерывно, без сна между опросами. Котировка
+    /// передается обработчику сразу после приема, но поток приема занимает ядро целиком,
+    /// поэтому его стоит закрепить за отдельным ядром средствами системы
+    pub fn with_busy_poll(mut self) -> Self {
+        self.busy_poll = true;
+        self
+    }
+
+    /// Размер приемного буфера сокетов приема котировок (SO_RCVBUF).
+    /// Система может округлить или ограничить размер
+    pub fn with_recv_buffer_bytes(mut self, bytes: usize) -> Self {
+        self.recv_buffer_bytes = Some(bytes);
+        self
+    }
+
+    /// Адрес, на котором открываются сокеты приема котировок. По умолчанию - loopback
+    /// той же версии IP, что и адрес сервера
+    pub fn with_bind_ip(mut self, ip: IpAddr) -> Self {
+        self.bind_ip = Some(ip);
+        self
+    }
+
+    /// Время ожидания подключения к серверу по tcp
+    pub fn with_connect_timeout_millis(mut self, millis: u64) -> Self {
+        self.connect_timeout_millis = millis;
+        self
+    }
+
+    /// Если при живом соединении данные не приходят дольше millis, отправить серверу
+    /// запрос тикеров заново: сервер мог молча потерять подписку. На паузе не действует
+    pub fn with_no_data_timeout_millis(mut self, millis: u64) -> Self {
+        self.no_data_millis = Some(millis);
+        self
+    }
+
+    /// Фильтр котировок на стороне клиента, который применяется до передачи котировок
+    /// обработчику. В отличие от фильтра на сервере не уменьшает трафик
+    pub fn with_local_filter(mut self, filter: QuoteFilter) -> Self {
+        self.local_filter = Some(filter);
+        self
+    }
+
+    /// Передавать обработчику не каждую котировку, а последнюю котировку каждого тикера
+    /// раз в millis. Точность ограничена периодом опроса сокетов
+    pub fn with_conflation_millis(mut self, millis: u64) -> Self {
+        self.conflation_millis = Some(millis);
+        self
+    }
+
+    /// Проверять живые котировки на повторы номеров, нарушение порядка временных меток
+    /// и устаревание. Котировки истории не проверяются
+    pub fn with_validation(mut self, validation: QuoteValidation) -> Self {
+        self.validation = Some(validation);
+        self
+    }
+
+    /// Не передавать обработчику повторно доставленные живые котировки. Повтор ищется
+    /// среди последних window котировок тикера по номеру последовательности,
+    /// а у котировок без номера - по временной метке
+    pub fn with_dedup(mut self, window: usize) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Передавать вызовы обработчику через очередь на capacity вызовов в отдельном потоке,
+    /// чтобы медленный обработчик не задерживал прием котировок
+    pub fn with_handler_queue(mut self, capacity: usize) -> Self {
+        self.handler_queue = Some(capacity);
+        self
+    }
+
+    /// Что делать при переполнении очереди обработчика и буфера котировок.
+    /// По умолчанию вытесняются самые старые. `OverflowPolicy::Block` не допускается:
+    /// ожидание места остановило бы поток приема вместе с пингом
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Собирает клиент, проверяя параметры
+    pub fn build(self) -> ClientResult<QuotesClient> {
+        let server_addr = resolve_server_addr(&self.server_addr)?;
+        let config_error = |msg: &str| Err(ClientError::Config(msg.to_string()));
+        if self.ping_timeouts.ping_period_millis == 0 || self.ping_timeouts.wait_pong_millis == 0 {
+            return config_error("ping period and pong timeout must be positive");
+        }
+        if self.poll_millis == 0 {
+            return config_error("poll period must be positive");
+        }
+        if self.recv_buffer_bytes == Some(0) {
+            return config_error("receive buffer size must be positive");
+        }
+        if self.connect_timeout_millis == 0 {
+            return config_error("connect timeout must be positive");
+        }
+        if self.no_data_millis == Some(0) {
+            return config_error("no data timeout must be positive");
+        }
+        if self.conflation_millis == Some(0) {
+            return config_error("conflation interval must be positive");
+        }
+        if self.handler_queue == Some(0) {
+            return config_error("handler queue capacity must be positive");
+        }
+        if self.overflow_policy == OverflowPolicy::Block {
+            return config_error("block overflow policy would stall ping on the receive thread");
+        }
+        if self.dedup_window == Some(0) {
+            return config_error("dedup window must be positive");
+        }
+        let client = QuotesClient {
+            server_addr,
+            servers: vec![server_addr],
+            recv_quote_port: self.recv_quote_port,
+            stripe_ports: Vec::new(),
+            tickers: self.tickers,
+            token: None,
+            ping_timeouts: self.ping_timeouts,
+            tls: None,
+            session: None,
+            filter: None,
+            bars: false,
+            history: 0,
+            reconnect: None,
+            poll_millis: self.poll_millis,
+            busy_poll: self.busy_poll,
+            recv_buffer_bytes: self.recv_buffer_bytes,
+            bind_ip: self.bind_ip.unwrap_or_else(|| loopback_for(server_addr)),
+            connect_timeout: Duration::from_millis(self.connect_timeout_millis),
+            no_data_timeout: self.no_data_millis.map(Duration::from_millis),
+            local_filter: self.local_filter,
+            conflation_millis: self.conflation_millis,
+            validation: self.validation,
+            dedup_window: self.dedup_window,
+            handler_queue: self.handler_queue,
+            overflow_policy: self.overflow_policy,
+            recovery: RecoveryPolicy::default(),
+            middleware: MiddlewareChain::default(),
+            recorder: None,
+            restored_positions: Vec::new(),
+            paused: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(StateWatch::default()),
+            acked_tickers: Arc::new(Mutex::new(Vec::new())),
+        };
+        Ok(match self.session_state.as_ref() {
+            Some(state) => client.with_session_state(state),
+            None => client,
+        })
+    }
+}
+
+struct Subscribed {
+    unknown_tickers: Vec<String>,
+    tickers: Vec<String>,
+    multicast_group: Option<SocketAddr>,
+    session: String,
+    resumed: bool,
 }
 
 impl Display for QuotesClient {
@@ -167,6 +941,9 @@ impl Display for QuotesClient {
             "serv addr: {}, receive quotes port: {}",
             self.server_addr, self.recv_quote_port
         )?;
+        if !self.stripe_ports.is_empty() {
+            write!(f, ", stripe ports: {:?}", self.stripe_ports)?;
+        }
         writeln!(f, "Tickers:")?;
         for ticker in self.tickers.iter() {
             writeln!(f, "{ticker}")?;
@@ -177,125 +954,1755 @@ impl Display for QuotesClient {
 
 impl QuotesClient {
     /// Создаёт новый клиент котировок:
-    /// server_addr - ip-алрес сервера для подключения по tcp
-    /// recv_quote_port - Порт для приема котировок
+    /// server_addr - ip-адрес или имя хоста сервера с портом для подключения по tcp
+    /// recv_quote_port - Порт для приема котировок, 0 - любой свободный порт
     /// tickers_path - Путь к файлу с котировками в формате:
     ///
     /// TICKER1
     /// TICKER2
-    pub fn new(server_addr: &str, recv_quote_port: u16, tickers_path: &str) -> Result<Self> {
-        let file = std::fs::File::open(tickers_path)?;
-        let read_buf = BufReader::new(file);
-        let mut tickers = Vec::new();
-        for line in read_buf.lines() {
-            tickers.push(line?);
-        }
+    /// BANK_*
+    ///
+    /// Шаблоны раскрывает сервер, подтвержденные тикеры - в `ClientControl::acked_tickers`
+    pub fn new(server_addr: &str, recv_quote_port: u16, tickers_path: &str) -> ClientResult<Self> {
+        Self::with_tickers(server_addr, recv_quote_port, read_tickers(tickers_path)?)
+    }
 
-        Ok(Self {
-            server_addr: server_addr.parse()?,
-            recv_quote_port,
-            tickers,
-        })
+    /// Создаёт новый клиент котировок со списком тикеров в памяти,
+    /// например `vec!["AMD".to_string()]` или `&["AMD", "INT"]`
+    pub fn with_tickers<I>(
+        server_addr: &str,
+        recv_quote_port: u16,
+        tickers: I,
+    ) -> ClientResult<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        QuotesClientBuilder::new(server_addr, recv_quote_port, tickers).build()
     }
 
-    fn recv_quotes(sock: &UdpSocket, ping_control: &mut Option<PingControl>) -> Result<()> {
-        let mut recv_buf = [0u8; MAX_SIZE_DATAGRAM];
-        let (pack_len, server_addr) = match sock.recv_from(&mut recv_buf) {
-            Ok((len, addr)) => (len, addr),
-            Err(e) => match e.kind() {
-                ErrorKind::WouldBlock => return Ok(()),
-                _ => bail!("{e}"),
-            },
-        };
+    /// Фильтр котировок на стороне сервера: присылать котировку, только если цена
+    /// изменилась достаточно с последней присланной котировки или объем, накопленный
+    /// с нее, достаточно велик
+    pub fn with_delta_filter(mut self, filter: DeltaFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
 
-        if let Some(control) = ping_control.as_ref() {
-            if control.thread_handle.is_finished() {
-                bail!("Server at address {server_addr} doesn't response");
-            }
-        } else {
-            let control = match PingPong::new(server_addr).start() {
-                Ok(val) => val,
-                Err(e) => {
-                    bail!("Can't start ping pong logic: {e}");
-                }
-            };
-            *ping_control = Some(control);
-        }
+    /// Получать свечи OHLCV вместо котировок. Интервал свечей задается на сервере
+    pub fn with_bars(mut self) -> Self {
+        self.bars = true;
+        self
+    }
 
-        let msg = postcard::from_bytes::<Message>(&recv_buf[..pack_len])?;
-        let quotes = match msg {
-            Message::Quote(quotes) => quotes,
-            _ => {
-                bail!("Wrong response");
-            }
-        };
-        println!("{}", quotes.quote);
-        Ok(())
+    /// Перед приемом котировок запросить у сервера last_n последних котировок каждого тикера
+    /// и передать их обработчику как котировки истории. При изменении подписки
+    /// история запрашивается только для новых тикеров
+    pub fn with_history(mut self, last_n: u32) -> Self {
+        self.history = last_n;
+        self
     }
 
-    /// Запуск потока приёма котировок
-    pub fn start_receive_quotes(self) -> Result<ClientControl> {
-        let (tx, rx) = mpsc::channel();
-        let udp_addr = SocketAddr::from(([127, 0, 0, 1], self.recv_quote_port));
-        let udp_sock = UdpSocket::bind(udp_addr)?;
-        log::info!("Start receive quotes at addr: {udp_addr}");
-        udp_sock.set_nonblocking(true)?;
+    /// Записывать все принятые датаграммы со временем приема в файл path.
+    /// Запись воспроизводится без сервера через `record::replay`
+    pub fn with_recording(mut self, path: &Path) -> ClientResult<Self> {
+        self.recorder = Some(Arc::new(DatagramRecorder::create(path)?));
+        Ok(self)
+    }
 
-        let mut stream = TcpStream::connect(self.server_addr)?;
-        let ticker_req = Message::Tickers(TickerReqMessage {
-            port: self.recv_quote_port,
-            tickers: self.tickers.clone(),
-        });
+    /// Дополнительные порты приема котировок. Сервер распределяет котировки
+    /// между основным и дополнительными портами по кругу, каждый порт обслуживается своим потоком.
+    /// Порт 0 заменяется свободным портом, назначенным системой
+    pub fn with_stripe_ports(mut self, stripe_ports: Vec<u16>) -> Self {
+        self.stripe_ports = stripe_ports;
+        self
+    }
 
-        log::debug!("Request tickers: {:?}", ticker_req);
+    /// Токен доступа, который передается серверу вместе с запросом котировок
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
 
-        let bin_req = pack_message_with_len(&ticker_req)?;
-        log::debug!("Pack message len: {}", bin_req.len());
-        stream.write_all(&bin_req)?;
+    /// Таймауты проверки связи с сервером. Период пинга должен быть меньше
+    /// времени ожидания пинга на сервере
+    pub fn with_ping_timeouts(mut self, ping_timeouts: PingTimeouts) -> Self {
+        self.ping_timeouts = ping_timeouts;
+        self
+    }
 
-        let handle = std::thread::spawn(move || {
-            let mut ping_control: Option<PingControl> = None;
-            let mut timer = Timer::default();
-            timer.add_event(WAIT_QUOTES_EVENT, WAIT_QUOTES_MILLIS);
-            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
-            loop {
-                timer.sleep();
-                if timer.is_expired_event(WAIT_CMD_EVENT)? {
-                    timer.reset_event(WAIT_CMD_EVENT)?;
-                    if is_stop_cmd(&rx) {
-                        log::debug!("Stop cmd");
-                        break;
-                    }
-                }
+    /// Защищать управляющее соединение TLS. ca_path - сертификаты удостоверяющих центров
+    /// в формате PEM, server_name - имя или ip-адрес сервера в его сертификате
+    pub fn with_tls(mut self, ca_path: &str, server_name: &str) -> ClientResult<Self> {
+        let config = tls::client_config(ca_path).map_err(ClientError::Tls)?;
+        self.tls = Some((config, server_name.to_string()));
+        Ok(self)
+    }
 
-                if timer.is_expired_event(WAIT_QUOTES_EVENT)? {
-                    timer.reset_event(WAIT_QUOTES_EVENT)?;
-                    if let Err(e) = Self::recv_quotes(&udp_sock, &mut ping_control) {
-                        log::error!("Can't receive quotes: {e}");
-                        break;
-                    }
-                }
-            }
+    /// Токен сессии прошлого подключения. Если сервер еще хранит подписку этой сессии,
+    /// она восстанавливается без повторного согласования тикеров
+    pub fn with_session(mut self, session: String) -> Self {
+        self.session = Some(session);
+        self
+    }
 
-            let res = if let Some(control) = ping_control {
-                control.tx.send(ClientCmd::Stop)?;
-                match control.thread_handle.join() {
-                    Ok(res) => res,
-                    Err(_) => {
-                        bail!("Can't join thread");
-                    }
-                }
-            } else {
-                Ok(())
-            };
+    /// Продолжает сессию, сохраненную через `ClientControl::save_state`: передает серверу
+    /// токен сессии и продолжает статистику потерь котировок. Если сервер еще хранит
+    /// подписку сессии, тикеры клиента заменяются ею
+    pub fn with_session_state(mut self, state: &SessionState) -> Self {
+        self.session = Some(state.session.clone());
+        self.restored_positions = state.positions.clone();
+        self
+    }
 
-            log::info!("Stop receive quotes");
-            res
-        });
+    /// Создает клиент, который продолжает сессию, сохраненную через
+    /// `ClientControl::save_state`, с тем же сервером, портом приема и подпиской.
+    /// Остальные настройки задаются методами with_* или через `QuotesClientBuilder::resume`
+    pub fn resume(state: &SessionState) -> ClientResult<Self> {
+        QuotesClientBuilder::resume(state).build()
+    }
 
-        Ok(ClientControl {
-            thread_handle: handle,
-            tx,
-        })
+    /// Переподключаться к серверу, если управляющее соединение разорвано или сервер
+    /// не отвечает на пинг. Подписка восстанавливается по токену сессии
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Повторять прием после временных ошибок сокетов, например ECONNREFUSED на udp,
+    /// вместо переподключения или остановки клиента
+    pub fn with_recovery(mut self, policy: RecoveryPolicy) -> Self {
+        self.recovery = policy;
+        self
+    }
+
+    /// Добавляет промежуточный обработчик принятых датаграмм. Обработчики вызываются
+    /// в порядке добавления
+    pub fn with_middleware<M: MessageMiddleware + 'static>(self, middleware: M) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Резервные серверы. При потере связи клиент переключается на следующий сервер
+    /// списка, начиная с основного, и восстанавливает подписку. Если переподключение
+    /// не задано, включается переподключение по умолчанию
+    pub fn with_backup_servers(mut self, backup_servers: &[&str]) -> ClientResult<Self> {
+        self.servers.truncate(1);
+        for addr in backup_servers {
+            self.servers.push(resolve_server_addr(addr)?);
+        }
+        self.reconnect.get_or_insert_with(ReconnectPolicy::default);
+        Ok(self)
+    }
+
+    fn acked_tickers(&self) -> Vec<String> {
+        self.acked_tickers.lock().unwrap().clone()
+    }
+
+    fn recv_message(stream: &mut ControlStream) -> ClientResult<Message> {
+        stream
+            .tcp()
+            .set_read_timeout(Some(Duration::from_millis(WAIT_SUBSCRIBED_MILLIS)))?;
+        read_message_with_len(stream).map_err(|e| match e.downcast::<std::io::Error>() {
+            Ok(e) => ClientError::Io(e),
+            Err(e) => ClientError::Decode(e),
+        })
+    }
+
+    fn send_message(stream: &mut ControlStream, msg: &Message) -> ClientResult<()> {
+        stream.write_all(&pack_message_with_len(msg).map_err(ClientError::Encode)?)?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn recv_subscribed(stream: &mut ControlStream) -> ClientResult<Subscribed> {
+        match Self::recv_message(stream)? {
+            Message::Subscribed {
+                unknown_tickers,
+                tickers,
+                multicast_group,
+                session,
+                resumed,
+            } => Ok(Subscribed {
+                unknown_tickers,
+                tickers,
+                multicast_group,
+                session,
+                resumed,
+            }),
+            Message::Error { code } => Err(ClientError::Rejected(code)),
+            msg => Err(ClientError::UnexpectedMessage(format!("{msg:?}"))),
+        }
+    }
+
+    fn request_history(
+        stream: &mut ControlStream,
+        ticker: &str,
+        last_n: u32,
+    ) -> ClientResult<Vec<StockQuote>> {
+        let req = Message::HistoryReq {
+            ticker: ticker.to_string(),
+            last_n,
+        };
+        Self::send_message(stream, &req)?;
+
+        match Self::recv_message(stream)? {
+            Message::History { quotes, .. } => Ok(quotes),
+            Message::Error { code } => Err(ClientError::Rejected(code)),
+            msg => Err(ClientError::UnexpectedMessage(format!("{msg:?}"))),
+        }
+    }
+
+    fn backfill(
+        stream: &mut ControlStream,
+        tickers: &[String],
+        last_n: u32,
+        handler: &SharedHandler,
+    ) -> ClientResult<()> {
+        for ticker in tickers.iter() {
+            let quotes = Self::request_history(stream, ticker, last_n)?;
+            log::info!("Received {} history quotes of {ticker}", quotes.len());
+            let mut handler = handler.lock().unwrap();
+            for quote in quotes {
+                handler.on_replay(quote);
+            }
+        }
+        Ok(())
+    }
+
+    fn change_subscription(
+        stream: &mut ControlStream,
+        mode: SubscriptionMode,
+        tickers: Vec<String>,
+        replies: &mut ControlReplies,
+        backfill: bool,
+    ) -> ClientResult<()> {
+        log::debug!("Change subscription: {mode:?} {tickers:?}");
+        Self::send_message(stream, &Message::ChangeSubscription { mode, tickers })?;
+        replies.change_sent(backfill);
+        Ok(())
+    }
+
+    fn poll_replies(stream: &mut ControlStream, replies: &mut ControlReplies) -> ClientResult<()> {
+        stream.tcp().set_nonblocking(true)?;
+        let read = replies.read_available(stream);
+        stream.tcp().set_nonblocking(false)?;
+        read
+    }
+
+    fn handle_reply(
+        &self,
+        msg: Message,
+        stream: &mut ControlStream,
+        replies: &mut ControlReplies,
+        handler: &SharedHandler,
+    ) -> ClientResult<()> {
+        match msg {
+            Message::SubscriptionChanged {
+                mode,
+                unknown_tickers,
+                tickers,
+            } => {
+                if !unknown_tickers.is_empty() {
+                    log::warn!("Unknown tickers are ignored by server: {unknown_tickers:?}");
+                }
+                let added: Vec<String> = {
+                    let mut acked = self.acked_tickers.lock().unwrap();
+                    let added = tickers
+                        .iter()
+                        .filter(|ticker| !acked.contains(ticker))
+                        .cloned()
+                        .collect();
+                    mode.apply(&mut acked, &tickers);
+                    added
+                };
+                log::info!("Subscription is changed: {mode:?} {tickers:?}");
+                let backfill = replies.change_acked();
+                if backfill && self.history > 0 && mode != SubscriptionMode::Remove {
+                    for ticker in added {
+                        let req = Message::HistoryReq {
+                            ticker,
+                            last_n: self.history,
+                        };
+                        Self::send_message(stream, &req)?;
+                        replies.history_sent();
+                    }
+                }
+            }
+            Message::History { ticker, quotes } => {
+                replies.history_received();
+                log::info!("Received {} history quotes of {ticker}", quotes.len());
+                let mut handler = handler.lock().unwrap();
+                for quote in quotes {
+                    handler.on_replay(quote);
+                }
+            }
+            Message::Error { code } => return Err(ClientError::Rejected(code)),
+            msg => return Err(ClientError::UnexpectedMessage(format!("{msg:?}"))),
+        }
+        Ok(())
+    }
+
+    fn recv_replies(
+        &self,
+        stream: &mut ControlStream,
+        replies: &mut ControlReplies,
+        handler: &SharedHandler,
+    ) -> ClientResult<()> {
+        Self::poll_replies(stream, replies)?;
+        while let Some(msg) = replies.next_message()? {
+            self.handle_reply(msg, stream, replies, handler)?;
+        }
+        Ok(())
+    }
+
+    fn request_pause(stream: &mut ControlStream, paused: bool) -> ClientResult<()> {
+        let msg = if paused {
+            Message::Pause
+        } else {
+            Message::Resume
+        };
+        Self::send_message(stream, &msg)?;
+        log::info!("Receiving quotes is paused: {paused}");
+        Ok(())
+    }
+
+    fn unsubscribe(stream: &mut ControlStream) -> ClientResult<()> {
+        Self::send_message(stream, &Message::Unsubscribe)?;
+        stream.tcp().shutdown(std::net::Shutdown::Both)?;
+        log::info!("Unsubscribed from server");
+        Ok(())
+    }
+
+    fn connect(
+        &self,
+        server_addr: SocketAddr,
+        handler: &SharedHandler,
+    ) -> ClientResult<(ControlStream, Subscribed)> {
+        let tls = self
+            .tls
+            .as_ref()
+            .map(|(config, server_name)| (config, server_name.as_str()));
+        let conn = match TcpStream::connect_timeout(&server_addr, self.connect_timeout) {
+            Ok(val) => val,
+            Err(e) if e.kind() == ErrorKind::TimedOut => {
+                return Err(ClientError::ConnectTimeout {
+                    addr: server_addr,
+                    millis: self.connect_timeout.as_millis() as u64,
+                });
+            }
+            Err(source) => {
+                return Err(ClientError::Connect {
+                    addr: server_addr,
+                    source,
+                });
+            }
+        };
+        let mut stream = ControlStream::connect(conn, tls).map_err(ClientError::Tls)?;
+        let ticker_req = Message::Tickers(TickerReqMessage {
+            port: self.recv_quote_port,
+            stripe_ports: self.stripe_ports.clone(),
+            tickers: self.tickers.clone(),
+            token: self.token.clone(),
+            session: self.session.clone(),
+            filter: self.filter,
+            bars: self.bars,
+        });
+
+        log::debug!("Request tickers: {:?}", ticker_req);
+
+        Self::send_message(&mut stream, &ticker_req)?;
+
+        let subscribed = Self::recv_subscribed(&mut stream)?;
+        if subscribed.resumed {
+            log::info!("Subscription of session {} is resumed", subscribed.session);
+        } else if subscribed.tickers.is_empty() {
+            return Err(ClientError::Subscribe(format!(
+                "server doesn't know any of requested tickers: {:?}",
+                subscribed.unknown_tickers
+            )));
+        }
+        if !subscribed.unknown_tickers.is_empty() {
+            log::warn!(
+                "Unknown tickers are ignored by server: {:?}",
+                subscribed.unknown_tickers
+            );
+        }
+        if self.tickers.iter().any(|ticker| is_ticker_pattern(ticker)) {
+            log::info!("Subscribed tickers: {:?}", subscribed.tickers);
+        }
+        *self.acked_tickers.lock().unwrap() = subscribed.tickers.clone();
+        if self.history > 0 {
+            Self::backfill(&mut stream, &subscribed.tickers, self.history, handler)?;
+        }
+        if self.paused.load(Ordering::Relaxed) {
+            Self::request_pause(&mut stream, true)?;
+        }
+
+        Ok((stream, subscribed))
+    }
+
+    fn connect_any(
+        &mut self,
+        handler: &SharedHandler,
+    ) -> ClientResult<(ControlStream, Subscribed)> {
+        let mut last_error = ClientError::Config("no servers to connect".to_string());
+        for server_addr in self.servers.clone() {
+            match self.connect(server_addr, handler) {
+                Ok(val) => {
+                    self.server_addr = server_addr;
+                    return Ok(val);
+                }
+                Err(e) => {
+                    log::warn!("Can't connect to server {server_addr}: {e}");
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    fn is_connection_closed(stream: &ControlStream) -> bool {
+        let tcp = stream.tcp();
+        if tcp.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let mut buf = [0u8; 1];
+        let closed = match tcp.peek(&mut buf) {
+            Ok(len) => len == 0,
+            Err(e) => e.kind() != ErrorKind::WouldBlock,
+        };
+        closed || tcp.set_nonblocking(false).is_err()
+    }
+
+    fn wait_reconnect(&mut self, delay: Duration, rx: &mpsc::Receiver<ClientCmd>) -> bool {
+        let deadline = Instant::now() + delay;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(HANDLE_CMD_PERIOD_MILLIS).min(left));
+            match rx.try_recv() {
+                Ok(ClientCmd::Stop) => return false,
+                Ok(ClientCmd::Subscribe { mode, tickers }) => {
+                    mode.apply(&mut self.tickers, &tickers)
+                }
+                Ok(ClientCmd::Pause) => self.paused.store(true, Ordering::Relaxed),
+                Ok(ClientCmd::Resume) => self.paused.store(false, Ordering::Relaxed),
+                Err(TryRecvError::Disconnected) => {
+                    log::warn!("Parent thread is died");
+                    return false;
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+    }
+
+    fn reconnect(
+        &mut self,
+        policy: ReconnectPolicy,
+        handler: &SharedHandler,
+        rx: &mpsc::Receiver<ClientCmd>,
+    ) -> ClientResult<Option<(ControlStream, Subscribed)>> {
+        let servers = self.servers.clone();
+        let next_idx = servers
+            .iter()
+            .position(|addr| *addr == self.server_addr)
+            .map_or(0, |idx| idx + 1);
+        let mut attempt = 0;
+        loop {
+            if policy
+                .max_retries
+                .is_some_and(|max_retries| attempt >= max_retries)
+            {
+                return Err(ClientError::ReconnectExhausted(attempt));
+            }
+            let server_addr = servers[(next_idx + attempt as usize) % servers.len()];
+            let delay = policy.delay(attempt / servers.len() as u32);
+            attempt += 1;
+            log::info!(
+                "Reconnect to server {server_addr} in {} ms, attempt {attempt}",
+                delay.as_millis()
+            );
+            if !self.wait_reconnect(delay, rx) {
+                return Ok(None);
+            }
+            match self.connect(server_addr, handler) {
+                Ok(val) => {
+                    self.server_addr = server_addr;
+                    return Ok(Some(val));
+                }
+                Err(e) => log::warn!("Can't reconnect to server {server_addr}: {e}"),
+            }
+        }
+    }
+
+    fn join_multicast(group: SocketAddr, recv_context: &RecvContext) -> ClientResult<UdpSocket> {
+        let multicast_error = |source| ClientError::Multicast { group, source };
+        let IpAddr::V4(group_ip) = group.ip() else {
+            return Err(multicast_error(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "only IPv4 multicast groups are supported",
+            )));
+        };
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket
+            .bind(&SocketAddr::from(([0, 0, 0, 0], group.port())).into())
+            .map_err(multicast_error)?;
+        let socket = UdpSocket::from(socket);
+        socket
+            .join_multicast_v4(&group_ip, &Ipv4Addr::UNSPECIFIED)
+            .map_err(multicast_error)?;
+        recv_context.set_recv_buffer(&socket)?;
+        socket.set_nonblocking(true)?;
+        log::info!("Join multicast group {group}");
+        Ok(socket)
+    }
+
+    fn recv_datagram(
+        sock: &UdpSocket,
+        recv_context: &RecvContext,
+    ) -> ClientResult<Option<Datagram>> {
+        let mut recv_buf = [0u8; MAX_SIZE_DATAGRAM];
+        let (pack_len, server_addr) = match sock.recv_from(&mut recv_buf) {
+            Ok((len, addr)) => (len, addr),
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock => return Ok(None),
+                _ => return Err(e.into()),
+            },
+        };
+
+        recv_context.metrics.datagram(pack_len);
+        if let Some(recorder) = recv_context.recorder.as_ref() {
+            recorder.record(&recv_buf[..pack_len]);
+        }
+        let msg = match postcard::from_bytes::<Message>(&recv_buf[..pack_len]) {
+            Ok(msg) => msg,
+            Err(e) => {
+                recv_context.metrics.decode_error();
+                log::warn!("Skip datagram from {server_addr}: {e}");
+                return Ok(None);
+            }
+        };
+        let Some(msg) = recv_context.middleware.apply(msg) else {
+            return Ok(None);
+        };
+        match msg {
+            Message::Quote(quotes) => Ok(Some(Datagram::Quote(quotes, server_addr))),
+            Message::ReplayQuote(quotes) => Ok(Some(Datagram::Replay(quotes.quote, server_addr))),
+            Message::Bar(bars) => Ok(Some(Datagram::Bar(bars.bar, server_addr))),
+            Message::Pong => Ok(Some(Datagram::Pong)),
+            Message::Shutdown => Ok(Some(Datagram::Shutdown)),
+            msg => Err(ClientError::UnexpectedMessage(format!("{msg:?}"))),
+        }
+    }
+
+    /// Пинг всегда идет через сокет приема udp_sock. При многоадресной рассылке
+    /// понг тоже приходит в udp_sock, а не в сокет группы, поэтому читается отсюда
+    fn check_ping(
+        udp_sock: &UdpSocket,
+        ping_pong: &mut Option<PingPong>,
+        timer: &mut Timer,
+        recv_context: &RecvContext,
+        multicast: bool,
+    ) -> ClientResult<()> {
+        let Some(ping_pong) = ping_pong.as_mut() else {
+            return Ok(());
+        };
+        ping_pong.check(udp_sock, timer)?;
+        if !multicast {
+            return Ok(());
+        }
+        if let Some(Datagram::Pong) = Self::recv_datagram(udp_sock, recv_context)? {
+            ping_pong.pong(timer)?;
+        }
+        Ok(())
+    }
+
+    fn recv_quotes(
+        &self,
+        sock: &UdpSocket,
+        handler: &SharedHandler,
+        ping_pong: &mut Option<PingPong>,
+        timer: &mut Timer,
+        recv_context: &RecvContext,
+        multicast_tickers: Option<&[String]>,
+    ) -> ClientResult<Received> {
+        let Some(datagram) = Self::recv_datagram(sock, recv_context)? else {
+            return Ok(Received::Nothing);
+        };
+        if let Datagram::Pong = datagram {
+            if let Some(ping_pong) = ping_pong.as_mut() {
+                ping_pong.pong(timer)?;
+            }
+            return Ok(Received::Datagram);
+        }
+        let Some((ticker, server_addr)) = datagram.source() else {
+            log::info!("Server is shutting down");
+            return Ok(Received::Shutdown);
+        };
+
+        if let Some(tickers) = multicast_tickers {
+            if tickers.iter().any(|name| name == ticker) {
+                datagram.deliver(handler, recv_context);
+            }
+            return Ok(Received::Datagram);
+        }
+
+        if ping_pong.is_none() {
+            *ping_pong = Some(PingPong::new(server_addr, self.ping_timeouts, timer));
+        }
+
+        datagram.deliver(handler, recv_context);
+        Ok(Received::Datagram)
+    }
+
+    /// Состояние соединения с сервером. Получатель из `StateWatch::subscribe`,
+    /// созданный до запуска приема, видит и подключение к серверу
+    pub fn state(&self) -> Arc<StateWatch> {
+        self.state.clone()
+    }
+
+    /// Запуск потока приёма котировок с выводом котировок и свечей
+    /// в стандартный вывод через `ConsolePrinter`
+    pub fn start_receive_quotes(self) -> ClientResult<ClientControl> {
+        self.start_receive_handler(ConsolePrinter::default())
+    }
+
+    /// Запуск потока приёма котировок. Принятые котировки, котировки истории
+    /// и свечи передаются handler
+    pub fn start_receive_handler<H: QuoteHandler + 'static>(
+        self,
+        handler: H,
+    ) -> ClientResult<ClientControl> {
+        let state = self.state.clone();
+        self.start_receive(handler).inspect_err(|e| {
+            state.set(ClientState::Dead {
+                reason: e.to_string(),
+            });
+        })
+    }
+
+    fn start_receive<H: QuoteHandler + 'static>(
+        mut self,
+        handler: H,
+    ) -> ClientResult<ClientControl> {
+        let (handler, handler_queue): (Box<dyn QuoteHandler>, _) = match self.handler_queue {
+            Some(capacity) => {
+                let (handler, queue) = QueuedHandler::new(handler, capacity, self.overflow_policy);
+                (Box::new(handler), Some(queue))
+            }
+            None => (Box::new(handler), None),
+        };
+        let handler: Box<dyn QuoteHandler> = match self.local_filter.clone() {
+            Some(filter) => Box::new(FilteredHandler::new(handler, filter)),
+            None => Box::new(handler),
+        };
+        let handler: SharedHandler = match self.conflation_millis {
+            Some(millis) => Arc::new(Mutex::new(ConflatingHandler::new(handler, millis))),
+            None => Arc::new(Mutex::new(handler)),
+        };
+        let (tx, rx) = mpsc::channel();
+        let stats = Arc::new(ThreadStats::default());
+        let loss = Arc::new(LossStats::default());
+        loss.restore(&self.restored_positions);
+        let metrics = Arc::new(ClientMetrics::new());
+        let recv_context = RecvContext {
+            loss: loss.clone(),
+            metrics: metrics.clone(),
+            recorder: self.recorder.clone(),
+            validator: self
+                .validation
+                .map(|val| Arc::new(QuoteValidator::new(val))),
+            dedup: self
+                .dedup_window
+                .map(|window| Arc::new(QuoteDeduplicator::new(window))),
+            paused: self.paused.clone(),
+            state: self.state.clone(),
+            summary: Arc::new(SessionSummary::default()),
+            recovery: self.recovery,
+            busy_poll: self.busy_poll,
+            recv_buffer_bytes: self.recv_buffer_bytes,
+            middleware: self.middleware.clone(),
+        };
+        let udp_sock = recv_context.bind(SocketAddr::new(self.bind_ip, self.recv_quote_port))?;
+        let udp_addr = udp_sock.local_addr()?;
+        self.recv_quote_port = udp_addr.port();
+        log::info!("Start receive quotes at addr: {udp_addr}");
+        let validator = recv_context.validator.clone();
+        let dedup = recv_context.dedup.clone();
+        let state = recv_context.state.clone();
+        let summary = recv_context.summary.clone();
+        let mut stripe_receivers = Vec::new();
+        for port in self.stripe_ports.iter_mut() {
+            let receiver = StripeReceiver::new(
+                SocketAddr::new(self.bind_ip, *port),
+                self.poll_millis,
+                stats.subsystem(STRIPE_SUBSYSTEM),
+                handler.clone(),
+                recv_context.clone(),
+            )?;
+            *port = receiver.sock.local_addr()?.port();
+            stripe_receivers.push(receiver);
+        }
+
+        let (mut stream, subscribed) = self.connect_any(&handler)?;
+        state.set(ClientState::Subscribed);
+        notify(
+            &handler,
+            ConnectionEvent::Connected {
+                server: self.server_addr,
+                failover: false,
+            },
+        );
+        let Subscribed {
+            multicast_group,
+            session,
+            ..
+        } = subscribed;
+        self.session = Some(session.clone());
+        let current_session = Arc::new(CurrentSession::new(self.server_addr, session.clone()));
+
+        let mut multicast_sock = multicast_group
+            .map(|group| Self::join_multicast(group, &recv_context))
+            .transpose()?;
+        let mut multicast_tickers = multicast_group.map(|_| self.acked_tickers());
+
+        let recv_quote_port = self.recv_quote_port;
+        let acked_tickers = self.acked_tickers.clone();
+        let thread_current_session = current_session.clone();
+        let thread_stats = stats.clone();
+        let thread_state = state.clone();
+        let handle = std::thread::spawn(move || {
+            let receive = move || -> ClientResult<String> {
+                let stripe_controls: Vec<StripeReceiverControl> = stripe_receivers
+                    .into_iter()
+                    .map(|receiver| receiver.start())
+                    .collect();
+                let mut ping_pong: Option<PingPong> = None;
+                let mut timer = Timer::with_stats(thread_stats.subsystem(CLIENT_SUBSYSTEM));
+                let mut recovery = Recovery::new(recv_context.recovery);
+                let mut replies = ControlReplies::default();
+                let mut watchdog = self
+                    .no_data_timeout
+                    .map(|timeout| NoDataWatchdog::new(timeout, Instant::now()));
+                timer.add_event(WAIT_QUOTES_EVENT, self.poll_millis);
+                timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+                let mut stop_reason = "stopped".to_string();
+                loop {
+                    recv_context.wait_poll(&mut timer);
+                    let mut connection_error = None;
+                    if timer
+                        .is_expired_event(WAIT_CMD_EVENT)
+                        .map_err(ClientError::Timer)?
+                    {
+                        timer
+                            .reset_event(WAIT_CMD_EVENT)
+                            .map_err(ClientError::Timer)?;
+                        match rx.try_recv() {
+                            Ok(ClientCmd::Stop) => {
+                                log::debug!("Stop cmd");
+                                if let Err(e) = Self::unsubscribe(&mut stream) {
+                                    log::warn!("Can't unsubscribe: {e}");
+                                }
+                                break;
+                            }
+                            Ok(ClientCmd::Subscribe { mode, tickers }) => {
+                                mode.apply(&mut self.tickers, &tickers);
+                                if let Err(e) = Self::change_subscription(
+                                    &mut stream,
+                                    mode,
+                                    tickers,
+                                    &mut replies,
+                                    true,
+                                ) {
+                                    connection_error =
+                                        Some(format!("Can't change subscription: {e}"));
+                                }
+                                if let Some(watchdog) = watchdog.as_mut() {
+                                    watchdog.reset(Instant::now());
+                                }
+                            }
+                            Ok(cmd @ (ClientCmd::Pause | ClientCmd::Resume)) => {
+                                let paused = matches!(cmd, ClientCmd::Pause);
+                                self.paused.store(paused, Ordering::Relaxed);
+                                let request = match multicast_tickers {
+                                    Some(_) => Ok(()),
+                                    None => Self::request_pause(&mut stream, paused),
+                                };
+                                if let Err(e) = request {
+                                    connection_error = Some(format!("Can't pause quotes: {e}"));
+                                }
+                                if let (false, Some(watchdog)) = (paused, watchdog.as_mut()) {
+                                    watchdog.reset(Instant::now());
+                                }
+                            }
+                            Err(TryRecvError::Disconnected) => {
+                                log::warn!("Parent thread is died");
+                                stop_reason = "parent thread is died".to_string();
+                                break;
+                            }
+                            Err(TryRecvError::Empty) => {}
+                        }
+                        let no_data = connection_error.is_none()
+                            && !self.paused.load(Ordering::Relaxed)
+                            && watchdog.as_mut().is_some_and(|watchdog| {
+                                watchdog.is_expired(
+                                    recv_context.metrics.last_data_age(),
+                                    Instant::now(),
+                                )
+                            });
+                        if no_data {
+                            log::warn!(
+                                "No data from server {} for {} ms, resubscribe",
+                                self.server_addr,
+                                self.no_data_timeout.unwrap_or_default().as_millis()
+                            );
+                            recv_context.metrics.resubscribe();
+                            if let Err(e) = Self::change_subscription(
+                                &mut stream,
+                                SubscriptionMode::Replace,
+                                self.tickers.clone(),
+                                &mut replies,
+                                false,
+                            ) {
+                                connection_error = Some(format!("Can't resubscribe: {e}"));
+                            }
+                        }
+                        if connection_error.is_none()
+                            && self.reconnect.is_some()
+                            && Self::is_connection_closed(&stream)
+                        {
+                            connection_error = Some("Server closed connection".to_string());
+                        }
+                    }
+
+                    if connection_error.is_none() && replies.is_waiting() {
+                        match self.recv_replies(&mut stream, &mut replies, &handler) {
+                            Ok(()) if multicast_tickers.is_some() => {
+                                multicast_tickers = Some(self.acked_tickers());
+                            }
+                            Ok(()) => {}
+                            Err(e) => {
+                                connection_error = Some(format!("Can't change subscription: {e}"))
+                            }
+                        }
+                    }
+
+                    let poll_due = timer
+                        .is_expired_event(WAIT_QUOTES_EVENT)
+                        .map_err(ClientError::Timer)?;
+                    if connection_error.is_none() && (poll_due || recv_context.busy_poll) {
+                        if poll_due {
+                            timer
+                                .reset_event(WAIT_QUOTES_EVENT)
+                                .map_err(ClientError::Timer)?;
+                        }
+                        let received = if recovery.is_ready(Instant::now()) {
+                            let received = Self::check_ping(
+                                &udp_sock,
+                                &mut ping_pong,
+                                &mut timer,
+                                &recv_context,
+                                multicast_sock.is_some(),
+                            )
+                            .and_then(|()| {
+                                self.recv_quotes(
+                                    multicast_sock.as_ref().unwrap_or(&udp_sock),
+                                    &handler,
+                                    &mut ping_pong,
+                                    &mut timer,
+                                    &recv_context,
+                                    multicast_tickers.as_deref(),
+                                )
+                            });
+                            if let Ok(Received::Datagram) = received {
+                                recovery.succeeded();
+                            }
+                            received
+                        } else {
+                            Ok(Received::Nothing)
+                        };
+                        match received {
+                            Ok(Received::Shutdown) => {
+                                stop_reason = "server is shutting down".to_string();
+                                break;
+                            }
+                            Ok(_) if poll_due => handler.lock().unwrap().on_tick(),
+                            Ok(_) => {}
+                            Err(e) => {
+                                let reason = e.to_string();
+                                match recovery.failed(e, Instant::now()) {
+                                    Ok(delay) => {
+                                        recv_context.metrics.socket_error();
+                                        log::warn!(
+                                            "Transient receive error: {reason}, retry in {} ms",
+                                            delay.as_millis()
+                                        );
+                                    }
+                                    Err(_) => {
+                                        connection_error =
+                                            Some(format!("Can't receive quotes: {reason}"))
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    let Some(e) = connection_error else {
+                        continue;
+                    };
+                    log::error!("{e}");
+                    let lost_server = self.server_addr;
+                    notify(
+                        &handler,
+                        ConnectionEvent::Lost {
+                            server: lost_server,
+                            reason: e.clone(),
+                        },
+                    );
+                    let Some(policy) = self.reconnect else {
+                        stop_reason = e;
+                        break;
+                    };
+                    recv_context.state.set(ClientState::Reconnecting);
+                    if let Some(val) = ping_pong.take() {
+                        val.stop(&mut timer);
+                    }
+                    let (new_stream, subscribed) = match self.reconnect(policy, &handler, &rx) {
+                        Ok(Some(val)) => val,
+                        Ok(None) => break,
+                        Err(e) => {
+                            log::error!("{e}");
+                            stop_reason = e.to_string();
+                            notify(
+                                &handler,
+                                ConnectionEvent::Failed {
+                                    reason: e.to_string(),
+                                },
+                            );
+                            break;
+                        }
+                    };
+                    log::info!("Reconnected to server {}", self.server_addr);
+                    recovery.succeeded();
+                    recv_context.state.set(ClientState::Subscribed);
+                    notify(
+                        &handler,
+                        ConnectionEvent::Connected {
+                            server: self.server_addr,
+                            failover: self.server_addr != lost_server,
+                        },
+                    );
+                    recv_context.loss.restart();
+                    if let Some(watchdog) = watchdog.as_mut() {
+                        watchdog.reset(Instant::now());
+                    }
+                    if let Some(validator) = recv_context.validator.as_ref() {
+                        validator.restart();
+                    }
+                    if let Some(dedup) = recv_context.dedup.as_ref() {
+                        dedup.restart();
+                    }
+                    stream = new_stream;
+                    replies = ControlReplies::default();
+                    thread_current_session.set(self.server_addr, subscribed.session.clone());
+                    self.session = Some(subscribed.session);
+                    multicast_sock = match subscribed.multicast_group {
+                        Some(group) => Some(Self::join_multicast(group, &recv_context)?),
+                        None => None,
+                    };
+                    multicast_tickers = multicast_sock.as_ref().map(|_| self.acked_tickers());
+                }
+
+                for control in stripe_controls {
+                    let _ = control.tx.send(ClientCmd::Stop);
+                    match control.thread_handle.join() {
+                        Ok(Err(e)) => log::error!("Stripe receiver error: {e}"),
+                        Err(_) => log::error!("Can't join stripe receiver thread"),
+                        Ok(Ok(())) => {}
+                    }
+                }
+
+                if let Some(Err(e)) = recv_context.recorder.as_ref().map(|val| val.flush()) {
+                    log::error!("Can't flush recorded datagrams: {e}");
+                }
+                for (ticker, summary) in recv_context.summary.snapshot() {
+                    log::info!("Session summary of {ticker}: {summary}");
+                }
+                log::info!("Stop receive quotes");
+                Ok(stop_reason)
+            };
+            let res = receive();
+            thread_state.set(ClientState::Dead {
+                reason: match res.as_ref() {
+                    Ok(reason) => reason.clone(),
+                    Err(e) => e.to_string(),
+                },
+            });
+            res.map(|_| ())
+        });
+
+        Ok(ClientControl {
+            thread_handle: handle,
+            tx,
+            stats,
+            loss,
+            metrics,
+            recv_quote_port,
+            session,
+            current_session,
+            quotes: None,
+            channel_dropped: None,
+            buffer: None,
+            broadcast: None,
+            handler_queue,
+            groups: None,
+            validator,
+            dedup,
+            state,
+            summary,
+            acked_tickers,
+        })
+    }
+
+    /// Запуск потока приёма котировок, которые приложение забирает из канала
+    /// `ClientControl::quotes` емкостью capacity. Переполнение канала не задерживает прием
+    pub fn start_receive_channel(self, capacity: usize) -> ClientResult<ClientControl> {
+        let (handler, quotes) = ChannelHandler::new(capacity);
+        let dropped = handler.dropped();
+        let mut control = self.start_receive_handler(handler)?;
+        control.quotes = Some(quotes);
+        control.channel_dropped = Some(dropped);
+        Ok(control)
+    }
+
+    /// Запуск потока приёма котировок в буфер емкостью capacity, из которого приложение
+    /// забирает их через `ClientControl::try_recv_quotes`. При переполнении буфер
+    /// поступает по политике `QuotesClientBuilder::with_overflow_policy`
+    pub fn start_receive_buffered(self, capacity: usize) -> ClientResult<ClientControl> {
+        let (handler, buffer) = BufferHandler::with_policy(capacity, self.overflow_policy);
+        let mut control = self.start_receive_handler(handler)?;
+        control.buffer = Some(buffer);
+        Ok(control)
+    }
+
+    /// Запуск потока приёма котировок с раздачей нескольким получателям приложения:
+    /// одна подписка на сервере может питать интерфейс, запись и стратегию.
+    /// Получатели добавляются через `ClientControl::subscribe_quotes` в любой момент
+    pub fn start_receive_broadcast(self) -> ClientResult<ClientControl> {
+        let (handler, broadcast) = BroadcastHandler::new();
+        let mut control = self.start_receive_handler(handler)?;
+        control.broadcast = Some(broadcast);
+        Ok(control)
+    }
+
+    /// Запуск потока приёма котировок по группам тикеров. Клиент подписывается
+    /// на тикеры всех групп вместо своих, группы можно менять через
+    /// `ClientControl::set_group` и `ClientControl::remove_group`
+    pub fn start_receive_groups(mut self, groups: TickerGroups) -> ClientResult<ClientControl> {
+        self.tickers = groups.tickers();
+        if self.tickers.is_empty() {
+            return Err(ClientError::Config(
+                "ticker groups have no tickers".to_string(),
+            ));
+        }
+        let groups = Arc::new(groups);
+        let mut control = self.start_receive_handler(GroupHandler::new(groups.clone()))?;
+        control.groups = Some(groups);
+        Ok(control)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::config::ServerConfig;
+    use crate::server::quotes_server::{ControlCmd, QuotesServer, ServerControl};
+    use crate::server::session::ServerEvent;
+    use crate::testing::MockServerBuilder;
+
+    #[test]
+    fn test_reconnect_delay() {
+        let policy = ReconnectPolicy {
+            initial_delay_millis: 100,
+            max_delay_millis: 1000,
+            max_retries: None,
+        };
+        let delays: Vec<u128> = (0..6).map(|n| policy.delay(n).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.delay(100).as_millis(), 1000);
+    }
+
+    #[test]
+    fn test_client_with_tickers() {
+        let from_slice = QuotesClient::with_tickers("127.0.0.1:8090", 0, &["AMD", "INT"]).unwrap();
+        let from_vec =
+            QuotesClient::with_tickers("127.0.0.1:8090", 0, vec!["AMD".to_string()]).unwrap();
+        assert_eq!(from_slice.tickers, vec!["AMD", "INT"]);
+        assert_eq!(from_vec.tickers, vec!["AMD"]);
+        assert!(matches!(
+            QuotesClient::with_tickers("localhost", 0, &["AMD"]),
+            Err(ClientError::Resolve { .. })
+        ));
+        let by_name = QuotesClient::with_tickers("localhost:8090", 0, &["AMD"]).unwrap();
+        assert!(by_name.server_addr.ip().is_loopback());
+        assert_eq!(by_name.server_addr.port(), 8090);
+    }
+
+    #[test]
+    fn test_client_builder() {
+        let client = QuotesClientBuilder::new("[::1]:8090", 0, &["AMD"])
+            .with_wait_pong_millis(100)
+            .with_poll_millis(5)
+            .build()
+            .unwrap();
+        assert_eq!(client.bind_ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(client.ping_timeouts.wait_pong_millis, 100);
+        assert_eq!(client.poll_millis, 5);
+
+        let builder = QuotesClientBuilder::new("127.0.0.1:8090", 0, &["AMD"]);
+        assert!(matches!(
+            builder.clone().with_poll_millis(0).build(),
+            Err(ClientError::Config(_))
+        ));
+        assert!(builder.clone().with_conflation_millis(0).build().is_err());
+        assert!(
+            builder
+                .clone()
+                .with_overflow_policy(OverflowPolicy::Block)
+                .build()
+                .is_err()
+        );
+        assert!(builder.with_connect_timeout_millis(0).build().is_err());
+    }
+
+    #[test]
+    fn test_resume() {
+        let state = SessionState {
+            server_addr: "127.0.0.1:8090".to_string(),
+            recv_quote_port: 34254,
+            tickers: vec!["AMD".to_string()],
+            session: "s1".to_string(),
+            positions: vec![SeqPosition {
+                ticker: "AMD".to_string(),
+                loss: Default::default(),
+            }],
+        };
+        let client = QuotesClientBuilder::resume(&state)
+            .with_poll_millis(5)
+            .build()
+            .unwrap();
+        assert_eq!(client.server_addr.to_string(), state.server_addr);
+        assert_eq!(client.recv_quote_port, state.recv_quote_port);
+        assert_eq!(client.tickers, state.tickers);
+        assert_eq!(client.session.as_deref(), Some("s1"));
+        assert_eq!(client.restored_positions, state.positions);
+        assert_eq!(client.poll_millis, 5);
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        let addr: SocketAddr = "127.0.0.1:8090".parse().unwrap();
+        assert!(ClientError::PingTimeout(addr).is_transient());
+        assert!(ClientError::Rejected(ErrorCode::Overloaded).is_transient());
+        assert!(!ClientError::Rejected(ErrorCode::Unauthorized).is_transient());
+        assert!(!ClientError::Subscribe("no tickers".to_string()).is_transient());
+    }
+
+    #[test]
+    fn test_recv_message_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut stream = ControlStream::Plain(TcpStream::connect(addr).unwrap());
+        drop(listener.accept().unwrap());
+        let err = QuotesClient::recv_message(&mut stream).unwrap_err();
+        assert!(matches!(err, ClientError::Io(_)));
+        assert!(err.is_transient());
+
+        let mut stream = ControlStream::Plain(TcpStream::connect(addr).unwrap());
+        let (mut peer, _) = listener.accept().unwrap();
+        peer.write_all(&[0, 0, 0, 1, 0xFF]).unwrap();
+        let err = QuotesClient::recv_message(&mut stream).unwrap_err();
+        assert!(matches!(err, ClientError::Decode(_)));
+        assert!(!err.is_transient());
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let timeouts = PingTimeouts {
+            ping_period_millis: 20,
+            wait_pong_millis: 30,
+        };
+        let mut timer = Timer::default();
+        let mut ping_pong = PingPong::new(server.local_addr().unwrap(), timeouts, &mut timer);
+
+        ping_pong.check(&client, &mut timer).unwrap();
+        assert!(matches!(ping_pong.state, PingState::WaitPing));
+        timer.sleep();
+        timer.sleep();
+        ping_pong.check(&client, &mut timer).unwrap();
+        assert!(matches!(ping_pong.state, PingState::WaitPong));
+        let mut buf = [0u8; 16];
+        let (len, from) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(from, client.local_addr().unwrap());
+        assert!(matches!(
+            postcard::from_bytes::<Message>(&buf[..len]).unwrap(),
+            Message::Ping
+        ));
+
+        ping_pong.pong(&mut timer).unwrap();
+        assert!(matches!(ping_pong.state, PingState::WaitPing));
+        for _ in 0..2 {
+            timer.sleep();
+        }
+        ping_pong.check(&client, &mut timer).unwrap();
+        for _ in 0..3 {
+            timer.sleep();
+        }
+        assert!(matches!(
+            ping_pong.check(&client, &mut timer),
+            Err(ClientError::PingTimeout(_))
+        ));
+        ping_pong.stop(&mut timer);
+        assert!(timer.is_expired_event(WAIT_PONG_EVENT).is_err());
+    }
+
+    struct QuotesOnly(mpsc::Sender<StockQuote>);
+
+    impl QuoteHandler for QuotesOnly {
+        fn on_quote(&mut self, quote: StockQuote) {
+            let _ = self.0.send(quote);
+        }
+    }
+
+    #[test]
+    fn test_handler_with_quotes_only() {
+        let quotes: Vec<StockQuote> = (1..=3)
+            .map(|timestamp| StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 1,
+                timestamp,
+            })
+            .collect();
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .with_quotes(quotes)
+            .start()
+            .unwrap();
+        let (tx, rx) = mpsc::channel();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_handler(QuotesOnly(tx))
+            .unwrap();
+        let mut handler = QuotesOnly(mpsc::channel().0);
+        handler.on_bar(Bar {
+            ticker: "AMD".to_string(),
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 1,
+            start_millis: 0,
+            interval_millis: 1,
+        });
+
+        let timestamps: Vec<u64> = (0..3)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap().timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+        control.tx.send(ClientCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_ephemeral_ports() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_thread = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let Message::Tickers(req) = read_message_with_len(&mut conn).unwrap() else {
+                panic!("Subscribe is expected");
+            };
+            let ack = Message::Subscribed {
+                unknown_tickers: Vec::new(),
+                tickers: req.tickers.clone(),
+                multicast_group: None,
+                session: "ephemeral".to_string(),
+                resumed: false,
+            };
+            conn.write_all(&pack_message_with_len(&ack).unwrap())
+                .unwrap();
+            (req, conn)
+        });
+
+        let control = QuotesClientBuilder::new(&addr.to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .with_stripe_ports(vec![0])
+            .start_receive_quotes()
+            .unwrap();
+        let (req, _conn) = server_thread.join().unwrap();
+
+        assert_ne!(control.recv_quote_port, 0);
+        assert_eq!(req.port, control.recv_quote_port);
+        assert_eq!(req.stripe_ports.len(), 1);
+        assert_ne!(req.stripe_ports[0], 0);
+        assert_ne!(req.stripe_ports[0], req.port);
+        assert!(UdpSocket::bind(("127.0.0.1", req.port)).is_err());
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+    }
+
+    fn start_server(config: ServerConfig) -> ServerControl {
+        let config = ServerConfig {
+            listen_addr: "127.0.0.1:0".to_string(),
+            quotes_udp_port: 0,
+            ..config
+        };
+        QuotesServer::with_config(&["generator_config.json"], config)
+            .unwrap()
+            .start()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_configured_ping_keeps_connection() {
+        let server = start_server(ServerConfig {
+            ping_wait_millis: 1500,
+            check_ping_millis: 20,
+            ..Default::default()
+        });
+        let builder = QuotesClientBuilder::new(&server.local_addr.to_string(), 0, ["AMD"]);
+        assert!(matches!(
+            builder.clone().with_ping_period_millis(0).build(),
+            Err(ClientError::Config(_))
+        ));
+        let control = builder
+            .with_ping_period_millis(50)
+            .with_wait_pong_millis(1000)
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_channel(1000)
+            .unwrap();
+
+        let quotes = control.quotes.as_ref().unwrap();
+        assert!(quotes.recv_timeout(Duration::from_secs(5)).is_ok());
+        thread::sleep(Duration::from_millis(1200));
+        let snapshot = server.metrics.snapshot();
+        assert_eq!(snapshot.ping_timeouts, 0);
+        assert_eq!(snapshot.connected_clients, 1);
+        assert_eq!(control.state.get(), ClientState::Streaming);
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+        server.tx.send(ControlCmd::Stop).unwrap();
+        server.thread_handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_unsubscribe_on_stop() {
+        let server = start_server(ServerConfig::default());
+        let control = QuotesClientBuilder::new(&server.local_addr.to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_quotes()
+            .unwrap();
+        control.tx.send(ClientCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+
+        let reason = std::iter::from_fn(|| server.events.recv_timeout(Duration::from_secs(2)).ok())
+            .find_map(|event| match event {
+                ServerEvent::ClientDisconnected { reason, .. } => Some(reason),
+                _ => None,
+            });
+        assert_eq!(reason.as_deref(), Some("client unsubscribed"));
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while server.metrics.snapshot().connected_clients > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.metrics.snapshot().connected_clients, 0);
+
+        server.tx.send(ControlCmd::Stop).unwrap();
+        server.thread_handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_stop_on_server_shutdown() {
+        let server = start_server(ServerConfig::default());
+        let control = QuotesClientBuilder::new(&server.local_addr.to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_channel(1000)
+            .unwrap();
+        let quotes = control.quotes.as_ref().unwrap();
+        assert!(quotes.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        server.tx.send(ControlCmd::Stop).unwrap();
+        server.thread_handle.join().unwrap().unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+        assert_eq!(
+            control.state.get(),
+            ClientState::Dead {
+                reason: "server is shutting down".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_tickers() {
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD", "INT", "GAZ"])
+            .start()
+            .unwrap();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD", "INT"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_quotes()
+            .unwrap();
+        assert_eq!(control.subscribed_tickers(), vec!["AMD", "INT"]);
+
+        let change = control
+            .set_tickers(vec!["INT".to_string(), "GAZ".to_string()])
+            .unwrap();
+        assert_eq!(change.added, vec!["GAZ"]);
+        assert_eq!(change.removed, vec!["AMD"]);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while control.subscribed_tickers() != ["INT", "GAZ"] && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let requests: Vec<(SubscriptionMode, Vec<String>)> = server
+            .wait_subscriptions(3, Duration::from_secs(5))
+            .into_iter()
+            .map(|req| (req.mode, req.tickers))
+            .collect();
+        assert_eq!(
+            requests[1..],
+            [
+                (SubscriptionMode::Add, vec!["GAZ".to_string()]),
+                (SubscriptionMode::Remove, vec!["AMD".to_string()]),
+            ]
+        );
+
+        assert_eq!(control.subscribed_tickers(), vec!["INT", "GAZ"]);
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_subscribe_commands() {
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD", "INT", "GAZ"])
+            .start()
+            .unwrap();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD", "INT"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_quotes()
+            .unwrap();
+        control
+            .tx
+            .send(ClientCmd::subscribe(vec!["GAZ".to_string()]))
+            .unwrap();
+        control
+            .tx
+            .send(ClientCmd::unsubscribe(vec!["AMD".to_string()]))
+            .unwrap();
+
+        let requests: Vec<(SubscriptionMode, Vec<String>)> = server
+            .wait_subscriptions(3, Duration::from_secs(5))
+            .into_iter()
+            .map(|req| (req.mode, req.tickers))
+            .collect();
+        assert_eq!(
+            requests[1..],
+            [
+                (SubscriptionMode::Add, vec!["GAZ".to_string()]),
+                (SubscriptionMode::Remove, vec!["AMD".to_string()]),
+            ]
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while control.subscribed_tickers() != ["INT", "GAZ"] && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(control.subscribed_tickers(), vec!["INT", "GAZ"]);
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
+    }
+
+    struct Connections(mpsc::Sender<ConnectionEvent>);
+
+    impl QuoteHandler for Connections {
+        fn on_quote(&mut self, _quote: StockQuote) {}
+
+        fn on_connection(&mut self, event: ConnectionEvent) {
+            let _ = self.0.send(event);
+        }
+    }
+
+    #[test]
+    fn test_failover_to_backup() {
+        let primary = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let primary_addr = primary.local_addr().unwrap();
+        let primary_thread = thread::spawn(move || {
+            let (mut conn, _) = primary.accept().unwrap();
+            read_message_with_len::<Message, _>(&mut conn).unwrap();
+            let ack = Message::Subscribed {
+                unknown_tickers: Vec::new(),
+                tickers: vec!["AMD".to_string()],
+                multicast_group: None,
+                session: "primary".to_string(),
+                resumed: false,
+            };
+            conn.write_all(&pack_message_with_len(&ack).unwrap())
+                .unwrap();
+        });
+        let backup = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .start()
+            .unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let control = QuotesClientBuilder::new(&primary_addr.to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .with_backup_servers(&[&backup.addr().to_string()])
+            .unwrap()
+            .with_reconnect(ReconnectPolicy {
+                initial_delay_millis: 10,
+                max_delay_millis: 100,
+                max_retries: Some(5),
+            })
+            .start_receive_handler(Connections(tx))
+            .unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            ConnectionEvent::Connected {
+                server: primary_addr,
+                failover: false,
+            }
+        );
+        primary_thread.join().unwrap();
+        assert!(matches!(
+            rx.recv_timeout(timeout).unwrap(),
+            ConnectionEvent::Lost { server, .. } if server == primary_addr
+        ));
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            ConnectionEvent::Connected {
+                server: backup.addr(),
+                failover: true,
+            }
+        );
+        let resubscribed = backup.wait_subscriptions(1, timeout);
+        assert_eq!(resubscribed[0].tickers, vec!["AMD"]);
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+    }
+
+    struct Replays(mpsc::Sender<StockQuote>);
+
+    impl QuoteHandler for Replays {
+        fn on_quote(&mut self, _quote: StockQuote) {}
+
+        fn on_replay(&mut self, quote: StockQuote) {
+            let _ = self.0.send(quote);
+        }
+    }
+
+    #[test]
+    fn test_backfill_added_tickers() {
+        let history = |ticker: &str| {
+            vec![StockQuote {
+                ticker: ticker.to_string(),
+                ..Default::default()
+            }]
+        };
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD", "INT", "GAZ"])
+            .with_history("AMD", history("AMD"))
+            .with_history("INT", history("INT"))
+            .with_history("GAZ", history("GAZ"))
+            .start()
+            .unwrap();
+        let (tx, rx) = mpsc::channel();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .with_history(1)
+            .start_receive_handler(Replays(tx))
+            .unwrap();
+        let recv = || rx.recv_timeout(Duration::from_secs(5)).unwrap().ticker;
+        assert_eq!(recv(), "AMD");
+
+        control
+            .tx
+            .send(ClientCmd::subscribe(vec![
+                "AMD".to_string(),
+                "INT".to_string(),
+            ]))
+            .unwrap();
+        assert_eq!(recv(), "INT");
+        control
+            .set_tickers(vec!["INT".to_string(), "GAZ".to_string()])
+            .unwrap();
+        assert_eq!(recv(), "GAZ");
+        control
+            .tx
+            .send(ClientCmd::unsubscribe(vec!["INT".to_string()]))
+            .unwrap();
+        server.wait_subscriptions(4, Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(100));
+        assert!(rx.try_recv().is_err());
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_no_data_resubscribe() {
+        let history = StockQuote {
+            ticker: "AMD".to_string(),
+            ..Default::default()
+        };
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD", "INT"])
+            .with_history("AMD", vec![history])
+            .start()
+            .unwrap();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .with_no_data_timeout_millis(600)
+            .build()
+            .unwrap()
+            .with_history(1)
+            .start_receive_channel(10)
+            .unwrap();
+
+        let requests = server.wait_subscriptions(3, Duration::from_secs(5));
+        assert!(requests.len() >= 3);
+        assert_eq!(requests[1].mode, SubscriptionMode::Replace);
+        assert_eq!(requests[1].tickers, vec!["AMD"]);
+        assert!(control.client_stats().resubscribes >= 2);
+        let quotes = control.quotes.as_ref().unwrap();
+        assert_eq!(quotes.try_iter().count(), 1);
+
+        control.tx.send(ClientCmd::Pause).unwrap();
+        thread::sleep(Duration::from_millis(1000));
+        let paused_requests = server.subscriptions().len();
+        control.tx.send(ClientCmd::Resume).unwrap();
+        thread::sleep(Duration::from_millis(350));
+        assert_eq!(server.subscriptions().len(), paused_requests);
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_client_states() {
+        let server = MockServerBuilder::default()
+            .with_quotes(vec![StockQuote {
+                ticker: "AMD".to_string(),
+                ..Default::default()
+            }])
+            .start()
+            .unwrap();
+        let client = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap();
+        let state = client.state();
+        let mut receiver = state.subscribe();
+        assert_eq!(receiver.current(), ClientState::Connecting);
+
+        let control = client.start_receive_channel(10).unwrap();
+        let quotes = control.quotes.as_ref().unwrap();
+        assert!(quotes.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert_eq!(state.get(), ClientState::Streaming);
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
+        assert_eq!(
+            receiver.changed(Duration::from_secs(1)),
+            Some(ClientState::Dead {
+                reason: "stopped".to_string()
+            })
+        );
+
+        let rejecting = MockServerBuilder::default()
+            .with_rejection(ErrorCode::Unauthorized)
+            .start()
+            .unwrap();
+        let client = QuotesClientBuilder::new(&rejecting.addr().to_string(), 0, ["AMD"])
+            .build()
+            .unwrap();
+        let state = client.state();
+        assert!(client.start_receive_channel(10).is_err());
+        assert!(matches!(state.get(), ClientState::Dead { .. }));
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let quotes = (1..=200)
+            .map(|timestamp| StockQuote {
+                ticker: "AMD".to_string(),
+                timestamp,
+                ..Default::default()
+            })
+            .collect();
+        let server = MockServerBuilder::default()
+            .with_quotes(quotes)
+            .with_interval_millis(20)
+            .start()
+            .unwrap();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_channel(1000)
+            .unwrap();
+        let quotes = control.quotes.as_ref().unwrap();
+        let recv = || {
+            quotes
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap()
+                .timestamp
+        };
+        let first = recv();
+
+        control.tx.send(ClientCmd::Pause).unwrap();
+        thread::sleep(Duration::from_millis(400));
+        let last = quotes.try_iter().map(|quote| quote.timestamp).max();
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(quotes.try_iter().count(), 0);
+
+        control.tx.send(ClientCmd::Resume).unwrap();
+        let next = recv();
+        assert!(next - last.unwrap_or(first) <= 5);
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
     }
 }
@@ -0,0 +1,191 @@
+use super::handler::QuoteHandler;
+use crate::protocol::Message;
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+/// Записывает принятые клиентом датаграммы вместе со временем приема.
+/// Каждая датаграмма в файле начинается с времени приема (u64, мкс с начала эпохи Unix)
+/// и длины (u32), big endian
+#[derive(Debug)]
+pub struct DatagramRecorder {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl DatagramRecorder {
+    /// Создает файл записи, перезаписывая существующий
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+
+    /// Записывает датаграмму, принятую сейчас
+    pub(super) fn record(&self, datagram: &[u8]) {
+        let mut writer = self.writer.lock().unwrap();
+        let res = write_record(&mut *writer, unix_micros(), datagram);
+        if let Err(e) = res {
+            log::error!("Can't record datagram: {e}");
+        }
+    }
+
+    /// Сбрасывает буферизованные датаграммы в файл
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, received_micros: u64, datagram: &[u8]) -> Result<()> {
+    writer.write_all(&received_micros.to_be_bytes())?;
+    writer.write_all(&(datagram.len() as u32).to_be_bytes())?;
+    writer.write_all(datagram)?;
+    Ok(())
+}
+
+/// Датаграмма из записи
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedDatagram {
+    /// Время приема, мкс с начала эпохи Unix
+    pub received_micros: u64,
+    /// Датаграмма в том виде, в котором ее прислал сервер
+    pub datagram: Vec<u8>,
+}
+
+/// Читает датаграммы из записи по порядку
+pub struct RecordReader<R: Read> {
+    reader: R,
+}
+
+impl RecordReader<BufReader<File>> {
+    /// Открывает файл записи
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Читает запись из reader
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Следующая датаграмма или None в конце записи
+    pub fn next_datagram(&mut self) -> Result<Option<RecordedDatagram>> {
+        let mut received = [0u8; 8];
+        match self.reader.read_exact(&mut received) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let mut datagram = vec![0u8; u32::from_be_bytes(len) as usize];
+        self.reader.read_exact(&mut datagram)?;
+        Ok(Some(RecordedDatagram {
+            received_micros: u64::from_be_bytes(received),
+            datagram,
+        }))
+    }
+}
+
+/// Передает котировки, котировки истории и свечи из записи обработчику.
+/// Если realtime, паузы между датаграммами воспроизводятся как при записи.
+/// Возвращает количество переданных датаграмм
+pub fn replay<R: Read, H: QuoteHandler>(
+    reader: &mut RecordReader<R>,
+    handler: &mut H,
+    realtime: bool,
+) -> Result<u64> {
+    let mut delivered = 0;
+    let mut prev_micros = None;
+    while let Some(record) = reader.next_datagram()? {
+        if realtime {
+            if let Some(prev) = prev_micros {
+                let gap = record.received_micros.saturating_sub(prev);
+                std::thread::sleep(Duration::from_micros(gap));
+            }
+            prev_micros = Some(record.received_micros);
+        }
+        let msg = match postcard::from_bytes::<Message>(&record.datagram) {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::warn!("Skip recorded datagram: {e}");
+                continue;
+            }
+        };
+        match msg {
+            Message::Quote(resp) => handler.on_quote(resp.quote),
+            Message::ReplayQuote(resp) => handler.on_replay(resp.quote),
+            Message::Bar(resp) => handler.on_bar(resp.bar),
+            Message::Shutdown => break,
+            _ => continue,
+        }
+        delivered += 1;
+    }
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::QuoteRespMessage;
+    use crate::quote::{Bar, StockQuote};
+
+    #[derive(Default)]
+    struct Collector {
+        quotes: Vec<StockQuote>,
+    }
+
+    impl QuoteHandler for Collector {
+        fn on_quote(&mut self, quote: StockQuote) {
+            self.quotes.push(quote);
+        }
+
+        fn on_bar(&mut self, _bar: Bar) {}
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let quote = StockQuote {
+            ticker: "AMD".to_string(),
+            price: 10.0,
+            volume: 1,
+            timestamp: 2,
+        };
+        let packed = |msg: &Message| postcard::to_stdvec(msg).unwrap();
+        let mut file = Vec::new();
+        let resp = QuoteRespMessage {
+            quote: quote.clone(),
+            seq: 1,
+        };
+        write_record(&mut file, 100, &packed(&Message::Quote(resp))).unwrap();
+        write_record(&mut file, 150, &[0xff, 0xff]).unwrap();
+        write_record(&mut file, 200, &packed(&Message::Shutdown)).unwrap();
+        write_record(&mut file, 300, &packed(&Message::Ping)).unwrap();
+
+        let mut reader = RecordReader::new(file.as_slice());
+        let first = reader.next_datagram().unwrap().unwrap();
+        assert_eq!(first.received_micros, 100);
+
+        let mut reader = RecordReader::new(file.as_slice());
+        let mut collector = Collector::default();
+        assert_eq!(replay(&mut reader, &mut collector, true).unwrap(), 1);
+        assert_eq!(collector.quotes, vec![quote]);
+        assert_eq!(
+            reader.next_datagram().unwrap().unwrap().received_micros,
+            300
+        );
+        assert!(reader.next_datagram().unwrap().is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use super::error::{ClientError, ClientResult};
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_TRANSIENT_RETRIES: u32 = 10;
+const DEFAULT_TRANSIENT_INITIAL_MILLIS: u64 = 50;
+const DEFAULT_TRANSIENT_MAX_MILLIS: u64 = 2000;
+
+/// Класс ошибки приема котировок
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    /// Временная ошибка сокета, например ECONNREFUSED на udp-сокете после
+    /// недоставленного пинга. Прием повторяется на том же сокете
+    Transient,
+    /// Связь потеряна: клиент переподключается по `ReconnectPolicy` или останавливается
+    Fatal,
+}
+
+/// Класс ошибки приема котировок
+pub fn classify(error: &ClientError) -> ErrorClass {
+    let ClientError::Io(e) = error else {
+        return ErrorClass::Fatal;
+    };
+    match e.kind() {
+        ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+        | ErrorKind::Interrupted
+        | ErrorKind::TimedOut
+        | ErrorKind::HostUnreachable
+        | ErrorKind::NetworkUnreachable => ErrorClass::Transient,
+        _ => ErrorClass::Fatal,
+    }
+}
+
+/// Повтор приема после временных ошибок сокета. Задержка перед повтором удваивается
+/// с каждой ошибкой подряд, начиная с initial_delay_millis, но не превышает
+/// max_delay_millis. После max_retries ошибок подряд связь считается потерянной
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecoveryPolicy {
+    /// Наибольшее число временных ошибок подряд, 0 - любая ошибка считается потерей связи
+    pub max_retries: u32,
+    /// Задержка перед первым повтором
+    pub initial_delay_millis: u64,
+    /// Наибольшая задержка перед повтором
+    pub max_delay_millis: u64,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_TRANSIENT_RETRIES,
+            initial_delay_millis: DEFAULT_TRANSIENT_INITIAL_MILLIS,
+            max_delay_millis: DEFAULT_TRANSIENT_MAX_MILLIS,
+        }
+    }
+}
+
+/// Учет временных ошибок одного сокета приема
+pub(super) struct Recovery {
+    policy: RecoveryPolicy,
+    retries: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Recovery {
+    pub(super) fn new(policy: RecoveryPolicy) -> Self {
+        Self {
+            policy,
+            retries: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Пауза после временной ошибки закончилась
+    pub(super) fn is_ready(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    /// Прием прошел без ошибок
+    pub(super) fn succeeded(&mut self) {
+        self.retries = 0;
+        self.retry_at = None;
+    }
+
+    /// Ошибка приема. Временная ошибка откладывает следующий прием и возвращает Ok,
+    /// пока не исчерпаны повторы; остальные ошибки возвращаются как есть
+    pub(super) fn failed(&mut self, error: ClientError, now: Instant) -> ClientResult<Duration> {
+        if classify(&error) == ErrorClass::Fatal || self.retries >= self.policy.max_retries {
+            return Err(error);
+        }
+        let delay = Duration::from_millis(
+            self.policy
+                .initial_delay_millis
+                .saturating_mul(1u64 << self.retries.min(32))
+                .min(self.policy.max_delay_millis),
+        );
+        self.retries += 1;
+        self.retry_at = Some(now + delay);
+        Ok(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery() {
+        let refused = || ClientError::Io(std::io::Error::from(ErrorKind::ConnectionRefused));
+        assert_eq!(classify(&refused()), ErrorClass::Transient);
+        assert_eq!(
+            classify(&ClientError::Io(ErrorKind::InvalidData.into())),
+            ErrorClass::Fatal
+        );
+        assert_eq!(classify(&ClientError::Stopped), ErrorClass::Fatal);
+
+        let mut recovery = Recovery::new(RecoveryPolicy {
+            max_retries: 2,
+            initial_delay_millis: 10,
+            max_delay_millis: 15,
+        });
+        let now = Instant::now();
+        assert_eq!(
+            recovery.failed(refused(), now).unwrap(),
+            Duration::from_millis(10)
+        );
+        assert!(!recovery.is_ready(now));
+        assert!(recovery.is_ready(now + Duration::from_millis(10)));
+        assert_eq!(
+            recovery.failed(refused(), now).unwrap(),
+            Duration::from_millis(15)
+        );
+        assert!(recovery.failed(refused(), now).is_err());
+
+        recovery.succeeded();
+        assert!(recovery.is_ready(now));
+        assert!(recovery.failed(refused(), now).is_ok());
+        assert!(recovery.failed(ClientError::Stopped, now).is_err());
+    }
+}
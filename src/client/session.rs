@@ -0,0 +1,102 @@
+use super::error::{ClientError, ClientResult};
+use super::loss::SeqPosition;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Состояние сессии клиента, по которому перезапущенный процесс продолжает прием
+/// с того места, где остановился: сервер, подписка, положения потоков котировок
+/// и токен сессии. Сохраняется в json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionState {
+    /// Адрес сервера, с которым клиент был соединен
+    pub server_addr: String,
+    /// Порт приема котировок
+    pub recv_quote_port: u16,
+    /// Тикеры подписки, подтвержденные сервером
+    pub tickers: Vec<String>,
+    /// Токен сессии для восстановления подписки на сервере
+    pub session: String,
+    /// Положения потоков котировок по тикерам
+    pub positions: Vec<SeqPosition>,
+}
+
+impl SessionState {
+    /// Записывает состояние в файл path
+    pub fn save(&self, path: &Path) -> ClientResult<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| ClientError::Encode(e.into()))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Читает состояние из файла path
+    pub fn load(path: &Path) -> ClientResult<Self> {
+        let json = std::fs::read(path)?;
+        serde_json::from_slice(&json).map_err(|e| {
+            ClientError::Config(format!("invalid session state in {}: {e}", path.display()))
+        })
+    }
+}
+
+/// Сервер и токен сессии текущего подключения клиента. Обновляются при переподключении
+#[derive(Debug)]
+pub struct CurrentSession {
+    current: Mutex<(SocketAddr, String)>,
+}
+
+impl CurrentSession {
+    pub(super) fn new(server_addr: SocketAddr, session: String) -> Self {
+        Self {
+            current: Mutex::new((server_addr, session)),
+        }
+    }
+
+    pub(super) fn set(&self, server_addr: SocketAddr, session: String) {
+        *self.current.lock().unwrap() = (server_addr, session);
+    }
+
+    /// Адрес сервера текущего подключения
+    pub fn server_addr(&self) -> SocketAddr {
+        self.current.lock().unwrap().0
+    }
+
+    /// Токен сессии текущего подключения
+    pub fn session(&self) -> String {
+        self.current.lock().unwrap().1.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::loss::LossSnapshot;
+
+    #[test]
+    fn test_session_state_file() {
+        let state = SessionState {
+            server_addr: "127.0.0.1:8090".to_string(),
+            recv_quote_port: 34254,
+            tickers: vec!["AMD".to_string(), "INT".to_string()],
+            session: "a1b2".to_string(),
+            positions: vec![SeqPosition {
+                ticker: "AMD".to_string(),
+                loss: LossSnapshot {
+                    received: 40,
+                    lost: 2,
+                    reordered: 0,
+                },
+            }],
+        };
+        let path = std::env::temp_dir().join(format!("session_state_{}.json", std::process::id()));
+        state.save(&path).unwrap();
+        assert_eq!(SessionState::load(&path).unwrap(), state);
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(matches!(
+            SessionState::load(&path),
+            Err(ClientError::Config(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+use super::handler::QuoteHandler;
+use crate::quote::{Bar, StockQuote};
+use anyhow::{Result, bail};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+#[cfg(feature = "sqlite")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "sqlite")]
+const SQLITE_BATCH_QUOTES: usize = 1000;
+#[cfg(feature = "sqlite")]
+const SQLITE_BATCH_MILLIS: u64 = 500;
+
+/// Хранилище, в которое записываются принятые котировки
+pub trait QuoteSink: Send {
+    /// Записывает котировку
+    fn write(&mut self, quote: &StockQuote) -> Result<()>;
+
+    /// Сбрасывает буферизованные котировки в хранилище
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl QuoteSink for Box<dyn QuoteSink> {
+    fn write(&mut self, quote: &StockQuote) -> Result<()> {
+        (**self).write(quote)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Записывает котировки в CSV с заголовком ticker,price,volume,timestamp
+pub struct CsvSink<W: Write + Send> {
+    writer: csv::Writer<W>,
+}
+
+impl CsvSink<File> {
+    /// Открывает файл CSV, дописывая котировки в конец. Заголовок пишется только в новый файл
+    pub fn append(path: &Path) -> Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        let has_headers = file.metadata()?.len() == 0;
+        Ok(Self {
+            writer: csv::WriterBuilder::new()
+                .has_headers(has_headers)
+                .from_writer(file),
+        })
+    }
+}
+
+impl<W: Write + Send> CsvSink<W> {
+    /// Записывает котировки в writer
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+}
+
+impl<W: Write + Send> QuoteSink for CsvSink<W> {
+    fn write(&mut self, quote: &StockQuote) -> Result<()> {
+        self.writer.serialize(quote)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Записывает котировки в формате JSON lines: одна котировка JSON в строке
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: BufWriter<W>,
+}
+
+impl JsonLinesSink<File> {
+    /// Открывает файл, дописывая котировки в конец
+    pub fn append(path: &Path) -> Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    /// Записывает котировки в writer
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send> QuoteSink for JsonLinesSink<W> {
+    fn write(&mut self, quote: &StockQuote) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, quote)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Записывает котировки в таблицу SQLite (ticker, price, volume, timestamp).
+/// Таблица создается, если ее нет. Котировки записываются транзакциями
+/// по SQLITE_BATCH_QUOTES котировок, но не реже раза в SQLITE_BATCH_MILLIS
+#[cfg(feature = "sqlite")]
+pub struct SqliteSink {
+    conn: rusqlite::Connection,
+    insert: String,
+    pending: usize,
+    batch_started: Instant,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSink {
+    /// Открывает базу path и таблицу table
+    pub fn open(path: &Path, table: &str) -> Result<Self> {
+        if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("Invalid SQLite table name: {table}");
+        }
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (
+                ticker TEXT NOT NULL,
+                price REAL NOT NULL,
+                volume INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL
+            )"
+        ))?;
+        Ok(Self {
+            conn,
+            insert: format!(
+                "INSERT INTO {table} (ticker, price, volume, timestamp) VALUES (?1, ?2, ?3, ?4)"
+            ),
+            pending: 0,
+            batch_started: Instant::now(),
+        })
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if self.pending > 0 {
+            self.conn.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl QuoteSink for SqliteSink {
+    fn write(&mut self, quote: &StockQuote) -> Result<()> {
+        if self.pending == 0 {
+            self.conn.execute_batch("BEGIN")?;
+            self.batch_started = Instant::now();
+        }
+        self.conn.prepare_cached(&self.insert)?.execute((
+            &quote.ticker,
+            quote.price,
+            quote.volume,
+            quote.timestamp as i64,
+        ))?;
+        self.pending += 1;
+        if self.pending >= SQLITE_BATCH_QUOTES
+            || self.batch_started.elapsed() >= Duration::from_millis(SQLITE_BATCH_MILLIS)
+        {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.commit()
+    }
+}
+
+/// Открывает хранилище по расширению файла: .csv, .jsonl или .ndjson,
+/// .db или .sqlite (таблица quotes, нужна функция sqlite). Котировки дописываются
+/// к уже записанным
+pub fn open_sink(path: &Path) -> Result<Box<dyn QuoteSink>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    match extension {
+        "csv" => Ok(Box::new(CsvSink::append(path)?)),
+        "jsonl" | "ndjson" => Ok(Box::new(JsonLinesSink::append(path)?)),
+        #[cfg(feature = "sqlite")]
+        "db" | "sqlite" => Ok(Box::new(SqliteSink::open(path, "quotes")?)),
+        _ => bail!("Unknown output format of {}", path.display()),
+    }
+}
+
+/// Обработчик котировок, который записывает их в хранилище.
+/// Ошибки записи выводятся в лог, свечи не записываются
+pub struct SinkHandler<S: QuoteSink> {
+    sink: S,
+    write_failed: bool,
+    bars_skipped: bool,
+}
+
+impl<S: QuoteSink> SinkHandler<S> {
+    /// Записывает котировки в sink
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            write_failed: false,
+            bars_skipped: false,
+        }
+    }
+}
+
+impl<S: QuoteSink> QuoteHandler for SinkHandler<S> {
+    fn on_quote(&mut self, quote: StockQuote) {
+        match self.sink.write(&quote) {
+            Ok(()) => self.write_failed = false,
+            Err(e) => {
+                if !self.write_failed {
+                    log::error!("Can't write quote of {}: {e}", quote.ticker);
+                }
+                self.write_failed = true;
+            }
+        }
+    }
+
+    fn on_bar(&mut self, bar: Bar) {
+        if !self.bars_skipped {
+            log::warn!("Bars are not written to output, skip bar of {}", bar.ticker);
+            self.bars_skipped = true;
+        }
+    }
+}
+
+impl<S: QuoteSink> Drop for SinkHandler<S> {
+    fn drop(&mut self) {
+        if let Err(e) = self.sink.flush() {
+            log::error!("Can't flush quotes output: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_sinks() {
+        let quote = StockQuote {
+            ticker: "AMD".to_string(),
+            price: 10.5,
+            volume: 3,
+            timestamp: 7,
+        };
+
+        let mut csv_sink = CsvSink::new(Vec::new());
+        csv_sink.write(&quote).unwrap();
+        csv_sink.write(&quote).unwrap();
+        let text = String::from_utf8(csv_sink.writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            "ticker,price,volume,timestamp\nAMD,10.5,3,7\nAMD,10.5,3,7\n"
+        );
+
+        let mut json_sink = JsonLinesSink::new(Vec::new());
+        json_sink.write(&quote).unwrap();
+        let text = String::from_utf8(json_sink.writer.into_inner().unwrap()).unwrap();
+        let parsed: StockQuote = serde_json::from_str(text.trim_end()).unwrap();
+        assert_eq!(parsed, quote);
+        assert!(text.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_file_sinks_append() {
+        let quote = StockQuote {
+            ticker: "AMD".to_string(),
+            price: 10.5,
+            volume: 3,
+            timestamp: 7,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["quotes.csv", "quotes.jsonl"] {
+            let path = dir.path().join(name);
+            for _ in 0..2 {
+                let mut sink = open_sink(&path).unwrap();
+                sink.write(&quote).unwrap();
+                sink.flush().unwrap();
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(text.matches("AMD").count(), 2);
+        }
+        let csv = std::fs::read_to_string(dir.path().join("quotes.csv")).unwrap();
+        assert_eq!(
+            csv,
+            "ticker,price,volume,timestamp\nAMD,10.5,3,7\nAMD,10.5,3,7\n"
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_sink_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotes.db");
+        let mut sink = SqliteSink::open(&path, "quotes").unwrap();
+        for timestamp in 0..3 {
+            sink.write(&StockQuote {
+                ticker: "AMD".to_string(),
+                timestamp,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        let count = |conn: &rusqlite::Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM quotes", [], |row| row.get(0))
+                .unwrap()
+        };
+        let reader = rusqlite::Connection::open(&path).unwrap();
+        assert_eq!(count(&reader), 0);
+        sink.flush().unwrap();
+        assert_eq!(count(&reader), 3);
+    }
+}
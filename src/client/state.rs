@@ -0,0 +1,171 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Состояние соединения клиента с сервером
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientState {
+    /// Подключение к серверу
+    Connecting,
+    /// Подписка принята сервером, котировок еще нет
+    Subscribed,
+    /// Котировки поступают
+    Streaming,
+    /// Связь потеряна, идет переподключение
+    Reconnecting,
+    /// Прием котировок остановлен
+    Dead {
+        /// Причина
+        reason: String,
+    },
+}
+
+impl Display for ClientState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Subscribed => write!(f, "subscribed"),
+            Self::Streaming => write!(f, "streaming"),
+            Self::Reconnecting => write!(f, "reconnecting"),
+            Self::Dead { reason } => write!(f, "dead: {reason}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Versioned {
+    state: ClientState,
+    version: u64,
+}
+
+/// Текущее состояние клиента, которое обновляют потоки приема.
+/// Получатели из `subscribe` ждут изменений, как в канале watch
+#[derive(Debug)]
+pub struct StateWatch {
+    current: Mutex<Versioned>,
+    changed: Condvar,
+    streaming: AtomicBool,
+}
+
+impl Default for StateWatch {
+    fn default() -> Self {
+        Self {
+            current: Mutex::new(Versioned {
+                state: ClientState::Connecting,
+                version: 0,
+            }),
+            changed: Condvar::new(),
+            streaming: AtomicBool::new(false),
+        }
+    }
+}
+
+impl StateWatch {
+    /// Меняет состояние и будит получателей, если оно отличается от текущего.
+    /// Из `ClientState::Dead` клиент не выходит
+    pub(super) fn set(&self, state: ClientState) {
+        let mut current = self.current.lock().unwrap();
+        if current.state == state || matches!(current.state, ClientState::Dead { .. }) {
+            return;
+        }
+        log::debug!("Client state: {state}");
+        self.streaming
+            .store(state == ClientState::Streaming, Ordering::Relaxed);
+        current.state = state;
+        current.version += 1;
+        self.changed.notify_all();
+    }
+
+    /// Котировки поступают. Блокировка берется только при смене состояния
+    pub(super) fn set_streaming(&self) {
+        if !self.streaming.load(Ordering::Relaxed) {
+            self.set(ClientState::Streaming);
+        }
+    }
+
+    /// Текущее состояние
+    pub fn get(&self) -> ClientState {
+        self.current.lock().unwrap().state.clone()
+    }
+
+    /// Получатель изменений состояния, начиная с текущего
+    pub fn subscribe(self: &Arc<Self>) -> StateReceiver {
+        StateReceiver {
+            watch: self.clone(),
+            seen_version: self.current.lock().unwrap().version,
+        }
+    }
+}
+
+/// Получатель изменений состояния клиента
+pub struct StateReceiver {
+    watch: Arc<StateWatch>,
+    seen_version: u64,
+}
+
+impl StateReceiver {
+    /// Текущее состояние, которое отмечается как полученное
+    pub fn current(&mut self) -> ClientState {
+        let current = self.watch.current.lock().unwrap();
+        self.seen_version = current.version;
+        current.state.clone()
+    }
+
+    /// Ждет изменения состояния после последнего полученного не дольше timeout.
+    /// Несколько изменений подряд сливаются в последнее. None - состояние не менялось
+    pub fn changed(&mut self, timeout: Duration) -> Option<ClientState> {
+        let deadline = Instant::now() + timeout;
+        let mut current = self.watch.current.lock().unwrap();
+        while current.version == self.seen_version {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return None;
+            }
+            current = self.watch.changed.wait_timeout(current, left).unwrap().0;
+        }
+        self.seen_version = current.version;
+        Some(current.state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_state_watch() {
+        let watch = Arc::new(StateWatch::default());
+        let mut receiver = watch.subscribe();
+        assert_eq!(receiver.changed(Duration::from_millis(10)), None);
+
+        watch.set(ClientState::Subscribed);
+        watch.set_streaming();
+        watch.set_streaming();
+        assert_eq!(
+            receiver.changed(Duration::from_millis(10)),
+            Some(ClientState::Streaming)
+        );
+
+        let setter = {
+            let watch = watch.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                watch.set(ClientState::Dead {
+                    reason: "stopped".to_string(),
+                });
+                watch.set(ClientState::Connecting);
+            })
+        };
+        assert_eq!(
+            receiver.changed(Duration::from_secs(5)),
+            Some(ClientState::Dead {
+                reason: "stopped".to_string()
+            })
+        );
+        setter.join().unwrap();
+        assert_eq!(receiver.changed(Duration::from_millis(10)), None);
+        assert_eq!(watch.get().to_string(), "dead: stopped");
+    }
+}
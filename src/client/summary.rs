@@ -0,0 +1,112 @@
+use crate::quote::StockQuote;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+/// Сводка по котировкам тикера за сессию клиента
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TickerSummary {
+    /// Количество котировок
+    pub updates: u64,
+    /// Наименьшая цена
+    pub min_price: f64,
+    /// Наибольшая цена
+    pub max_price: f64,
+    /// Средняя цена
+    pub mean_price: f64,
+    /// Суммарный объем
+    pub total_volume: u64,
+}
+
+impl TickerSummary {
+    fn add(&mut self, quote: &StockQuote) {
+        if self.updates == 0 {
+            self.min_price = quote.price;
+            self.max_price = quote.price;
+        } else {
+            self.min_price = self.min_price.min(quote.price);
+            self.max_price = self.max_price.max(quote.price);
+        }
+        self.updates += 1;
+        self.mean_price += (quote.price - self.mean_price) / self.updates as f64;
+        self.total_volume += quote.volume as u64;
+    }
+}
+
+impl Display for TickerSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "updates: {}, min: {:.4}, max: {:.4}, mean: {:.4}, volume: {}",
+            self.updates, self.min_price, self.max_price, self.mean_price, self.total_volume
+        )
+    }
+}
+
+/// Сводка по тикерам за сессию клиента: цены и объемы котировок,
+/// переданных обработчику. Разделяется между потоками приема котировок клиента
+#[derive(Default)]
+pub struct SessionSummary {
+    tickers: Mutex<BTreeMap<String, TickerSummary>>,
+}
+
+impl SessionSummary {
+    /// Учитывает котировку
+    pub(super) fn record(&self, quote: &StockQuote) {
+        let mut tickers = self.tickers.lock().unwrap();
+        match tickers.get_mut(&quote.ticker) {
+            Some(summary) => summary.add(quote),
+            None => {
+                let mut summary = TickerSummary::default();
+                summary.add(quote);
+                tickers.insert(quote.ticker.clone(), summary);
+            }
+        }
+    }
+
+    /// Сводки всех тикеров по алфавиту
+    pub fn snapshot(&self) -> Vec<(String, TickerSummary)> {
+        self.tickers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(ticker, summary)| (ticker.clone(), *summary))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_summary() {
+        let summary = SessionSummary::default();
+        let quote = |ticker: &str, price: f64, volume: u32| StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume,
+            timestamp: 0,
+        };
+        summary.record(&quote("INT", 50.0, 1));
+        for (price, volume) in [(10.0, 100), (14.0, 50), (9.0, 10)] {
+            summary.record(&quote("AMD", price, volume));
+        }
+
+        let snapshot = summary.snapshot();
+        assert_eq!(snapshot[0].0, "AMD");
+        assert_eq!(
+            snapshot[0].1,
+            TickerSummary {
+                updates: 3,
+                min_price: 9.0,
+                max_price: 14.0,
+                mean_price: 11.0,
+                total_volume: 160,
+            }
+        );
+        assert_eq!(snapshot[1].1.updates, 1);
+        assert_eq!(snapshot[1].1.min_price, 50.0);
+    }
+}
@@ -0,0 +1,258 @@
+use super::quotes_client::ClientControl;
+use crate::quote::StockQuote;
+use anyhow::{Result, bail};
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::mpsc::TryRecvError;
+use std::time::{Duration, Instant};
+
+const REDRAW_MILLIS: u64 = 100;
+const HIGHLIGHT_MILLIS: u64 = 1000;
+
+/// Столбец, по которому сортируется таблица котировок
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortColumn {
+    /// Тикер
+    #[default]
+    Ticker,
+    /// Последняя цена
+    Price,
+    /// Изменение цены в процентах
+    Change,
+    /// Объем
+    Volume,
+}
+
+impl SortColumn {
+    /// Следующий столбец сортировки по кругу
+    pub fn next(self) -> Self {
+        match self {
+            Self::Ticker => Self::Price,
+            Self::Price => Self::Change,
+            Self::Change => Self::Volume,
+            Self::Volume => Self::Ticker,
+        }
+    }
+}
+
+/// Строка таблицы котировок
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteRow {
+    /// Тикер
+    pub ticker: String,
+    /// Последняя цена
+    pub price: f64,
+    /// Изменение цены относительно предыдущей котировки
+    pub change: f64,
+    /// Изменение цены в процентах
+    pub change_percent: f64,
+    /// Объем последней котировки
+    pub volume: u32,
+    /// Время приема последней котировки
+    pub updated: Instant,
+}
+
+/// Таблица последних котировок по тикерам
+#[derive(Debug, Default)]
+pub struct QuoteBoard {
+    rows: BTreeMap<String, QuoteRow>,
+    /// Столбец сортировки
+    pub sort: SortColumn,
+    /// Сортировка по убыванию
+    pub descending: bool,
+}
+
+impl QuoteBoard {
+    /// Обновляет строку тикера котировкой
+    pub fn update(&mut self, quote: StockQuote) {
+        let now = Instant::now();
+        match self.rows.get_mut(&quote.ticker) {
+            Some(row) => {
+                row.change = quote.price - row.price;
+                row.change_percent = if row.price == 0.0 {
+                    0.0
+                } else {
+                    row.change / row.price * 100.0
+                };
+                row.price = quote.price;
+                row.volume = quote.volume;
+                row.updated = now;
+            }
+            None => {
+                self.rows.insert(
+                    quote.ticker.clone(),
+                    QuoteRow {
+                        ticker: quote.ticker,
+                        price: quote.price,
+                        change: 0.0,
+                        change_percent: 0.0,
+                        volume: quote.volume,
+                        updated: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Строки в порядке сортировки
+    pub fn rows(&self) -> Vec<&QuoteRow> {
+        let mut rows: Vec<&QuoteRow> = self.rows.values().collect();
+        rows.sort_by(|a, b| {
+            let ord = match self.sort {
+                SortColumn::Ticker => a.ticker.cmp(&b.ticker),
+                SortColumn::Price => a.price.total_cmp(&b.price),
+                SortColumn::Change => a.change_percent.total_cmp(&b.change_percent),
+                SortColumn::Volume => a.volume.cmp(&b.volume),
+            };
+            let ord = if self.descending { ord.reverse() } else { ord };
+            ord.then_with(|| a.ticker.cmp(&b.ticker))
+        });
+        rows
+    }
+}
+
+fn row_style(row: &QuoteRow) -> Style {
+    if row.updated.elapsed() > Duration::from_millis(HIGHLIGHT_MILLIS) {
+        return Style::default();
+    }
+    let color = match row.change.partial_cmp(&0.0) {
+        Some(Ordering::Greater) => Color::Green,
+        Some(Ordering::Less) => Color::Red,
+        _ => Color::Yellow,
+    };
+    Style::default().fg(color).add_modifier(Modifier::BOLD)
+}
+
+fn draw(terminal: &mut DefaultTerminal, board: &QuoteBoard, control: &ClientControl) -> Result<()> {
+    terminal.draw(|frame| {
+        let [table_area, status_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(frame.area());
+
+        let header = Row::new(["Ticker", "Price", "Change", "Change %", "Volume"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = board.rows().into_iter().map(|row| {
+            Row::new([
+                Cell::from(row.ticker.clone()),
+                Cell::from(format!("{:.2}", row.price)),
+                Cell::from(format!("{:+.2}", row.change)),
+                Cell::from(format!("{:+.2}", row.change_percent)),
+                Cell::from(row.volume.to_string()),
+            ])
+            .style(row_style(row))
+        });
+        let order = if board.descending { "desc" } else { "asc" };
+        let table = Table::new(rows, [Constraint::Fill(1); 5])
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" Quotes, sorted by {:?} {order} ", board.sort)),
+            );
+        frame.render_widget(table, table_area);
+
+        let status = Paragraph::new(format!(
+            "{}, {}",
+            control.state.get(),
+            control.client_stats()
+        ))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" q - exit, s - sort column, r - reverse order "),
+        );
+        frame.render_widget(status, status_area);
+    })?;
+    Ok(())
+}
+
+fn run_loop(terminal: &mut DefaultTerminal, control: &ClientControl) -> Result<()> {
+    let Some(quotes) = control.quotes.as_ref() else {
+        bail!("Client is not started with quotes channel");
+    };
+    let mut board = QuoteBoard::default();
+    loop {
+        loop {
+            match quotes.try_recv() {
+                Ok(quote) => board.update(quote),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        if control.thread_handle.is_finished() {
+            return Ok(());
+        }
+
+        draw(terminal, &board, control)?;
+
+        if !event::poll(Duration::from_millis(REDRAW_MILLIS))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('s') => board.sort = board.sort.next(),
+            KeyCode::Char('r') => board.descending = !board.descending,
+            _ => {}
+        }
+    }
+}
+
+/// Показывает таблицу котировок в терминале, пока пользователь не выйдет
+/// или не остановится поток клиента. Клиент должен быть запущен через
+/// `QuotesClient::start_receive_channel`
+pub fn run(control: &ClientControl) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let res = run_loop(&mut terminal, control);
+    ratatui::restore();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(ticker: &str, price: f64, volume: u32) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_quote_board() {
+        let mut board = QuoteBoard::default();
+        board.update(quote("INT", 50.0, 10));
+        board.update(quote("AMD", 100.0, 5));
+        board.update(quote("AMD", 110.0, 7));
+        board.update(quote("INT", 45.0, 20));
+
+        let tickers: Vec<&str> = board.rows().iter().map(|r| r.ticker.as_str()).collect();
+        assert_eq!(tickers, vec!["AMD", "INT"]);
+
+        let amd = board.rows()[0].clone();
+        assert_eq!(amd.price, 110.0);
+        assert_eq!(amd.change, 10.0);
+        assert_eq!(amd.change_percent, 10.0);
+        assert_eq!(amd.volume, 7);
+
+        board.sort = SortColumn::Change;
+        assert_eq!(board.rows()[0].ticker, "INT");
+        board.descending = true;
+        assert_eq!(board.rows()[0].ticker, "AMD");
+        board.sort = board.sort.next();
+        assert_eq!(board.sort, SortColumn::Volume);
+        assert_eq!(board.rows()[0].ticker, "INT");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::quote::StockQuote;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_TRACKED_SEQS: usize = 1024;
+
+/// Что делать с котировкой, не прошедшей проверку
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ValidationPolicy {
+    /// Не передавать обработчику
+    Drop,
+    /// Записать предупреждение в лог и передать обработчику как обычную котировку
+    #[default]
+    Warn,
+    /// Передать обработчику через `QuoteHandler::on_flagged` вместе с причиной
+    Flag,
+}
+
+/// Причина, по которой котировка не прошла проверку
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteIssue {
+    /// Котировка с тем же номером последовательности уже принята
+    Duplicate {
+        /// Номер последовательности
+        seq: u64,
+    },
+    /// Временная метка меньше метки последней котировки тикера
+    OutOfOrder {
+        /// Метка последней котировки тикера
+        last_timestamp: u64,
+    },
+    /// Временная метка отстает от часов клиента больше допустимого
+    Stale {
+        /// Возраст котировки, мс
+        age_millis: u64,
+    },
+}
+
+impl Display for QuoteIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Duplicate { seq } => write!(f, "duplicate seq {seq}"),
+            Self::OutOfOrder { last_timestamp } => {
+                write!(f, "out of order, last timestamp {last_timestamp}")
+            }
+            Self::Stale { age_millis } => write!(f, "stale by {age_millis} ms"),
+        }
+    }
+}
+
+/// Параметры проверки принятых котировок
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QuoteValidation {
+    /// Что делать с котировкой, не прошедшей проверку
+    pub policy: ValidationPolicy,
+    /// Наибольший возраст котировки по часам клиента, мс. Временные метки сервера
+    /// должны идти по часам (`TimestampMode::WallClock`). None - не проверять
+    pub max_age_millis: Option<u64>,
+}
+
+/// Количество котировок, не прошедших проверку
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ValidationStats {
+    /// Повторы номеров последовательности
+    pub duplicates: u64,
+    /// Котировки с временной меткой меньше предыдущей
+    pub out_of_order: u64,
+    /// Устаревшие котировки
+    pub stale: u64,
+}
+
+impl Display for ValidationStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "duplicates: {}, out of order: {}, stale: {}",
+            self.duplicates, self.out_of_order, self.stale
+        )
+    }
+}
+
+#[derive(Default)]
+struct TickerState {
+    last_timestamp: Option<u64>,
+    seqs: BTreeSet<u64>,
+}
+
+#[derive(Default)]
+struct ValidatorState {
+    tickers: HashMap<String, TickerState>,
+    stats: ValidationStats,
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Проверяет живые котировки на повторы, нарушение порядка временных меток и устаревание.
+/// Разделяется между потоками приема котировок клиента
+pub struct QuoteValidator {
+    validation: QuoteValidation,
+    state: Mutex<ValidatorState>,
+}
+
+impl QuoteValidator {
+    /// Проверка с параметрами validation
+    pub fn new(validation: QuoteValidation) -> Self {
+        Self {
+            validation,
+            state: Mutex::new(ValidatorState::default()),
+        }
+    }
+
+    /// Что делать с котировкой, не прошедшей проверку
+    pub fn policy(&self) -> ValidationPolicy {
+        self.validation.policy
+    }
+
+    /// Проверяет котировку с номером seq, 0 - без номера
+    pub(super) fn check(&self, quote: &StockQuote, seq: u64) -> Option<QuoteIssue> {
+        self.check_at(quote, seq, unix_millis())
+    }
+
+    fn check_at(&self, quote: &StockQuote, seq: u64, now_millis: u64) -> Option<QuoteIssue> {
+        let mut state = self.state.lock().unwrap();
+        let ticker = state.tickers.entry(quote.ticker.clone()).or_default();
+
+        let issue = if seq != 0 && !ticker.seqs.insert(seq) {
+            Some(QuoteIssue::Duplicate { seq })
+        } else if let Some(last) = ticker.last_timestamp.filter(|last| quote.timestamp < *last) {
+            Some(QuoteIssue::OutOfOrder {
+                last_timestamp: last,
+            })
+        } else {
+            let age_millis = now_millis.saturating_sub(quote.timestamp);
+            self.validation
+                .max_age_millis
+                .filter(|max_age| age_millis > *max_age)
+                .map(|_| QuoteIssue::Stale { age_millis })
+        };
+        while ticker.seqs.len() > MAX_TRACKED_SEQS {
+            ticker.seqs.pop_first();
+        }
+        if matches!(issue, None | Some(QuoteIssue::Stale { .. })) {
+            ticker.last_timestamp = Some(quote.timestamp);
+        }
+
+        match issue {
+            Some(QuoteIssue::Duplicate { .. }) => state.stats.duplicates += 1,
+            Some(QuoteIssue::OutOfOrder { .. }) => state.stats.out_of_order += 1,
+            Some(QuoteIssue::Stale { .. }) => state.stats.stale += 1,
+            None => {}
+        }
+        issue
+    }
+
+    /// Новый поток котировок после переподключения: номера и метки начинаются заново
+    pub(super) fn restart(&self) {
+        self.state.lock().unwrap().tickers.clear();
+    }
+
+    /// Количество котировок, не прошедших проверку
+    pub fn stats(&self) -> ValidationStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_validator() {
+        let validator = QuoteValidator::new(QuoteValidation {
+            policy: ValidationPolicy::Drop,
+            max_age_millis: Some(10),
+        });
+        let now = 30;
+        let quote = |ticker: &str, timestamp: u64| StockQuote {
+            ticker: ticker.to_string(),
+            timestamp,
+            ..Default::default()
+        };
+        assert_eq!(validator.check_at(&quote("AMD", 25), 1, now), None);
+        assert_eq!(validator.check_at(&quote("INT", 20), 1, now), None);
+        assert_eq!(
+            validator.check_at(&quote("AMD", 26), 1, now),
+            Some(QuoteIssue::Duplicate { seq: 1 })
+        );
+        assert_eq!(
+            validator.check_at(&quote("AMD", 24), 2, now),
+            Some(QuoteIssue::OutOfOrder { last_timestamp: 25 })
+        );
+        assert_eq!(
+            validator.check_at(&quote("AMD", 27), 3, 40),
+            Some(QuoteIssue::Stale { age_millis: 13 })
+        );
+        assert_eq!(validator.check_at(&quote("AMD", 32), 0, 40), None);
+        assert_eq!(
+            validator.stats(),
+            ValidationStats {
+                duplicates: 1,
+                out_of_order: 1,
+                stale: 1,
+            }
+        );
+
+        validator.restart();
+        assert_eq!(validator.check_at(&quote("AMD", 21), 1, now), None);
+    }
+}
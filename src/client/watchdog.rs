@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+/// Сторож потока данных: замечает, что при живом соединении котировки не приходят
+/// дольше таймаута, например если сервер молча потерял подписку
+pub(super) struct NoDataWatchdog {
+    timeout: Duration,
+    since: Instant,
+}
+
+impl NoDataWatchdog {
+    pub(super) fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            since: now,
+        }
+    }
+
+    /// Подписка отправлена заново: отсчет начинается с now
+    pub(super) fn reset(&mut self, now: Instant) {
+        self.since = now;
+    }
+
+    /// Данных нет дольше таймаута ни с последних данных, ни с последней подписки.
+    /// last_data_age - время с последних принятых данных, None - данных еще не было.
+    /// Срабатывает один раз за таймаут: отсчет начинается заново
+    pub(super) fn is_expired(&mut self, last_data_age: Option<Duration>, now: Instant) -> bool {
+        let idle = now.duration_since(self.since);
+        let idle = last_data_age.map_or(idle, |age| age.min(idle));
+        if idle < self.timeout {
+            return false;
+        }
+        self.since = now;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_data_watchdog() {
+        let start = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let mut watchdog = NoDataWatchdog::new(timeout, start);
+        assert!(!watchdog.is_expired(None, start + Duration::from_millis(50)));
+        assert!(watchdog.is_expired(None, start + timeout));
+        assert!(!watchdog.is_expired(None, start + Duration::from_millis(150)));
+
+        let now = start + Duration::from_millis(300);
+        assert!(!watchdog.is_expired(Some(Duration::from_millis(10)), now));
+        assert!(watchdog.is_expired(Some(timeout), now));
+
+        watchdog.reset(now + timeout);
+        assert!(!watchdog.is_expired(Some(Duration::from_secs(1)), now + timeout));
+    }
+}
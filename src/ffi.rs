@@ -0,0 +1,237 @@
+//! C ABI клиента котировок для приложений на C/C++:
+//! ```c
+//! typedef struct SqClient SqClient;
+//! typedef struct {
+//!     char ticker[16];
+//!     double price;
+//!     uint32_t volume;
+//!     uint64_t timestamp;
+//! } SqQuote;
+//!
+//! SqClient* sq_client_new(const char* server_addr, uint16_t recv_quote_port);
+//! int32_t sq_client_subscribe(SqClient* client, const char* tickers);
+//! int32_t sq_client_poll_quote(SqClient* client, SqQuote* quote);
+//! void sq_client_free(SqClient* client);
+//! ```
+//! `tickers` - тикеры через запятую. Первый вызов `sq_client_subscribe` запускает прием котировок,
+//! следующие добавляют тикеры к подписке. `sq_client_poll_quote` не ждет котировок:
+//! возвращает 1, если котировка записана в `quote`, 0, если котировок нет, и -1 при ошибке.
+//! Библиотека собирается командой
+//! `cargo rustc --lib --release --features cdylib --crate-type cdylib`
+
+use crate::client::quotes_client::{ClientCmd, ClientControl, QuotesClient, resolve_server_addr};
+use crate::quote::StockQuote;
+use anyhow::{Result, bail};
+use std::ffi::{CStr, c_char};
+
+/// Максимальная длина тикера в `SqQuote` вместе с завершающим нулем
+pub const SQ_TICKER_LEN: usize = 16;
+
+const POLL_BUFFER_CAPACITY: usize = 4096;
+
+/// Котировка в представлении C
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SqQuote {
+    /// Тикер, завершенный нулем. Длинные тикеры обрезаются
+    pub ticker: [c_char; SQ_TICKER_LEN],
+    /// Цена
+    pub price: f64,
+    /// Объем
+    pub volume: u32,
+    /// Время котировки
+    pub timestamp: u64,
+}
+
+impl From<&StockQuote> for SqQuote {
+    fn from(quote: &StockQuote) -> Self {
+        let mut ticker = [0; SQ_TICKER_LEN];
+        for (dst, src) in ticker
+            .iter_mut()
+            .zip(quote.ticker.bytes().take(SQ_TICKER_LEN - 1))
+        {
+            *dst = src as c_char;
+        }
+        Self {
+            ticker,
+            price: quote.price,
+            volume: quote.volume,
+            timestamp: quote.timestamp,
+        }
+    }
+}
+
+/// Клиент котировок, которым владеет приложение на C
+pub struct SqClient {
+    server_addr: String,
+    recv_quote_port: u16,
+    control: Option<ClientControl>,
+}
+
+impl SqClient {
+    fn subscribe(&mut self, tickers: &str) -> Result<()> {
+        let tickers: Vec<String> = tickers
+            .split(',')
+            .map(|ticker| ticker.trim().to_string())
+            .filter(|ticker| !ticker.is_empty())
+            .collect();
+        if tickers.is_empty() {
+            bail!("Tickers are not set");
+        }
+        match self.control.as_ref() {
+            Some(control) => control.tx.send(ClientCmd::subscribe(tickers))?,
+            None => {
+                let client =
+                    QuotesClient::with_tickers(&self.server_addr, self.recv_quote_port, tickers)?;
+                self.control = Some(client.start_receive_buffered(POLL_BUFFER_CAPACITY)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_quote(&self) -> Result<Option<StockQuote>> {
+        let Some(control) = self.control.as_ref() else {
+            bail!("Client is not subscribed");
+        };
+        if let Some(quote) = control.try_recv_quotes(1).pop() {
+            return Ok(Some(quote));
+        }
+        if control.thread_handle.is_finished() {
+            bail!("Client is stopped");
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for SqClient {
+    fn drop(&mut self) {
+        let Some(control) = self.control.take() else {
+            return;
+        };
+        if let Err(e) = control.tx.send(ClientCmd::Stop) {
+            log::debug!("Client is already stopped: {e}");
+        }
+        if control.thread_handle.join().is_err() {
+            log::error!("Can't join client thread");
+        }
+    }
+}
+
+unsafe fn read_str<'a>(ptr: *const c_char) -> Result<&'a str> {
+    if ptr.is_null() {
+        bail!("Null string");
+    }
+    Ok(unsafe { CStr::from_ptr(ptr) }.to_str()?)
+}
+
+/// Создает клиент сервера server_addr (например "127.0.0.1:8090" или "localhost:8090"), принимающий котировки
+/// на порт recv_quote_port, 0 - любой свободный порт. Возвращает NULL при ошибке
+///
+/// # Safety
+/// `server_addr` должен быть строкой, завершенной нулем
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sq_client_new(
+    server_addr: *const c_char,
+    recv_quote_port: u16,
+) -> *mut SqClient {
+    let server_addr = match unsafe { read_str(server_addr) } {
+        Ok(val) => val,
+        Err(e) => {
+            log::error!("Invalid server addr: {e}");
+            return std::ptr::null_mut();
+        }
+    };
+    if let Err(e) = resolve_server_addr(server_addr) {
+        log::error!("Invalid server addr: {e}");
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(SqClient {
+        server_addr: server_addr.to_string(),
+        recv_quote_port,
+        control: None,
+    }))
+}
+
+/// Подписывается на тикеры, перечисленные через запятую. Возвращает 0 или -1 при ошибке
+///
+/// # Safety
+/// `client` должен быть получен из `sq_client_new`, `tickers` - строка, завершенная нулем
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sq_client_subscribe(client: *mut SqClient, tickers: *const c_char) -> i32 {
+    let Some(client) = (unsafe { client.as_mut() }) else {
+        return -1;
+    };
+    let res = unsafe { read_str(tickers) }.and_then(|tickers| client.subscribe(tickers));
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            log::error!("Can't subscribe: {e}");
+            -1
+        }
+    }
+}
+
+/// Забирает без ожидания одну принятую котировку. Возвращает 1, если котировка
+/// записана в quote, 0, если котировок нет, -1 при ошибке или остановке клиента
+///
+/// # Safety
+/// `client` должен быть получен из `sq_client_new`, `quote` - указатель на `SqQuote`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sq_client_poll_quote(client: *mut SqClient, quote: *mut SqQuote) -> i32 {
+    let (Some(client), Some(quote)) = (unsafe { client.as_ref() }, unsafe { quote.as_mut() })
+    else {
+        return -1;
+    };
+    match client.poll_quote() {
+        Ok(Some(val)) => {
+            *quote = SqQuote::from(&val);
+            1
+        }
+        Ok(None) => 0,
+        Err(e) => {
+            log::error!("Can't poll quote: {e}");
+            -1
+        }
+    }
+}
+
+/// Останавливает прием котировок и освобождает клиент
+///
+/// # Safety
+/// `client` должен быть получен из `sq_client_new` и не использоваться после вызова
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sq_client_free(client: *mut SqClient) {
+    if !client.is_null() {
+        drop(unsafe { Box::from_raw(client) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_client() {
+        unsafe {
+            assert!(sq_client_new(std::ptr::null(), 0).is_null());
+            assert!(sq_client_new(c"not an addr".as_ptr(), 0).is_null());
+
+            let client = sq_client_new(c"127.0.0.1:1".as_ptr(), 0);
+            assert!(!client.is_null());
+            let mut quote = SqQuote::from(&StockQuote::default());
+            assert_eq!(sq_client_poll_quote(client, &mut quote), -1);
+            assert_eq!(sq_client_subscribe(client, c" , ".as_ptr()), -1);
+            sq_client_free(client);
+        }
+
+        let quote = SqQuote::from(&StockQuote {
+            ticker: "VERY_LONG_TICKER_NAME".to_string(),
+            price: 1.5,
+            volume: 2,
+            timestamp: 3,
+        });
+        let ticker = unsafe { CStr::from_ptr(quote.ticker.as_ptr()) };
+        assert_eq!(ticker.to_str().unwrap(), "VERY_LONG_TICKE");
+        assert_eq!(quote.price, 1.5);
+    }
+}
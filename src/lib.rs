@@ -20,6 +20,27 @@ pub mod timer;
 /// Утилиты
 pub mod utils;
 
+/// TLS для управляющего канала
+pub mod tls;
+
+/// Статистика работы фоновых потоков
+pub mod stats;
+
+/// Тестовый сервер котировок для проверки клиентского кода
+pub mod testing;
+
+/// Загрузка моделей цены из динамических библиотек
+#[cfg(feature = "plugins")]
+pub mod plugin;
+
+/// C ABI клиента котировок
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+
+/// Модуль Python с клиентом котировок
+#[cfg(feature = "python")]
+pub mod python;
+
 use anyhow::Result;
 use flexi_logger::{Duplicate, FileSpec, Logger, opt_format};
 use std::path::Path;
@@ -40,6 +61,25 @@ pub fn init_log(log_path_dir: &Path, base_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Инициализация лога только в файл, когда стандартный вывод занят интерфейсом в терминале
+pub fn init_file_log(log_path_dir: &Path, base_name: &str) -> Result<()> {
+    let level = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "info"
+    };
+    Logger::try_with_str(level)?
+        .log_to_file(
+            FileSpec::default()
+                .directory(log_path_dir)
+                .basename(base_name),
+        )
+        .format(opt_format)
+        .start()?;
+
+    Ok(())
+}
+
 #[cfg(not(debug_assertions))]
 pub fn init_log(log_path_dir: &Path) -> Result<()> {
     Logger::try_with_str("info")?
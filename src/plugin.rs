@@ -0,0 +1,77 @@
+//! Плагин модели цены — динамическая библиотека, экспортирующая функции C ABI:
+//! ```c
+//! uint32_t sq_model_abi_version(void);
+//! void* sq_model_create(const char* ticker, double upper_bound_price);
+//! double sq_model_next_price(void* state, double current_price);
+//! void sq_model_destroy(void* state);
+//! ```
+//! `sq_model_abi_version` должна возвращать [`PLUGIN_ABI_VERSION`].
+//! Состояние модели создается отдельно для каждого тикера и освобождается через `sq_model_destroy`
+
+use crate::quote::PriceModel;
+use anyhow::{Result, bail};
+use libloading::Library;
+use std::ffi::{CString, c_char, c_void};
+
+/// Версия C ABI плагинов моделей цены
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn(*const c_char, f64) -> *mut c_void;
+type NextPriceFn = unsafe extern "C" fn(*mut c_void, f64) -> f64;
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+
+/// Модель цены, загруженная из динамической библиотеки
+pub struct PluginPriceModel {
+    state: *mut c_void,
+    next_price: NextPriceFn,
+    destroy: DestroyFn,
+    _library: Library,
+}
+
+// Состояние плагина используется только из потока, владеющего моделью
+unsafe impl Send for PluginPriceModel {}
+
+impl PluginPriceModel {
+    /// Загружает плагин и создает состояние модели для тикера
+    pub fn load(path: &str, ticker_name: &str, upper_bound_price: f64) -> Result<Self> {
+        let ticker = CString::new(ticker_name)?;
+        unsafe {
+            let library = Library::new(path)?;
+            let abi_version = *library.get::<AbiVersionFn>(b"sq_model_abi_version")?;
+            let create = *library.get::<CreateFn>(b"sq_model_create")?;
+            let next_price = *library.get::<NextPriceFn>(b"sq_model_next_price")?;
+            let destroy = *library.get::<DestroyFn>(b"sq_model_destroy")?;
+
+            let version = abi_version();
+            if version != PLUGIN_ABI_VERSION {
+                bail!("Plugin {path} has ABI version {version}, expected {PLUGIN_ABI_VERSION}");
+            }
+
+            let state = create(ticker.as_ptr(), upper_bound_price);
+            if state.is_null() {
+                bail!("Plugin {path} can't create price model for {ticker_name}");
+            }
+            log::info!("Price model for {ticker_name} is loaded from {path}");
+
+            Ok(Self {
+                state,
+                next_price,
+                destroy,
+                _library: library,
+            })
+        }
+    }
+}
+
+impl PriceModel for PluginPriceModel {
+    fn next_price(&mut self, current_price: f64) -> f64 {
+        unsafe { (self.next_price)(self.state, current_price) }
+    }
+}
+
+impl Drop for PluginPriceModel {
+    fn drop(&mut self) {
+        unsafe { (self.destroy)(self.state) }
+    }
+}
@@ -1,26 +1,225 @@
-use super::quote::StockQuote;
-use anyhow::Result;
+use super::quote::{Bar, StockQuote};
+use anyhow::{Result, bail};
 use postcard::to_stdvec;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::io::Read;
+use std::net::SocketAddr;
 
 /// Максимальный размер датаграммы. Если пакет будет больше, то нужно учесть нумерацию пакетов
 pub const MAX_SIZE_DATAGRAM: usize = 100;
 
+/// Максимальный размер сообщения управляющего канала
+pub const MAX_CONTROL_MESSAGE_LEN: u32 = 64 * 1024;
+
+/// Тип сервиса mDNS, под которым сервер объявляет себя в локальной сети
+pub const MDNS_SERVICE_TYPE: &str = "_quotes._tcp.local.";
+
+/// Свойство mDNS: управляющий канал защищен TLS
+pub const MDNS_TLS_PROPERTY: &str = "tls";
+
+/// Свойство mDNS: порт точки подключения клиентов WebSocket
+pub const MDNS_WEBSOCKET_PROPERTY: &str = "ws_port";
+
 #[derive(Serialize, Deserialize, Debug)]
 /// Котировки ответ сервера
 pub struct QuoteRespMessage {
     /// котировка
     pub quote: StockQuote,
+    /// Номер котировки тикера в потоке, начиная с 1. По пропускам номеров клиент
+    /// считает потерянные датаграммы. 0 - котировка без номера
+    pub seq: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+/// Свеча ответ сервера
+pub struct BarRespMessage {
+    /// свеча
+    pub bar: Bar,
+}
+
+/// Как запрос тикеров меняет текущую подписку клиента
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubscriptionMode {
+    /// Подписка заменяется запрошенными тикерами
+    #[default]
+    Replace,
+    /// Запрошенные тикеры добавляются к подписке
+    Add,
+    /// Запрошенные тикеры удаляются из подписки
+    Remove,
+}
+
+impl SubscriptionMode {
+    /// Применяет запрошенные тикеры к подписке current
+    pub fn apply(&self, current: &mut Vec<String>, tickers: &[String]) {
+        match self {
+            Self::Replace => *current = tickers.to_vec(),
+            Self::Add => {
+                for ticker in tickers {
+                    if !current.contains(ticker) {
+                        current.push(ticker.clone());
+                    }
+                }
+            }
+            Self::Remove => current.retain(|ticker| !tickers.contains(ticker)),
+        }
+    }
+}
+
+/// Тикер запроса является шаблоном: `*` - любая последовательность символов,
+/// `?` - один любой символ, например "BANK_*"
+pub fn is_ticker_pattern(ticker: &str) -> bool {
+    ticker.contains(['*', '?'])
+}
+
+/// Тикер соответствует шаблону. Тикер без `*` и `?` соответствует только себе
+pub fn ticker_matches(pattern: &str, ticker: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let ticker: Vec<char> = ticker.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < ticker.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == ticker[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Раскрывает шаблоны запрошенных тикеров по известным тикерам.
+/// Возвращает тикеры без повторов и запрошенные тикеры и шаблоны, которым
+/// не соответствует ни один известный тикер
+pub fn expand_tickers(requested: &[String], known: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut tickers = Vec::new();
+    let mut unknown = Vec::new();
+    for name in requested.iter() {
+        let mut matched = known
+            .iter()
+            .filter(|ticker| ticker_matches(name, ticker))
+            .peekable();
+        if matched.peek().is_none() {
+            unknown.push(name.clone());
+        }
+        for ticker in matched {
+            if !tickers.contains(ticker) {
+                tickers.push(ticker.clone());
+            }
+        }
+    }
+    (tickers, unknown)
+}
+
+/// Фильтр котировок подписки: котировка отправляется, если цена изменилась
+/// достаточно с последней отправленной котировки тикера или объем, накопленный
+/// с ее отправки, достаточно велик. Первая котировка тикера отправляется всегда
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct DeltaFilter {
+    /// Минимальное изменение цены в процентах
+    pub min_price_change_percent: Option<f64>,
+    /// Минимальный объем, накопленный с последней отправленной котировки тикера
+    pub min_volume: Option<u32>,
+}
+
+/// Состояние `DeltaFilter` по одному тикеру
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DeltaState {
+    /// Цена последней отправленной котировки. None - котировки еще не отправлялись
+    pub last_price: Option<f64>,
+    /// Объем котировок, принятых после последней отправленной котировки
+    pub volume: u64,
+}
+
+impl DeltaState {
+    /// Котировка с ценой price отправлена: объем начинает копиться заново
+    pub fn sent(&mut self, price: f64) {
+        self.last_price = Some(price);
+        self.volume = 0;
+    }
+}
+
+impl DeltaFilter {
+    /// Нужно ли отправлять котировку. Объем котировки добавляется к накопленному в state
+    pub fn passes(&self, state: &mut DeltaState, quote: &StockQuote) -> bool {
+        state.volume += u64::from(quote.volume);
+        let Some(last_price) = state.last_price else {
+            return true;
+        };
+        if self.min_price_change_percent.is_none() && self.min_volume.is_none() {
+            return true;
+        }
+        let price_moved = self.min_price_change_percent.is_some_and(|percent| {
+            last_price == 0.0 || (quote.price - last_price).abs() / last_price * 100.0 >= percent
+        });
+        let volume_reached = self
+            .min_volume
+            .is_some_and(|volume| state.volume >= u64::from(volume));
+        price_moved || volume_reached
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 /// Запрос котировок
 pub struct TickerReqMessage {
     /// UDP порт, на который присылать котировки
     pub port: u16,
+    /// Дополнительные UDP порты. Если заданы, сервер распределяет котировки
+    /// по основному и дополнительным портам по кругу
+    pub stripe_ports: Vec<u16>,
     /// Названия фин. инструментов, по которым необходимо получать котировки
     /// Эти инструменты должны быть в конфигурации сервера
     pub tickers: Vec<String>,
+    /// Токен доступа к котировкам
+    pub token: Option<String>,
+    /// Токен сессии, выданный сервером при прошлом подключении. Если сервер еще хранит
+    /// подписку этой сессии, она восстанавливается вместо запрошенных тикеров
+    pub session: Option<String>,
+    /// Фильтр котировок подписки. Если не задан, отправляются все котировки
+    pub filter: Option<DeltaFilter>,
+    /// Присылать свечи OHLCV вместо котировок
+    pub bars: bool,
+}
+
+impl Debug for TickerReqMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TickerReqMessage")
+            .field("port", &self.port)
+            .field("stripe_ports", &self.stripe_ports)
+            .field("tickers", &self.tickers)
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("session", &self.session.as_ref().map(|_| "***"))
+            .field("filter", &self.filter)
+            .field("bars", &self.bars)
+            .finish()
+    }
+}
+
+/// Коды ошибок, которые сервер сообщает клиенту
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Превышено максимальное количество клиентов
+    TooManyClients,
+    /// Клиент не прошел аутентификацию
+    Unauthorized,
+    /// Длина пакета равна нулю или больше MAX_CONTROL_MESSAGE_LEN
+    BadFrameLength,
+    /// Пакет не удалось разобрать как сообщение протокола
+    DecodeFailure,
+    /// Сообщение этого типа не ожидается от клиента, например запрос до подписки
+    UnexpectedMessage,
+    /// Сервер перегружен и не принимает новые подписки
+    Overloaded,
 }
 
 /// Типы сообщений в протоколе
@@ -28,7 +227,7 @@ pub struct TickerReqMessage {
 pub enum Message {
     /// Котировка
     Quote(QuoteRespMessage),
-    /// Запрос котировок
+    /// Запрос котировок. Сервер отвечает `Subscribed`
     Tickers(TickerReqMessage),
     /// Пинг
     Ping,
@@ -36,6 +235,72 @@ pub enum Message {
     Pong,
     /// Не поддерживаемы тип
     Unknown,
+    /// Ошибка, после которой сервер закрывает соединение
+    Error {
+        /// Код ошибки
+        code: ErrorCode,
+    },
+    /// Сервер останавливается, котировок больше не будет
+    Shutdown,
+    /// Подтверждение подписки на котировки
+    Subscribed {
+        /// Запрошенные тикеры и шаблоны, которым не соответствует ни один тикер
+        /// конфигурации сервера. По ним котировки не присылаются
+        unknown_tickers: Vec<String>,
+        /// Тикеры запроса после раскрытия шаблонов. При восстановлении сессии - вся подписка
+        tickers: Vec<String>,
+        /// Адрес multicast группы, в которую сервер публикует котировки по всем тикерам.
+        /// Если не задан, котировки присылаются на порты клиента
+        multicast_group: Option<SocketAddr>,
+        /// Токен сессии для восстановления подписки при повторном подключении
+        session: String,
+        /// Подписка восстановлена из прошлой сессии
+        resumed: bool,
+    },
+    /// Свеча
+    Bar(BarRespMessage),
+    /// Запрос последних котировок тикера. Доступен после подписки
+    HistoryReq {
+        /// Тикер
+        ticker: String,
+        /// Количество последних котировок
+        last_n: u32,
+    },
+    /// Последние котировки тикера в порядке поступления
+    History {
+        /// Тикер
+        ticker: String,
+        /// Котировки. Их меньше запрошенного, если сервер хранит меньше
+        quotes: Vec<StockQuote>,
+    },
+    /// Котировка из истории, которую сервер отправляет при подписке до живых котировок
+    ReplayQuote(QuoteRespMessage),
+    /// Клиент отписывается от всех котировок и закрывает соединение.
+    /// Сервер сразу останавливает поток котировок и не сохраняет подписку
+    Unsubscribe,
+    /// Клиент просит приостановить отправку котировок и свечей, не разрывая соединение.
+    /// Доступен после подписки
+    Pause,
+    /// Клиент просит возобновить отправку котировок и свечей после `Pause`
+    Resume,
+    /// Клиент меняет подписку, не повторяя запрос котировок. Доступен после подписки,
+    /// сервер отвечает `SubscriptionChanged`
+    ChangeSubscription {
+        /// Как тикеры меняют текущую подписку
+        mode: SubscriptionMode,
+        /// Тикеры или шаблоны тикеров
+        tickers: Vec<String>,
+    },
+    /// Подтверждение изменения подписки
+    SubscriptionChanged {
+        /// Как тикеры изменили подписку
+        mode: SubscriptionMode,
+        /// Запрошенные тикеры и шаблоны, которым не соответствует ни один тикер
+        /// конфигурации сервера
+        unknown_tickers: Vec<String>,
+        /// Тикеры запроса после раскрытия шаблонов
+        tickers: Vec<String>,
+    },
 }
 
 /// Добавляет длину пакета перед самим бинарным пакетом.
@@ -47,3 +312,131 @@ pub fn pack_message_with_len<T: Serialize>(msg: &T) -> Result<Vec<u8>> {
     res.append(&mut bin_msg);
     Ok(res)
 }
+
+/// Читает из потока пакет с длиной, записанный pack_message_with_len.
+/// Пакет длиннее MAX_CONTROL_MESSAGE_LEN отклоняется без чтения
+pub fn read_message_with_len<T: DeserializeOwned, R: Read>(reader: &mut R) -> Result<T> {
+    let mut bin_len = [0u8; 4];
+    reader.read_exact(&mut bin_len)?;
+    let len = u32::from_be_bytes(bin_len);
+    if len > MAX_CONTROL_MESSAGE_LEN {
+        bail!("control message of {len} bytes is too long");
+    }
+    let mut bin_msg = vec![0u8; len as usize];
+    reader.read_exact(&mut bin_msg)?;
+    Ok(postcard::from_bytes(&bin_msg)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_tickers() {
+        assert!(ticker_matches("BANK_*", "BANK_A"));
+        assert!(ticker_matches("BANK_*", "BANK_"));
+        assert!(ticker_matches("*A*D", "AMD"));
+        assert!(ticker_matches("A?D", "AMD"));
+        assert!(!ticker_matches("A?D", "AMMD"));
+        assert!(!ticker_matches("BANK_*", "AMD"));
+        assert!(ticker_matches("AMD", "AMD"));
+        assert!(!ticker_matches("AMD", "AMDX"));
+        assert!(is_ticker_pattern("BANK_*"));
+        assert!(!is_ticker_pattern("AMD"));
+
+        let names = |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+        let known = names(&["AMD", "BANK_A", "BANK_B", "INT"]);
+        let (tickers, unknown) =
+            expand_tickers(&names(&["BANK_*", "AMD", "BANK_A", "FX_*", "GAZ"]), &known);
+        assert_eq!(tickers, names(&["BANK_A", "BANK_B", "AMD"]));
+        assert_eq!(unknown, names(&["FX_*", "GAZ"]));
+    }
+
+    #[test]
+    fn test_subscription_mode() {
+        let names = |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+        let mut current = names(&["AMD", "INT"]);
+
+        SubscriptionMode::Add.apply(&mut current, &names(&["INT", "GAZ"]));
+        assert_eq!(current, names(&["AMD", "INT", "GAZ"]));
+
+        SubscriptionMode::Remove.apply(&mut current, &names(&["AMD"]));
+        assert_eq!(current, names(&["INT", "GAZ"]));
+
+        SubscriptionMode::Replace.apply(&mut current, &names(&["SBER"]));
+        assert_eq!(current, names(&["SBER"]));
+    }
+
+    #[test]
+    fn test_delta_filter() {
+        let quote = |price: f64, volume: u32| StockQuote {
+            ticker: "AMD".to_string(),
+            price,
+            volume,
+            timestamp: 1,
+        };
+        let filter = DeltaFilter {
+            min_price_change_percent: Some(1.0),
+            min_volume: Some(1000),
+        };
+        let sent = || DeltaState {
+            last_price: Some(100.0),
+            volume: 0,
+        };
+        assert!(filter.passes(&mut DeltaState::default(), &quote(100.0, 10)));
+        assert!(!filter.passes(&mut sent(), &quote(100.5, 10)));
+        assert!(filter.passes(&mut sent(), &quote(98.9, 10)));
+        assert!(filter.passes(&mut sent(), &quote(100.0, 1000)));
+        assert!(DeltaFilter::default().passes(&mut sent(), &quote(100.0, 10)));
+
+        let mut state = sent();
+        for _ in 0..3 {
+            assert!(!filter.passes(&mut state, &quote(100.0, 300)));
+        }
+        assert!(filter.passes(&mut state, &quote(100.0, 300)));
+        state.sent(100.0);
+        assert!(!filter.passes(&mut state, &quote(100.0, 300)));
+    }
+
+    #[test]
+    fn test_wire_layout() {
+        let encode = |msg: &Message| postcard::to_stdvec(msg).unwrap();
+        assert_eq!(encode(&Message::Ping), vec![2]);
+        let subscribed = Message::Subscribed {
+            unknown_tickers: Vec::new(),
+            tickers: Vec::new(),
+            multicast_group: None,
+            session: "s".to_string(),
+            resumed: false,
+        };
+        assert_eq!(encode(&subscribed), vec![7, 0, 0, 0, 1, b's', 0]);
+        let change = Message::ChangeSubscription {
+            mode: SubscriptionMode::Add,
+            tickers: Vec::new(),
+        };
+        assert_eq!(encode(&change), vec![15, 1, 0]);
+    }
+
+    #[test]
+    fn test_read_message_with_len() {
+        let packed = pack_message_with_len(&Message::HistoryReq {
+            ticker: "AMD".to_string(),
+            last_n: 5,
+        })
+        .unwrap();
+        let mut reader = packed.as_slice();
+        match read_message_with_len(&mut reader).unwrap() {
+            Message::HistoryReq { ticker, last_n } => {
+                assert_eq!(ticker, "AMD");
+                assert_eq!(last_n, 5);
+            }
+            msg => panic!("Unexpected message: {msg:?}"),
+        }
+        assert!(reader.is_empty());
+
+        for len in [MAX_CONTROL_MESSAGE_LEN + 1, u32::MAX] {
+            let bin_len = len.to_be_bytes();
+            assert!(read_message_with_len::<Message, _>(&mut bin_len.as_slice()).is_err());
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Модуль Python `streaming_quotes` с классом `QuotesClient`:
+//! ```python
+//! import pandas as pd
+//! from streaming_quotes import QuotesClient
+//!
+//! client = QuotesClient("127.0.0.1:8090", ["AMD", "INT"])
+//! client.connect()
+//! for quote in client:
+//!     print(quote["ticker"], quote["price"])
+//!     break
+//! df = pd.DataFrame(client.poll(1000))
+//! print(client.stats())
+//! client.close()
+//! ```
+//! Котировки передаются словарями с ключами ticker, price, volume, timestamp.
+//! Модуль собирается через maturin с функциями `python,pyo3/extension-module`
+
+use crate::client::error::ClientResult;
+use crate::client::quotes_client::{ClientCmd, ClientControl, QuotesClient};
+use crate::quote::StockQuote;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const DEFAULT_BUFFER_CAPACITY: usize = 4096;
+const WAIT_QUOTE_MILLIS: u64 = 10;
+
+fn runtime_error(e: impl std::fmt::Display) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn quote_dict<'py>(py: Python<'py>, quote: StockQuote) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("ticker", quote.ticker)?;
+    dict.set_item("price", quote.price)?;
+    dict.set_item("volume", quote.volume)?;
+    dict.set_item("timestamp", quote.timestamp)?;
+    Ok(dict)
+}
+
+/// Клиент котировок для Python. Котировки принимаются в фоновом потоке в буфер
+/// ограниченной емкости, из которого их забирают `poll` или итерация
+#[pyclass(name = "QuotesClient")]
+pub struct PyQuotesClient {
+    server_addr: String,
+    tickers: Vec<String>,
+    port: u16,
+    buffer: usize,
+    control: Mutex<Option<ClientControl>>,
+}
+
+impl PyQuotesClient {
+    fn with_control<T>(&self, f: impl FnOnce(&ClientControl) -> PyResult<T>) -> PyResult<T> {
+        match self.control.lock().unwrap().as_ref() {
+            Some(control) => f(control),
+            None => Err(runtime_error("Client is not connected")),
+        }
+    }
+
+    fn send(&self, cmd: ClientCmd) -> PyResult<()> {
+        self.with_control(|control| control.tx.send(cmd).map_err(runtime_error))
+    }
+}
+
+/// Останавливает поток приема и ждет его завершения. Вызывается без GIL:
+/// обработчики потока приема могут ждать GIL
+fn stop_client(control: ClientControl) -> PyResult<ClientResult<()>> {
+    if control.tx.send(ClientCmd::Stop).is_err() {
+        log::debug!("Client is already stopped");
+    }
+    control
+        .thread_handle
+        .join()
+        .map_err(|_| runtime_error("Can't join client thread"))
+}
+
+#[pymethods]
+impl PyQuotesClient {
+    #[new]
+    #[pyo3(signature = (server_addr, tickers, port = 0, buffer = DEFAULT_BUFFER_CAPACITY))]
+    fn new(server_addr: String, tickers: Vec<String>, port: u16, buffer: usize) -> Self {
+        Self {
+            server_addr,
+            tickers,
+            port,
+            buffer,
+            control: Mutex::new(None),
+        }
+    }
+
+    /// Подключается к серверу и запускает прием котировок
+    fn connect(&self, py: Python<'_>) -> PyResult<()> {
+        let mut control = self.control.lock().unwrap();
+        if control.is_some() {
+            return Err(runtime_error("Client is already connected"));
+        }
+        let client = QuotesClient::with_tickers(&self.server_addr, self.port, &self.tickers)
+            .map_err(runtime_error)?;
+        let buffer = self.buffer;
+        *control = Some(
+            py.detach(|| client.start_receive_buffered(buffer))
+                .map_err(runtime_error)?,
+        );
+        Ok(())
+    }
+
+    /// Добавляет тикеры к подписке
+    fn subscribe(&self, tickers: Vec<String>) -> PyResult<()> {
+        self.send(ClientCmd::subscribe(tickers))
+    }
+
+    /// Удаляет тикеры из подписки
+    fn unsubscribe(&self, tickers: Vec<String>) -> PyResult<()> {
+        self.send(ClientCmd::unsubscribe(tickers))
+    }
+
+    /// Приостанавливает прием котировок, не разрывая соединение
+    fn pause(&self) -> PyResult<()> {
+        self.send(ClientCmd::Pause)
+    }
+
+    /// Возобновляет прием котировок
+    fn resume(&self) -> PyResult<()> {
+        self.send(ClientCmd::Resume)
+    }
+
+    /// Забирает без ожидания до max принятых котировок
+    #[pyo3(signature = (max = DEFAULT_BUFFER_CAPACITY))]
+    fn poll<'py>(&self, py: Python<'py>, max: usize) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let quotes = self.with_control(|control| Ok(control.try_recv_quotes(max)))?;
+        quotes
+            .into_iter()
+            .map(|quote| quote_dict(py, quote))
+            .collect()
+    }
+
+    /// Состояние соединения: connecting, subscribed, streaming, reconnecting
+    /// или dead с причиной
+    fn state(&self) -> PyResult<String> {
+        self.with_control(|control| Ok(control.state.get().to_string()))
+    }
+
+    /// Метрики клиента: количество и скорость приема котировок, потери, переполнения буфера
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.with_control(|control| {
+            let stats = control.client_stats();
+            let dict = PyDict::new(py);
+            dict.set_item("quotes", stats.quotes)?;
+            dict.set_item("bytes", stats.bytes)?;
+            dict.set_item("quotes_per_sec", stats.quotes_per_sec)?;
+            dict.set_item("bytes_per_sec", stats.bytes_per_sec)?;
+            dict.set_item(
+                "latency",
+                stats.latency.map(|latency| latency.as_secs_f64()),
+            )?;
+            dict.set_item("decode_errors", stats.decode_errors)?;
+            dict.set_item("socket_errors", stats.socket_errors)?;
+            dict.set_item("resubscribes", stats.resubscribes)?;
+            dict.set_item("loss_ratio", stats.loss_ratio)?;
+            dict.set_item(
+                "last_quote_age",
+                stats.last_quote_age.map(|age| age.as_secs_f64()),
+            )?;
+            dict.set_item("overflowed", control.overflowed_quotes())?;
+            Ok(dict)
+        })
+    }
+
+    /// Сводка по тикерам за сессию: количество котировок, наименьшая, наибольшая
+    /// и средняя цена, суммарный объем
+    fn summary<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.with_control(|control| {
+            let dict = PyDict::new(py);
+            for (ticker, summary) in control.summary.snapshot() {
+                let item = PyDict::new(py);
+                item.set_item("updates", summary.updates)?;
+                item.set_item("min_price", summary.min_price)?;
+                item.set_item("max_price", summary.max_price)?;
+                item.set_item("mean_price", summary.mean_price)?;
+                item.set_item("total_volume", summary.total_volume)?;
+                dict.set_item(ticker, item)?;
+            }
+            Ok(dict)
+        })
+    }
+
+    /// Останавливает прием котировок
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        let Some(control) = self.control.lock().unwrap().take() else {
+            return Ok(());
+        };
+        py.detach(|| stop_client(control))?.map_err(runtime_error)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Ждет следующую котировку. Итерация заканчивается, когда клиент остановлен
+    fn __next__<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        loop {
+            let (quote, finished) = self.with_control(|control| {
+                Ok((
+                    control.try_recv_quotes(1).pop(),
+                    control.thread_handle.is_finished(),
+                ))
+            })?;
+            if let Some(quote) = quote {
+                return quote_dict(py, quote).map(Some);
+            }
+            if finished {
+                return Ok(None);
+            }
+            py.check_signals()?;
+            py.detach(|| std::thread::sleep(Duration::from_millis(WAIT_QUOTE_MILLIS)));
+        }
+    }
+}
+
+impl Drop for PyQuotesClient {
+    fn drop(&mut self) {
+        let mut control = self.control.lock().unwrap().take();
+        if control.is_none() {
+            return;
+        }
+        let mut stop = || control.take().map(stop_client);
+        let res = Python::try_attach(|py| py.detach(&mut stop)).unwrap_or_else(stop);
+        if let Some(Err(e)) = res {
+            log::error!("{e}");
+        }
+    }
+}
+
+/// Модуль Python `streaming_quotes`
+#[pymodule]
+pub fn streaming_quotes(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyQuotesClient>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServerBuilder;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, mpsc};
+
+    fn quotes(count: u64) -> Vec<StockQuote> {
+        (1..=count)
+            .map(|timestamp| StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 1,
+                timestamp,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_close_and_drop_release_gil() {
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .with_quotes(quotes(3))
+            .start()
+            .unwrap();
+        Python::initialize();
+        Python::attach(|py| {
+            let client =
+                PyQuotesClient::new(server.addr().to_string(), vec!["AMD".to_string()], 0, 16);
+            client.connect(py).unwrap();
+            let received = (0..3).filter_map(|_| client.__next__(py).unwrap()).count();
+            assert_eq!(received, 3);
+            assert_eq!(
+                client
+                    .stats(py)
+                    .unwrap()
+                    .get_item("quotes")
+                    .unwrap()
+                    .unwrap()
+                    .extract::<u64>()
+                    .unwrap(),
+                3
+            );
+            client.close(py).unwrap();
+            assert!(client.poll(py, 10).is_err());
+
+            let dropped =
+                PyQuotesClient::new(server.addr().to_string(), vec!["AMD".to_string()], 0, 16);
+            dropped.connect(py).unwrap();
+            let attached = Arc::new(AtomicBool::new(false));
+            let waiter_attached = attached.clone();
+            let (tx, rx) = mpsc::channel();
+            let waiter = std::thread::spawn(move || {
+                tx.send(()).unwrap();
+                Python::attach(|_| waiter_attached.store(true, Ordering::Relaxed));
+            });
+            rx.recv().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!attached.load(Ordering::Relaxed));
+            drop(dropped);
+            assert!(attached.load(Ordering::Relaxed));
+            py.detach(|| waiter.join().unwrap());
+        });
+    }
+}
@@ -1,12 +1,17 @@
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use rand::prelude::*;
-use rand_distr::{Normal, StandardUniform};
+use rand::rngs::StdRng;
+use rand_distr::{Normal, StandardUniform, StudentT, Uniform};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
 /// Информация о котировке
 pub struct StockQuote {
     /// Короткое название фин. инструмента
@@ -29,11 +34,314 @@ impl Display for StockQuote {
     }
 }
 
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+/// Свеча OHLCV по котировкам тикера за интервал
+pub struct Bar {
+    /// Короткое название фин. инструмента
+    pub ticker: String,
+    /// Цена первой котировки интервала
+    pub open: f64,
+    /// Максимальная цена за интервал
+    pub high: f64,
+    /// Минимальная цена за интервал
+    pub low: f64,
+    /// Цена последней котировки интервала
+    pub close: f64,
+    /// Суммарный объем за интервал
+    pub volume: u64,
+    /// Начало интервала, мс с начала эпохи Unix
+    pub start_millis: u64,
+    /// Длина интервала, мс
+    pub interval_millis: u64,
+}
+
+impl Bar {
+    /// Свеча, открытая котировкой quote в интервале, который начинается в start_millis
+    pub fn open(quote: &StockQuote, start_millis: u64, interval_millis: u64) -> Self {
+        Self {
+            ticker: quote.ticker.clone(),
+            open: quote.price,
+            high: quote.price,
+            low: quote.price,
+            close: quote.price,
+            volume: u64::from(quote.volume),
+            start_millis,
+            interval_millis,
+        }
+    }
+
+    /// Учитывает следующую котировку интервала
+    pub fn update(&mut self, quote: &StockQuote) {
+        self.high = self.high.max(quote.price);
+        self.low = self.low.min(quote.price);
+        self.close = quote.price;
+        self.volume += u64::from(quote.volume);
+    }
+}
+
+impl Display for Bar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "T: {}, O: {:.4}, H: {:.4}, L: {:.4}, C: {:.4}, V: {}, START: {}, INTERVAL: {}",
+            self.ticker,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.start_millis,
+            self.interval_millis
+        )
+    }
+}
+
+/// Обработчик котировок, вызываемый генератором для каждой новой котировки
+pub trait QuoteCallback: Send {
+    /// Вызывается после генерации котировки
+    fn on_quote(&mut self, quote: &StockQuote);
+}
+
+/// Модель изменения цены фин. инструмента
+pub trait PriceModel: Send {
+    /// Следующая цена по текущей. Генератор ограничивает результат диапазоном [0, upper_bound_price]
+    fn next_price(&mut self, current_price: f64) -> f64;
+}
+
+/// Случайное блуждание цены с нормально распределенным шагом
+pub struct NormalPriceModel {
+    step: f64,
+    normal_distr: Normal<f64>,
+}
+
+impl NormalPriceModel {
+    /// Модель для инструмента с указанной максимальной ценой
+    pub fn new(upper_bound_price: f64) -> Result<Self> {
+        Ok(Self {
+            step: upper_bound_price / 64.0,
+            normal_distr: Normal::new(0.0, 0.5)?,
+        })
+    }
+}
+
+impl PriceModel for NormalPriceModel {
+    fn next_price(&mut self, current_price: f64) -> f64 {
+        let val_price: f64 = rand::rng().sample(self.normal_distr);
+        current_price + self.step * val_price
+    }
+}
+
+/// Распределение случайного шага цены. Шаг выражен в долях 1/64 максимальной цены
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Innovation {
+    /// Нормальное распределение с нулевым средним
+    Normal {
+        /// Стандартное отклонение
+        std_dev: f64,
+    },
+    /// Равномерное распределение на [-half_width, half_width)
+    Uniform {
+        /// Половина ширины диапазона
+        half_width: f64,
+    },
+    /// Распределение Стьюдента с тяжелыми хвостами: редкие резкие скачки цены
+    StudentT {
+        /// Число степеней свободы, чем меньше, тем тяжелее хвосты
+        degrees_of_freedom: f64,
+        /// Масштаб
+        scale: f64,
+    },
+}
+
+impl Default for Innovation {
+    fn default() -> Self {
+        Self::Normal { std_dev: 0.5 }
+    }
+}
+
+enum InnovationSampler {
+    Normal(Normal<f64>),
+    Uniform(Uniform<f64>),
+    StudentT(StudentT<f64>, f64),
+}
+
+impl InnovationSampler {
+    fn new(innovation: Innovation) -> Result<Self> {
+        Ok(match innovation {
+            Innovation::Normal { std_dev } => Self::Normal(Normal::new(0.0, std_dev)?),
+            Innovation::Uniform { half_width } => {
+                if half_width <= 0.0 {
+                    bail!("Half width of uniform distribution must be positive: {half_width}");
+                }
+                Self::Uniform(Uniform::new(-half_width, half_width)?)
+            }
+            Innovation::StudentT {
+                degrees_of_freedom,
+                scale,
+            } => {
+                if scale.is_nan() || scale <= 0.0 {
+                    bail!("Scale of Student-t distribution must be positive: {scale}");
+                }
+                Self::StudentT(StudentT::new(degrees_of_freedom)?, scale)
+            }
+        })
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match self {
+            Self::Normal(distr) => rng.sample(distr),
+            Self::Uniform(distr) => rng.sample(distr),
+            Self::StudentT(distr, scale) => scale * rng.sample(distr),
+        }
+    }
+}
+
+/// Случайное блуждание цены с шагом по заданному распределению
+pub struct RandomWalkPriceModel {
+    step: f64,
+    sampler: InnovationSampler,
+    rng: StdRng,
+}
+
+impl RandomWalkPriceModel {
+    /// Модель для инструмента с указанной максимальной ценой
+    pub fn new(upper_bound_price: f64, innovation: Innovation) -> Result<Self> {
+        Ok(Self {
+            step: upper_bound_price / 64.0,
+            sampler: InnovationSampler::new(innovation)?,
+            rng: StdRng::from_os_rng(),
+        })
+    }
+
+    /// Генерировать шаги из последовательности, заданной seed: модели с одинаковым seed
+    /// выдают одинаковые цены
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl PriceModel for RandomWalkPriceModel {
+    fn next_price(&mut self, current_price: f64) -> f64 {
+        current_price + self.step * self.sampler.sample(&mut self.rng)
+    }
+}
+
+/// Seed тикера по общему seed генератора. Не зависит от порядка тикеров
+/// в конфигурации и от версии компилятора
+fn ticker_seed(seed: u64, ticker_name: &str) -> u64 {
+    ticker_name
+        .bytes()
+        .fold(seed ^ 0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[cfg(feature = "plugins")]
+fn load_price_model(
+    path: &str,
+    ticker_name: &str,
+    upper_bound_price: f64,
+) -> Result<Box<dyn PriceModel>> {
+    let model = crate::plugin::PluginPriceModel::load(path, ticker_name, upper_bound_price)?;
+    Ok(Box::new(model))
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load_price_model(
+    path: &str,
+    _ticker_name: &str,
+    _upper_bound_price: f64,
+) -> Result<Box<dyn PriceModel>> {
+    bail!("Can't load price model plugin {path}: crate is built without \"plugins\" feature");
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Источник временных меток сгенерированных котировок
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TimestampMode {
+    /// Номер котировки, начиная с 1
+    #[default]
+    Counter,
+    /// Текущее время, мс с начала эпохи Unix. Метки строго возрастают: если время
+    /// не сдвинулось с прошлой котировки, метка на 1 больше прошлой
+    WallClock,
+    /// Моделируемое время, мс с начала эпохи Unix. Начинается с start_millis
+    /// или с текущего времени и идет в speed раз быстрее реального
+    Simulated {
+        /// Начало моделируемого времени. Если не задано - время запуска генератора
+        #[serde(default)]
+        start_millis: Option<u64>,
+        /// Скорость моделируемого времени относительно реального
+        speed: f64,
+    },
+}
+
+struct QuoteClock {
+    mode: TimestampMode,
+    counter: AtomicU64,
+    last_millis: AtomicU64,
+    started: Instant,
+    start_millis: u64,
+}
+
+impl QuoteClock {
+    fn new(mode: TimestampMode) -> Result<Self> {
+        let start_millis = match mode {
+            TimestampMode::Simulated { speed, .. } if !speed.is_finite() || speed <= 0.0 => {
+                bail!("Speed of simulated time must be positive: {speed}");
+            }
+            TimestampMode::Simulated {
+                start_millis: Some(millis),
+                ..
+            } => millis,
+            _ => unix_millis(),
+        };
+        Ok(Self {
+            mode,
+            counter: AtomicU64::new(1),
+            last_millis: AtomicU64::new(0),
+            started: Instant::now(),
+            start_millis,
+        })
+    }
+
+    fn next_timestamp(&self) -> u64 {
+        match self.mode {
+            TimestampMode::Counter => self.counter.fetch_add(1, Ordering::Relaxed),
+            TimestampMode::WallClock => {
+                let now = unix_millis();
+                let next = |last: u64| Some(now.max(last + 1));
+                match self
+                    .last_millis
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, next)
+                {
+                    Ok(last) | Err(last) => now.max(last + 1),
+                }
+            }
+            TimestampMode::Simulated { speed, .. } => {
+                let elapsed_millis = self.started.elapsed().as_secs_f64() * 1000.0 * speed;
+                self.start_millis + elapsed_millis as u64
+            }
+        }
+    }
+}
+
 struct Ticker {
     upper_bound_price: f64,
     upper_bound_volume: u32,
     lower_bound_volume: u32,
     current_price: f64,
+    price_model: Box<dyn PriceModel>,
+    rng: StdRng,
 }
 
 impl Ticker {
@@ -44,14 +352,13 @@ impl Ticker {
             upper_bound_volume: json["upper_bound_volume"].as_u64()? as u32,
             lower_bound_volume: json["lower_bound_volume"].as_u64()? as u32,
             current_price: upper_bound_price / 2.0,
+            price_model: Box::new(NormalPriceModel::new(upper_bound_price).ok()?),
+            rng: StdRng::from_os_rng(),
         })
     }
 }
 
 impl Ticker {
-    fn price_range(&self) -> f64 {
-        self.upper_bound_price
-    }
     fn volume_range(&self) -> u32 {
         self.upper_bound_volume - self.lower_bound_volume
     }
@@ -61,12 +368,14 @@ impl Ticker {
 /// и равномерное распределение для объема
 pub struct QuoteGenerator {
     tickers: HashMap<String, Ticker>,
-    timestamp_counter: u64,
-    normal_distr: Normal<f64>,
+    clock: Arc<QuoteClock>,
+    callbacks: Vec<Box<dyn QuoteCallback>>,
 }
 
 impl QuoteGenerator {
-    /// Создать новый генератор с указанием пути к конфигурации json
+    /// Создать новый генератор с указанием пути к конфигурации json.
+    /// Если задан seed, каждый тикер выдает одну и ту же последовательность котировок
+    /// при каждом запуске
     /// ```
     /// [
     ///     {
@@ -79,11 +388,27 @@ impl QuoteGenerator {
     ///         "name": "INT",
     ///         "upper_bound_price": 2000.0,
     ///         "upper_bound_volume": 2000000,
-    ///         "lower_bound_volume": 1000
+    ///         "lower_bound_volume": 1000,
+    ///         "price_model_plugin": "./libmy_model.so"
+    ///     },
+    ///     {
+    ///         "name": "GAZ",
+    ///         "upper_bound_price": 3000.0,
+    ///         "upper_bound_volume": 3000000,
+    ///         "lower_bound_volume": 3000,
+    ///         "distribution": {"kind": "student_t", "degrees_of_freedom": 3.0, "scale": 0.3},
+    ///         "seed": 42
     ///     }
     ///]
     /// ```
-    pub fn new(config_path: &str) -> Result<Self> {
+    /// Необязательное поле `price_model_plugin` задает путь к динамической библиотеке
+    /// с моделью цены (требуется feature `plugins`). Необязательное поле `distribution`
+    /// задает распределение шага цены, см. `Innovation`: `normal` с `std_dev`,
+    /// `uniform` с `half_width` или `student_t` с `degrees_of_freedom` и `scale`.
+    /// По умолчанию шаг распределен нормально со стандартным отклонением 0.5.
+    /// Необязательное поле `seed` задает seed тикера вместо полученного из seed генератора.
+    /// Цены модели из плагина не зависят от seed
+    pub fn new(config_path: &str, seed: Option<u64>) -> Result<Self> {
         let json_str = std::fs::read_to_string(config_path)?;
         let json = serde_json::from_str::<Vec<Value>>(&json_str)?;
         let mut tickers = HashMap::new();
@@ -94,31 +419,144 @@ impl QuoteGenerator {
             } else {
                 bail!("Can't read ticker name from config: {json_str}");
             };
-            let ticker = if let Some(val) = Ticker::from_json(ticker_json) {
+            let plugin_path = ticker_json["price_model_plugin"]
+                .as_str()
+                .map(|val| val.to_string());
+            let innovation = match ticker_json.get("distribution") {
+                Some(val) => Some(serde_json::from_value::<Innovation>(val.clone()).map_err(
+                    |e| anyhow!("Can't read distribution of ticker {ticker_name}: {e}"),
+                )?),
+                None => None,
+            };
+            if innovation.is_some() && plugin_path.is_some() {
+                bail!("Ticker {ticker_name} has both distribution and price model plugin");
+            }
+            let ticker_seed = match ticker_json.get("seed") {
+                Some(val) => Some(val.as_u64().ok_or_else(|| {
+                    anyhow!("Seed of ticker {ticker_name} must be an unsigned integer")
+                })?),
+                None => seed.map(|seed| ticker_seed(seed, &ticker_name)),
+            };
+            let mut ticker = if let Some(val) = Ticker::from_json(ticker_json) {
                 val
             } else {
                 bail!("Can't read ticker params from config: {json_str}");
             };
+            if let Some(path) = plugin_path.as_deref() {
+                ticker.price_model =
+                    load_price_model(path, &ticker_name, ticker.upper_bound_price)?;
+            }
+            if let Some(seed) = ticker_seed {
+                ticker.rng = StdRng::seed_from_u64(seed);
+            }
+            if plugin_path.is_none() && (innovation.is_some() || ticker_seed.is_some()) {
+                let mut model = RandomWalkPriceModel::new(
+                    ticker.upper_bound_price,
+                    innovation.unwrap_or_default(),
+                )
+                .map_err(|e| anyhow!("Invalid distribution of ticker {ticker_name}: {e}"))?;
+                if ticker_seed.is_some() {
+                    model = model.with_seed(ticker.rng.next_u64());
+                }
+                ticker.price_model = Box::new(model);
+            }
+            if tickers.contains_key(&ticker_name) {
+                bail!("Ticker {ticker_name} is defined twice in {config_path}");
+            }
             tickers.insert(ticker_name, ticker);
         }
         Ok(Self {
             tickers,
-            timestamp_counter: 1,
-            normal_distr: Normal::new(0.0, 0.5)?,
+            clock: Arc::new(QuoteClock::new(TimestampMode::Counter)?),
+            callbacks: Vec::new(),
+        })
+    }
+
+    /// Создать генератор по нескольким конфигурациям json. Тикеры конфигураций объединяются,
+    /// один тикер не может быть задан в нескольких конфигурациях. Seed - как в `QuoteGenerator::new`
+    pub fn from_files(config_paths: &[&str], seed: Option<u64>) -> Result<Self> {
+        if config_paths.is_empty() {
+            bail!("No generator config is set");
+        }
+        let mut tickers = HashMap::new();
+        let mut origins: HashMap<String, &str> = HashMap::new();
+        for config_path in config_paths {
+            let generator = Self::new(config_path, seed)
+                .map_err(|e| anyhow!("Can't load generator config {config_path}: {e}"))?;
+            for (name, ticker) in generator.tickers {
+                if let Some(origin) = origins.get(&name) {
+                    bail!("Ticker {name} is defined in both {origin} and {config_path}");
+                }
+                origins.insert(name.clone(), config_path);
+                tickers.insert(name, ticker);
+            }
+        }
+        Ok(Self {
+            tickers,
+            clock: Arc::new(QuoteClock::new(TimestampMode::Counter)?),
+            callbacks: Vec::new(),
         })
     }
 
+    /// Источник временных меток котировок. По умолчанию метка - номер котировки
+    pub fn with_timestamps(mut self, mode: TimestampMode) -> Result<Self> {
+        self.clock = Arc::new(QuoteClock::new(mode)?);
+        Ok(self)
+    }
+
+    /// Добавляет обработчик, который будет вызываться для каждой сгенерированной котировки
+    pub fn add_callback(&mut self, callback: Box<dyn QuoteCallback>) {
+        self.callbacks.push(callback);
+    }
+
+    /// Разбивает генератор на shards частей по хешу названия тикера.
+    /// Части используют общий источник временных меток. Обработчики котировок у каждой части
+    /// свои, их создает callbacks, чтобы части не ждали друг друга.
+    /// Обработчики исходного генератора не переносятся
+    pub fn into_shards<F>(self, shards: usize, mut callbacks: F) -> Vec<QuoteGenerator>
+    where
+        F: FnMut() -> Vec<Box<dyn QuoteCallback>>,
+    {
+        let shards = shards.max(1);
+        let mut res: Vec<QuoteGenerator> = (0..shards)
+            .map(|_| QuoteGenerator {
+                tickers: HashMap::new(),
+                clock: self.clock.clone(),
+                callbacks: callbacks(),
+            })
+            .collect();
+        for (name, ticker) in self.tickers {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            let shard = (hasher.finish() % shards as u64) as usize;
+            res[shard].tickers.insert(name, ticker);
+        }
+        res
+    }
+
+    /// Названия всех тикеров из конфигурации генератора
+    pub fn ticker_names(&self) -> Vec<String> {
+        self.tickers.keys().cloned().collect()
+    }
+
+    /// Возвращает тикеры из списка, которых нет в конфигурации генератора
+    pub fn unknown_tickers(&self, tickers: &[String]) -> Vec<String> {
+        tickers
+            .iter()
+            .filter(|name| !self.tickers.contains_key(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
     /// Генерация котировки по выбранному тикеру
     pub fn generate_quote(&mut self, ticker_name: &str) -> Option<StockQuote> {
         let ticker = self.tickers.get_mut(ticker_name)?;
         let mut quote = StockQuote::default();
         quote.ticker = ticker_name.to_string();
 
-        quote.timestamp = self.timestamp_counter;
-        self.timestamp_counter += 1;
+        quote.timestamp = self.clock.next_timestamp();
 
-        let val_price: f64 = rand::rng().sample(self.normal_distr);
-        quote.price = ticker.current_price + (ticker.price_range() / 64.0) * val_price;
+        quote.price = ticker.price_model.next_price(ticker.current_price);
         if quote.price < 0.0 {
             quote.price = 0.0;
         }
@@ -127,9 +565,12 @@ impl QuoteGenerator {
         }
         ticker.current_price = quote.price;
 
-        let val_volume: u32 = rand::rng().sample(StandardUniform);
+        let val_volume: u32 = ticker.rng.sample(StandardUniform);
         quote.volume = val_volume % ticker.volume_range() + ticker.lower_bound_volume;
 
+        for callback in self.callbacks.iter_mut() {
+            callback.on_quote(&quote);
+        }
         Some(quote)
     }
 }
@@ -145,6 +586,21 @@ mod tests {
 
     const EPSILON: f64 = 1e-6;
 
+    fn ticker_json(name: &str) -> Value {
+        json!({
+            "name": name,
+            "upper_bound_price": 100.0,
+            "upper_bound_volume": 1000,
+            "lower_bound_volume": 10
+        })
+    }
+
+    fn write_config(dir: &std::path::Path, file_name: &str, tickers: Vec<Value>) -> String {
+        let path = dir.join(file_name);
+        std::fs::write(&path, Value::Array(tickers).to_string()).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
     #[test]
     fn test_ticker_from_json() {
         let val = json!({
@@ -181,9 +637,189 @@ mod tests {
         file.write_all(config.as_bytes()).unwrap();
         file.flush().unwrap();
 
-        let mut generator = QuoteGenerator::new(path.to_str().unwrap()).unwrap();
+        let mut generator = QuoteGenerator::new(path.to_str().unwrap(), None).unwrap();
         assert!(generator.generate_quote("AMD").is_some());
         assert!(generator.generate_quote("INT").is_some());
         assert!(generator.generate_quote("GAZ").is_none());
+        let requested = vec!["AMD".to_string(), "GAZ".to_string()];
+        assert_eq!(
+            generator.unknown_tickers(&requested),
+            vec!["GAZ".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_distributions() {
+        let dir = tempdir().unwrap();
+        let with_distributions = |distributions: Vec<Value>| {
+            let tickers = distributions
+                .into_iter()
+                .enumerate()
+                .map(|(i, distribution)| {
+                    let mut ticker = ticker_json(&format!("T{i}"));
+                    ticker["upper_bound_price"] = json!(64.0);
+                    ticker["distribution"] = distribution;
+                    ticker
+                })
+                .collect();
+            QuoteGenerator::new(&write_config(dir.path(), "config.json", tickers), None)
+        };
+
+        let mut generator = with_distributions(vec![
+            json!({"kind": "normal", "std_dev": 0.1}),
+            json!({"kind": "uniform", "half_width": 1.0}),
+            json!({"kind": "student_t", "degrees_of_freedom": 3.0, "scale": 0.2}),
+        ])
+        .unwrap();
+        let mut price = 32.0;
+        for _ in 0..100 {
+            let next = generator.generate_quote("T1").unwrap().price;
+            assert!((next - price).abs() <= 1.0);
+            price = next;
+        }
+        assert!(generator.generate_quote("T0").is_some());
+        assert!(generator.generate_quote("T2").is_some());
+
+        assert!(with_distributions(vec![json!({"kind": "uniform", "half_width": 0.0})]).is_err());
+        assert!(
+            with_distributions(vec![
+                json!({"kind": "student_t", "degrees_of_freedom": 3.0, "scale": -1.0})
+            ])
+            .is_err()
+        );
+        assert!(with_distributions(vec![json!({"kind": "cauchy"})]).is_err());
+    }
+
+    #[test]
+    fn test_seed() {
+        let dir = tempdir().unwrap();
+        let mut int = ticker_json("INT");
+        int["distribution"] = json!({"kind": "uniform", "half_width": 1.0});
+        int["seed"] = json!(7);
+        let path = write_config(dir.path(), "config.json", vec![ticker_json("AMD"), int]);
+        let generate = |seed: Option<u64>| {
+            let mut generator = QuoteGenerator::new(&path, seed).unwrap();
+            ["AMD", "INT"].map(|ticker| {
+                (0..20)
+                    .map(|_| {
+                        let quote = generator.generate_quote(ticker).unwrap();
+                        (quote.price, quote.volume)
+                    })
+                    .collect::<Vec<(f64, u32)>>()
+            })
+        };
+        let [amd, int] = generate(Some(1));
+        assert_eq!([amd.clone(), int.clone()], generate(Some(1)));
+        let [other_amd, other_int] = generate(Some(2));
+        assert_ne!(amd, other_amd);
+        assert_eq!(int, other_int);
+        assert_eq!(int, generate(None)[1]);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "config.json", vec![ticker_json("AMD")]);
+        let generator = || QuoteGenerator::new(&path, None).unwrap();
+
+        let before = unix_millis();
+        let mut wall_clock = generator()
+            .with_timestamps(TimestampMode::WallClock)
+            .unwrap();
+        let timestamp = wall_clock.generate_quote("AMD").unwrap().timestamp;
+        assert!(timestamp >= before && timestamp <= unix_millis());
+        let timestamps: Vec<u64> = (0..1000)
+            .map(|_| wall_clock.generate_quote("AMD").unwrap().timestamp)
+            .collect();
+        assert!(timestamps[0] > timestamp);
+        assert!(timestamps.windows(2).all(|w| w[0] < w[1]));
+
+        let mut simulated = generator()
+            .with_timestamps(TimestampMode::Simulated {
+                start_millis: Some(1_000_000),
+                speed: 1000.0,
+            })
+            .unwrap();
+        let first = simulated.generate_quote("AMD").unwrap().timestamp;
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let second = simulated.generate_quote("AMD").unwrap().timestamp;
+        assert!((1_000_000..1_010_000).contains(&first));
+        assert!(second - first >= 10_000);
+
+        let mode: TimestampMode =
+            serde_json::from_value(json!({"mode": "simulated", "speed": 0.0})).unwrap();
+        assert!(generator().with_timestamps(mode).is_err());
+    }
+
+    #[test]
+    fn test_shards() {
+        struct Count(Arc<AtomicU64>);
+
+        impl QuoteCallback for Count {
+            fn on_quote(&mut self, _quote: &StockQuote) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let dir = tempdir().unwrap();
+        let names = ["AMD", "INT", "GAZ", "SBER", "LKOH"];
+        let tickers = names.iter().map(|name| ticker_json(name)).collect();
+        let path = write_config(dir.path(), "config.json", tickers);
+
+        let generator = QuoteGenerator::new(&path, None).unwrap();
+        let mut counters = Vec::new();
+        let mut shards = generator.into_shards(3, || {
+            let counter = Arc::new(AtomicU64::new(0));
+            counters.push(counter.clone());
+            vec![Box::new(Count(counter)) as Box<dyn QuoteCallback>]
+        });
+        assert_eq!(shards.len(), 3);
+
+        let mut all_names: Vec<String> = shards
+            .iter()
+            .flat_map(|shard| shard.ticker_names())
+            .collect();
+        all_names.sort();
+        let mut expected: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        expected.sort();
+        assert_eq!(all_names, expected);
+
+        let mut timestamps = Vec::new();
+        for shard in shards.iter_mut() {
+            for name in shard.ticker_names() {
+                timestamps.push(shard.generate_quote(&name).unwrap().timestamp);
+            }
+        }
+        timestamps.sort();
+        assert_eq!(timestamps, vec![1, 2, 3, 4, 5]);
+        for (shard, counter) in shards.iter().zip(counters) {
+            assert_eq!(
+                counter.load(Ordering::Relaxed),
+                shard.ticker_names().len() as u64
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_files() {
+        let dir = tempdir().unwrap();
+        let write_config = |file_name: &str, names: &[&str]| {
+            let tickers = names.iter().map(|name| ticker_json(name)).collect();
+            write_config(dir.path(), file_name, tickers)
+        };
+        let equities = write_config("equities.json", &["AMD", "INT"]);
+        let fx = write_config("fx.json", &["EURUSD"]);
+        let duplicate = write_config("duplicate.json", &["EURUSD", "AMD"]);
+
+        let generator = QuoteGenerator::from_files(&[&equities, &fx], None).unwrap();
+        let mut names = generator.ticker_names();
+        names.sort();
+        assert_eq!(names, vec!["AMD", "EURUSD", "INT"]);
+
+        let err = QuoteGenerator::from_files(&[&equities, &fx, &duplicate], None)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("is defined in both"));
+        assert!(QuoteGenerator::from_files(&[], None).is_err());
     }
 }
@@ -0,0 +1,281 @@
+use super::error::{ServerError, ServerResult};
+use super::quotes_server::{ClientInfo, ControlCmd, cmd_from_channel};
+use crate::timer::Timer;
+use anyhow::{Result, bail};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const ACCEPT_MILLIS: u64 = 100;
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const READ_REQUEST_TIMEOUT_MILLIS: u64 = 1000;
+const SERVER_REPLY_TIMEOUT_MILLIS: u64 = 3000;
+
+const ACCEPT_EVENT: &str = "accept";
+const WAIT_CMD_EVENT: &str = "cmd";
+
+#[derive(Debug, PartialEq)]
+enum AdminCmd {
+    List,
+    Kick(SocketAddr),
+    Stats(SocketAddr),
+    Transcript(SocketAddr),
+}
+
+impl AdminCmd {
+    /// Разбирает команду. Если задан токен, команда начинается с него
+    fn parse(line: &str, token: Option<&str>) -> Result<Self> {
+        let mut parts: Vec<&str> = line.split_whitespace().collect();
+        if let Some(token) = token {
+            if parts.first() != Some(&token) {
+                bail!("Unauthorized");
+            }
+            parts.remove(0);
+        }
+        match parts.as_slice() {
+            ["list"] => Ok(AdminCmd::List),
+            ["kick", addr] => Ok(AdminCmd::Kick(addr.parse()?)),
+            ["stats", addr] => Ok(AdminCmd::Stats(addr.parse()?)),
+            ["transcript", addr] => Ok(AdminCmd::Transcript(addr.parse()?)),
+            _ => bail!("Unknown command: {line}"),
+        }
+    }
+}
+
+pub(super) struct AdminServerControl {
+    pub(super) tx: mpsc::Sender<ControlCmd>,
+    pub(super) thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+pub(super) struct AdminServer {
+    listener: TcpListener,
+    token: Option<String>,
+    server_tx: mpsc::Sender<ControlCmd>,
+}
+
+/// Без токена административный интерфейс доступен только с той же машины
+fn check_access(addr: SocketAddr, token: Option<&str>) -> ServerResult<()> {
+    if token.is_none() && !addr.ip().is_loopback() {
+        return Err(ServerError::Config(format!(
+            "admin interface at {addr} requires admin_token"
+        )));
+    }
+    Ok(())
+}
+
+impl AdminServer {
+    pub(super) fn new(
+        addr: &str,
+        token: Option<String>,
+        server_tx: mpsc::Sender<ControlCmd>,
+    ) -> ServerResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        check_access(local_addr, token.as_deref())?;
+        listener.set_nonblocking(true)?;
+        log::info!("Admin interface is listening at {local_addr}");
+        Ok(Self {
+            listener,
+            token,
+            server_tx,
+        })
+    }
+
+    pub(super) fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    fn list_clients(&self) -> Result<Vec<ClientInfo>> {
+        let (tx, rx) = mpsc::channel();
+        self.server_tx.send(ControlCmd::ListClients(tx))?;
+        Ok(rx.recv_timeout(Duration::from_millis(SERVER_REPLY_TIMEOUT_MILLIS))?)
+    }
+
+    fn execute(&self, cmd: AdminCmd) -> Result<String> {
+        let mut res = String::new();
+        match cmd {
+            AdminCmd::List => {
+                for info in self.list_clients()? {
+                    res.push_str(&format!("{} {}\n", info.addr, info.tickers.join(",")));
+                }
+            }
+            AdminCmd::Kick(addr) => {
+                let (tx, rx) = mpsc::channel();
+                self.server_tx.send(ControlCmd::KickClient(addr, tx))?;
+                let kicked = rx.recv_timeout(Duration::from_millis(SERVER_REPLY_TIMEOUT_MILLIS))?;
+                res = if kicked {
+                    "OK\n".to_string()
+                } else {
+                    "NOT FOUND\n".to_string()
+                };
+            }
+            AdminCmd::Stats(addr) => {
+                match self
+                    .list_clients()?
+                    .into_iter()
+                    .find(|info| info.addr == addr)
+                {
+                    Some(info) => res = format!("{info}\n"),
+                    None => res = "NOT FOUND\n".to_string(),
+                }
+            }
+            AdminCmd::Transcript(addr) => {
+                let (tx, rx) = mpsc::channel();
+                self.server_tx.send(ControlCmd::ClientTranscript(addr, tx))?;
+                match rx.recv_timeout(Duration::from_millis(SERVER_REPLY_TIMEOUT_MILLIS))? {
+                    Some(transcript) => {
+                        res = serde_json::to_string_pretty(&transcript)?;
+                        res.push('\n');
+                    }
+                    None => res = "NOT FOUND\n".to_string(),
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn handle_request(&self, conn: TcpStream, addr: SocketAddr) -> Result<()> {
+        conn.set_nonblocking(false)?;
+        conn.set_read_timeout(Some(Duration::from_millis(READ_REQUEST_TIMEOUT_MILLIS)))?;
+
+        let mut line = String::new();
+        BufReader::new(&conn).read_line(&mut line)?;
+        let response = match AdminCmd::parse(&line, self.token.as_deref()) {
+            Ok(cmd) => {
+                log::info!("Admin command from {addr}: {cmd:?}");
+                self.execute(cmd)
+            }
+            Err(e) => {
+                log::warn!("Bad admin command from {addr}: {e}");
+                Err(e)
+            }
+        };
+        let response = match response {
+            Ok(val) => val,
+            Err(e) => format!("ERROR {e}\n"),
+        };
+        (&conn).write_all(response.as_bytes())?;
+        Ok(())
+    }
+
+    pub(super) fn start(self) -> AdminServerControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::default();
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(ACCEPT_EVENT, ACCEPT_MILLIS);
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    if let ControlCmd::Stop = cmd_from_channel(&rx) {
+                        break;
+                    }
+                }
+
+                if timer.is_expired_event(ACCEPT_EVENT)? {
+                    timer.reset_event(ACCEPT_EVENT)?;
+                    match self.listener.accept() {
+                        Ok((conn, addr)) => {
+                            if let Err(e) = self.handle_request(conn, addr) {
+                                log::warn!("Can't handle admin request from {addr}: {e}");
+                            }
+                        }
+                        Err(e) => match e.kind() {
+                            ErrorKind::WouldBlock => {}
+                            _ => {
+                                log::error!("Can't accept admin connection: {e}");
+                                break;
+                            }
+                        },
+                    }
+                }
+            }
+
+            log::info!("Admin interface is stopped");
+            Ok(())
+        });
+        AdminServerControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_admin_cmd() {
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        assert_eq!(AdminCmd::parse("list\n", None).unwrap(), AdminCmd::List);
+        assert_eq!(
+            AdminCmd::parse("kick 127.0.0.1:5000", None).unwrap(),
+            AdminCmd::Kick(addr)
+        );
+        assert_eq!(
+            AdminCmd::parse(" stats  127.0.0.1:5000 ", None).unwrap(),
+            AdminCmd::Stats(addr)
+        );
+        assert_eq!(
+            AdminCmd::parse("transcript 127.0.0.1:5000", None).unwrap(),
+            AdminCmd::Transcript(addr)
+        );
+        assert!(AdminCmd::parse("kick", None).is_err());
+        assert!(AdminCmd::parse("kick localhost", None).is_err());
+        assert!(AdminCmd::parse("reboot", None).is_err());
+    }
+
+    #[test]
+    fn test_admin_token() {
+        let token = Some("secret");
+        assert_eq!(
+            AdminCmd::parse("secret list\n", token).unwrap(),
+            AdminCmd::List
+        );
+        assert!(AdminCmd::parse("list", token).is_err());
+        assert!(AdminCmd::parse("wrong list", token).is_err());
+        assert!(AdminCmd::parse("", token).is_err());
+
+        let loopback: SocketAddr = "127.0.0.1:9101".parse().unwrap();
+        let any: SocketAddr = "0.0.0.0:9101".parse().unwrap();
+        assert!(check_access(loopback, None).is_ok());
+        assert!(matches!(
+            check_access(any, None),
+            Err(ServerError::Config(_))
+        ));
+        assert!(check_access(any, token).is_ok());
+    }
+
+    #[test]
+    fn test_admin_server() {
+        let (server_tx, server_rx) = mpsc::channel();
+        let admin = AdminServer::new("127.0.0.1:0", Some("secret".to_string()), server_tx).unwrap();
+        let addr = admin.local_addr().unwrap();
+        let control = admin.start();
+        let request = |line: &str| {
+            let mut conn = TcpStream::connect(addr).unwrap();
+            conn.write_all(line.as_bytes()).unwrap();
+            let mut response = String::new();
+            BufReader::new(conn).read_line(&mut response).unwrap();
+            response
+        };
+
+        assert!(request("kick 127.0.0.1:5000\n").starts_with("ERROR"));
+        assert!(server_rx.try_recv().is_err());
+
+        let server = thread::spawn(move || match server_rx.recv().unwrap() {
+            ControlCmd::KickClient(_, reply) => reply.send(true).unwrap(),
+            _ => panic!("Unexpected command"),
+        });
+        assert_eq!(request("secret kick 127.0.0.1:5000\n"), "OK\n");
+        server.join().unwrap();
+
+        control.tx.send(ControlCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Событие управляющего канала клиента
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// Клиент подключился
+    Connected,
+    /// Получено сообщение. Токены в сообщении скрыты
+    Message {
+        /// Содержимое сообщения
+        message: String,
+    },
+    /// Проверка токена доступа
+    Auth {
+        /// Доступ разрешен
+        accepted: bool,
+    },
+    /// Получен пакет, который не удалось разобрать
+    Malformed {
+        /// Описание ошибки
+        description: String,
+    },
+    /// Соединение закрыто
+    Closed {
+        /// Причина закрытия
+        reason: String,
+    },
+}
+
+/// Запись журнала аудита. Одна строка NDJSON в файле журнала
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    /// Время события, мс с начала эпохи Unix
+    pub unix_millis: u64,
+    /// Адрес управляющего соединения клиента
+    pub peer: SocketAddr,
+    /// Событие
+    pub event: AuditEvent,
+}
+
+/// Журнал аудита управляющих каналов всех клиентов. Записи только дописываются в конец файла
+pub struct AuditLog {
+    writer: Mutex<LineWriter<File>>,
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl AuditLog {
+    /// Открывает журнал в файле path. Файл создается, если его нет
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        log::info!("Write audit log to {}", path.display());
+        Ok(Self {
+            writer: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    /// Добавляет событие клиента peer в журнал
+    pub fn write(&self, peer: SocketAddr, event: AuditEvent) {
+        let record = AuditRecord {
+            unix_millis: unix_millis(),
+            peer,
+            event,
+        };
+        let res = serde_json::to_string(&record)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.writer.lock().unwrap(), "{line}")?));
+        if let Err(e) = res {
+            log::error!("Can't write audit record: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_audit_log_appends() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("audit.ndjson");
+        let peer: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+
+        let audit = AuditLog::open(&path).unwrap();
+        audit.write(peer, AuditEvent::Connected);
+        drop(audit);
+        let audit = AuditLog::open(&path).unwrap();
+        audit.write(peer, AuditEvent::Auth { accepted: false });
+        drop(audit);
+
+        let records: Vec<AuditRecord> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].event, AuditEvent::Connected);
+        assert_eq!(records[1].peer, peer);
+        assert_eq!(records[1].event, AuditEvent::Auth { accepted: false });
+    }
+}
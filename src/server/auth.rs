@@ -0,0 +1,18 @@
+use std::net::SocketAddr;
+
+/// Проверка доступа клиента к котировкам. Вызывается сервером до того,
+/// как подписка клиента будет принята
+pub trait Authenticator: Send + Sync {
+    /// Возвращает true, если клиенту с адресом peer и токеном token разрешен доступ.
+    /// Если клиент не передал токен, token пустой
+    fn authenticate(&self, token: &str, peer: SocketAddr) -> bool;
+}
+
+/// Разрешает доступ всем клиентам
+pub struct AllowAll;
+
+impl Authenticator for AllowAll {
+    fn authenticate(&self, _token: &str, _peer: SocketAddr) -> bool {
+        true
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Окно учета трафика длиной WINDOW
+struct Window<T> {
+    started: Instant,
+    used: T,
+}
+
+impl<T: Default> Window<T> {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            used: T::default(),
+        }
+    }
+
+    /// Начинает новое окно, если текущее закончилось к now. Возвращает учет закончившегося окна
+    fn roll(&mut self, now: Instant) -> Option<T> {
+        if now.duration_since(self.started) < WINDOW {
+            return None;
+        }
+        self.started = now;
+        Some(std::mem::take(&mut self.used))
+    }
+}
+
+/// Трафик клиентов в окне общего лимита
+#[derive(Default)]
+struct SharedUsage {
+    clients: HashMap<u64, u64>,
+    prev_active: usize,
+}
+
+/// Общий для всех клиентов лимит трафика котировок. Лимит окна делится поровну
+/// между клиентами, которые отправляли котировки в текущем или прошлом окне,
+/// поэтому первые клиенты не забирают весь лимит
+pub(super) struct BandwidthBudget {
+    bytes_per_window: u64,
+    next_id: AtomicU64,
+    window: Mutex<Window<SharedUsage>>,
+}
+
+impl BandwidthBudget {
+    pub(super) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_window: bytes_per_sec,
+            next_id: AtomicU64::new(0),
+            window: Mutex::new(Window::new()),
+        }
+    }
+
+    /// Доля лимита для нового клиента
+    pub(super) fn share(self: &Arc<Self>) -> BandwidthShare {
+        BandwidthShare {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            budget: self.clone(),
+        }
+    }
+
+    fn try_consume_at(&self, id: u64, len: u64, now: Instant) -> bool {
+        let mut window = self.window.lock().unwrap();
+        if let Some(prev) = window.roll(now) {
+            window.used.prev_active = prev.clients.len();
+        }
+        let usage = &mut window.used;
+        let is_new = !usage.clients.contains_key(&id);
+        let active = usage
+            .prev_active
+            .max(usage.clients.len() + usize::from(is_new))
+            .max(1);
+        let fair_share = self.bytes_per_window / active as u64;
+        let used = usage.clients.entry(id).or_insert(0);
+        if *used > 0 && *used + len > fair_share {
+            return false;
+        }
+        *used += len;
+        true
+    }
+}
+
+/// Доля общего лимита трафика одного клиента
+pub(super) struct BandwidthShare {
+    id: u64,
+    budget: Arc<BandwidthBudget>,
+}
+
+impl BandwidthShare {
+    /// Разрешает отправку len байт, если клиент не исчерпал свою долю в текущем окне.
+    /// Первая отправка клиента в окне разрешена всегда
+    pub(super) fn try_consume(&self, len: usize) -> bool {
+        self.budget
+            .try_consume_at(self.id, len as u64, Instant::now())
+    }
+}
+
+impl Drop for BandwidthShare {
+    fn drop(&mut self) {
+        self.budget
+            .window
+            .lock()
+            .unwrap()
+            .used
+            .clients
+            .remove(&self.id);
+    }
+}
+
+/// Лимит трафика котировок одного клиента
+pub(super) struct ClientCap {
+    bytes_per_window: u64,
+    window: Window<u64>,
+}
+
+impl ClientCap {
+    pub(super) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_window: bytes_per_sec,
+            window: Window::new(),
+        }
+    }
+
+    /// Можно ли отправить len байт в текущем окне. Первая отправка окна разрешена всегда,
+    /// чтобы лимит меньше одной датаграммы не останавливал поток
+    pub(super) fn allows(&mut self, len: usize, now: Instant) -> bool {
+        self.window.roll(now);
+        let used = self.window.used;
+        used == 0 || used + len as u64 <= self.bytes_per_window
+    }
+
+    /// Учитывает отправленные байты в текущем окне
+    pub(super) fn consume(&mut self, len: usize) {
+        self.window.used += len as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fair_share() {
+        let budget = Arc::new(BandwidthBudget::new(100));
+        let first = budget.share();
+        let second = budget.share();
+        let start = Instant::now();
+
+        assert!(budget.try_consume_at(first.id, 60, start));
+        assert!(budget.try_consume_at(second.id, 50, start));
+        assert!(!budget.try_consume_at(second.id, 10, start));
+
+        let next = start + WINDOW;
+        assert!(budget.try_consume_at(first.id, 30, next));
+        assert!(!budget.try_consume_at(first.id, 30, next));
+        assert!(budget.try_consume_at(first.id, 20, next));
+        assert!(budget.try_consume_at(second.id, 50, next));
+        assert!(!budget.try_consume_at(first.id, 1, next));
+    }
+
+    #[test]
+    fn test_client_cap() {
+        let mut cap = ClientCap::new(100);
+        let start = cap.window.started;
+
+        assert!(cap.allows(60, start));
+        cap.consume(60);
+        assert!(cap.allows(40, start));
+        assert!(!cap.allows(41, start));
+        assert!(cap.allows(100, start + WINDOW));
+    }
+
+    #[test]
+    fn test_limit_below_datagram() {
+        let mut cap = ClientCap::new(10);
+        let start = cap.window.started;
+        assert!(cap.allows(40, start));
+        cap.consume(40);
+        assert!(!cap.allows(40, start));
+        assert!(cap.allows(40, start + WINDOW));
+
+        let budget = Arc::new(BandwidthBudget::new(10));
+        let share = budget.share();
+        let start = Instant::now();
+        assert!(budget.try_consume_at(share.id, 40, start));
+        assert!(!budget.try_consume_at(share.id, 40, start));
+        assert!(budget.try_consume_at(share.id, 40, start + WINDOW));
+    }
+}
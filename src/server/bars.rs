@@ -0,0 +1,118 @@
+use crate::quote::{Bar, QuoteCallback, StockQuote};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+struct BarState {
+    open: HashMap<String, Bar>,
+    subscribers: Vec<mpsc::Sender<Bar>>,
+}
+
+/// Собирает свечи OHLCV по всем котировкам сервера и рассылает закрытые свечи подписчикам.
+/// Свеча закрывается первой котировкой любого тикера после конца ее интервала
+pub(super) struct BarAggregator {
+    interval_millis: u64,
+    state: Mutex<BarState>,
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl BarAggregator {
+    pub(super) fn new(interval_millis: u64) -> Self {
+        Self {
+            interval_millis,
+            state: Mutex::new(BarState::default()),
+        }
+    }
+
+    pub(super) fn subscribe(&self) -> mpsc::Receiver<Bar> {
+        let (tx, rx) = mpsc::channel();
+        self.state.lock().unwrap().subscribers.push(tx);
+        rx
+    }
+
+    fn on_quote_at(&self, quote: &StockQuote, now_millis: u64) {
+        let start_millis = now_millis - now_millis % self.interval_millis;
+        let mut state = self.state.lock().unwrap();
+        let mut closed = Vec::new();
+        state.open.retain(|_, bar| {
+            if bar.start_millis < start_millis {
+                closed.push(bar.clone());
+                return false;
+            }
+            true
+        });
+        match state.open.get_mut(&quote.ticker) {
+            Some(bar) => bar.update(quote),
+            None => {
+                let bar = Bar::open(quote, start_millis, self.interval_millis);
+                state.open.insert(quote.ticker.clone(), bar);
+            }
+        }
+        for bar in closed {
+            state.subscribers.retain(|tx| tx.send(bar.clone()).is_ok());
+        }
+    }
+}
+
+impl QuoteCallback for Arc<BarAggregator> {
+    fn on_quote(&mut self, quote: &StockQuote) {
+        self.on_quote_at(quote, unix_millis());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bars() {
+        let quote = |ticker: &str, price: f64, volume: u32| StockQuote {
+            ticker: ticker.to_string(),
+            price,
+            volume,
+            timestamp: 0,
+        };
+        let aggregator = BarAggregator::new(1000);
+        let rx = aggregator.subscribe();
+
+        aggregator.on_quote_at(&quote("AMD", 10.0, 1), 5100);
+        aggregator.on_quote_at(&quote("AMD", 12.0, 2), 5400);
+        aggregator.on_quote_at(&quote("AMD", 9.0, 3), 5700);
+        aggregator.on_quote_at(&quote("INT", 5.0, 1), 5900);
+        assert!(rx.try_recv().is_err());
+
+        aggregator.on_quote_at(&quote("INT", 6.0, 1), 6000);
+        let mut bars: Vec<Bar> = rx.try_iter().collect();
+        bars.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[1].ticker, "INT");
+        assert_eq!(bars[1].close, 5.0);
+        assert_eq!(
+            bars[0],
+            Bar {
+                ticker: "AMD".to_string(),
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 9.0,
+                volume: 6,
+                start_millis: 5000,
+                interval_millis: 1000,
+            }
+        );
+
+        aggregator.on_quote_at(&quote("AMD", 1.0, 1), 7000);
+        let bars: Vec<Bar> = rx.try_iter().collect();
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].ticker, "INT");
+        assert_eq!(bars[0].open, 6.0);
+        assert_eq!(bars[0].start_millis, 6000);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::quote::{QuoteCallback, StockQuote};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Последние котировки по каждому тикеру
+#[derive(Default, Clone)]
+pub(super) struct LastValueCache {
+    quotes: Arc<Mutex<HashMap<String, StockQuote>>>,
+}
+
+impl LastValueCache {
+    /// Последние котировки по тикерам из списка, для которых они есть
+    pub(super) fn get(&self, tickers: &[String]) -> Vec<StockQuote> {
+        let quotes = self.quotes.lock().unwrap();
+        tickers
+            .iter()
+            .filter_map(|ticker| quotes.get(ticker).cloned())
+            .collect()
+    }
+}
+
+impl QuoteCallback for LastValueCache {
+    fn on_quote(&mut self, quote: &StockQuote) {
+        self.quotes
+            .lock()
+            .unwrap()
+            .insert(quote.ticker.clone(), quote.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_value_cache() {
+        let cache = LastValueCache::default();
+        let mut callback = cache.clone();
+        for (ticker, timestamp) in [("AMD", 1), ("INT", 2), ("AMD", 3)] {
+            callback.on_quote(&StockQuote {
+                ticker: ticker.to_string(),
+                price: 1.0,
+                volume: 1,
+                timestamp,
+            });
+        }
+
+        let quotes = cache.get(&["AMD".to_string(), "GAZ".to_string()]);
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].timestamp, 3);
+    }
+}
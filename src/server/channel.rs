@@ -0,0 +1,337 @@
+use super::quotes_server::{QuotesStream, QuotesStreamControl};
+use crate::protocol::{ErrorCode, MAX_CONTROL_MESSAGE_LEN, Message, pack_message_with_len};
+use crate::utils::StreamReader;
+use anyhow::{Result, anyhow, bail};
+use std::io::{ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SEND_TIMEOUT_MILLIS: u64 = 1000;
+const SEND_RETRY_MILLIS: u64 = 1;
+const FRAME_TIMEOUT_MILLIS: u64 = 5000;
+
+/// Ошибка приема запроса по управляющему каналу
+pub(super) enum RecvError {
+    /// Соединение закрыто или сломано
+    Connection(anyhow::Error),
+    /// Запрос нарушает протокол: сервер закрывает соединение с кодом ошибки
+    Malformed {
+        code: ErrorCode,
+        reason: &'static str,
+        description: String,
+    },
+}
+
+/// Управляющее соединение клиента: запросы подписки и ответы сервера.
+/// Обработчик клиента работает с любым протоколом через этот интерфейс
+pub(super) trait ControlChannel: Send {
+    /// Следующий запрос клиента или None, если полного запроса еще нет
+    fn recv(&mut self) -> Result<Option<Message>, RecvError>;
+
+    /// Отправляет ответ клиенту
+    fn send(&mut self, msg: &Message) -> Result<()>;
+
+    /// Запускает поток котировок клиента. None — котировки идут через multicast
+    fn start_stream(&self, stream: QuotesStream) -> Option<QuotesStreamControl>;
+
+    /// Закрывает соединение. По умолчанию соединение закрывается при удалении
+    fn close(&mut self) {}
+}
+
+enum FrameState {
+    WaitPackLen,
+    WaitPack { len: u32, deadline: Instant },
+}
+
+/// Управляющее соединение родного протокола: сообщения postcard с префиксом длины,
+/// котировки идут датаграммами udp
+pub(super) struct TcpChannel<S> {
+    stream: S,
+    reader: StreamReader,
+    state: FrameState,
+}
+
+impl<S> TcpChannel<S> {
+    pub(super) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            reader: StreamReader::default(),
+            state: FrameState::WaitPackLen,
+        }
+    }
+}
+
+/// Записывает весь буфер в неблокирующий поток, ожидая освобождения буфера отправки
+fn write_all_nonblocking<W: Write>(stream: &mut W, mut buf: &[u8]) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_millis(SEND_TIMEOUT_MILLIS);
+    let retry = |e: std::io::Error| -> Result<()> {
+        match e.kind() {
+            ErrorKind::Interrupted => Ok(()),
+            ErrorKind::WouldBlock if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(SEND_RETRY_MILLIS));
+                Ok(())
+            }
+            _ => Err(e.into()),
+        }
+    };
+    while !buf.is_empty() {
+        match stream.write(buf) {
+            Ok(0) => bail!("Connection is closed"),
+            Ok(len) => buf = &buf[len..],
+            Err(e) => retry(e)?,
+        }
+    }
+    loop {
+        match stream.flush() {
+            Ok(()) => return Ok(()),
+            Err(e) => retry(e)?,
+        }
+    }
+}
+
+pub(super) fn check_frame_len(len: u32) -> Result<usize, ErrorCode> {
+    if len == 0 || len > MAX_CONTROL_MESSAGE_LEN {
+        return Err(ErrorCode::BadFrameLength);
+    }
+    Ok(len as usize)
+}
+
+impl<S: Read + Write + Send> ControlChannel for TcpChannel<S> {
+    fn recv(&mut self) -> Result<Option<Message>, RecvError> {
+        let read_res = self.reader.read_from_stream(&mut self.stream);
+        match self.state {
+            FrameState::WaitPackLen => {
+                let Some(bin_len) = self.reader.extract_chunk(4) else {
+                    return read_res.map(|_| None).map_err(RecvError::Connection);
+                };
+                let len = u32::from_be_bytes([bin_len[0], bin_len[1], bin_len[2], bin_len[3]]);
+                log::debug!("Packet len is received: {len}");
+                check_frame_len(len).map_err(|code| RecvError::Malformed {
+                    code,
+                    reason: "bad frame length",
+                    description: format!("bad frame length {len}"),
+                })?;
+                self.state = FrameState::WaitPack {
+                    len,
+                    deadline: Instant::now() + Duration::from_millis(FRAME_TIMEOUT_MILLIS),
+                };
+                Ok(None)
+            }
+            FrameState::WaitPack { len, deadline } => {
+                let Some(bin_message) = self.reader.extract_chunk(len as usize) else {
+                    read_res.map_err(RecvError::Connection)?;
+                    if Instant::now() >= deadline {
+                        log::error!("Can't receive full packet");
+                        return Err(RecvError::Connection(anyhow!(
+                            "packet of {len} bytes isn't received in {FRAME_TIMEOUT_MILLIS} ms"
+                        )));
+                    }
+                    return Ok(None);
+                };
+                self.state = FrameState::WaitPackLen;
+                postcard::from_bytes(&bin_message)
+                    .map(Some)
+                    .map_err(|e| RecvError::Malformed {
+                        code: ErrorCode::DecodeFailure,
+                        reason: "decode failure",
+                        description: e.to_string(),
+                    })
+            }
+        }
+    }
+
+    fn send(&mut self, msg: &Message) -> Result<()> {
+        let bin_msg = pack_message_with_len(msg)?;
+        write_all_nonblocking(&mut self.stream, &bin_msg)
+    }
+
+    fn start_stream(&self, stream: QuotesStream) -> Option<QuotesStreamControl> {
+        stream.start_udp()
+    }
+}
+
+/// Сообщает клиенту код ошибки, после которой соединение закрывается
+pub(super) fn close_with_error<C: ControlChannel>(conn: &mut C, addr: SocketAddr, code: ErrorCode) {
+    log::warn!("Close connection {addr}: {code:?}");
+    if let Err(e) = conn.send(&Message::Error { code }) {
+        log::warn!("Can't send error to {addr}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn recv_all<S: Read + Write + Send>(channel: &mut TcpChannel<S>) -> Option<Message> {
+        (0..2).find_map(|_| channel.recv().ok().flatten())
+    }
+
+    #[test]
+    fn test_check_frame_len() {
+        assert_eq!(check_frame_len(0), Err(ErrorCode::BadFrameLength));
+        assert_eq!(check_frame_len(10), Ok(10));
+        assert_eq!(
+            check_frame_len(MAX_CONTROL_MESSAGE_LEN),
+            Ok(MAX_CONTROL_MESSAGE_LEN as usize)
+        );
+        assert_eq!(
+            check_frame_len(MAX_CONTROL_MESSAGE_LEN + 1),
+            Err(ErrorCode::BadFrameLength)
+        );
+    }
+
+    #[test]
+    fn test_close_with_error() {
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        for code in [
+            ErrorCode::BadFrameLength,
+            ErrorCode::DecodeFailure,
+            ErrorCode::UnexpectedMessage,
+        ] {
+            let mut channel = TcpChannel::new(Cursor::new(Vec::new()));
+            close_with_error(&mut channel, addr, code);
+
+            let mut client = TcpChannel::new(Cursor::new(channel.stream.into_inner()));
+            match recv_all(&mut client) {
+                Some(Message::Error { code: received }) => assert_eq!(received, code),
+                msg => panic!("Unexpected message: {msg:?}"),
+            }
+        }
+    }
+
+    struct Congested {
+        written: Vec<u8>,
+        blocked: bool,
+    }
+
+    impl Write for Congested {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let len = buf.len().min(100);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send_waits_for_send_buffer() {
+        let mut stream = Congested {
+            written: Vec::new(),
+            blocked: false,
+        };
+        let msg = Message::History {
+            ticker: "AMD".to_string(),
+            quotes: (0..100)
+                .map(|timestamp| crate::quote::StockQuote {
+                    ticker: "AMD".to_string(),
+                    price: 1.0,
+                    volume: 1,
+                    timestamp,
+                })
+                .collect(),
+        };
+        let bin_msg = pack_message_with_len(&msg).unwrap();
+        write_all_nonblocking(&mut stream, &bin_msg).unwrap();
+        assert_eq!(stream.written, bin_msg);
+    }
+
+    struct Chunks {
+        chunks: Vec<Vec<u8>>,
+        blocked: bool,
+    }
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.blocked = !self.blocked;
+            if self.blocked || self.chunks.is_empty() {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let chunk = self.chunks.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_split_frame() {
+        let tickers: Vec<String> = (0..1000).map(|i| format!("TICKER{i}")).collect();
+        let frame = pack_message_with_len(&Message::ChangeSubscription {
+            mode: crate::protocol::SubscriptionMode::Add,
+            tickers: tickers.clone(),
+        })
+        .unwrap();
+        assert!(frame.len() > 4096);
+        let mut channel = TcpChannel::new(Chunks {
+            chunks: frame.chunks(500).map(<[u8]>::to_vec).collect(),
+            blocked: false,
+        });
+
+        let msg = (0..100).find_map(|_| match channel.recv() {
+            Ok(msg) => msg,
+            Err(_) => panic!("Split frame is rejected"),
+        });
+        match msg {
+            Some(Message::ChangeSubscription {
+                tickers: received, ..
+            }) => {
+                assert_eq!(received, tickers)
+            }
+            msg => panic!("Unexpected message: {msg:?}"),
+        }
+    }
+
+    #[test]
+    fn test_closed_connection() {
+        let mut channel = TcpChannel::new(Cursor::new(Vec::new()));
+        assert!(matches!(channel.recv(), Err(RecvError::Connection(_))));
+
+        let mut frame = pack_message_with_len(&Message::Pong).unwrap();
+        frame.pop();
+        let mut channel = TcpChannel::new(Cursor::new(frame));
+        assert!(matches!(channel.recv(), Ok(None)));
+        assert!(matches!(channel.recv(), Err(RecvError::Connection(_))));
+    }
+
+    #[test]
+    fn test_malformed_frames() {
+        let mut channel = TcpChannel::new(Cursor::new(0u32.to_be_bytes().to_vec()));
+        assert!(matches!(
+            channel.recv(),
+            Err(RecvError::Malformed {
+                code: ErrorCode::BadFrameLength,
+                ..
+            })
+        ));
+
+        let mut frame = 1u32.to_be_bytes().to_vec();
+        frame.push(u8::MAX);
+        let mut channel = TcpChannel::new(Cursor::new(frame));
+        assert!(matches!(channel.recv(), Ok(None)));
+        assert!(matches!(
+            channel.recv(),
+            Err(RecvError::Malformed {
+                code: ErrorCode::DecodeFailure,
+                ..
+            })
+        ));
+    }
+}
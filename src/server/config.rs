@@ -0,0 +1,510 @@
+use crate::quote::TimestampMode;
+use anyhow::Result;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
+use std::net::{IpAddr, TcpStream, UdpSocket};
+use std::time::Duration;
+
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:80";
+const DEFAULT_QUOTES_UDP_PORT: u16 = 34254;
+const DEFAULT_MAX_CLIENTS: usize = 64;
+const DEFAULT_PING_WAIT_MILLIS: u64 = 0;
+const DEFAULT_CHECK_PING_MILLIS: u64 = 100;
+const DEFAULT_RECORD_SEGMENT_QUOTES: u64 = 100000;
+const DEFAULT_REPLAY_SPEED: f64 = 1.0;
+const DEFAULT_SESSION_GRACE_MILLIS: u64 = 30000;
+const DEFAULT_SEND_QUEUE_LEN: usize = 256;
+const DEFAULT_HEALTH_STALL_MILLIS: u64 = 5000;
+const DEFAULT_OVERLOAD_BUSY_RATIO: f64 = 0.8;
+const DEFAULT_OVERLOAD_DROPPED_PER_SEC: f64 = 100.0;
+const DEFAULT_OVERLOAD_CHECK_MILLIS: u64 = 1000;
+const DEFAULT_OVERLOAD_RECOVER_CHECKS: u32 = 3;
+const DEFAULT_OVERLOAD_STREAM_FACTOR: u64 = 4;
+
+/// Поведение сервера, когда клиент не успевает принимать котировки
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    /// Неотправленные котировки копятся в очереди, при переполнении выбрасываются самые старые
+    DropOldest {
+        /// Размер очереди отправки
+        queue_len: usize,
+    },
+    /// В очереди хранится только последняя неотправленная котировка по каждому тикеру
+    ConflateLatest,
+    /// Котировка, которую не удалось отправить, выбрасывается. Клиент отключается
+    /// после max_failures ошибок отправки подряд
+    Disconnect {
+        /// Количество ошибок отправки подряд до отключения клиента
+        max_failures: u32,
+    },
+}
+
+impl Default for BackpressurePolicy {
+    fn default() -> Self {
+        Self::DropOldest {
+            queue_len: DEFAULT_SEND_QUEUE_LEN,
+        }
+    }
+}
+
+/// Параметры UDP сокетов, с которых отправляются котировки.
+/// Незаданные параметры остаются системными по умолчанию
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct UdpSocketOptions {
+    /// Размер буфера отправки, байт
+    pub send_buffer_size: Option<usize>,
+    /// Размер буфера приема, байт
+    pub recv_buffer_size: Option<usize>,
+    /// Байт TOS (IPv4) или Traffic Class (IPv6). DSCP — старшие 6 бит, например 184 для EF
+    pub tos: Option<u32>,
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+fn set_tclass_v6(sock: &SockRef, tclass: u32) -> Result<()> {
+    Ok(sock.set_tclass_v6(tclass)?)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+)))]
+fn set_tclass_v6(_sock: &SockRef, _tclass: u32) -> Result<()> {
+    anyhow::bail!("Traffic class for IPv6 sockets isn't supported on this platform")
+}
+
+impl UdpSocketOptions {
+    /// Применяет параметры к сокету
+    pub fn apply(&self, socket: &UdpSocket) -> Result<()> {
+        let sock = SockRef::from(socket);
+        if let Some(size) = self.send_buffer_size {
+            sock.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            sock.set_recv_buffer_size(size)?;
+        }
+        if let Some(tos) = self.tos {
+            if socket.local_addr()?.is_ipv6() {
+                set_tclass_v6(&sock, tos)?;
+            } else {
+                sock.set_tos_v4(tos)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Параметры принятых TCP соединений клиентов
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct TcpSocketOptions {
+    /// Отключить алгоритм Нейгла, чтобы управляющие сообщения отправлялись без задержки.
+    /// По умолчанию выключено
+    pub nodelay: bool,
+    /// Время простоя соединения, после которого ОС начинает проверять клиента (keepalive), с.
+    /// Если не задано, keepalive не включается
+    pub keepalive_secs: Option<u64>,
+    /// Таймаут блокирующего чтения, мс. Управляющие соединения читаются без блокировки,
+    /// таймаут действует для операций вне цикла обработчика, например отказа в подключении
+    pub read_timeout_millis: Option<u64>,
+    /// Таймаут блокирующей записи, мс
+    pub write_timeout_millis: Option<u64>,
+}
+
+impl TcpSocketOptions {
+    /// Применяет параметры к соединению
+    pub fn apply(&self, conn: &TcpStream) -> Result<()> {
+        conn.set_nodelay(self.nodelay)?;
+        if let Some(secs) = self.keepalive_secs {
+            let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(secs));
+            SockRef::from(conn).set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(millis) = self.read_timeout_millis {
+            conn.set_read_timeout(Some(Duration::from_millis(millis)))?;
+        }
+        if let Some(millis) = self.write_timeout_millis {
+            conn.set_write_timeout(Some(Duration::from_millis(millis)))?;
+        }
+        Ok(())
+    }
+
+    /// Возвращает соединению таймауты из настроек после рукопожатия
+    /// с собственными таймаутами. Не заданный таймаут снимается
+    pub fn restore_timeouts(&self, conn: &TcpStream) -> Result<()> {
+        conn.set_read_timeout(self.read_timeout_millis.map(Duration::from_millis))?;
+        conn.set_write_timeout(self.write_timeout_millis.map(Duration::from_millis))?;
+        Ok(())
+    }
+}
+
+/// Дополнительная точка подключения клиентов
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListenerConfig {
+    /// Адрес, на котором принимаются подключения
+    pub addr: String,
+    /// Сертификат в формате PEM. Если задан вместе с ключом, соединения защищаются TLS
+    #[serde(default)]
+    pub tls_cert: Option<String>,
+    /// Закрытый ключ в формате PEM
+    #[serde(default)]
+    pub tls_key: Option<String>,
+}
+
+/// Вышестоящий сервер, котировки которого ретранслируются клиентам
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpstreamConfig {
+    /// Адрес управляющего канала вышестоящего сервера
+    pub addr: String,
+    /// Тикеры, на которые подписывается ретранслятор
+    pub tickers: Vec<String>,
+    /// Токен доступа к вышестоящему серверу
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Условия перехода сервера в режим перегрузки. В этом режиме котировки отправляются реже
+/// и заменяются последними по тикеру, а новые клиенты получают ошибку `Overloaded`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct OverloadConfig {
+    /// Доля времени работы цикла потока сервера за период проверки,
+    /// выше которой сервер считается перегруженным
+    pub max_busy_ratio: f64,
+    /// Количество котировок, выброшенных из очередей отправки за секунду,
+    /// выше которого сервер считается перегруженным
+    pub max_dropped_per_sec: f64,
+    /// Период проверки перегрузки, мс
+    pub check_millis: u64,
+    /// Количество проверок подряд без перегрузки для выхода из режима
+    pub recover_checks: u32,
+    /// Во сколько раз увеличивается интервал отправки котировок в режиме перегрузки
+    pub stream_interval_factor: u64,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            max_busy_ratio: DEFAULT_OVERLOAD_BUSY_RATIO,
+            max_dropped_per_sec: DEFAULT_OVERLOAD_DROPPED_PER_SEC,
+            check_millis: DEFAULT_OVERLOAD_CHECK_MILLIS,
+            recover_checks: DEFAULT_OVERLOAD_RECOVER_CHECKS,
+            stream_interval_factor: DEFAULT_OVERLOAD_STREAM_FACTOR,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+/// Настройки сервера котировок
+pub struct ServerConfig {
+    /// Адрес, на котором сервер принимает подключения клиентов. UDP сокеты котировок
+    /// открываются на том же IP. Адрес `[::]` принимает и IPv4, и IPv6 клиентов
+    pub listen_addr: String,
+    /// Порт UDP сокета, с которого котировки отправляются клиенту. 0 — у каждого клиента
+    /// свой случайный порт, клиент узнает его по адресу отправителя котировок
+    pub quotes_udp_port: u16,
+    /// Максимальное количество одновременно подключенных клиентов
+    pub max_clients: usize,
+    /// Адрес HTTP точки `/metrics` для Prometheus и проверок `/healthz`, `/readyz`.
+    /// Если не задан, метрики не публикуются
+    pub metrics_addr: Option<String>,
+    /// Адрес административного интерфейса. Если не задан, интерфейс не запускается.
+    /// Без `admin_token` интерфейс слушает только loopback адрес
+    pub admin_addr: Option<String>,
+    /// Токен административного интерфейса: каждая команда начинается с токена
+    pub admin_token: Option<String>,
+    /// Количество ошибок ConnectionReset подряд, после которого клиент считается отключенным.
+    /// Если не задано, такие ошибки игнорируются и клиент отключается только по таймауту пинга,
+    /// если он задан
+    pub connection_reset_threshold: Option<u32>,
+    /// Подсети, из которых разрешено подключение. Если список пуст, разрешены все адреса
+    pub allow: Vec<IpNet>,
+    /// Подсети, из которых подключение запрещено. Имеет приоритет над `allow`
+    pub deny: Vec<IpNet>,
+    /// Адрес multicast группы. Если задан, котировки по всем тикерам публикуются в группу,
+    /// а TCP соединение с клиентом используется только для подписки
+    pub multicast_addr: Option<String>,
+    /// Время ожидания пинга от клиента, после которого клиент считается отключенным.
+    /// 0 — клиент не отключается по отсутствию пинга
+    pub ping_wait_millis: u64,
+    /// Период проверки входящих пингов
+    pub check_ping_millis: u64,
+    /// Каталог для записи всех сгенерированных котировок. Если не задан, котировки не пишутся
+    pub record_dir: Option<String>,
+    /// Количество котировок в одном сегменте записи
+    pub record_segment_quotes: u64,
+    /// Каталог с записью котировок. Если задан, котировки воспроизводятся из записи
+    /// вместо генератора. Тикеры подписки по-прежнему проверяются по конфигурации генератора
+    pub replay_dir: Option<String>,
+    /// Ускорение воспроизведения записи
+    pub replay_speed: f64,
+    /// Сертификат сервера в формате PEM. Если задан вместе с ключом,
+    /// управляющие соединения с клиентами защищаются TLS
+    pub tls_cert: Option<String>,
+    /// Закрытый ключ сервера в формате PEM
+    pub tls_key: Option<String>,
+    /// Время хранения подписки отключившегося клиента для восстановления по токену сессии.
+    /// 0 отключает хранение
+    pub session_grace_millis: u64,
+    /// Файл, в который при остановке сервера сохраняются подписки клиентов. После перезапуска
+    /// клиенты восстанавливают подписки по токену сессии в течение session_grace_millis.
+    /// Если не задан, подписки не сохраняются
+    pub session_state_path: Option<String>,
+    /// Поведение при ошибках отправки котировок клиенту
+    pub backpressure: BackpressurePolicy,
+    /// Количество потоков генерации котировок. Тикеры распределяются между потоками
+    /// по хешу названия. 0 — котировки генерируются потоками отправки по запросу
+    pub generator_shards: usize,
+    /// Seed генератора котировок: при каждом запуске тикеры выдают одни и те же
+    /// последовательности котировок. Если не задан, котировки случайны
+    pub generator_seed: Option<u64>,
+    /// Источник временных меток сгенерированных котировок: номер котировки,
+    /// текущее время или моделируемое время
+    pub timestamps: TimestampMode,
+    /// Время без итераций цикла потока сервера, после которого `/healthz` сообщает о зависании
+    pub health_stall_millis: u64,
+    /// Минимальный интервал между котировками одного тикера для каждого клиента.
+    /// Промежуточные котировки заменяются последней. 0 отключает ограничение
+    pub conflation_millis: u64,
+    /// Файл журнала аудита, в который дописываются все сообщения управляющих каналов
+    /// клиентов, проверки токенов и ошибки разбора пакетов. Если не задан, журнал не ведется
+    pub audit_log: Option<String>,
+    /// Время ожидания первого корректного запроса котировок после подключения,
+    /// по истечении которого соединение закрывается. 0 отключает ограничение, по умолчанию
+    pub subscribe_timeout_millis: u64,
+    /// Параметры UDP сокетов котировок, включая multicast
+    pub udp_socket: UdpSocketOptions,
+    /// Параметры TCP соединений клиентов
+    pub tcp_socket: TcpSocketOptions,
+    /// Дополнительные точки подключения клиентов со своими настройками TLS.
+    /// Все точки обслуживаются общими генератором и обработчиками
+    pub listeners: Vec<ListenerConfig>,
+    /// Точка подключения клиентов WebSocket. Клиенты получают котировки сообщениями JSON
+    /// по тому же соединению. Если не задана, WebSocket не поддерживается
+    pub websocket: Option<ListenerConfig>,
+    /// Имя экземпляра сервера для объявления в локальной сети через mDNS
+    /// под типом `_quotes._tcp`. Если не задано, сервер не объявляется
+    pub mdns_name: Option<String>,
+    /// Общий лимит трафика котировок всем клиентам, байт/с. Лимит делится между клиентами
+    /// поровну, котировки сверх доли клиента ждут в очереди и заменяются последними
+    /// по тикеру. Если доля меньше датаграммы, клиент получает одну датаграмму в секунду.
+    /// 0 отключает ограничение
+    pub bandwidth_bytes_per_sec: u64,
+    /// Лимит трафика котировок одному клиенту, байт/с. Котировки сверх лимита ждут в очереди
+    /// и заменяются последними по тикеру. Если лимит меньше датаграммы, клиент получает
+    /// одну датаграмму в секунду. 0 отключает ограничение
+    pub client_bandwidth_bytes_per_sec: u64,
+    /// Интервал свечей OHLCV для клиентов, которые подписываются на свечи, мс.
+    /// Свечи собираются по котировкам генератора и внешнего источника. 0 отключает свечи
+    pub bar_interval_millis: u64,
+    /// Количество последних котировок каждого тикера, которые сервер хранит для запросов
+    /// истории. 0 отключает историю, по умолчанию
+    pub history_depth: usize,
+    /// Количество последних котировок каждого тикера, которые отправляются клиенту
+    /// при подписке до живых котировок. Котировки берутся из истории, поэтому их
+    /// не больше history_depth. 0 отправляет только последнее значение тикера
+    pub replay_on_subscribe: usize,
+    /// Вышестоящий сервер. Если задан, сервер ретранслирует его котировки
+    /// вместо генерации своих
+    pub upstream: Option<UpstreamConfig>,
+    /// Отслеживание перегрузки. Если не задано, сервер не переходит в режим перегрузки
+    pub overload: Option<OverloadConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: DEFAULT_LISTEN_ADDR.to_string(),
+            quotes_udp_port: DEFAULT_QUOTES_UDP_PORT,
+            max_clients: DEFAULT_MAX_CLIENTS,
+            metrics_addr: None,
+            admin_addr: None,
+            admin_token: None,
+            connection_reset_threshold: None,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            multicast_addr: None,
+            ping_wait_millis: DEFAULT_PING_WAIT_MILLIS,
+            check_ping_millis: DEFAULT_CHECK_PING_MILLIS,
+            record_dir: None,
+            record_segment_quotes: DEFAULT_RECORD_SEGMENT_QUOTES,
+            replay_dir: None,
+            replay_speed: DEFAULT_REPLAY_SPEED,
+            tls_cert: None,
+            tls_key: None,
+            session_grace_millis: DEFAULT_SESSION_GRACE_MILLIS,
+            session_state_path: None,
+            backpressure: BackpressurePolicy::default(),
+            generator_shards: 0,
+            generator_seed: None,
+            timestamps: TimestampMode::default(),
+            health_stall_millis: DEFAULT_HEALTH_STALL_MILLIS,
+            conflation_millis: 0,
+            audit_log: None,
+            subscribe_timeout_millis: 0,
+            udp_socket: UdpSocketOptions::default(),
+            tcp_socket: TcpSocketOptions::default(),
+            listeners: Vec::new(),
+            websocket: None,
+            mdns_name: None,
+            bandwidth_bytes_per_sec: 0,
+            client_bandwidth_bytes_per_sec: 0,
+            bar_interval_millis: 0,
+            history_depth: 0,
+            replay_on_subscribe: 0,
+            upstream: None,
+            overload: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Читает настройки из json файла. Отсутствующие поля принимают значения по умолчанию
+    /// ```json
+    /// {
+    ///     "listen_addr": "[::]:80",
+    ///     "quotes_udp_port": 0,
+    ///     "max_clients": 64,
+    ///     "metrics_addr": "127.0.0.1:9100",
+    ///     "admin_addr": "127.0.0.1:9101",
+    ///     "admin_token": "secret",
+    ///     "connection_reset_threshold": 3,
+    ///     "allow": ["192.168.0.0/16", "127.0.0.1/32"],
+    ///     "deny": ["192.168.10.0/24"],
+    ///     "multicast_addr": "239.255.0.1:5000",
+    ///     "ping_wait_millis": 40000,
+    ///     "check_ping_millis": 100,
+    ///     "record_dir": "./records",
+    ///     "record_segment_quotes": 100000,
+    ///     "replay_dir": "./records",
+    ///     "replay_speed": 2.0,
+    ///     "tls_cert": "./server.crt",
+    ///     "tls_key": "./server.key",
+    ///     "session_grace_millis": 30000,
+    ///     "session_state_path": "./sessions.json",
+    ///     "backpressure": {"policy": "disconnect", "max_failures": 10},
+    ///     "generator_shards": 4,
+    ///     "generator_seed": 42,
+    ///     "timestamps": {"mode": "simulated", "start_millis": 1700000000000, "speed": 60.0},
+    ///     "health_stall_millis": 5000,
+    ///     "conflation_millis": 250,
+    ///     "audit_log": "./audit.ndjson",
+    ///     "subscribe_timeout_millis": 10000,
+    ///     "udp_socket": {"send_buffer_size": 1048576, "recv_buffer_size": 65536, "tos": 184},
+    ///     "tcp_socket": {"nodelay": true, "keepalive_secs": 60, "write_timeout_millis": 1000},
+    ///     "listeners": [{"addr": "192.168.1.10:8443", "tls_cert": "./lan.crt", "tls_key": "./lan.key"}],
+    ///     "websocket": {"addr": "0.0.0.0:8080"},
+    ///     "mdns_name": "quotes-1",
+    ///     "bandwidth_bytes_per_sec": 1000000,
+    ///     "client_bandwidth_bytes_per_sec": 100000,
+    ///     "bar_interval_millis": 60000,
+    ///     "history_depth": 1000,
+    ///     "replay_on_subscribe": 20,
+    ///     "upstream": {"addr": "10.0.0.1:80", "tickers": ["AMD", "INT"], "token": "secret"},
+    ///     "overload": {"max_busy_ratio": 0.8, "max_dropped_per_sec": 100, "recover_checks": 3}
+    /// }
+    /// ```
+    pub fn from_file(path: &str) -> Result<Self> {
+        let json_str = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json_str)?)
+    }
+
+    /// Защищать управляющие соединения с клиентами TLS
+    /// с указанными сертификатом и закрытым ключом в формате PEM
+    pub fn tls(mut self, cert: &str, key: &str) -> Self {
+        self.tls_cert = Some(cert.to_string());
+        self.tls_key = Some(key.to_string());
+        self
+    }
+
+    /// Проверяет, разрешено ли подключение с адреса по спискам `allow` и `deny`
+    pub fn is_allowed(&self, addr: IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(&addr)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config() {
+        let config: ServerConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.max_clients, DEFAULT_MAX_CLIENTS);
+        assert_eq!(config.ping_wait_millis, DEFAULT_PING_WAIT_MILLIS);
+        assert_eq!(config.subscribe_timeout_millis, 0);
+
+        let config: ServerConfig = serde_json::from_str(r#"{"max_clients": 2}"#).unwrap();
+        assert_eq!(config.max_clients, 2);
+
+        let config: ServerConfig =
+            serde_json::from_str(r#"{"backpressure": {"policy": "conflate_latest"}}"#).unwrap();
+        assert_eq!(config.backpressure, BackpressurePolicy::ConflateLatest);
+    }
+
+    #[test]
+    fn test_allow_deny() {
+        let config = ServerConfig::default();
+        assert!(config.is_allowed("10.0.0.1".parse().unwrap()));
+
+        let config: ServerConfig =
+            serde_json::from_str(r#"{"allow": ["192.168.0.0/16"], "deny": ["192.168.10.0/24"]}"#)
+                .unwrap();
+        assert!(config.is_allowed("192.168.1.5".parse().unwrap()));
+        assert!(!config.is_allowed("192.168.10.5".parse().unwrap()));
+        assert!(!config.is_allowed("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_udp_socket_options() {
+        let config: ServerConfig =
+            serde_json::from_str(r#"{"udp_socket": {"send_buffer_size": 65536, "tos": 184}}"#)
+                .unwrap();
+        assert_eq!(config.udp_socket.recv_buffer_size, None);
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        config.udp_socket.apply(&socket).unwrap();
+        let sock = SockRef::from(&socket);
+        assert!(sock.send_buffer_size().unwrap() >= 65536);
+        assert_eq!(sock.tos_v4().unwrap(), 184);
+    }
+
+    #[test]
+    fn test_tcp_socket_options() {
+        let config: ServerConfig = serde_json::from_str(
+            r#"{"tcp_socket": {"keepalive_secs": 60, "write_timeout_millis": 500}}"#,
+        )
+        .unwrap();
+        assert!(!config.tcp_socket.nodelay);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        config.tcp_socket.apply(&conn).unwrap();
+        assert!(!conn.nodelay().unwrap());
+        assert!(SockRef::from(&conn).keepalive().unwrap());
+        assert_eq!(conn.read_timeout().unwrap(), None);
+        assert_eq!(
+            conn.write_timeout().unwrap(),
+            Some(Duration::from_millis(500))
+        );
+
+        let nodelay = TcpSocketOptions {
+            nodelay: true,
+            ..Default::default()
+        };
+        nodelay.apply(&conn).unwrap();
+        assert!(conn.nodelay().unwrap());
+    }
+}
@@ -0,0 +1,56 @@
+use std::net::{IpAddr, SocketAddr};
+use thiserror::Error;
+
+/// Ошибки сервера котировок, по которым встраивающее приложение
+/// может различать причины сбоя
+#[derive(Error, Debug)]
+pub enum ServerError {
+    /// Некорректные настройки сервера
+    #[error("Invalid server config: {0}")]
+    Config(String),
+    /// Не удалось загрузить сертификат или ключ TLS
+    #[error("Can't load TLS config: {0}")]
+    Tls(anyhow::Error),
+    /// Не удалось открыть сокет для приема клиентов
+    #[error("Can't bind {addr}: {source}")]
+    Bind {
+        /// Адрес сокета
+        addr: SocketAddr,
+        /// Причина
+        source: anyhow::Error,
+    },
+    /// Не удалось принять подключение клиента
+    #[error("Can't accept connection: {0}")]
+    Accept(std::io::Error),
+    /// Не удалось разобрать сохраненные сервером данные
+    #[error("Can't decode {what}: {source}")]
+    Decode {
+        /// Что разбиралось
+        what: String,
+        /// Причина
+        source: anyhow::Error,
+    },
+    /// Клиент не подписался или не присылал пинг в течение времени ожидания
+    #[error("Client {addr} is silent during {millis} ms")]
+    ClientTimeout {
+        /// Адрес клиента
+        addr: IpAddr,
+        /// Время ожидания
+        millis: u64,
+    },
+    /// Генератор или внешний источник котировок остановился
+    #[error("Quote generator is stopped")]
+    GeneratorDead,
+    /// Поток сервера завершился аварийно
+    #[error("Can't join {0} thread")]
+    ThreadJoin(String),
+    /// Ошибка ввода-вывода
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Прочие ошибки
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Результат операций сервера котировок
+pub type ServerResult<T> = Result<T, ServerError>;
@@ -0,0 +1,65 @@
+use crate::quote::{QuoteCallback, StockQuote};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Последние котировки по каждому тикеру, не больше depth на тикер
+#[derive(Clone)]
+pub(super) struct QuoteHistory {
+    depth: usize,
+    quotes: Arc<Mutex<HashMap<String, VecDeque<StockQuote>>>>,
+}
+
+impl QuoteHistory {
+    pub(super) fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            quotes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Не больше last_n последних котировок тикера в порядке поступления
+    pub(super) fn last(&self, ticker: &str, last_n: usize) -> Vec<StockQuote> {
+        let quotes = self.quotes.lock().unwrap();
+        let Some(history) = quotes.get(ticker) else {
+            return Vec::new();
+        };
+        let skip = history.len().saturating_sub(last_n);
+        history.iter().skip(skip).cloned().collect()
+    }
+}
+
+impl QuoteCallback for QuoteHistory {
+    fn on_quote(&mut self, quote: &StockQuote) {
+        let mut quotes = self.quotes.lock().unwrap();
+        let history = quotes.entry(quote.ticker.clone()).or_default();
+        if history.len() >= self.depth {
+            history.pop_front();
+        }
+        history.push_back(quote.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = QuoteHistory::new(3);
+        let mut callback = history.clone();
+        for timestamp in 1..=5 {
+            callback.on_quote(&StockQuote {
+                ticker: "AMD".to_string(),
+                timestamp,
+                ..Default::default()
+            });
+        }
+
+        let timestamps = |quotes: Vec<StockQuote>| -> Vec<u64> {
+            quotes.iter().map(|quote| quote.timestamp).collect()
+        };
+        assert_eq!(timestamps(history.last("AMD", 2)), vec![4, 5]);
+        assert_eq!(timestamps(history.last("AMD", 10)), vec![3, 4, 5]);
+        assert!(history.last("INT", 10).is_empty());
+    }
+}
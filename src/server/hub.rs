@@ -0,0 +1,112 @@
+use crate::quote::{QuoteCallback, QuoteGenerator, StockQuote};
+use std::sync::{Arc, Mutex, mpsc};
+
+/// Период, с которым потоки отправки забирают котировки из рассылки
+pub(super) const HUB_POLL_MILLIS: u64 = 10;
+
+/// Рассылка котировок, которые публикуются фоновыми потоками, потокам отправки
+#[derive(Default)]
+pub(super) struct QuoteHub {
+    subscribers: Mutex<Vec<mpsc::Sender<StockQuote>>>,
+}
+
+impl QuoteHub {
+    pub(super) fn subscribe(&self) -> mpsc::Receiver<StockQuote> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub(super) fn publish(&self, quote: &StockQuote) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(quote.clone()).is_ok());
+    }
+}
+
+impl QuoteCallback for Arc<QuoteHub> {
+    fn on_quote(&mut self, quote: &StockQuote) {
+        self.publish(quote);
+    }
+}
+
+/// Откуда потоки отправки берут котировки
+#[derive(Clone)]
+pub(super) enum QuoteOrigin {
+    /// Котировки генерируются потоком отправки по запросу
+    Generator(Arc<Mutex<QuoteGenerator>>),
+    /// Котировки публикуются фоновыми потоками
+    Hub(Arc<QuoteHub>),
+}
+
+impl QuoteOrigin {
+    /// Генератор недоступен: поток отправки аварийно завершился, удерживая его
+    pub(super) fn is_stopped(&self) -> bool {
+        match self {
+            Self::Generator(generator) => generator.is_poisoned(),
+            Self::Hub(_) => false,
+        }
+    }
+
+    pub(super) fn feed(&self) -> QuoteFeed {
+        match self {
+            Self::Generator(generator) => QuoteFeed::Generator(generator.clone()),
+            Self::Hub(hub) => QuoteFeed::Hub(hub.subscribe()),
+        }
+    }
+}
+
+/// Котировки для одного потока отправки
+pub(super) enum QuoteFeed {
+    Generator(Arc<Mutex<QuoteGenerator>>),
+    Hub(mpsc::Receiver<StockQuote>),
+}
+
+impl QuoteFeed {
+    /// Период опроса: generate_millis для генератора, HUB_POLL_MILLIS для рассылки
+    pub(super) fn poll_millis(&self, generate_millis: u64) -> u64 {
+        match self {
+            Self::Generator(_) => generate_millis,
+            Self::Hub(_) => HUB_POLL_MILLIS,
+        }
+    }
+
+    /// Новые котировки по тикерам из списка
+    pub(super) fn quotes(&self, tickers: &[String]) -> Vec<StockQuote> {
+        match self {
+            Self::Generator(generator) => {
+                let mut generator = generator.lock().unwrap();
+                tickers
+                    .iter()
+                    .filter_map(|ticker| generator.generate_quote(ticker))
+                    .collect()
+            }
+            Self::Hub(rx) => rx
+                .try_iter()
+                .filter(|quote| tickers.contains(&quote.ticker))
+                .collect(),
+        }
+    }
+
+    /// Продвигает поток без выдачи котировок: генератор создает котировки тикеров
+    /// для обработчиков, например свечей, рассылка отбрасывает накопленные котировки
+    pub(super) fn advance(&self, tickers: &[String]) {
+        match self {
+            Self::Generator(generator) => {
+                let mut generator = generator.lock().unwrap();
+                for ticker in tickers {
+                    generator.generate_quote(ticker);
+                }
+            }
+            Self::Hub(_) => self.discard(),
+        }
+    }
+
+    /// Выбрасывает котировки, накопленные рассылкой. Генератор новых котировок не создает
+    pub(super) fn discard(&self) {
+        if let Self::Hub(rx) = self {
+            rx.try_iter().for_each(drop);
+        }
+    }
+}
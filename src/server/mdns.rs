@@ -0,0 +1,109 @@
+use crate::protocol::{MDNS_SERVICE_TYPE, MDNS_TLS_PROPERTY, MDNS_WEBSOCKET_PROPERTY};
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const UNREGISTER_TIMEOUT_MILLIS: u64 = 1000;
+
+/// Объявление сервера в локальной сети через mDNS
+pub(super) struct MdnsAnnouncer {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl MdnsAnnouncer {
+    /// Объявляет сервер, принимающий клиентов по адресу addr. Если адрес не указан явно,
+    /// объявляются все адреса хоста
+    pub(super) fn register(
+        name: &str,
+        addr: SocketAddr,
+        tls: bool,
+        websocket_addr: Option<SocketAddr>,
+    ) -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        let info = service_info(name, addr, tls, websocket_addr)?;
+        let fullname = info.get_fullname().to_string();
+        daemon.register(info)?;
+        log::info!("Announce server as {fullname} via mDNS");
+        Ok(Self { daemon, fullname })
+    }
+
+    /// Отзывает объявление и останавливает службу mDNS
+    pub(super) fn stop(self) {
+        match self.daemon.unregister(&self.fullname) {
+            Ok(rx) => {
+                let _ = rx.recv_timeout(Duration::from_millis(UNREGISTER_TIMEOUT_MILLIS));
+            }
+            Err(e) => log::warn!("Can't withdraw mDNS announcement: {e}"),
+        }
+        if let Err(e) = self.daemon.shutdown() {
+            log::warn!("Can't stop mDNS daemon: {e}");
+        }
+    }
+}
+
+/// Описание сервиса mDNS сервера с адресом addr. Если адрес не указан явно,
+/// в описание попадают все адреса хоста
+fn service_info(
+    name: &str,
+    addr: SocketAddr,
+    tls: bool,
+    websocket_addr: Option<SocketAddr>,
+) -> Result<ServiceInfo> {
+    let mut properties = HashMap::new();
+    properties.insert(MDNS_TLS_PROPERTY.to_string(), u8::from(tls).to_string());
+    if let Some(addr) = websocket_addr {
+        properties.insert(MDNS_WEBSOCKET_PROPERTY.to_string(), addr.port().to_string());
+    }
+    let host_name = format!("{name}.local.");
+    let info = if addr.ip().is_unspecified() {
+        ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            name,
+            &host_name,
+            (),
+            addr.port(),
+            properties,
+        )?
+        .enable_addr_auto()
+    } else {
+        ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            name,
+            &host_name,
+            addr.ip(),
+            addr.port(),
+            properties,
+        )?
+    };
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_info() {
+        let addr: SocketAddr = "192.168.1.10:8090".parse().unwrap();
+        let websocket_addr: SocketAddr = "192.168.1.10:8091".parse().unwrap();
+        let info = service_info("quotes", addr, true, Some(websocket_addr)).unwrap();
+        assert_eq!(info.get_fullname(), "quotes._quotes._tcp.local.");
+        assert_eq!(info.get_port(), 8090);
+        assert!(info.get_addresses().contains(&addr.ip()));
+        assert_eq!(info.get_property_val_str(MDNS_TLS_PROPERTY), Some("1"));
+        assert_eq!(
+            info.get_property_val_str(MDNS_WEBSOCKET_PROPERTY),
+            Some("8091")
+        );
+
+        let any: SocketAddr = "0.0.0.0:8090".parse().unwrap();
+        let info = service_info("quotes", any, false, None).unwrap();
+        assert!(info.get_addresses().is_empty());
+        assert!(info.is_addr_auto());
+        assert_eq!(info.get_property_val_str(MDNS_TLS_PROPERTY), Some("0"));
+        assert_eq!(info.get_property_val_str(MDNS_WEBSOCKET_PROPERTY), None);
+    }
+}
@@ -0,0 +1,464 @@
+use super::quotes_server::{ControlCmd, cmd_from_channel};
+use crate::stats::ThreadStats;
+use crate::timer::Timer;
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+const ACCEPT_MILLIS: u64 = 100;
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const READ_REQUEST_TIMEOUT_MILLIS: u64 = 1000;
+const MAX_REQUEST_LEN: usize = 1024;
+
+const ACCEPT_EVENT: &str = "accept";
+const WAIT_CMD_EVENT: &str = "cmd";
+
+#[derive(Default)]
+/// Счетчики и измерители работы сервера
+pub struct ServerMetrics {
+    connected_clients: AtomicU64,
+    quotes_sent: AtomicU64,
+    udp_send_errors: AtomicU64,
+    udp_send_blocked: AtomicU64,
+    decode_failures: AtomicU64,
+    ping_timeouts: AtomicU64,
+    quotes_dropped: AtomicU64,
+    degraded_dropped: AtomicU64,
+    bandwidth_capped: AtomicU64,
+    overloaded: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Снимок метрик сервера
+pub struct MetricsSnapshot {
+    /// Количество подключенных клиентов
+    pub connected_clients: u64,
+    /// Количество отправленных котировок
+    pub quotes_sent: u64,
+    /// Количество ошибок отправки по UDP
+    pub udp_send_errors: u64,
+    /// Количество отложенных отправок из-за заполненного буфера UDP сокета
+    pub udp_send_blocked: u64,
+    /// Количество сообщений, которые не удалось декодировать
+    pub decode_failures: u64,
+    /// Количество клиентов, отключенных из-за отсутствия пинга
+    pub ping_timeouts: u64,
+    /// Количество котировок, выброшенных из-за медленных клиентов
+    pub quotes_dropped: u64,
+    /// Количество котировок, замененных последними по тикеру в режиме перегрузки.
+    /// Входит в quotes_dropped
+    pub degraded_dropped: u64,
+    /// Количество отправок, отложенных из-за лимита трафика клиента
+    pub bandwidth_capped: u64,
+    /// Сервер в режиме перегрузки: 1 — да, 0 — нет
+    pub overloaded: u64,
+}
+
+/// Уменьшает количество подключенных клиентов при уничтожении
+pub struct ConnectedClientGuard {
+    metrics: Arc<ServerMetrics>,
+}
+
+impl Drop for ConnectedClientGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .connected_clients
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ServerMetrics {
+    /// Учитывает подключенного клиента на время жизни возвращаемого объекта
+    pub fn client_connected(self: &Arc<Self>) -> ConnectedClientGuard {
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+        ConnectedClientGuard {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Котировка отправлена клиенту
+    pub fn quote_sent(&self) {
+        self.quotes_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Ошибка отправки котировки
+    pub fn udp_send_error(&self) {
+        self.udp_send_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Отправка отложена: буфер UDP сокета заполнен
+    pub fn udp_send_blocked(&self) {
+        self.udp_send_blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Ошибка декодирования сообщения клиента
+    pub fn decode_failure(&self) {
+        self.decode_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Клиент перестал присылать пинг
+    pub fn ping_timeout(&self) {
+        self.ping_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Котировки выброшены из очереди отправки
+    pub fn quotes_dropped(&self, count: u64) {
+        self.quotes_dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Котировки заменены последними по тикеру в режиме перегрузки
+    pub fn degraded_dropped(&self, count: u64) {
+        self.degraded_dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Отправка котировок отложена из-за лимита трафика клиента
+    pub fn bandwidth_capped(&self) {
+        self.bandwidth_capped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Сервер вошел в режим перегрузки или вышел из него
+    pub fn set_overloaded(&self, overloaded: bool) {
+        self.overloaded
+            .store(u64::from(overloaded), Ordering::Relaxed);
+    }
+
+    /// Текущий снимок метрик
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            connected_clients: self.connected_clients.load(Ordering::Relaxed),
+            quotes_sent: self.quotes_sent.load(Ordering::Relaxed),
+            udp_send_errors: self.udp_send_errors.load(Ordering::Relaxed),
+            udp_send_blocked: self.udp_send_blocked.load(Ordering::Relaxed),
+            decode_failures: self.decode_failures.load(Ordering::Relaxed),
+            ping_timeouts: self.ping_timeouts.load(Ordering::Relaxed),
+            quotes_dropped: self.quotes_dropped.load(Ordering::Relaxed),
+            degraded_dropped: self.degraded_dropped.load(Ordering::Relaxed),
+            bandwidth_capped: self.bandwidth_capped.load(Ordering::Relaxed),
+            overloaded: self.overloaded.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Метрики в текстовом формате Prometheus
+    pub fn render(&self, thread_stats: &ThreadStats) -> String {
+        let snapshot = self.snapshot();
+        let mut res = String::new();
+        let metrics = [
+            (
+                "quotes_connected_clients",
+                "gauge",
+                "Connected clients",
+                snapshot.connected_clients,
+            ),
+            (
+                "quotes_sent_total",
+                "counter",
+                "Quotes sent to clients",
+                snapshot.quotes_sent,
+            ),
+            (
+                "quotes_udp_send_errors_total",
+                "counter",
+                "UDP send errors",
+                snapshot.udp_send_errors,
+            ),
+            (
+                "quotes_udp_send_blocked_total",
+                "counter",
+                "UDP sends postponed because the socket buffer is full",
+                snapshot.udp_send_blocked,
+            ),
+            (
+                "quotes_decode_failures_total",
+                "counter",
+                "Client messages that failed to decode",
+                snapshot.decode_failures,
+            ),
+            (
+                "quotes_ping_timeouts_total",
+                "counter",
+                "Clients dropped by ping timeout",
+                snapshot.ping_timeouts,
+            ),
+            (
+                "quotes_dropped_total",
+                "counter",
+                "Quotes dropped for slow clients",
+                snapshot.quotes_dropped,
+            ),
+            (
+                "quotes_degraded_dropped_total",
+                "counter",
+                "Quotes superseded by the latest quote of the ticker in overload mode",
+                snapshot.degraded_dropped,
+            ),
+            (
+                "quotes_client_bandwidth_capped_total",
+                "counter",
+                "Sends postponed by per-client bandwidth cap",
+                snapshot.bandwidth_capped,
+            ),
+            (
+                "quotes_overloaded",
+                "gauge",
+                "Server is in overload mode",
+                snapshot.overloaded,
+            ),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(res, "# HELP {name} {help}");
+            let _ = writeln!(res, "# TYPE {name} {kind}");
+            let _ = writeln!(res, "{name} {value}");
+        }
+
+        let loops = thread_stats.snapshot();
+        let _ = writeln!(res, "# HELP quotes_loop_iterations_total Polling loop iterations");
+        let _ = writeln!(res, "# TYPE quotes_loop_iterations_total counter");
+        for (subsystem, stats) in loops.iter() {
+            let _ = writeln!(
+                res,
+                "quotes_loop_iterations_total{{subsystem=\"{subsystem}\"}} {}",
+                stats.iterations
+            );
+        }
+        let _ = writeln!(res, "# HELP quotes_loop_busy_ratio Share of loop time spent working");
+        let _ = writeln!(res, "# TYPE quotes_loop_busy_ratio gauge");
+        for (subsystem, stats) in loops.iter() {
+            let _ = writeln!(
+                res,
+                "quotes_loop_busy_ratio{{subsystem=\"{subsystem}\"}} {}",
+                stats.busy_ratio
+            );
+        }
+        res
+    }
+}
+
+/// Состояние потоков сервера, за которыми следит проверка здоровья
+#[derive(Debug, PartialEq)]
+pub(super) enum Health {
+    /// Все потоки работают
+    Ready,
+    /// Некоторые потоки еще не начали работу
+    Starting(String),
+    /// Поток не выполнял итераций дольше допустимого
+    Stalled(String),
+}
+
+pub(super) fn check_health(
+    thread_stats: &ThreadStats,
+    subsystems: &[String],
+    stall_timeout: Duration,
+) -> Health {
+    let mut res = Health::Ready;
+    for subsystem in subsystems {
+        match thread_stats.subsystem(subsystem).since_last_iteration() {
+            Some(val) if val > stall_timeout => return Health::Stalled(subsystem.clone()),
+            Some(_) => {}
+            None => {
+                if res == Health::Ready {
+                    res = Health::Starting(subsystem.clone());
+                }
+            }
+        }
+    }
+    res
+}
+
+pub(super) struct MetricsExporterControl {
+    pub(super) tx: mpsc::Sender<ControlCmd>,
+    pub(super) thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+pub(super) struct MetricsExporter {
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    thread_stats: Arc<ThreadStats>,
+    health_subsystems: Vec<String>,
+    stall_timeout: Duration,
+}
+
+impl MetricsExporter {
+    pub(super) fn new(
+        addr: &str,
+        metrics: Arc<ServerMetrics>,
+        thread_stats: Arc<ThreadStats>,
+        health_subsystems: Vec<String>,
+        stall_timeout: Duration,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        log::info!(
+            "Metrics exporter is listening at {}",
+            listener.local_addr()?
+        );
+        Ok(Self {
+            listener,
+            metrics,
+            thread_stats,
+            health_subsystems,
+            stall_timeout,
+        })
+    }
+
+    pub(super) fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    fn handle_request(&self, mut conn: TcpStream, addr: SocketAddr) -> Result<()> {
+        conn.set_nonblocking(false)?;
+        conn.set_read_timeout(Some(Duration::from_millis(READ_REQUEST_TIMEOUT_MILLIS)))?;
+
+        let mut buf = [0u8; MAX_REQUEST_LEN];
+        let len = conn.read(&mut buf)?;
+        let request = String::from_utf8_lossy(&buf[..len]);
+        let request_line = request.lines().next().unwrap_or_default();
+        log::debug!("Metrics request from {addr}: {request_line}");
+
+        let (status, body) = if request_line.starts_with("GET /metrics ") {
+            ("200 OK", self.metrics.render(&self.thread_stats))
+        } else if request_line.starts_with("GET /healthz ") {
+            match self.health() {
+                Health::Stalled(subsystem) => {
+                    log::warn!("Health check failed, {subsystem} is stalled");
+                    ("503 Service Unavailable", format!("stalled: {subsystem}\n"))
+                }
+                _ => ("200 OK", "ok\n".to_string()),
+            }
+        } else if request_line.starts_with("GET /readyz ") {
+            match self.health() {
+                Health::Ready => ("200 OK", "ok\n".to_string()),
+                Health::Starting(subsystem) => (
+                    "503 Service Unavailable",
+                    format!("starting: {subsystem}\n"),
+                ),
+                Health::Stalled(subsystem) => {
+                    ("503 Service Unavailable", format!("stalled: {subsystem}\n"))
+                }
+            }
+        } else {
+            ("404 Not Found", String::new())
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        conn.write_all(response.as_bytes())?;
+        Ok(())
+    }
+
+    fn health(&self) -> Health {
+        check_health(
+            &self.thread_stats,
+            &self.health_subsystems,
+            self.stall_timeout,
+        )
+    }
+
+    pub(super) fn start(self) -> MetricsExporterControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::default();
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(ACCEPT_EVENT, ACCEPT_MILLIS);
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    if let ControlCmd::Stop = cmd_from_channel(&rx) {
+                        break;
+                    }
+                }
+
+                if timer.is_expired_event(ACCEPT_EVENT)? {
+                    timer.reset_event(ACCEPT_EVENT)?;
+                    match self.listener.accept() {
+                        Ok((conn, addr)) => {
+                            if let Err(e) = self.handle_request(conn, addr) {
+                                log::warn!("Can't handle metrics request from {addr}: {e}");
+                            }
+                        }
+                        Err(e) => match e.kind() {
+                            ErrorKind::WouldBlock => {}
+                            _ => {
+                                log::error!("Can't accept metrics connection: {e}");
+                                break;
+                            }
+                        },
+                    }
+                }
+            }
+
+            log::info!("Metrics exporter is stopped");
+            Ok(())
+        });
+        MetricsExporterControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Arc::new(ServerMetrics::default());
+        let thread_stats = ThreadStats::default();
+        thread_stats
+            .subsystem("stream")
+            .record(Duration::from_millis(1), Duration::from_millis(10));
+
+        let guard = metrics.client_connected();
+        metrics.quote_sent();
+        metrics.quote_sent();
+        metrics.udp_send_error();
+
+        let text = metrics.render(&thread_stats);
+        assert!(text.contains("quotes_connected_clients 1\n"));
+        assert!(text.contains("quotes_sent_total 2\n"));
+        assert!(text.contains("quotes_udp_send_errors_total 1\n"));
+        assert!(text.contains("quotes_decode_failures_total 0\n"));
+        assert!(text.contains("quotes_dropped_total 0\n"));
+        assert!(text.contains("quotes_loop_iterations_total{subsystem=\"stream\"} 1\n"));
+
+        drop(guard);
+        assert_eq!(metrics.snapshot().connected_clients, 0);
+    }
+
+    #[test]
+    fn test_check_health() {
+        let thread_stats = ThreadStats::default();
+        let subsystems = vec!["server".to_string(), "replay".to_string()];
+        let stall_timeout = Duration::from_secs(5);
+
+        thread_stats
+            .subsystem("server")
+            .record(Duration::from_millis(1), Duration::from_millis(10));
+        assert_eq!(
+            check_health(&thread_stats, &subsystems, stall_timeout),
+            Health::Starting("replay".to_string())
+        );
+
+        thread_stats
+            .subsystem("replay")
+            .record(Duration::from_millis(1), Duration::from_millis(10));
+        assert_eq!(
+            check_health(&thread_stats, &subsystems, stall_timeout),
+            Health::Ready
+        );
+
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            check_health(&thread_stats, &subsystems, Duration::from_millis(1)),
+            Health::Stalled("server".to_string())
+        );
+    }
+}
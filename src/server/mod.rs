@@ -1,2 +1,60 @@
 /// Сервер котировок
 pub mod quotes_server;
+
+/// Настройки сервера
+pub mod config;
+
+/// Метрики сервера и их публикация для Prometheus
+pub mod metrics;
+
+/// Аутентификация клиентов
+pub mod auth;
+
+/// Запись сгенерированных котировок на диск
+pub mod recorder;
+
+/// Воспроизведение записанных котировок
+pub mod replay;
+
+/// Запись сессий клиентов
+pub mod session;
+
+/// Журнал аудита управляющих каналов клиентов
+pub mod audit;
+
+/// Источники котировок для сервера
+pub mod source;
+
+/// Ретрансляция котировок вышестоящего сервера
+pub mod relay;
+
+/// Ошибки сервера
+pub mod error;
+
+mod admin;
+
+mod bandwidth;
+
+mod bars;
+
+mod cache;
+
+mod channel;
+
+mod history;
+
+mod hub;
+
+mod mdns;
+
+mod multicast;
+
+mod overload;
+
+mod retention;
+
+mod sender;
+
+mod shards;
+
+mod websocket;
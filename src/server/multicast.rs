@@ -0,0 +1,123 @@
+use super::config::UdpSocketOptions;
+use super::hub::{QuoteFeed, QuoteOrigin};
+use super::metrics::ServerMetrics;
+use super::quotes_server::{ControlCmd, cmd_from_channel};
+use crate::protocol::{Message, QuoteRespMessage};
+use crate::stats::LoopStats;
+use crate::timer::Timer;
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const PUBLISH_MILLIS: u64 = 1000;
+
+const WAIT_CMD_EVENT: &str = "cmd";
+const PUBLISH_EVENT: &str = "publish";
+
+pub(super) struct MulticastPublisherControl {
+    pub(super) tx: mpsc::Sender<ControlCmd>,
+    pub(super) thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+pub(super) struct MulticastPublisher {
+    socket: UdpSocket,
+    group: SocketAddr,
+    feed: QuoteFeed,
+    tickers: Arc<Vec<String>>,
+    metrics: Arc<ServerMetrics>,
+    loop_stats: Arc<LoopStats>,
+    next_seq: HashMap<String, u64>,
+}
+
+impl MulticastPublisher {
+    pub(super) fn new(
+        group: SocketAddr,
+        origin: &QuoteOrigin,
+        tickers: Arc<Vec<String>>,
+        socket_options: &UdpSocketOptions,
+        metrics: Arc<ServerMetrics>,
+        loop_stats: Arc<LoopStats>,
+    ) -> Result<Self> {
+        if !group.ip().is_multicast() {
+            bail!("{group} is not a multicast address");
+        }
+        let socket = match group {
+            SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+            SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+        };
+        socket_options.apply(&socket)?;
+        log::info!("Quotes are published to multicast group {group}");
+        Ok(Self {
+            socket,
+            group,
+            feed: origin.feed(),
+            tickers,
+            metrics,
+            loop_stats,
+            next_seq: HashMap::new(),
+        })
+    }
+
+    fn send(&self, msg: &Message) -> Result<()> {
+        let bin_msg = postcard::to_stdvec(msg)?;
+        self.socket.send_to(&bin_msg, self.group)?;
+        Ok(())
+    }
+
+    fn publish(&mut self) {
+        for quote in self.feed.quotes(&self.tickers) {
+            let ticker = quote.ticker.clone();
+            let seq = self.next_seq.get(&ticker).copied().unwrap_or(1);
+            match self.send(&Message::Quote(QuoteRespMessage { quote, seq })) {
+                Ok(()) => {
+                    self.next_seq.insert(ticker, seq + 1);
+                    self.metrics.quote_sent();
+                }
+                Err(e) => {
+                    log::error!("Can't publish quote to {}: {e}", self.group);
+                    self.metrics.udp_send_error();
+                }
+            }
+        }
+    }
+
+    pub(super) fn start(mut self) -> MulticastPublisherControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::with_stats(self.loop_stats.clone());
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(PUBLISH_EVENT, self.feed.poll_millis(PUBLISH_MILLIS));
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    match cmd_from_channel(&rx) {
+                        ControlCmd::Stop => break,
+                        ControlCmd::Shutdown => {
+                            log::info!("Notify multicast group about shutdown");
+                            self.send(&Message::Shutdown)?;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+
+                if timer.is_expired_event(PUBLISH_EVENT)? {
+                    timer.reset_event(PUBLISH_EVENT)?;
+                    self.publish();
+                }
+            }
+
+            log::info!("Multicast publisher is stopped");
+            Ok(())
+        });
+        MulticastPublisherControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
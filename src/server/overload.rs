@@ -0,0 +1,202 @@
+use super::config::OverloadConfig;
+use super::metrics::ServerMetrics;
+use crate::stats::LoopStats;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Признак режима перегрузки, общий для потоков сервера
+#[derive(Default)]
+pub(super) struct OverloadState {
+    degraded: AtomicBool,
+}
+
+impl OverloadState {
+    pub(super) fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+}
+
+fn slow_client_drops(metrics: &ServerMetrics) -> u64 {
+    let snapshot = metrics.snapshot();
+    snapshot
+        .quotes_dropped
+        .saturating_sub(snapshot.degraded_dropped)
+}
+
+/// Следит за загрузкой циклов потоков и выбросом котировок из очередей отправки,
+/// переключает сервер в режим перегрузки и обратно
+pub(super) struct OverloadDetector {
+    config: OverloadConfig,
+    loops: Vec<(Arc<LoopStats>, (u64, u64))>,
+    last_dropped: u64,
+    last_check: Instant,
+    healthy_checks: u32,
+    state: Arc<OverloadState>,
+    metrics: Arc<ServerMetrics>,
+}
+
+impl OverloadDetector {
+    pub(super) fn new(
+        config: OverloadConfig,
+        loops: Vec<Arc<LoopStats>>,
+        state: Arc<OverloadState>,
+        metrics: Arc<ServerMetrics>,
+    ) -> Self {
+        Self {
+            config,
+            loops: loops
+                .into_iter()
+                .map(|stats| {
+                    let micros = stats.micros();
+                    (stats, micros)
+                })
+                .collect(),
+            last_dropped: slow_client_drops(&metrics),
+            last_check: Instant::now(),
+            healthy_checks: 0,
+            state,
+            metrics,
+        }
+    }
+
+    pub(super) fn check_millis(&self) -> u64 {
+        self.config.check_millis
+    }
+
+    /// Наибольшая доля времени работы циклов с прошлой проверки
+    fn max_busy_ratio(&mut self) -> f64 {
+        let mut max_ratio: f64 = 0.0;
+        for (stats, last) in self.loops.iter_mut() {
+            let (busy, total) = stats.micros();
+            let (last_busy, last_total) = *last;
+            *last = (busy, total);
+            if total > last_total {
+                let ratio = (busy - last_busy) as f64 / (total - last_total) as f64;
+                max_ratio = max_ratio.max(ratio);
+            }
+        }
+        max_ratio
+    }
+
+    /// Котировки, выброшенные за секунду без учета замен в режиме перегрузки:
+    /// иначе сервер не выходил бы из режима из-за замен, которые делает сам режим
+    fn dropped_per_sec(&mut self) -> f64 {
+        let dropped = slow_client_drops(&self.metrics);
+        let elapsed = self.last_check.elapsed().as_secs_f64();
+        let count = dropped.saturating_sub(self.last_dropped);
+        self.last_dropped = dropped;
+        self.last_check = Instant::now();
+        if elapsed > 0.0 {
+            count as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Переключает режим по результату проверки. Возвращает новый режим, если он изменился
+    fn update(&mut self, overloaded: bool) -> Option<bool> {
+        let degraded = self.state.is_degraded();
+        if overloaded {
+            self.healthy_checks = 0;
+            if degraded {
+                return None;
+            }
+        } else {
+            if !degraded {
+                return None;
+            }
+            self.healthy_checks += 1;
+            if self.healthy_checks < self.config.recover_checks {
+                return None;
+            }
+        }
+        self.state.degraded.store(overloaded, Ordering::Relaxed);
+        self.metrics.set_overloaded(overloaded);
+        Some(overloaded)
+    }
+
+    pub(super) fn check(&mut self) {
+        let busy_ratio = self.max_busy_ratio();
+        let dropped_per_sec = self.dropped_per_sec();
+        let overloaded = busy_ratio > self.config.max_busy_ratio
+            || dropped_per_sec > self.config.max_dropped_per_sec;
+        match self.update(overloaded) {
+            Some(true) => log::warn!(
+                "Server is overloaded (busy: {:.2}%, dropped: {dropped_per_sec:.1}/s), degrade streaming",
+                busy_ratio * 100.0
+            ),
+            Some(false) => log::info!("Server is recovered from overload"),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overload_hysteresis() {
+        let state = Arc::new(OverloadState::default());
+        let metrics = Arc::new(ServerMetrics::default());
+        let mut detector = OverloadDetector::new(
+            OverloadConfig {
+                recover_checks: 2,
+                ..Default::default()
+            },
+            Vec::new(),
+            state.clone(),
+            metrics.clone(),
+        );
+
+        assert_eq!(detector.update(false), None);
+        assert_eq!(detector.update(true), Some(true));
+        assert!(state.is_degraded());
+        assert_eq!(metrics.snapshot().overloaded, 1);
+        assert_eq!(detector.update(true), None);
+        assert_eq!(detector.update(false), None);
+        assert_eq!(detector.update(true), None);
+        assert_eq!(detector.update(false), None);
+        assert_eq!(detector.update(false), Some(false));
+        assert!(!state.is_degraded());
+        assert_eq!(metrics.snapshot().overloaded, 0);
+    }
+
+    #[test]
+    fn test_degraded_drops_are_ignored() {
+        let metrics = Arc::new(ServerMetrics::default());
+        let mut detector = OverloadDetector::new(
+            OverloadConfig::default(),
+            Vec::new(),
+            Arc::new(OverloadState::default()),
+            metrics.clone(),
+        );
+        metrics.quotes_dropped(1000);
+        metrics.degraded_dropped(1000);
+        assert_eq!(detector.dropped_per_sec(), 0.0);
+        metrics.quotes_dropped(10);
+        assert!(detector.dropped_per_sec() > 0.0);
+    }
+
+    #[test]
+    fn test_busy_ratio_window() {
+        let stats = Arc::new(LoopStats::default());
+        stats.record(
+            std::time::Duration::from_millis(10),
+            std::time::Duration::from_millis(100),
+        );
+        let mut detector = OverloadDetector::new(
+            OverloadConfig::default(),
+            vec![stats.clone()],
+            Arc::new(OverloadState::default()),
+            Arc::new(ServerMetrics::default()),
+        );
+        stats.record(
+            std::time::Duration::from_millis(90),
+            std::time::Duration::from_millis(100),
+        );
+        assert!((detector.max_busy_ratio() - 0.9).abs() < 1e-9);
+        assert_eq!(detector.max_busy_ratio(), 0.0);
+    }
+}
@@ -1,37 +1,259 @@
+use super::admin::AdminServer;
+use super::audit::{AuditEvent, AuditLog};
+use super::auth::{AllowAll, Authenticator};
+use super::bandwidth::BandwidthBudget;
+use super::bars::BarAggregator;
+use super::cache::LastValueCache;
+use super::channel::{ControlChannel, RecvError, TcpChannel, close_with_error};
+use super::config::{ServerConfig, TcpSocketOptions};
+use super::error::{ServerError, ServerResult};
+use super::history::QuoteHistory;
+use super::hub::{QuoteHub, QuoteOrigin};
+use super::mdns::MdnsAnnouncer;
+use super::metrics::{MetricsExporter, ServerMetrics};
+use super::multicast::MulticastPublisher;
+use super::overload::{OverloadDetector, OverloadState};
+use super::recorder::{BackgroundRecorder, QuoteRecorder, RECORD_QUEUE_CAPACITY};
+use super::replay::{ReplayPlayer, ReplayPlayerControl};
+use super::retention::RetainedSubscriptions;
+use super::sender::{FlushReport, QuotesSender, Transport, UdpTransport};
+use super::session::{ServerEvent, SessionEvent, SessionLog, SessionTranscript};
+use super::shards::{GeneratorShard, GeneratorShardControl};
+use super::source::{QuoteSource, SourcePoller, SourcePollerControl};
+use super::websocket::{WsChannel, WsConnection};
 use crate::protocol::*;
-use crate::quote::{QuoteGenerator, StockQuote};
+use crate::quote::{Bar, QuoteCallback, QuoteGenerator, StockQuote};
+use crate::stats::{LoopStats, ThreadStats};
 use crate::timer::Timer;
-use crate::utils::StreamReader;
+use crate::tls::{self, ControlStream};
+use crate::utils::{bind_tcp_listener, bind_udp};
 use anyhow::{Result, anyhow, bail};
-use std::io::ErrorKind;
+use serde::Serialize;
+use std::any::Any;
+use std::fmt::Display;
+use std::io::{ErrorKind, Write};
 use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
-use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError, TrySendError};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
+const SERVER_EVENTS_CAPACITY: usize = 1024;
 const STREAMING_TIMEOUT_MILLIS: u64 = 1000;
 const CHECK_TCP_CMD_MILLIS: u64 = 100;
 const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
-const CHECK_PING_MILLIS: u64 = 100;
 const ACCEPT_MILLIS: u64 = 100;
+const REAP_HANDLERS_MILLIS: u64 = 1000;
+const CLIENT_INFO_TIMEOUT_MILLIS: u64 = 1000;
+const REJECT_TIMEOUT_MILLIS: u64 = 1000;
+const REJECT_QUEUE_CAPACITY: usize = 64;
+const TLS_HANDSHAKE_TIMEOUT_MILLIS: u64 = 1000;
 
 const STREAM_EVENT: &str = "stream";
 const WAIT_CMD_EVENT: &str = "cmd";
 const CHECK_PING_EVENT: &str = "check_ping";
+const PING_WAIT_EVENT: &str = "ping_wait";
 const CHECK_TCP_CMD_EVENT: &str = "check_tcp_cmd";
+const SUBSCRIBE_WAIT_EVENT: &str = "subscribe_wait";
 const ACCEPT_EVENT: &str = "accept";
+const REAP_HANDLERS_EVENT: &str = "reap_handlers";
+const OVERLOAD_EVENT: &str = "overload";
+
+const SERVER_SUBSYSTEM: &str = "server";
+const HANDLER_SUBSYSTEM: &str = "handler";
+const STREAM_SUBSYSTEM: &str = "stream";
+const MULTICAST_SUBSYSTEM: &str = "multicast";
+const REPLAY_SUBSYSTEM: &str = "replay";
+const GENERATOR_SUBSYSTEM: &str = "generator";
+const SOURCE_SUBSYSTEM: &str = "source";
 
 /// Управляющие команды сервером
 pub enum ControlCmd {
     /// Остановить сервер
     Stop,
-    /// Генерировать выбранные котировки
-    Quotes(TickerReqMessage),
+    /// Остановить обработку клиента, предварительно уведомив его об остановке сервера
+    Shutdown,
+    /// Генерировать выбранные котировки: тикеры запроса меняют подписку по режиму
+    Quotes(TickerReqMessage, SubscriptionMode),
+    /// Приостановить или возобновить отправку котировок клиенту
+    Pause(bool),
     /// Нет команды
     Noop,
+    /// Получить список подключенных клиентов
+    ListClients(mpsc::Sender<Vec<ClientInfo>>),
+    /// Отключить клиента с указанным адресом. В ответ приходит признак того, что клиент найден
+    KickClient(SocketAddr, mpsc::Sender<bool>),
+    /// Запросить информацию о клиенте у его обработчика
+    ClientInfo(mpsc::Sender<ClientInfo>),
+    /// Получить запись сессии клиента с указанным адресом
+    ClientTranscript(SocketAddr, mpsc::Sender<Option<SessionTranscript>>),
+    /// Запросить запись сессии у обработчика клиента
+    Transcript(mpsc::Sender<SessionTranscript>),
+}
+
+/// Информация о подключенном клиенте
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    /// Адрес управляющего соединения клиента
+    pub addr: SocketAddr,
+    /// Тикеры, на которые подписан клиент
+    pub tickers: Vec<String>,
+    /// Количество отправленных котировок
+    pub quotes_sent: u64,
+    /// Количество отправленных байт
+    pub bytes_sent: u64,
+    /// Количество ошибок отправки
+    pub send_errors: u64,
+    /// Количество котировок, выброшенных из очереди отправки
+    pub quotes_dropped: u64,
+    /// Количество отправок, отложенных из-за лимита трафика клиента
+    pub bandwidth_capped: u64,
+    /// Количество обменов пинг-понг с клиентом
+    pub pings: u64,
+    /// Время с момента подключения
+    pub connected: Duration,
+}
+
+impl Display for ClientInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} quotes: {}, bytes: {}, errors: {}, dropped: {}, capped: {}, pings: {}, connected: {}s",
+            self.addr,
+            self.quotes_sent,
+            self.bytes_sent,
+            self.send_errors,
+            self.quotes_dropped,
+            self.bandwidth_capped,
+            self.pings,
+            self.connected.as_secs()
+        )
+    }
+}
+
+#[derive(Default)]
+struct ClientSendStats {
+    quotes_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    send_errors: AtomicU64,
+    quotes_dropped: AtomicU64,
+    bandwidth_capped: AtomicU64,
+    pings: AtomicU64,
+}
+
+struct ClientSession {
+    addr: SocketAddr,
+    connected_at: Instant,
+    send_stats: ClientSendStats,
+    log: Mutex<SessionLog>,
+    events: mpsc::SyncSender<ServerEvent>,
+    audit: Option<Arc<AuditLog>>,
+}
+
+impl ClientSession {
+    fn new(
+        addr: SocketAddr,
+        events: mpsc::SyncSender<ServerEvent>,
+        audit: Option<Arc<AuditLog>>,
+    ) -> Self {
+        Self {
+            addr,
+            connected_at: Instant::now(),
+            send_stats: ClientSendStats::default(),
+            log: Mutex::new(SessionLog::new()),
+            events,
+            audit,
+        }
+    }
+
+    fn record(&self, event: SessionEvent) {
+        if let Some(server_event) = ServerEvent::from_session(self.addr, &event) {
+            self.emit(server_event);
+        }
+        self.log.lock().unwrap().record(event);
+    }
+
+    fn emit(&self, event: ServerEvent) {
+        let _ = self.events.try_send(event);
+    }
+
+    fn audit(&self, event: AuditEvent) {
+        if let Some(audit) = self.audit.as_ref() {
+            audit.write(self.addr, event);
+        }
+    }
+
+    fn info(&self, addr: SocketAddr, tickers: &[String]) -> ClientInfo {
+        ClientInfo {
+            addr,
+            tickers: tickers.to_vec(),
+            quotes_sent: self.send_stats.quotes_sent.load(Ordering::Relaxed),
+            bytes_sent: self.send_stats.bytes_sent.load(Ordering::Relaxed),
+            send_errors: self.send_stats.send_errors.load(Ordering::Relaxed),
+            quotes_dropped: self.send_stats.quotes_dropped.load(Ordering::Relaxed),
+            bandwidth_capped: self.send_stats.bandwidth_capped.load(Ordering::Relaxed),
+            pings: self.send_stats.pings.load(Ordering::Relaxed),
+            connected: self.connected_at.elapsed(),
+        }
+    }
+
+    fn transcript(&self, addr: SocketAddr, tickers: &[String]) -> SessionTranscript {
+        self.log
+            .lock()
+            .unwrap()
+            .transcript(self.info(addr, tickers))
+    }
+
+    fn isolate_panic(&self, thread: &str, res: thread::Result<Result<()>>) -> Result<()> {
+        let payload = match res {
+            Ok(res) => return res,
+            Err(payload) => payload,
+        };
+        let message = panic_message(payload.as_ref());
+        log::error!(
+            "Thread {thread} of client {} panicked: {message}",
+            self.addr
+        );
+        self.emit(ServerEvent::ThreadPanicked {
+            addr: self.addr,
+            thread: thread.to_string(),
+            message: message.clone(),
+        });
+        bail!("Thread {thread} panicked: {message}")
+    }
+
+    fn close_on_panic(&self, thread: &str, res: thread::Result<Result<()>>) -> Result<()> {
+        let panicked = res.is_err();
+        let res = self.isolate_panic(thread, res);
+        if panicked {
+            let reason = format!("{thread} thread panicked");
+            self.audit(AuditEvent::Closed {
+                reason: reason.clone(),
+            });
+            self.emit(ServerEvent::ClientDisconnected {
+                addr: self.addr,
+                reason,
+                summary: self.info(self.addr, &[]),
+            });
+        }
+        res
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    "unknown panic".to_string()
 }
 
-fn cmd_from_channel(rx: &mpsc::Receiver<ControlCmd>) -> ControlCmd {
+pub(super) fn cmd_from_channel(rx: &mpsc::Receiver<ControlCmd>) -> ControlCmd {
     match rx.try_recv() {
         Ok(cmd) => cmd,
         Err(e) => match e {
@@ -44,30 +266,84 @@ fn cmd_from_channel(rx: &mpsc::Receiver<ControlCmd>) -> ControlCmd {
     }
 }
 
-struct QuotesStreamControl {
+#[derive(Clone)]
+struct ServerContext {
+    config: Arc<ServerConfig>,
+    origin: QuoteOrigin,
+    tickers: Arc<Vec<String>>,
+    thread_stats: Arc<ThreadStats>,
+    metrics: Arc<ServerMetrics>,
+    authenticator: Arc<dyn Authenticator>,
+    multicast_group: Option<SocketAddr>,
+    retained: Arc<RetainedSubscriptions>,
+    events: mpsc::SyncSender<ServerEvent>,
+    last_values: LastValueCache,
+    audit: Option<Arc<AuditLog>>,
+    bandwidth: Option<Arc<BandwidthBudget>>,
+    overload: Arc<OverloadState>,
+    bars: Option<Arc<BarAggregator>>,
+    history: QuoteHistory,
+    rejector: ClientRejector,
+}
+
+impl ServerContext {
+    fn replay_quotes(&self, tickers: &[String]) -> Vec<StockQuote> {
+        tickers
+            .iter()
+            .flat_map(|ticker| self.history.last(ticker, self.config.replay_on_subscribe))
+            .collect()
+    }
+}
+
+enum PingStatus {
+    Nothing,
+    Ping,
+    ConnectionReset,
+}
+
+fn is_connection_reset(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::ConnectionReset)
+}
+
+pub(super) struct QuotesStreamControl {
     tx: mpsc::Sender<ControlCmd>,
     thread_handle: thread::JoinHandle<Result<()>>,
 }
 
-struct QuotesStream {
-    quote_generator: Arc<Mutex<QuoteGenerator>>,
+/// Поток котировок одного клиента
+pub(super) struct QuotesStream {
+    context: ServerContext,
     client_ip_addr: IpAddr,
+    listen_ip: IpAddr,
+    loop_stats: Arc<LoopStats>,
+    session: Arc<ClientSession>,
 }
 
 impl QuotesStream {
-    fn new(quote_generator: Arc<Mutex<QuoteGenerator>>, client_ip_addr: IpAddr) -> Self {
+    fn new(
+        context: ServerContext,
+        client_ip_addr: IpAddr,
+        listen_ip: IpAddr,
+        session: Arc<ClientSession>,
+    ) -> Self {
+        let loop_stats = context.thread_stats.subsystem(STREAM_SUBSYSTEM);
         Self {
-            quote_generator,
+            context,
             client_ip_addr,
+            listen_ip,
+            loop_stats,
+            session,
         }
     }
 
-    fn check_ping(&self, socket: &UdpSocket) -> Result<()> {
+    fn check_ping(&self, socket: &UdpSocket) -> Result<PingStatus> {
         let mut recv_buf = [0u8; MAX_SIZE_DATAGRAM];
         let (pack_len, client_addr) = match socket.recv_from(&mut recv_buf) {
             Ok((len, addr)) => (len, addr),
             Err(e) => match e.kind() {
-                ErrorKind::WouldBlock => return Ok(()),
+                ErrorKind::WouldBlock => return Ok(PingStatus::Nothing),
+                ErrorKind::ConnectionReset => return Ok(PingStatus::ConnectionReset),
                 _ => {
                     bail!("Can't read from socket: {e}");
                 }
@@ -75,12 +351,24 @@ impl QuotesStream {
         };
 
         if pack_len == 0 {
-            return Ok(());
+            return Ok(PingStatus::Nothing);
         }
 
-        let msg = postcard::from_bytes::<Message>(&recv_buf[..pack_len])?;
+        let msg = postcard::from_bytes::<Message>(&recv_buf[..pack_len]).inspect_err(|e| {
+            self.context.metrics.decode_failure();
+            self.session.record(SessionEvent::Error {
+                description: format!("Can't decode datagram: {e}"),
+            });
+        })?;
         match msg {
-            Message::Ping => log::info!("PING"),
+            Message::Ping => {
+                log::info!("PING");
+                self.session.record(SessionEvent::Ping);
+                self.session
+                    .send_stats
+                    .pings
+                    .fetch_add(1, Ordering::Relaxed);
+            }
             _ => bail!("Wrong message"),
         }
 
@@ -88,307 +376,2502 @@ impl QuotesStream {
         socket.send_to(&bin_pong, client_addr)?;
         log::info!("PONG");
 
-        Ok(())
+        Ok(PingStatus::Ping)
+    }
+
+    fn is_client_gone(&self, connection_resets: &mut u32) -> bool {
+        *connection_resets += 1;
+        match self.context.config.connection_reset_threshold {
+            Some(threshold) if *connection_resets >= threshold => {
+                log::info!("Client is unreachable: {connection_resets} connection resets in a row");
+                self.session.record(SessionEvent::Error {
+                    description: format!("{connection_resets} connection resets in a row"),
+                });
+                true
+            }
+            _ => {
+                log::debug!("Connection reset by client is ignored");
+                false
+            }
+        }
+    }
+
+    fn bar_feed(&self, requested: bool, current: Option<Receiver<Bar>>) -> Option<Receiver<Bar>> {
+        if !requested {
+            return None;
+        }
+        match self.context.bars.as_ref() {
+            Some(aggregator) => Some(current.unwrap_or_else(|| aggregator.subscribe())),
+            None => {
+                log::warn!("Bars are disabled on server, stream quotes");
+                None
+            }
+        }
+    }
+
+    fn push_bars(&self, sender: &mut QuotesSender, bars: &Receiver<Bar>, tickers: &[String]) {
+        for bar in bars.try_iter().filter(|bar| tickers.contains(&bar.ticker)) {
+            let dropped = sender.push_bar(bar);
+            self.quotes_dropped(dropped);
+        }
+    }
+
+    fn push_replay(&self, sender: &mut QuotesSender, tickers: &[String]) {
+        let quotes = self.context.replay_quotes(tickers);
+        log::debug!("Replay {} quotes to {}", quotes.len(), self.client_ip_addr);
+        for quote in quotes {
+            let dropped = sender.push_replay(quote);
+            self.quotes_dropped(dropped);
+        }
+    }
+
+    fn stream_millis(&self, stream_millis: u64, degraded: bool) -> u64 {
+        match self.context.config.overload.as_ref() {
+            Some(overload) if degraded => stream_millis * overload.stream_interval_factor.max(1),
+            _ => stream_millis,
+        }
+    }
+
+    fn quotes_dropped(&self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        self.context.metrics.quotes_dropped(count);
+        self.session
+            .send_stats
+            .quotes_dropped
+            .fetch_add(count, Ordering::Relaxed);
     }
 
-    fn send_quote(&self, socket: &UdpSocket, port: u16, quote: Option<StockQuote>) -> Result<()> {
-        let quote_msg = if let Some(val) = quote {
-            Message::Quote(QuoteRespMessage { quote: val })
+    fn bandwidth_capped(&self, sender: &QuotesSender) {
+        self.context.metrics.bandwidth_capped();
+        let capped = self
+            .session
+            .send_stats
+            .bandwidth_capped
+            .fetch_add(1, Ordering::Relaxed);
+        if capped == 0 {
+            log::warn!(
+                "Client {} reached bandwidth cap of {} bytes/s after {} bytes, conflate quotes",
+                self.client_ip_addr,
+                self.context.config.client_bandwidth_bytes_per_sec,
+                sender.bytes_sent()
+            );
         } else {
-            Message::Unknown
+            log::debug!("Client {} is over bandwidth cap", self.client_ip_addr);
+        }
+    }
+
+    fn account_flush(
+        &self,
+        report: FlushReport,
+        sender: &mut QuotesSender,
+        connection_resets: &mut u32,
+    ) -> bool {
+        self.context
+            .metrics
+            .degraded_dropped(sender.take_degraded_dropped());
+        for _ in 0..report.sent {
+            self.context.metrics.quote_sent();
+        }
+        self.session
+            .send_stats
+            .quotes_sent
+            .fetch_add(report.sent, Ordering::Relaxed);
+        self.session
+            .send_stats
+            .bytes_sent
+            .fetch_add(report.bytes, Ordering::Relaxed);
+        self.quotes_dropped(report.dropped);
+
+        if report.capped {
+            self.bandwidth_capped(sender);
+        }
+        if report.blocked {
+            log::debug!("Socket send buffer is full, postpone sending");
+            self.context.metrics.udp_send_blocked();
+        }
+        let connection_reset = match report.error {
+            Some(e) => {
+                log::error!("Send quote error: {e}");
+                self.context.metrics.udp_send_error();
+                self.session
+                    .send_stats
+                    .send_errors
+                    .fetch_add(1, Ordering::Relaxed);
+                self.session.record(SessionEvent::Error {
+                    description: format!("Send quote error: {e}"),
+                });
+                is_connection_reset(&e)
+            }
+            None => false,
         };
+        if report.disconnect {
+            log::info!("Client doesn't consume quotes, disconnect");
+            self.session.record(SessionEvent::Error {
+                description: "Too many send errors in a row".to_string(),
+            });
+            return true;
+        }
+        connection_reset && self.is_client_gone(connection_resets)
+    }
 
-        let bin_msg = postcard::to_stdvec(&quote_msg)?;
-        let _ = socket.send_to(&bin_msg, SocketAddr::new(self.client_ip_addr, port))?;
-        Ok(())
+    /// Запускает поток котировок в датаграммах udp. None — котировки идут через multicast
+    pub(super) fn start_udp(self) -> Option<QuotesStreamControl> {
+        if self.context.multicast_group.is_some() {
+            return None;
+        }
+        Some(self.start(|stream: &Self| {
+            let socket = bind_udp(SocketAddr::new(
+                stream.listen_ip,
+                stream.context.config.quotes_udp_port,
+            ))?;
+            stream.context.config.udp_socket.apply(&socket)?;
+            socket.set_nonblocking(true)?;
+            UdpTransport::new(socket, stream.client_ip_addr)
+        }))
     }
 
-    fn start(self) -> QuotesStreamControl {
+    /// Запускает поток котировок через транспорт, который открывает open
+    pub(super) fn start<T, F>(self, open: F) -> QuotesStreamControl
+    where
+        T: Transport,
+        F: FnOnce(&Self) -> Result<T> + Send + 'static,
+    {
         log::info!("Start streaming quotes");
         let (tx, rx): (Sender<ControlCmd>, Receiver<ControlCmd>) = mpsc::channel();
         let handle = thread::spawn(move || {
-            let socket = UdpSocket::bind("127.0.0.1:34254")?;
-            socket.set_nonblocking(true)?;
+            let session = self.session.clone();
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                let transport = open(&self)?;
+                self.run(rx, transport)
+            }));
+            session.isolate_panic(STREAM_SUBSYSTEM, res)
+        });
+        QuotesStreamControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
 
-            let mut need_quotes = Vec::new();
-            let mut cur_client_port = None;
-            let mut timer = Timer::default();
-            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
-            timer.add_event(STREAM_EVENT, STREAMING_TIMEOUT_MILLIS);
-            timer.add_event(CHECK_PING_EVENT, CHECK_PING_MILLIS);
+    fn run<T: Transport>(self, rx: Receiver<ControlCmd>, mut transport: T) -> Result<()> {
+        let mut need_quotes = Vec::new();
+        let mut bar_feed = None;
+        let mut paused = false;
+        let mut sender = QuotesSender::new(self.context.config.backpressure)
+            .with_conflation(Duration::from_millis(self.context.config.conflation_millis))
+            .with_client_cap(self.context.config.client_bandwidth_bytes_per_sec);
+        if let Some(budget) = self.context.bandwidth.as_ref() {
+            sender = sender.with_bandwidth(budget.share());
+        }
+        let mut connection_resets = 0;
+        let ping_wait_millis = self.context.config.ping_wait_millis;
+        let feed = self.context.origin.feed();
+        let stream_millis = feed.poll_millis(STREAMING_TIMEOUT_MILLIS);
+        let mut timer = Timer::with_stats(self.loop_stats.clone());
+        timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+        timer.add_event(STREAM_EVENT, stream_millis);
+        timer.add_event(CHECK_PING_EVENT, self.context.config.check_ping_millis);
+        let mut res = Ok(());
 
-            loop {
-                timer.sleep();
+        loop {
+            timer.sleep();
 
-                if timer.is_expired_event(WAIT_CMD_EVENT)? {
-                    timer.reset_event(WAIT_CMD_EVENT)?;
-                    match cmd_from_channel(&rx) {
-                        ControlCmd::Stop => {
-                            log::info!("Stop streaming");
-                            break;
-                        }
-                        ControlCmd::Quotes(req) => {
-                            log::debug!("Quotes request: {:?}", req);
-                            cur_client_port = Some(req.port);
-                            need_quotes = req.tickers;
+            if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                timer.reset_event(WAIT_CMD_EVENT)?;
+                match cmd_from_channel(&rx) {
+                    ControlCmd::Stop => {
+                        log::info!("Stop streaming");
+                        break;
+                    }
+                    ControlCmd::Shutdown => {
+                        log::info!("Stop streaming, notify client about shutdown");
+                        transport.send_shutdown();
+                        break;
+                    }
+                    ControlCmd::Pause(val) => {
+                        log::info!("Streaming paused: {val}");
+                        paused = val;
+                    }
+                    ControlCmd::Quotes(req, mode) => {
+                        log::debug!("Quotes request: {:?}", req);
+                        let mut client_ports = vec![req.port];
+                        client_ports.extend(req.stripe_ports);
+                        transport.set_ports(client_ports);
+                        mode.apply(&mut need_quotes, &req.tickers);
+                        sender.set_filter(req.filter);
+                        bar_feed = self.bar_feed(req.bars, bar_feed.take());
+                        timer.add_event(PING_WAIT_EVENT, ping_wait_millis);
+
+                        let replay = mode != SubscriptionMode::Remove && bar_feed.is_none();
+                        if replay {
+                            if self.context.config.replay_on_subscribe > 0 {
+                                self.push_replay(&mut sender, &req.tickers);
+                            } else {
+                                for quote in self.context.last_values.get(&req.tickers) {
+                                    let dropped = sender.push(quote);
+                                    self.quotes_dropped(dropped);
+                                }
+                            }
+                            let report = sender.flush(&mut transport);
+                            if self.account_flush(report, &mut sender, &mut connection_resets) {
+                                break;
+                            }
                         }
-                        ControlCmd::Noop => {}
                     }
+                    _ => {}
                 }
+            }
 
-                if timer.is_expired_event(CHECK_PING_EVENT)? {
-                    timer.reset_event(CHECK_PING_EVENT)?;
+            if timer.is_expired_event(CHECK_PING_EVENT)? {
+                timer.reset_event(CHECK_PING_EVENT)?;
 
-                    if let Err(e) = self.check_ping(&socket) {
+                let status = match transport.ping_socket() {
+                    Some(socket) => self.check_ping(socket),
+                    None => Ok(PingStatus::Nothing),
+                };
+                match status {
+                    Ok(PingStatus::Ping) => {
+                        connection_resets = 0;
+                        if transport.is_ready() {
+                            timer.reset_event(PING_WAIT_EVENT)?;
+                        }
+                    }
+                    Ok(PingStatus::ConnectionReset) => {
+                        if self.is_client_gone(&mut connection_resets) {
+                            break;
+                        }
+                    }
+                    Ok(PingStatus::Nothing) => {}
+                    Err(e) => {
                         log::error!("Check ping error: {e}");
+                        self.session.record(SessionEvent::Error {
+                            description: format!("Check ping error: {e}"),
+                        });
                         break;
                     }
                 }
+            }
 
-                if timer.is_expired_event(STREAM_EVENT)? {
-                    timer.reset_event(STREAM_EVENT)?;
-                    if let Some(port) = cur_client_port {
-                        for need_quote in need_quotes.iter() {
-                            let quote = self
-                                .quote_generator
-                                .lock()
-                                .unwrap()
-                                .generate_quote(need_quote.as_str());
-                            if let Err(e) = self.send_quote(&socket, port, quote) {
-                                log::error!("Send quote error: {e}");
-                                break;
-                            }
+            let expects_ping =
+                ping_wait_millis > 0 && transport.ping_socket().is_some() && transport.is_ready();
+            if expects_ping && timer.is_expired_event(PING_WAIT_EVENT)? {
+                log::info!("Client doesn't send ping during {ping_wait_millis} ms");
+                self.context.metrics.ping_timeout();
+                self.session.record(SessionEvent::Error {
+                    description: format!("No ping during {ping_wait_millis} ms"),
+                });
+                res = Err(ServerError::ClientTimeout {
+                    addr: self.client_ip_addr,
+                    millis: ping_wait_millis,
+                }
+                .into());
+                break;
+            }
+
+            if timer.is_expired_event(STREAM_EVENT)? {
+                timer.reset_event(STREAM_EVENT)?;
+                let degraded = self.context.overload.is_degraded();
+                if degraded != sender.is_degraded() {
+                    sender.set_degraded(degraded);
+                    timer.add_event(STREAM_EVENT, self.stream_millis(stream_millis, degraded));
+                }
+                if paused {
+                    feed.discard();
+                    continue;
+                }
+                match bar_feed.as_ref() {
+                    Some(bars) => {
+                        feed.advance(&need_quotes);
+                        self.push_bars(&mut sender, bars, &need_quotes);
+                    }
+                    None => {
+                        for quote in feed.quotes(&need_quotes) {
+                            let dropped = sender.push(quote);
+                            self.quotes_dropped(dropped);
                         }
                     }
                 }
+                let report = sender.flush(&mut transport);
+                if self.account_flush(report, &mut sender, &mut connection_resets) {
+                    break;
+                }
             }
-
-            log::info!("Close stream");
-            Ok(())
-        });
-        QuotesStreamControl {
-            tx,
-            thread_handle: handle,
         }
+
+        log::info!("Close stream");
+        res
     }
 }
 
-enum HandlerState {
-    WaitPackLen,
-    WaitPack(u32),
+struct CommandHandler<C> {
+    conn: C,
+    client_addr: SocketAddr,
+    listen_ip: IpAddr,
 }
 
-struct CommandHandler {
-    conn: TcpStream,
-    client_addr: SocketAddr,
+struct ClientListener {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+struct ListenerEndpoint {
+    addr: SocketAddr,
+    tls: Option<Arc<rustls::ServerConfig>>,
 }
 
 struct HanlerControl {
     tx: mpsc::Sender<ControlCmd>,
     thread_handle: thread::JoinHandle<Result<()>>,
+    client_addr: SocketAddr,
 }
 
-impl CommandHandler {
-    fn new(connection: TcpStream, client_addr: SocketAddr) -> Result<Self> {
-        connection.set_nonblocking(true)?;
-        Ok(Self {
-            conn: connection,
-            client_addr,
-        })
-    }
-
-    fn start(mut self, quote_generator: Arc<Mutex<QuoteGenerator>>) -> HanlerControl {
+fn list_clients(handlers: &[HanlerControl]) -> Vec<ClientInfo> {
+    let mut replies = Vec::new();
+    for handler in handlers.iter() {
         let (tx, rx) = mpsc::channel();
+        if handler.tx.send(ControlCmd::ClientInfo(tx)).is_ok() {
+            replies.push(rx);
+        }
+    }
 
-        log::info!("Start new handler for quote requests");
-        let handle = thread::spawn(move || {
-            let qoutes_stream_control =
-                QuotesStream::new(quote_generator, self.client_addr.ip()).start();
-            let mut state = HandlerState::WaitPackLen;
-            let mut timer = Timer::default();
-            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
-            timer.add_event(CHECK_TCP_CMD_EVENT, CHECK_TCP_CMD_MILLIS);
-
-            let mut stream_reader = StreamReader::default();
-
-            loop {
-                timer.sleep();
+    replies
+        .into_iter()
+        .filter_map(|rx| {
+            rx.recv_timeout(Duration::from_millis(CLIENT_INFO_TIMEOUT_MILLIS))
+                .ok()
+        })
+        .collect()
+}
 
-                if timer.is_expired_event(WAIT_CMD_EVENT)? {
-                    timer.reset_event(WAIT_CMD_EVENT)?;
-                    match cmd_from_channel(&rx) {
-                        ControlCmd::Stop => {
-                            log::debug!("Stop command received from Client handler");
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
+fn kick_client(handlers: &[HanlerControl], addr: SocketAddr) -> bool {
+    match handlers.iter().find(|handler| handler.client_addr == addr) {
+        Some(handler) => {
+            log::info!("Kick client {addr}");
+            let _ = handler.tx.send(ControlCmd::Stop);
+            true
+        }
+        None => false,
+    }
+}
 
-                if timer.is_expired_event(CHECK_TCP_CMD_EVENT)? {
-                    timer.reset_event(CHECK_TCP_CMD_EVENT)?;
-                    match state {
-                        HandlerState::WaitPackLen => {
-                            if let Err(e) = stream_reader.read_from_stream(&mut self.conn) {
-                                log::info!("Connection error: {e}");
-                                break;
-                            }
-                            let bin_len = if let Some(val) = stream_reader.extract_chunk(4) {
-                                val
-                            } else {
-                                continue;
-                            };
+fn client_transcript(handlers: &[HanlerControl], addr: SocketAddr) -> Option<SessionTranscript> {
+    let handler = handlers
+        .iter()
+        .find(|handler| handler.client_addr == addr)?;
+    let (tx, rx) = mpsc::channel();
+    handler.tx.send(ControlCmd::Transcript(tx)).ok()?;
+    rx.recv_timeout(Duration::from_millis(CLIENT_INFO_TIMEOUT_MILLIS))
+        .ok()
+}
 
-                            let len: [u8; 4] =
-                                bin_len.try_into().map_err(|_| anyhow!("Parse error"))?;
+fn handle_server_cmd(cmd: ControlCmd, handlers: &[HanlerControl]) {
+    match cmd {
+        ControlCmd::ClientTranscript(addr, reply) => {
+            let _ = reply.send(client_transcript(handlers, addr));
+        }
+        ControlCmd::ListClients(reply) => {
+            let _ = reply.send(list_clients(handlers));
+        }
+        ControlCmd::KickClient(addr, reply) => {
+            let _ = reply.send(kick_client(handlers, addr));
+        }
+        _ => {}
+    }
+}
 
-                            log::debug!(
-                                "Packet len is received: {}",
-                                u32::from_be_bytes(len.into())
-                            );
-                            state = HandlerState::WaitPack(u32::from_be_bytes(len));
-                        }
-                        HandlerState::WaitPack(len) => {
-                            if let Err(e) = stream_reader.read_from_stream(&mut self.conn) {
-                                log::info!("Connection error: {e}");
-                                break;
-                            }
-                            let bin_message =
-                                if let Some(val) = stream_reader.extract_chunk(len as usize) {
-                                    val
-                                } else {
-                                    log::error!("Can't receive full packet");
-                                    break;
-                                };
-
-                            let msg = postcard::from_bytes::<Message>(&bin_message)?;
-                            log::debug!("Message: {:?}", msg);
-                            let tickers = match msg {
-                                Message::Tickers(tickers) => tickers,
-                                _ => break,
-                            };
-
-                            qoutes_stream_control.tx.send(ControlCmd::Quotes(tickers))?;
-                            state = HandlerState::WaitPackLen;
-                        }
-                    }
-                }
-            }
+fn reap_finished_handlers(handlers: &mut Vec<HanlerControl>) {
+    let mut i = 0;
+    while i < handlers.len() {
+        if !handlers[i].thread_handle.is_finished() {
+            i += 1;
+            continue;
+        }
 
-            let _ = qoutes_stream_control.tx.send(ControlCmd::Stop);
-            let res = match qoutes_stream_control.thread_handle.join() {
-                Ok(val) => val,
-                Err(_) => {
-                    bail!("Can't join thread");
-                }
-            };
-            log::info!("Close connection {}", self.client_addr);
-            res
-        });
-        HanlerControl {
-            tx,
-            thread_handle: handle,
+        let handler = handlers.swap_remove(i);
+        match handler.thread_handle.join() {
+            Ok(Ok(())) => log::debug!("Handler thread is finished"),
+            Ok(Err(e)) => log::warn!("Handler thread is finished with error: {e}"),
+            Err(_) => log::error!("Can't join handler thread"),
         }
     }
 }
 
-/// Интерфейс управления потоком сервера
-pub struct ServerControl {
-    /// Лтправка команды серверу
-    pub tx: mpsc::Sender<ControlCmd>,
-    /// Дескриптор потока сервера
-    pub thread_handle: thread::JoinHandle<Result<()>>,
+fn is_generator_stopped(
+    origin: &QuoteOrigin,
+    replay: Option<&ReplayPlayerControl>,
+    shards: &[GeneratorShardControl],
+    source: Option<&SourcePollerControl>,
+) -> bool {
+    origin.is_stopped()
+        || replay.is_some_and(|control| control.thread_handle.is_finished())
+        || source.is_some_and(|control| control.thread_handle.is_finished())
+        || shards
+            .iter()
+            .any(|control| control.thread_handle.is_finished())
 }
 
-/// Объект-поток сервер
-pub struct QuotesServer {
-    quotes_generator: Arc<Mutex<QuoteGenerator>>,
+fn reject_connection<W: Write>(mut connection: W, code: ErrorCode) -> Result<()> {
+    let bin_msg = pack_message_with_len(&Message::Error { code })?;
+    connection.write_all(&bin_msg)?;
+    connection.flush()?;
+    Ok(())
 }
 
-impl QuotesServer {
-    /// Создание сервера с указанием пути к конфигурации генератора котировок
-    pub fn new(config_path: &str) -> Result<Self> {
-        let generator = Arc::new(Mutex::new(QuoteGenerator::new(config_path)?));
+struct QuoteCaches {
+    last_values: LastValueCache,
+    bars: Option<Arc<BarAggregator>>,
+    history: QuoteHistory,
+    recorder: Option<BackgroundRecorder>,
+}
+
+impl QuoteCaches {
+    fn new(config: &ServerConfig) -> ServerResult<Self> {
+        let recorder = match config.record_dir.as_ref() {
+            Some(dir) => Some(BackgroundRecorder::start(
+                QuoteRecorder::new(Path::new(dir), config.record_segment_quotes)?,
+                RECORD_QUEUE_CAPACITY,
+            )),
+            None => None,
+        };
         Ok(Self {
-            quotes_generator: generator,
+            last_values: LastValueCache::default(),
+            bars: bar_aggregator(config),
+            history: QuoteHistory::new(config.history_depth),
+            recorder,
         })
     }
 
-    /// Запуск потока сервера
-    pub fn start(self) -> Result<ServerControl> {
-        let listener = TcpListener::bind("127.0.0.1:80")?;
-        listener.set_nonblocking(true)?;
+    /// Обработчики котировок, которые заполняют кэши и запись котировок
+    fn callbacks(&self, config: &ServerConfig) -> Vec<Box<dyn QuoteCallback>> {
+        let mut callbacks: Vec<Box<dyn QuoteCallback>> = Vec::new();
+        if let Some(recorder) = self.recorder.as_ref() {
+            callbacks.push(Box::new(recorder.clone()));
+        }
+        callbacks.push(Box::new(self.last_values.clone()));
+        if let Some(aggregator) = self.bars.as_ref() {
+            callbacks.push(Box::new(aggregator.clone()));
+        }
+        if config.history_depth > 0 {
+            callbacks.push(Box::new(self.history.clone()));
+        }
+        callbacks
+    }
+}
 
-        log::info!("Quotes streaming server is started");
-        let (tx, rx) = mpsc::channel();
+fn bar_aggregator(config: &ServerConfig) -> Option<Arc<BarAggregator>> {
+    match config.bar_interval_millis {
+        0 => None,
+        interval_millis => Some(Arc::new(BarAggregator::new(interval_millis))),
+    }
+}
 
-        let handle = thread::spawn(move || {
-            let mut handlers = Vec::new();
-            let mut timer = Timer::default();
-            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
-            timer.add_event(ACCEPT_EVENT, ACCEPT_MILLIS);
+fn new_session_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
 
-            loop {
-                timer.sleep();
-                if timer.is_expired_event(WAIT_CMD_EVENT)? {
-                    timer.reset_event(WAIT_CMD_EVENT)?;
-                    match cmd_from_channel(&rx) {
-                        ControlCmd::Stop => {
-                            log::debug!("Stop command received in quote server");
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
+/// Подключение сверх лимита клиентов, которому сервер сообщает об отказе
+struct RejectedClient {
+    connection: TcpStream,
+    addr: SocketAddr,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    websocket: bool,
+}
 
-                if timer.is_expired_event(ACCEPT_EVENT)? {
-                    let (connection, addr) = match listener.accept() {
-                        Ok((conn, addr)) => {
-                            log::debug!("Accept new connection from address: {addr}");
-                            (conn, addr)
-                        }
-                        Err(e) => match e.kind() {
-                            std::io::ErrorKind::WouldBlock => {
-                                continue;
-                            }
-                            _ => {
-                                log::error!("Can't accept connection");
-                                break;
-                            }
-                        },
-                    };
+fn reject_too_many_clients(client: RejectedClient, options: &TcpSocketOptions) -> Result<()> {
+    let RejectedClient {
+        connection,
+        addr,
+        tls,
+        websocket,
+    } = client;
+    connection.set_read_timeout(Some(Duration::from_millis(REJECT_TIMEOUT_MILLIS)))?;
+    connection.set_write_timeout(Some(Duration::from_millis(REJECT_TIMEOUT_MILLIS)))?;
+    options.apply(&connection)?;
+    if !websocket {
+        return reject_connection(
+            ControlStream::accept(connection, tls.as_ref())?,
+            ErrorCode::TooManyClients,
+        );
+    }
+    let mut channel = WsChannel::new(WsConnection::accept(connection, tls.as_ref(), options)?);
+    close_with_error(&mut channel, addr, ErrorCode::TooManyClients);
+    channel.close();
+    Ok(())
+}
 
-                    let handler = match CommandHandler::new(connection, addr) {
-                        Ok(val) => val.start(self.quotes_generator.clone()),
-                        Err(e) => {
-                            log::error!("Can't handle connection: {e}");
-                            break;
-                        }
-                    };
+/// Поток отказов подключениям сверх лимита клиентов. Очередь ограничена:
+/// при переполнении подключение закрывается без сообщения об отказе
+#[derive(Clone)]
+struct ClientRejector {
+    tx: mpsc::SyncSender<RejectedClient>,
+}
 
-                    handlers.push(handler);
+impl ClientRejector {
+    fn start(options: TcpSocketOptions) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<RejectedClient>(REJECT_QUEUE_CAPACITY);
+        thread::spawn(move || {
+            for client in rx {
+                let addr = client.addr;
+                if let Err(e) = reject_too_many_clients(client, &options) {
+                    log::warn!("Can't send reject message to {addr}: {e}");
                 }
             }
+        });
+        Self { tx }
+    }
 
-            for handler in handlers {
-                handler.tx.send(ControlCmd::Stop)?;
-                match handler.thread_handle.join() {
-                    Ok(res) => {
-                        if res.is_err() {
-                            return res;
-                        }
-                    }
-                    Err(_) => {
-                        bail!("Can't join thread");
-                    }
-                }
+    fn reject(&self, client: RejectedClient) {
+        match self.tx.try_send(client) {
+            Ok(()) => {}
+            Err(TrySendError::Full(client)) => {
+                log::warn!(
+                    "Reject queue is full, close connection from {}",
+                    client.addr
+                );
             }
-            log::info!("Server is stopped");
-            Ok(())
-        });
-        Ok(ServerControl {
-            tx,
-            thread_handle: handle,
-        })
+            Err(TrySendError::Disconnected(_)) => log::debug!("Reject thread is stopped"),
+        }
+    }
+}
+
+fn accept_client(
+    listener: &ClientListener,
+    context: &ServerContext,
+    handlers: &mut Vec<HanlerControl>,
+) -> ServerResult<()> {
+    let (connection, addr) = match listener.listener.accept() {
+        Ok(val) => val,
+        Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+        Err(e) => return Err(ServerError::Accept(e)),
+    };
+    log::debug!(
+        "Accept new connection from address: {addr} at {}",
+        listener.local_addr
+    );
+
+    if !context.config.is_allowed(addr.ip()) {
+        log::warn!("Connection from {addr} is denied");
+        return Ok(());
+    }
+
+    reap_finished_handlers(handlers);
+    if handlers.len() >= context.config.max_clients {
+        log::warn!("Too many clients, reject connection from {addr}");
+        context.rejector.reject(RejectedClient {
+            connection,
+            addr,
+            tls: listener.tls.clone(),
+            websocket: false,
+        });
+        return Ok(());
+    }
+
+    let handler = TcpHandler::new(
+        connection,
+        addr,
+        listener.local_addr.ip(),
+        &context.config.tcp_socket,
+        listener.tls.as_ref(),
+    )
+    .map_err(|e| anyhow!("Can't handle connection: {e}"))?;
+    handlers.push(handler.start(context.clone()));
+    Ok(())
+}
+
+fn accept_ws_client(
+    listener: &ClientListener,
+    context: &ServerContext,
+    handlers: &mut Vec<HanlerControl>,
+) -> ServerResult<()> {
+    let (connection, addr) = match listener.listener.accept() {
+        Ok(val) => val,
+        Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+        Err(e) => return Err(ServerError::Accept(e)),
+    };
+    log::debug!("Accept new WebSocket connection from address: {addr}");
+
+    if !context.config.is_allowed(addr.ip()) {
+        log::warn!("Connection from {addr} is denied");
+        return Ok(());
+    }
+
+    reap_finished_handlers(handlers);
+    if handlers.len() >= context.config.max_clients {
+        log::warn!("Too many clients, reject WebSocket connection from {addr}");
+        context.rejector.reject(RejectedClient {
+            connection,
+            addr,
+            tls: listener.tls.clone(),
+            websocket: true,
+        });
+        return Ok(());
+    }
+
+    let handler = WebSocketHandler::new(
+        connection,
+        addr,
+        listener.local_addr.ip(),
+        &context.config.tcp_socket,
+        listener.tls.as_ref(),
+    )
+    .map_err(|e| anyhow!("Can't handle WebSocket connection: {e}"))?;
+    handlers.push(handler.start(context.clone()));
+    Ok(())
+}
+
+fn parse_addr(addr: &str) -> ServerResult<SocketAddr> {
+    addr.parse()
+        .map_err(|e| ServerError::Config(format!("bad address {addr}: {e}")))
+}
+
+fn tls_config(
+    cert: Option<&String>,
+    key: Option<&String>,
+) -> ServerResult<Option<Arc<rustls::ServerConfig>>> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some(
+            tls::server_config(cert, key).map_err(ServerError::Tls)?,
+        )),
+        (None, None) => Ok(None),
+        _ => Err(ServerError::Config(
+            "both TLS certificate and key must be set".to_string(),
+        )),
+    }
+}
+
+fn spawn_handler<F>(client_addr: SocketAddr, context: ServerContext, run: F) -> HanlerControl
+where
+    F: FnOnce(ServerContext, Receiver<ControlCmd>, Arc<ClientSession>) -> Result<()>
+        + Send
+        + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let session = Arc::new(ClientSession::new(
+            client_addr,
+            context.events.clone(),
+            context.audit.clone(),
+        ));
+        let res = panic::catch_unwind(AssertUnwindSafe(|| run(context, rx, session.clone())));
+        session.close_on_panic(HANDLER_SUBSYSTEM, res)
+    });
+    HanlerControl {
+        tx,
+        thread_handle: handle,
+        client_addr,
+    }
+}
+
+/// Принятое соединение родного протокола. Рукопожатие TLS выполняется
+/// в потоке клиента, чтобы медленный клиент не задерживал прием подключений
+struct TcpHandler {
+    conn: TcpStream,
+    client_addr: SocketAddr,
+    listen_ip: IpAddr,
+    options: TcpSocketOptions,
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl TcpHandler {
+    fn new(
+        connection: TcpStream,
+        client_addr: SocketAddr,
+        listen_ip: IpAddr,
+        options: &TcpSocketOptions,
+        tls: Option<&Arc<rustls::ServerConfig>>,
+    ) -> Result<Self> {
+        options.apply(&connection)?;
+        Ok(Self {
+            conn: connection,
+            client_addr,
+            listen_ip,
+            options: *options,
+            tls: tls.cloned(),
+        })
+    }
+
+    fn accept(
+        conn: TcpStream,
+        tls: Option<&Arc<rustls::ServerConfig>>,
+        options: &TcpSocketOptions,
+    ) -> Result<ControlStream> {
+        conn.set_read_timeout(Some(Duration::from_millis(TLS_HANDSHAKE_TIMEOUT_MILLIS)))?;
+        conn.set_write_timeout(Some(Duration::from_millis(TLS_HANDSHAKE_TIMEOUT_MILLIS)))?;
+        let mut stream = ControlStream::accept(conn, tls)?;
+        stream.complete_handshake()?;
+        let tcp = stream.tcp();
+        options.restore_timeouts(tcp)?;
+        tcp.set_nonblocking(true)?;
+        Ok(stream)
+    }
+
+    fn start(self, context: ServerContext) -> HanlerControl {
+        log::info!("Start new handler for quote requests");
+        spawn_handler(self.client_addr, context, move |context, rx, session| {
+            let conn = match Self::accept(self.conn, self.tls.as_ref(), &self.options) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log::warn!("TLS handshake with {} failed: {e}", self.client_addr);
+                    return Ok(());
+                }
+            };
+            let handler = CommandHandler {
+                conn: TcpChannel::new(conn),
+                client_addr: self.client_addr,
+                listen_ip: self.listen_ip,
+            };
+            handler.run(context, rx, session)
+        })
+    }
+}
+
+impl<C: ControlChannel> CommandHandler<C> {
+    /// Отправляет ответ клиенту. Ошибка отправки возвращается причиной закрытия соединения
+    fn reply(&mut self, msg: &Message) -> Result<(), String> {
+        self.conn.send(msg).map_err(|e| {
+            log::info!("Can't send message to {}: {e}", self.client_addr);
+            format!("send error: {e}")
+        })
+    }
+
+    fn run(
+        mut self,
+        context: ServerContext,
+        rx: Receiver<ControlCmd>,
+        session: Arc<ClientSession>,
+    ) -> Result<()> {
+        let _client_guard = context.metrics.client_connected();
+        session.record(SessionEvent::Connected);
+        session.audit(AuditEvent::Connected);
+        let mut subscription = Vec::new();
+        let mut session_id: Option<String> = None;
+        let mut last_request: Option<TickerReqMessage> = None;
+        let mut retain_subscription = true;
+        let qoutes_stream_control = self.conn.start_stream(QuotesStream::new(
+            context.clone(),
+            self.client_addr.ip(),
+            self.listen_ip,
+            session.clone(),
+        ));
+        let mut timer = Timer::with_stats(context.thread_stats.subsystem(HANDLER_SUBSYSTEM));
+        timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+        timer.add_event(CHECK_TCP_CMD_EVENT, CHECK_TCP_CMD_MILLIS);
+        let mut wait_subscribe = context.config.subscribe_timeout_millis > 0;
+        if wait_subscribe {
+            timer.add_event(
+                SUBSCRIBE_WAIT_EVENT,
+                context.config.subscribe_timeout_millis,
+            );
+        }
+
+        let mut shutdown = false;
+        let mut timeout = None;
+        let close_reason;
+
+        loop {
+            timer.sleep();
+
+            if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                timer.reset_event(WAIT_CMD_EVENT)?;
+                match cmd_from_channel(&rx) {
+                    ControlCmd::Stop => {
+                        log::debug!("Stop command received from Client handler");
+                        close_reason = "stopped by server".to_string();
+                        retain_subscription = false;
+                        break;
+                    }
+                    ControlCmd::Shutdown => {
+                        log::debug!("Shutdown command received from Client handler");
+                        if let Err(e) = self.conn.send(&Message::Shutdown) {
+                            log::warn!("Can't send shutdown message: {e}");
+                        }
+                        shutdown = true;
+                        retain_subscription = context.config.session_state_path.is_some();
+                        close_reason = "server shutdown".to_string();
+                        break;
+                    }
+                    ControlCmd::ClientInfo(reply) => {
+                        let _ = reply.send(session.info(self.client_addr, &subscription));
+                    }
+                    ControlCmd::Transcript(reply) => {
+                        let _ = reply.send(session.transcript(self.client_addr, &subscription));
+                    }
+                    _ => {}
+                }
+
+                if qoutes_stream_control
+                    .as_ref()
+                    .is_some_and(|control| control.thread_handle.is_finished())
+                {
+                    log::info!("Quotes stream for {} is finished", self.client_addr);
+                    close_reason = "quotes stream is finished".to_string();
+                    break;
+                }
+            }
+
+            if wait_subscribe && timer.is_expired_event(SUBSCRIBE_WAIT_EVENT)? {
+                log::warn!(
+                    "Client {} hasn't subscribed in {} ms",
+                    self.client_addr,
+                    context.config.subscribe_timeout_millis
+                );
+                close_reason = "subscribe timeout".to_string();
+                timeout = Some(ServerError::ClientTimeout {
+                    addr: self.client_addr.ip(),
+                    millis: context.config.subscribe_timeout_millis,
+                });
+                break;
+            }
+
+            if timer.is_expired_event(CHECK_TCP_CMD_EVENT)? {
+                timer.reset_event(CHECK_TCP_CMD_EVENT)?;
+                let msg = match self.conn.recv() {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => continue,
+                    Err(RecvError::Connection(e)) => {
+                        log::info!("Connection error: {e}");
+                        close_reason = format!("connection error: {e}");
+                        break;
+                    }
+                    Err(RecvError::Malformed {
+                        code,
+                        reason,
+                        description,
+                    }) => {
+                        if code == ErrorCode::DecodeFailure {
+                            context.metrics.decode_failure();
+                            session.record(SessionEvent::Error {
+                                description: format!("Can't decode message: {description}"),
+                            });
+                        }
+                        session.audit(AuditEvent::Malformed { description });
+                        close_with_error(&mut self.conn, self.client_addr, code);
+                        close_reason = reason.to_string();
+                        break;
+                    }
+                };
+                log::debug!("Message: {:?}", msg);
+                session.record(SessionEvent::Message {
+                    message: format!("{msg:?}"),
+                });
+                session.audit(AuditEvent::Message {
+                    message: format!("{msg:?}"),
+                });
+                let mut tickers = match msg {
+                    Message::Tickers(tickers) => tickers,
+                    Message::Unsubscribe => {
+                        log::info!("Client {} unsubscribed", self.client_addr);
+                        retain_subscription = false;
+                        close_reason = "client unsubscribed".to_string();
+                        break;
+                    }
+                    Message::Pause | Message::Resume => {
+                        if session_id.is_none() {
+                            close_with_error(
+                                &mut self.conn,
+                                self.client_addr,
+                                ErrorCode::UnexpectedMessage,
+                            );
+                            close_reason = "pause before subscription".to_string();
+                            break;
+                        }
+                        let Some(control) = qoutes_stream_control.as_ref() else {
+                            close_with_error(
+                                &mut self.conn,
+                                self.client_addr,
+                                ErrorCode::UnexpectedMessage,
+                            );
+                            close_reason = "pause without quotes stream".to_string();
+                            break;
+                        };
+                        let paused = matches!(msg, Message::Pause);
+                        log::info!("Client {} paused: {paused}", self.client_addr);
+                        if let Err(e) = control.tx.send(ControlCmd::Pause(paused)) {
+                            log::warn!("Can't pause quotes stream of {}: {e}", self.client_addr);
+                        }
+                        continue;
+                    }
+                    Message::ChangeSubscription { mode, tickers } => {
+                        let Some(mut req) = last_request.clone() else {
+                            close_with_error(
+                                &mut self.conn,
+                                self.client_addr,
+                                ErrorCode::UnexpectedMessage,
+                            );
+                            close_reason = "change before subscription".to_string();
+                            break;
+                        };
+                        let (known_tickers, unknown_tickers) =
+                            expand_tickers(&tickers, &context.tickers);
+                        if !unknown_tickers.is_empty() {
+                            log::warn!(
+                                "Client {} requested unknown tickers: {:?}",
+                                self.client_addr,
+                                unknown_tickers
+                            );
+                        }
+                        if let Err(reason) = self.reply(&Message::SubscriptionChanged {
+                            mode,
+                            unknown_tickers,
+                            tickers: known_tickers.clone(),
+                        }) {
+                            close_reason = reason;
+                            break;
+                        }
+                        mode.apply(&mut subscription, &known_tickers);
+                        session.record(SessionEvent::Subscription {
+                            tickers: subscription.clone(),
+                        });
+                        req.tickers = known_tickers;
+                        let stream_finished =
+                            qoutes_stream_control.as_ref().is_some_and(|control| {
+                                control.tx.send(ControlCmd::Quotes(req, mode)).is_err()
+                            });
+                        if stream_finished {
+                            close_reason = "quotes stream is finished".to_string();
+                            break;
+                        }
+                        continue;
+                    }
+                    Message::HistoryReq { ticker, last_n } => {
+                        if session_id.is_none() {
+                            close_with_error(
+                                &mut self.conn,
+                                self.client_addr,
+                                ErrorCode::UnexpectedMessage,
+                            );
+                            close_reason = "history before subscription".to_string();
+                            break;
+                        }
+                        let quotes = context.history.last(&ticker, last_n as usize);
+                        log::debug!(
+                            "Send {} history quotes of {ticker} to {}",
+                            quotes.len(),
+                            self.client_addr
+                        );
+                        if let Err(reason) = self.reply(&Message::History { ticker, quotes }) {
+                            close_reason = reason;
+                            break;
+                        }
+                        continue;
+                    }
+                    _ => {
+                        close_with_error(
+                            &mut self.conn,
+                            self.client_addr,
+                            ErrorCode::UnexpectedMessage,
+                        );
+                        close_reason = "unexpected message".to_string();
+                        break;
+                    }
+                };
+
+                let token = tickers.token.as_deref().unwrap_or_default();
+                let accepted = context.authenticator.authenticate(token, self.client_addr);
+                session.audit(AuditEvent::Auth { accepted });
+                if !accepted {
+                    log::warn!("Client {} is not authenticated", self.client_addr);
+                    session.record(SessionEvent::Error {
+                        description: "Authentication failed".to_string(),
+                    });
+                    close_with_error(&mut self.conn, self.client_addr, ErrorCode::Unauthorized);
+                    close_reason = "authentication failed".to_string();
+                    break;
+                }
+
+                if session_id.is_none()
+                    && tickers.session.is_none()
+                    && context.overload.is_degraded()
+                {
+                    session.record(SessionEvent::Error {
+                        description: "Server is overloaded".to_string(),
+                    });
+                    close_with_error(&mut self.conn, self.client_addr, ErrorCode::Overloaded);
+                    close_reason = "server is overloaded".to_string();
+                    break;
+                }
+
+                let resumed_session = tickers.session.take().and_then(|id| {
+                    let retained_tickers = context.retained.take(&id)?;
+                    Some((id, retained_tickers))
+                });
+                let (id, resumed) = match resumed_session {
+                    Some((id, retained_tickers)) => {
+                        log::info!(
+                            "Client {} resumes subscription: {:?}",
+                            self.client_addr,
+                            retained_tickers
+                        );
+                        tickers.tickers = retained_tickers;
+                        (id, true)
+                    }
+                    None => (session_id.clone().unwrap_or_else(new_session_id), false),
+                };
+
+                let (known_tickers, unknown_tickers) =
+                    expand_tickers(&tickers.tickers, &context.tickers);
+                if !unknown_tickers.is_empty() {
+                    log::warn!(
+                        "Client {} requested unknown tickers: {:?}",
+                        self.client_addr,
+                        unknown_tickers
+                    );
+                }
+                tickers.tickers = known_tickers;
+                let ack = Message::Subscribed {
+                    unknown_tickers,
+                    tickers: tickers.tickers.clone(),
+                    multicast_group: context.multicast_group,
+                    session: id.clone(),
+                    resumed,
+                };
+                session_id = Some(id);
+                if let Err(reason) = self.reply(&ack) {
+                    close_reason = reason;
+                    break;
+                }
+
+                if wait_subscribe {
+                    timer.remove_event(SUBSCRIBE_WAIT_EVENT)?;
+                    wait_subscribe = false;
+                }
+                subscription = tickers.tickers.clone();
+                session.record(SessionEvent::Subscription {
+                    tickers: subscription.clone(),
+                });
+                last_request = Some(tickers.clone());
+                let stream_finished = qoutes_stream_control.as_ref().is_some_and(|control| {
+                    control
+                        .tx
+                        .send(ControlCmd::Quotes(tickers, SubscriptionMode::Replace))
+                        .is_err()
+                });
+                if stream_finished {
+                    close_reason = "quotes stream is finished".to_string();
+                    break;
+                }
+            }
+        }
+
+        self.conn.close();
+        session.record(SessionEvent::Closed {
+            reason: close_reason.clone(),
+        });
+        session.audit(AuditEvent::Closed {
+            reason: close_reason.clone(),
+        });
+        if let Some(id) = session_id.filter(|_| retain_subscription) {
+            context.retained.retain(id, subscription.clone());
+        }
+        let res = match qoutes_stream_control {
+            Some(control) => {
+                let stream_cmd = if shutdown {
+                    ControlCmd::Shutdown
+                } else {
+                    ControlCmd::Stop
+                };
+                let _ = control.tx.send(stream_cmd);
+                match control.thread_handle.join() {
+                    Ok(val) => val,
+                    Err(_) => {
+                        bail!("Can't join thread");
+                    }
+                }
+            }
+            None => Ok(()),
+        };
+        let summary = session.info(self.client_addr, &subscription);
+        match serde_json::to_string(&summary) {
+            Ok(val) => log::info!("Client {} summary: {val}", self.client_addr),
+            Err(e) => log::warn!("Can't serialize client summary: {e}"),
+        }
+        session.emit(ServerEvent::ClientDisconnected {
+            addr: self.client_addr,
+            reason: close_reason,
+            summary,
+        });
+        log::info!("Close connection {}", self.client_addr);
+        match timeout {
+            Some(e) => Err(e.into()),
+            None => res,
+        }
+    }
+}
+
+struct WebSocketHandler {
+    conn: TcpStream,
+    client_addr: SocketAddr,
+    listen_ip: IpAddr,
+    options: TcpSocketOptions,
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl WebSocketHandler {
+    fn new(
+        connection: TcpStream,
+        client_addr: SocketAddr,
+        listen_ip: IpAddr,
+        options: &TcpSocketOptions,
+        tls: Option<&Arc<rustls::ServerConfig>>,
+    ) -> Result<Self> {
+        options.apply(&connection)?;
+        Ok(Self {
+            conn: connection,
+            client_addr,
+            listen_ip,
+            options: *options,
+            tls: tls.cloned(),
+        })
+    }
+
+    fn start(self, context: ServerContext) -> HanlerControl {
+        log::info!("Start new handler for WebSocket client");
+        spawn_handler(self.client_addr, context, move |context, rx, session| {
+            let conn = WsConnection::accept(self.conn, self.tls.as_ref(), &self.options)?;
+            let handler = CommandHandler {
+                conn: WsChannel::new(conn),
+                client_addr: self.client_addr,
+                listen_ip: self.listen_ip,
+            };
+            handler.run(context, rx, session)
+        })
+    }
+}
+
+/// Интерфейс управления потоком сервера
+pub struct ServerControl {
+    /// Лтправка команды серверу
+    pub tx: mpsc::Sender<ControlCmd>,
+    /// Дескриптор потока сервера
+    pub thread_handle: thread::JoinHandle<ServerResult<()>>,
+    /// Статистика циклов опроса фоновых потоков сервера
+    pub stats: Arc<ThreadStats>,
+    /// Метрики сервера
+    pub metrics: Arc<ServerMetrics>,
+    /// Адрес, на котором сервер принимает подключения клиентов
+    pub local_addr: SocketAddr,
+    /// Адреса всех точек подключения клиентов, первым идет `local_addr`
+    pub listen_addrs: Vec<SocketAddr>,
+    /// Адрес точки подключения клиентов WebSocket, если она запущена
+    pub websocket_addr: Option<SocketAddr>,
+    /// Адрес HTTP точки метрик, если она запущена
+    pub metrics_addr: Option<SocketAddr>,
+    /// Адрес административного интерфейса, если он запущен
+    pub admin_addr: Option<SocketAddr>,
+    /// События подключения, отключения и подписки клиентов.
+    /// Если события не читать, новые события выбрасываются при переполнении очереди
+    pub events: mpsc::Receiver<ServerEvent>,
+}
+
+impl ServerControl {
+    /// Останавливает сервер по сигналу SIGINT/SIGTERM: клиенты уведомляются об остановке,
+    /// все потоки сервера завершаются
+    pub fn stop_on_signal(&self) -> ServerResult<()> {
+        let tx = self.tx.clone();
+        ctrlc::set_handler(move || {
+            log::info!("Stop signal is received");
+            let _ = tx.send(ControlCmd::Stop);
+        })
+        .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+}
+
+/// Объект-поток сервер
+pub struct QuotesServer {
+    context: ServerContext,
+    shards: Vec<QuoteGenerator>,
+    source_poller: Option<SourcePoller>,
+    replay_player: Option<ReplayPlayer>,
+    events: mpsc::Receiver<ServerEvent>,
+    endpoints: Vec<ListenerEndpoint>,
+    websocket: Option<ListenerEndpoint>,
+    listener: Option<TcpListener>,
+}
+
+impl QuotesServer {
+    /// Создание сервера с указанием путей к конфигурациям генератора котировок.
+    /// Тикеры всех конфигураций объединяются
+    pub fn new(config_paths: &[&str]) -> ServerResult<Self> {
+        Self::with_config(config_paths, ServerConfig::default())
+    }
+
+    /// Создание сервера с указанием путей к конфигурациям генератора котировок и настроек сервера
+    pub fn with_config(config_paths: &[&str], config: ServerConfig) -> ServerResult<Self> {
+        let mut generator = QuoteGenerator::from_files(config_paths, config.generator_seed)?
+            .with_timestamps(config.timestamps)?;
+        let caches = QuoteCaches::new(&config)?;
+        let tickers = generator.ticker_names();
+        let mut shards = Vec::new();
+        let mut replay_player = None;
+        let origin = if let Some(dir) = config.replay_dir.as_ref() {
+            if config.generator_shards > 0 {
+                return Err(ServerError::Config(
+                    "replay and generator shards can't be used together".to_string(),
+                ));
+            }
+            let hub = Arc::new(QuoteHub::default());
+            let mut callbacks = caches.callbacks(&config);
+            callbacks.push(Box::new(hub.clone()));
+            replay_player = Some(ReplayPlayer::new(
+                Path::new(dir),
+                config.replay_speed,
+                callbacks,
+            )?);
+            QuoteOrigin::Hub(hub)
+        } else if config.generator_shards > 0 {
+            let hub = Arc::new(QuoteHub::default());
+            shards = generator.into_shards(config.generator_shards, || {
+                let mut callbacks = caches.callbacks(&config);
+                callbacks.push(Box::new(hub.clone()));
+                callbacks
+            });
+            QuoteOrigin::Hub(hub)
+        } else {
+            for callback in caches.callbacks(&config) {
+                generator.add_callback(callback);
+            }
+            QuoteOrigin::Generator(Arc::new(Mutex::new(generator)))
+        };
+        let mut server = Self::from_parts(config, origin, tickers, caches, shards, None)?;
+        server.replay_player = replay_player;
+        Ok(server)
+    }
+
+    /// Создание сервера, который берет котировки из внешнего источника.
+    /// Настройки воспроизведения записи и потоков генерации не поддерживаются
+    pub fn with_source<S: QuoteSource + 'static>(
+        source: S,
+        config: ServerConfig,
+    ) -> ServerResult<Self> {
+        if config.replay_dir.is_some() || config.generator_shards > 0 {
+            return Err(ServerError::Config(
+                "quote source can't be used with replay or generator shards".to_string(),
+            ));
+        }
+        let caches = QuoteCaches::new(&config)?;
+        let mut callbacks = caches.callbacks(&config);
+        let hub = Arc::new(QuoteHub::default());
+        callbacks.push(Box::new(hub.clone()));
+        let tickers = source.tickers();
+        let poller = SourcePoller::new(Box::new(source), callbacks);
+        Self::from_parts(
+            config,
+            QuoteOrigin::Hub(hub),
+            tickers,
+            caches,
+            Vec::new(),
+            Some(poller),
+        )
+    }
+
+    fn from_parts(
+        config: ServerConfig,
+        origin: QuoteOrigin,
+        mut tickers: Vec<String>,
+        caches: QuoteCaches,
+        shards: Vec<QuoteGenerator>,
+        source_poller: Option<SourcePoller>,
+    ) -> ServerResult<Self> {
+        tickers.sort();
+        if config.replay_on_subscribe > 0 && config.history_depth == 0 {
+            return Err(ServerError::Config(
+                "replay on subscribe requires quote history".to_string(),
+            ));
+        }
+        let audit = match config.audit_log.as_ref() {
+            Some(path) => Some(Arc::new(AuditLog::open(Path::new(path))?)),
+            None => None,
+        };
+        let mut endpoints = vec![ListenerEndpoint {
+            addr: parse_addr(&config.listen_addr)?,
+            tls: tls_config(config.tls_cert.as_ref(), config.tls_key.as_ref())?,
+        }];
+        for listener in config.listeners.iter() {
+            endpoints.push(ListenerEndpoint {
+                addr: parse_addr(&listener.addr)?,
+                tls: tls_config(listener.tls_cert.as_ref(), listener.tls_key.as_ref())?,
+            });
+        }
+        let websocket = match config.websocket.as_ref() {
+            Some(listener) => Some(ListenerEndpoint {
+                addr: parse_addr(&listener.addr)?,
+                tls: tls_config(listener.tls_cert.as_ref(), listener.tls_key.as_ref())?,
+            }),
+            None => None,
+        };
+        let retained = Arc::new(RetainedSubscriptions::new(Duration::from_millis(
+            config.session_grace_millis,
+        )));
+        if let Some(path) = config.session_state_path.as_ref() {
+            if config.session_grace_millis == 0 {
+                return Err(ServerError::Config(
+                    "session state file requires session grace period".to_string(),
+                ));
+            }
+            let restored =
+                retained
+                    .restore(Path::new(path))
+                    .map_err(|source| ServerError::Decode {
+                        what: format!("session state {path}"),
+                        source,
+                    })?;
+            log::info!("Restore {restored} client subscriptions from {path}");
+        }
+        let bandwidth = match config.bandwidth_bytes_per_sec {
+            0 => None,
+            bytes_per_sec => Some(Arc::new(BandwidthBudget::new(bytes_per_sec))),
+        };
+        let (events_tx, events_rx) = mpsc::sync_channel(SERVER_EVENTS_CAPACITY);
+        let multicast_group = match config.multicast_addr.as_ref() {
+            Some(addr) => Some(parse_addr(addr)?),
+            None => None,
+        };
+        let rejector = ClientRejector::start(config.tcp_socket);
+        Ok(Self {
+            context: ServerContext {
+                config: Arc::new(config),
+                origin,
+                tickers: Arc::new(tickers),
+                thread_stats: Arc::new(ThreadStats::default()),
+                metrics: Arc::new(ServerMetrics::default()),
+                authenticator: Arc::new(AllowAll),
+                multicast_group,
+                retained,
+                events: events_tx,
+                last_values: caches.last_values,
+                audit,
+                bandwidth,
+                overload: Arc::new(OverloadState::default()),
+                bars: caches.bars,
+                history: caches.history,
+                rejector,
+            },
+            shards,
+            source_poller,
+            replay_player: None,
+            events: events_rx,
+            endpoints,
+            websocket,
+            listener: None,
+        })
+    }
+
+    /// Проверка доступа клиентов к котировкам. По умолчанию доступ разрешен всем
+    pub fn with_authenticator<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.context.authenticator = Arc::new(authenticator);
+        self
+    }
+
+    /// Принимать клиентов через уже открытый сокет, например переданный systemd,
+    /// вместо открытия сокета по `listen_addr`. Дополнительные точки подключения
+    /// открываются как обычно
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Запуск потока сервера
+    pub fn start(mut self) -> ServerResult<ServerControl> {
+        let mut listeners = Vec::new();
+        for (idx, endpoint) in self.endpoints.into_iter().enumerate() {
+            let supplied = if idx == 0 { self.listener.take() } else { None };
+            let listener = match supplied {
+                Some(val) => val,
+                None => bind_tcp_listener(endpoint.addr).map_err(|source| ServerError::Bind {
+                    addr: endpoint.addr,
+                    source,
+                })?,
+            };
+            listener.set_nonblocking(true)?;
+            let local_addr = listener.local_addr()?;
+            if endpoint.tls.is_some() {
+                log::info!("Listen for clients at {local_addr} over TLS");
+            } else {
+                log::info!("Listen for clients at {local_addr}");
+            }
+            listeners.push(ClientListener {
+                listener,
+                local_addr,
+                tls: endpoint.tls,
+            });
+        }
+        let ws_listener = match self.websocket {
+            Some(endpoint) => {
+                let listener =
+                    bind_tcp_listener(endpoint.addr).map_err(|source| ServerError::Bind {
+                        addr: endpoint.addr,
+                        source,
+                    })?;
+                listener.set_nonblocking(true)?;
+                let local_addr = listener.local_addr()?;
+                log::info!("Listen for WebSocket clients at {local_addr}");
+                Some(ClientListener {
+                    listener,
+                    local_addr,
+                    tls: endpoint.tls,
+                })
+            }
+            None => None,
+        };
+        let websocket_addr = ws_listener.as_ref().map(|listener| listener.local_addr);
+        let listen_addrs: Vec<SocketAddr> = listeners
+            .iter()
+            .map(|listener| listener.local_addr)
+            .collect();
+        let mdns_announcer = match self.context.config.mdns_name.as_ref() {
+            Some(name) => Some(MdnsAnnouncer::register(
+                name,
+                listen_addrs[0],
+                listeners[0].tls.is_some(),
+                websocket_addr,
+            )?),
+            None => None,
+        };
+
+        let shard_subsystems: Vec<String> = (0..self.shards.len())
+            .map(|idx| format!("{GENERATOR_SUBSYSTEM}-{idx}"))
+            .collect();
+        let mut health_subsystems = vec![SERVER_SUBSYSTEM.to_string()];
+        health_subsystems.extend(shard_subsystems.iter().cloned());
+        if self.replay_player.is_some() {
+            health_subsystems.push(REPLAY_SUBSYSTEM.to_string());
+        }
+        if self.source_poller.is_some() {
+            health_subsystems.push(SOURCE_SUBSYSTEM.to_string());
+        }
+
+        let metrics_exporter = match self.context.config.metrics_addr.as_ref() {
+            Some(addr) => Some(MetricsExporter::new(
+                addr,
+                self.context.metrics.clone(),
+                self.context.thread_stats.clone(),
+                health_subsystems,
+                Duration::from_millis(self.context.config.health_stall_millis),
+            )?),
+            None => None,
+        };
+
+        let (admin_tx, admin_rx) = mpsc::channel();
+        let admin_server = match self.context.config.admin_addr.as_ref() {
+            Some(addr) => Some(AdminServer::new(
+                addr,
+                self.context.config.admin_token.clone(),
+                admin_tx,
+            )?),
+            None => None,
+        };
+
+        let metrics_addr = match metrics_exporter.as_ref() {
+            Some(exporter) => Some(exporter.local_addr()?),
+            None => None,
+        };
+        let admin_addr = match admin_server.as_ref() {
+            Some(admin) => Some(admin.local_addr()?),
+            None => None,
+        };
+
+        let multicast_publisher = match self.context.multicast_group {
+            Some(group) => Some(MulticastPublisher::new(
+                group,
+                &self.context.origin,
+                self.context.tickers.clone(),
+                &self.context.config.udp_socket,
+                self.context.metrics.clone(),
+                self.context.thread_stats.subsystem(MULTICAST_SUBSYSTEM),
+            )?),
+            None => None,
+        };
+
+        let replay_player = self.replay_player;
+        let replay_stats = self.context.thread_stats.subsystem(REPLAY_SUBSYSTEM);
+
+        if !self.shards.is_empty() {
+            log::info!("Generate quotes in {} shards", self.shards.len());
+        }
+        let shards: Vec<GeneratorShard> = self
+            .shards
+            .into_iter()
+            .zip(shard_subsystems.iter())
+            .map(|(generator, subsystem)| {
+                GeneratorShard::new(generator, self.context.thread_stats.subsystem(subsystem))
+            })
+            .collect();
+
+        let source_poller = self.source_poller;
+        let source_stats = self.context.thread_stats.subsystem(SOURCE_SUBSYSTEM);
+
+        let mut overload_detector = self.context.config.overload.map(|config| {
+            let mut subsystems = vec![SERVER_SUBSYSTEM, STREAM_SUBSYSTEM, SOURCE_SUBSYSTEM];
+            subsystems.extend(shard_subsystems.iter().map(String::as_str));
+            OverloadDetector::new(
+                config,
+                subsystems
+                    .into_iter()
+                    .map(|subsystem| self.context.thread_stats.subsystem(subsystem))
+                    .collect(),
+                self.context.overload.clone(),
+                self.context.metrics.clone(),
+            )
+        });
+
+        log::info!("Quotes streaming server is started");
+        let (tx, rx) = mpsc::channel();
+        let stats = self.context.thread_stats.clone();
+        let metrics = self.context.metrics.clone();
+        let events = self.events;
+
+        let handle = thread::spawn(move || {
+            let metrics_control = metrics_exporter.map(|exporter| exporter.start());
+            let admin_control = admin_server.map(|admin| admin.start());
+            let multicast_control = multicast_publisher.map(|publisher| publisher.start());
+            let replay_control = replay_player.map(|player| player.start(replay_stats));
+            let shard_controls: Vec<_> = shards.into_iter().map(|shard| shard.start()).collect();
+            let source_control = source_poller.map(|poller| poller.start(source_stats));
+            let mut handlers = Vec::new();
+            let mut timer =
+                Timer::with_stats(self.context.thread_stats.subsystem(SERVER_SUBSYSTEM));
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(ACCEPT_EVENT, ACCEPT_MILLIS);
+            timer.add_event(REAP_HANDLERS_EVENT, REAP_HANDLERS_MILLIS);
+            if let Some(detector) = overload_detector.as_ref() {
+                timer.add_event(OVERLOAD_EVENT, detector.check_millis());
+            }
+            let mut stop_error = None;
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    match cmd_from_channel(&rx) {
+                        ControlCmd::Stop => {
+                            log::debug!("Stop command received in quote server");
+                            break;
+                        }
+                        cmd => handle_server_cmd(cmd, &handlers),
+                    }
+                    if let Ok(cmd) = admin_rx.try_recv() {
+                        handle_server_cmd(cmd, &handlers);
+                    }
+                }
+
+                if timer.is_expired_event(REAP_HANDLERS_EVENT)? {
+                    timer.reset_event(REAP_HANDLERS_EVENT)?;
+                    reap_finished_handlers(&mut handlers);
+                    self.context.retained.purge_expired();
+                    if is_generator_stopped(
+                        &self.context.origin,
+                        replay_control.as_ref(),
+                        &shard_controls,
+                        source_control.as_ref(),
+                    ) {
+                        log::error!("Quote generator is stopped, shutdown server");
+                        stop_error = Some(ServerError::GeneratorDead);
+                        break;
+                    }
+                }
+
+                if overload_detector.is_some() && timer.is_expired_event(OVERLOAD_EVENT)? {
+                    timer.reset_event(OVERLOAD_EVENT)?;
+                    if let Some(detector) = overload_detector.as_mut() {
+                        detector.check();
+                    }
+                }
+
+                if timer.is_expired_event(ACCEPT_EVENT)? {
+                    let accepted = listeners.iter().try_for_each(|listener| {
+                        accept_client(listener, &self.context, &mut handlers)
+                    });
+                    let accepted = accepted.and_then(|()| match ws_listener.as_ref() {
+                        Some(listener) => accept_ws_client(listener, &self.context, &mut handlers),
+                        None => Ok(()),
+                    });
+                    if let Err(e) = accepted {
+                        log::error!("{e}");
+                        stop_error = Some(e);
+                        break;
+                    }
+                }
+            }
+
+            if let Some(announcer) = mdns_announcer {
+                announcer.stop();
+            }
+
+            if let Some(control) = admin_control {
+                let _ = control.tx.send(ControlCmd::Stop);
+                if control.thread_handle.join().is_err() {
+                    log::error!("Can't join admin interface thread");
+                }
+            }
+
+            if let Some(control) = replay_control {
+                let _ = control.tx.send(ControlCmd::Stop);
+                match control.thread_handle.join() {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log::error!("Replay player is failed: {e}"),
+                    Err(_) => log::error!("Can't join replay player thread"),
+                }
+            }
+
+            if let Some(control) = source_control {
+                let _ = control.tx.send(ControlCmd::Stop);
+                match control.thread_handle.join() {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log::error!("Quote source is failed: {e}"),
+                    Err(_) => log::error!("Can't join quote source thread"),
+                }
+            }
+
+            for control in shard_controls {
+                let _ = control.tx.send(ControlCmd::Stop);
+                match control.thread_handle.join() {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log::error!("Generator shard is failed: {e}"),
+                    Err(_) => log::error!("Can't join generator shard thread"),
+                }
+            }
+
+            if let Some(control) = metrics_control {
+                let _ = control.tx.send(ControlCmd::Stop);
+                if control.thread_handle.join().is_err() {
+                    log::error!("Can't join metrics exporter thread");
+                }
+            }
+
+            log::info!("Drain {} connections", handlers.len());
+            for handler in handlers.iter() {
+                let _ = handler.tx.send(ControlCmd::Shutdown);
+            }
+
+            let mut res = match stop_error {
+                Some(e) => Err(e),
+                None => Ok(()),
+            };
+            for handler in handlers {
+                match handler.thread_handle.join() {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        let e = e.downcast().unwrap_or_else(ServerError::Other);
+                        if matches!(e, ServerError::ClientTimeout { .. }) {
+                            log::info!("Handler for {} is finished: {e}", handler.client_addr);
+                            continue;
+                        }
+                        log::error!(
+                            "Handler for {} is finished with error: {e}",
+                            handler.client_addr
+                        );
+                        if res.is_ok() {
+                            res = Err(e);
+                        }
+                    }
+                    Err(_) => {
+                        log::error!("Can't join handler thread for {}", handler.client_addr);
+                        if res.is_ok() {
+                            res = Err(ServerError::ThreadJoin("handler".to_string()));
+                        }
+                    }
+                }
+            }
+
+            if let Some(path) = self.context.config.session_state_path.as_ref() {
+                match self.context.retained.persist(Path::new(path)) {
+                    Ok(count) => log::info!("Save {count} client subscriptions to {path}"),
+                    Err(e) => log::error!("Can't save client subscriptions to {path}: {e}"),
+                }
+            }
+
+            if let Some(control) = multicast_control {
+                let _ = control.tx.send(ControlCmd::Shutdown);
+                if control.thread_handle.join().is_err() {
+                    log::error!("Can't join multicast publisher thread");
+                }
+            }
+            log::info!("Server is stopped");
+            res
+        });
+        Ok(ServerControl {
+            tx,
+            thread_handle: handle,
+            stats,
+            metrics,
+            local_addr: listen_addrs[0],
+            listen_addrs,
+            websocket_addr,
+            metrics_addr,
+            admin_addr,
+            events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::handler::QuoteHandler;
+    use crate::client::quotes_client::{ClientCmd, QuotesClientBuilder};
+    use crate::server::config::ListenerConfig;
+    use std::io::Read;
+
+    struct Ticks {
+        timestamp: u64,
+    }
+
+    impl QuoteSource for Ticks {
+        fn tickers(&self) -> Vec<String> {
+            vec!["AMD".to_string()]
+        }
+
+        fn poll_millis(&self) -> u64 {
+            10
+        }
+
+        fn poll(&mut self) -> Result<Vec<StockQuote>> {
+            self.timestamp += 1;
+            Ok(vec![StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 1,
+                timestamp: self.timestamp,
+            }])
+        }
+    }
+
+    fn test_server(config: ServerConfig) -> QuotesServer {
+        let config = ServerConfig {
+            listen_addr: "127.0.0.1:0".to_string(),
+            quotes_udp_port: 0,
+            ..config
+        };
+        QuotesServer::with_source(Ticks { timestamp: 0 }, config).unwrap()
+    }
+
+    fn start_server(config: ServerConfig) -> ServerControl {
+        test_server(config).start().unwrap()
+    }
+
+    fn stop_server(control: ServerControl) {
+        control.tx.send(ControlCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+    }
+
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    fn ws_read<S: Read + Write>(ws: &mut tungstenite::WebSocket<S>) -> serde_json::Value {
+        loop {
+            if let tungstenite::Message::Text(text) = ws.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn read_message<S: Read>(stream: &mut S) -> Message {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut reply = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut reply).unwrap();
+        postcard::from_bytes(&reply).unwrap()
+    }
+
+    fn request_history(conn: &mut TcpStream, last_n: u32) -> Vec<StockQuote> {
+        let req = Message::HistoryReq {
+            ticker: "AMD".to_string(),
+            last_n,
+        };
+        conn.write_all(&pack_message_with_len(&req).unwrap())
+            .unwrap();
+        loop {
+            if let Message::History { ticker, quotes } = read_message(conn) {
+                assert_eq!(ticker, "AMD");
+                return quotes;
+            }
+        }
+    }
+
+    fn subscribe_request(udp: &UdpSocket, token: Option<&str>) -> Vec<u8> {
+        let req = Message::Tickers(TickerReqMessage {
+            port: udp.local_addr().unwrap().port(),
+            stripe_ports: Vec::new(),
+            tickers: vec!["AMD".to_string()],
+            token: token.map(str::to_string),
+            session: None,
+            filter: None,
+            bars: false,
+        });
+        pack_message_with_len(&req).unwrap()
+    }
+
+    fn send_subscribe(addr: SocketAddr, udp: &UdpSocket, token: Option<&str>) -> TcpStream {
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        conn.write_all(&subscribe_request(udp, token)).unwrap();
+        conn
+    }
+
+    fn subscribe_tls(addr: SocketAddr, udp: &UdpSocket) -> ControlStream {
+        let tls = tls::client_config("testdata/tls/ca.pem").unwrap();
+        let conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut stream = ControlStream::connect(conn, Some((&tls, "localhost"))).unwrap();
+        stream.write_all(&subscribe_request(udp, None)).unwrap();
+        stream.flush().unwrap();
+        assert!(matches!(
+            read_message(&mut stream),
+            Message::Subscribed { .. }
+        ));
+        stream
+    }
+
+    fn subscribe_amd(control: &ServerControl, udp: &UdpSocket) -> TcpStream {
+        let mut conn = send_subscribe(control.local_addr, udp, None);
+        assert!(matches!(
+            read_message(&mut conn),
+            Message::Subscribed { .. }
+        ));
+        conn
+    }
+
+    #[test]
+    fn test_history_request() {
+        let control = start_server(ServerConfig {
+            history_depth: 5,
+            ..Default::default()
+        });
+        let mut early = TcpStream::connect(control.local_addr).unwrap();
+        early
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let req = Message::HistoryReq {
+            ticker: "AMD".to_string(),
+            last_n: 1,
+        };
+        early
+            .write_all(&pack_message_with_len(&req).unwrap())
+            .unwrap();
+        assert!(matches!(
+            read_message(&mut early),
+            Message::Error {
+                code: ErrorCode::UnexpectedMessage
+            }
+        ));
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut conn = subscribe_amd(&control, &udp);
+        let mut quotes = Vec::new();
+        assert!(wait_until(|| {
+            quotes = request_history(&mut conn, 3);
+            quotes.len() == 3
+        }));
+        assert!(quotes.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        assert_eq!(request_history(&mut conn, 100).len(), 5);
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_client_lifecycle_events() {
+        let control = start_server(ServerConfig::default());
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let conn = subscribe_amd(&control, &udp);
+        let addr = conn.local_addr().unwrap();
+        assert!(wait_until(|| control.metrics.snapshot().quotes_sent > 0));
+        drop(conn);
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            control.events.recv_timeout(timeout).unwrap(),
+            ServerEvent::ClientConnected { addr }
+        );
+        assert_eq!(
+            control.events.recv_timeout(timeout).unwrap(),
+            ServerEvent::SubscriptionChanged {
+                addr,
+                tickers: vec!["AMD".to_string()],
+            }
+        );
+        match control.events.recv_timeout(timeout).unwrap() {
+            ServerEvent::ClientDisconnected {
+                addr: closed,
+                reason,
+                summary,
+            } => {
+                assert_eq!(closed, addr);
+                assert!(reason.starts_with("connection error"));
+                assert!(summary.quotes_sent > 0);
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_shutdown_notifies_clients() {
+        let control = start_server(ServerConfig::default());
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut conn = subscribe_amd(&control, &udp);
+        stop_server(control);
+        while !matches!(read_message(&mut conn), Message::Shutdown) {}
+        assert_eq!(conn.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_requests_before_subscription() {
+        let control = start_server(ServerConfig::default());
+        for req in [
+            Message::Pause,
+            Message::Resume,
+            Message::ChangeSubscription {
+                mode: SubscriptionMode::Add,
+                tickers: vec!["AMD".to_string()],
+            },
+        ] {
+            let mut conn = TcpStream::connect(control.local_addr).unwrap();
+            conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            conn.write_all(&pack_message_with_len(&req).unwrap())
+                .unwrap();
+            assert!(matches!(
+                read_message(&mut conn),
+                Message::Error {
+                    code: ErrorCode::UnexpectedMessage
+                }
+            ));
+            assert_eq!(conn.read(&mut [0u8; 16]).unwrap(), 0);
+        }
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_authenticator() {
+        struct Token;
+
+        impl Authenticator for Token {
+            fn authenticate(&self, token: &str, peer: SocketAddr) -> bool {
+                token == "secret" && peer.ip().is_loopback()
+            }
+        }
+
+        let control = test_server(ServerConfig::default())
+            .with_authenticator(Token)
+            .start()
+            .unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for token in [None, Some("wrong")] {
+            let mut conn = send_subscribe(control.local_addr, &udp, token);
+            assert!(matches!(
+                read_message(&mut conn),
+                Message::Error {
+                    code: ErrorCode::Unauthorized
+                }
+            ));
+            assert_eq!(conn.read(&mut [0u8; 16]).unwrap(), 0);
+        }
+
+        let mut conn = send_subscribe(control.local_addr, &udp, Some("secret"));
+        assert!(matches!(
+            read_message(&mut conn),
+            Message::Subscribed { .. }
+        ));
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_random_ports() {
+        let servers: Vec<ServerControl> = (0..2)
+            .map(|_| {
+                start_server(ServerConfig {
+                    metrics_addr: Some("127.0.0.1:0".to_string()),
+                    admin_addr: Some("127.0.0.1:0".to_string()),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let mut addrs = std::collections::HashSet::new();
+        for control in servers.iter() {
+            for addr in [
+                control.local_addr,
+                control.metrics_addr.unwrap(),
+                control.admin_addr.unwrap(),
+            ] {
+                assert_ne!(addr.port(), 0);
+                assert!(addrs.insert(addr));
+            }
+
+            let mut conn = TcpStream::connect(control.metrics_addr.unwrap()).unwrap();
+            conn.write_all(b"GET /healthz HTTP/1.1\r\n\r\n").unwrap();
+            let mut response = String::new();
+            conn.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"));
+            assert!(TcpStream::connect(control.admin_addr.unwrap()).is_ok());
+
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            udp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let _conn = subscribe_amd(control, &udp);
+            let (_, from) = udp.recv_from(&mut [0u8; MAX_SIZE_DATAGRAM]).unwrap();
+            assert_ne!(from.port(), 0);
+            assert_ne!(from, control.local_addr);
+        }
+        servers.into_iter().for_each(stop_server);
+    }
+
+    #[test]
+    fn test_error_before_close() {
+        let control = start_server(ServerConfig::default());
+        let mut bad_payload = 1u32.to_be_bytes().to_vec();
+        bad_payload.push(u8::MAX);
+        let requests = [
+            (0u32.to_be_bytes().to_vec(), ErrorCode::BadFrameLength),
+            (
+                (MAX_CONTROL_MESSAGE_LEN + 1).to_be_bytes().to_vec(),
+                ErrorCode::BadFrameLength,
+            ),
+            (bad_payload, ErrorCode::DecodeFailure),
+            (
+                pack_message_with_len(&Message::Pong).unwrap(),
+                ErrorCode::UnexpectedMessage,
+            ),
+        ];
+        for (request, code) in requests {
+            let mut conn = TcpStream::connect(control.local_addr).unwrap();
+            conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            conn.write_all(&request).unwrap();
+            match read_message(&mut conn) {
+                Message::Error { code: received } => assert_eq!(received, code),
+                msg => panic!("Unexpected message: {msg:?}"),
+            }
+            assert_eq!(conn.read(&mut [0u8; 16]).unwrap(), 0);
+        }
+        assert_eq!(control.metrics.snapshot().decode_failures, 1);
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_several_listeners() {
+        let control = start_server(ServerConfig {
+            listeners: vec![ListenerConfig {
+                addr: "127.0.0.1:0".to_string(),
+                tls_cert: Some("testdata/tls/server.pem".to_string()),
+                tls_key: Some("testdata/tls/server.key".to_string()),
+            }],
+            ..Default::default()
+        });
+        assert_eq!(control.listen_addrs.len(), 2);
+        assert_eq!(control.listen_addrs[0], control.local_addr);
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let _plain = subscribe_amd(&control, &udp);
+
+        let tls_udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let secure = subscribe_tls(control.listen_addrs[1], &tls_udp);
+
+        assert!(wait_until(
+            || control.metrics.snapshot().connected_clients == 2
+        ));
+        drop(secure);
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_history_is_opt_in() {
+        let control = start_server(ServerConfig::default());
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut conn = subscribe_amd(&control, &udp);
+        assert!(wait_until(|| control.metrics.snapshot().quotes_sent > 0));
+        assert!(request_history(&mut conn, 10).is_empty());
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_websocket_client_goes_through_sender() {
+        let control = start_server(ServerConfig {
+            websocket: Some(ListenerConfig {
+                addr: "127.0.0.1:0".to_string(),
+                tls_cert: None,
+                tls_key: None,
+            }),
+            client_bandwidth_bytes_per_sec: 200,
+            ..Default::default()
+        });
+        let addr = control.websocket_addr.unwrap();
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (mut ws, _) = tungstenite::client(format!("ws://{addr}/"), stream).unwrap();
+        ws.send(tungstenite::Message::text(
+            r#"{"type": "subscribe", "tickers": ["AMD", "XXX"]}"#,
+        ))
+        .unwrap();
+
+        let ack = ws_read(&mut ws);
+        assert_eq!(ack["type"], "subscribed");
+        assert_eq!(ack["unknown_tickers"], serde_json::json!(["XXX"]));
+        let quote = ws_read(&mut ws);
+        assert_eq!(quote["type"], "quote");
+        assert_eq!(quote["quote"]["ticker"], "AMD");
+
+        assert!(wait_until(
+            || control.metrics.snapshot().bandwidth_capped > 0
+        ));
+        assert!(control.metrics.snapshot().quotes_sent > 0);
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_too_many_clients() {
+        let control = start_server(ServerConfig {
+            max_clients: 2,
+            websocket: Some(ListenerConfig {
+                addr: "127.0.0.1:0".to_string(),
+                tls_cert: None,
+                tls_key: None,
+            }),
+            ..Default::default()
+        });
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let _clients: Vec<TcpStream> = (0..2).map(|_| subscribe_amd(&control, &udp)).collect();
+
+        let mut rejected = send_subscribe(control.local_addr, &udp, None);
+        assert!(matches!(
+            read_message(&mut rejected),
+            Message::Error {
+                code: ErrorCode::TooManyClients
+            }
+        ));
+
+        let addr = control.websocket_addr.unwrap();
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (mut ws, _) = tungstenite::client(format!("ws://{addr}/"), stream).unwrap();
+        let reply = ws_read(&mut ws);
+        assert_eq!(reply["type"], "error");
+        assert_eq!(
+            reply["code"],
+            serde_json::to_value(ErrorCode::TooManyClients).unwrap()
+        );
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_subscribe_timeout() {
+        let control = start_server(ServerConfig {
+            subscribe_timeout_millis: 100,
+            ..Default::default()
+        });
+        let mut silent = TcpStream::connect(control.local_addr).unwrap();
+        silent
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let started = Instant::now();
+        assert_eq!(silent.read(&mut [0u8; 16]).unwrap(), 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        stop_server(control);
+    }
+
+    fn recv_seqs(udp: &UdpSocket, count: usize) -> Vec<u64> {
+        udp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut buf = [0u8; MAX_SIZE_DATAGRAM];
+        (0..count)
+            .map(|_| {
+                let len = udp.recv(&mut buf).unwrap();
+                match postcard::from_bytes(&buf[..len]).unwrap() {
+                    Message::Quote(resp) => resp.seq,
+                    msg => panic!("Unexpected message: {msg:?}"),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stripe_ports() {
+        let control = start_server(ServerConfig::default());
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let stripe = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut conn = TcpStream::connect(control.local_addr).unwrap();
+        let req = Message::Tickers(TickerReqMessage {
+            port: udp.local_addr().unwrap().port(),
+            stripe_ports: vec![stripe.local_addr().unwrap().port()],
+            tickers: vec!["AMD".to_string()],
+            token: None,
+            session: None,
+            filter: None,
+            bars: false,
+        });
+        conn.write_all(&pack_message_with_len(&req).unwrap())
+            .unwrap();
+
+        let main_seqs = recv_seqs(&udp, 4);
+        let stripe_seqs = recv_seqs(&stripe, 4);
+        assert_eq!(stripe_seqs[0], main_seqs[0] + 1);
+        for seqs in [&main_seqs, &stripe_seqs] {
+            assert!(seqs.windows(2).all(|w| w[1] == w[0] + 2));
+        }
+        stop_server(control);
+    }
+
+    struct LiveQuotes(mpsc::Sender<u64>);
+
+    impl QuoteHandler for LiveQuotes {
+        fn on_quote(&mut self, quote: StockQuote) {
+            let _ = self.0.send(quote.timestamp);
+        }
+
+        fn on_replay(&mut self, _quote: StockQuote) {}
+    }
+
+    #[test]
+    fn test_striped_client_merges_quotes() {
+        let control = start_server(ServerConfig::default());
+        let (tx, rx) = mpsc::channel();
+        let client = QuotesClientBuilder::new(&control.local_addr.to_string(), 0, ["AMD"])
+            .with_poll_millis(1)
+            .build()
+            .unwrap()
+            .with_stripe_ports(vec![0, 0])
+            .start_receive_handler(LiveQuotes(tx))
+            .unwrap();
+
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        rx.try_iter().for_each(drop);
+        let timestamps: Vec<u64> = (0..12)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        assert!(timestamps.windows(2).all(|w| w[1] == w[0] + 1));
+
+        client.tx.send(ClientCmd::Stop).unwrap();
+        client.thread_handle.join().unwrap().unwrap();
+        stop_server(control);
+    }
+
+    fn multicast_group() -> Option<SocketAddr> {
+        let receiver = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let group_ip = std::net::Ipv4Addr::new(239, 255, 0, 1);
+        let group = SocketAddr::from((group_ip, receiver.local_addr().unwrap().port()));
+        receiver
+            .join_multicast_v4(&group_ip, &std::net::Ipv4Addr::UNSPECIFIED)
+            .ok()?;
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let sender = UdpSocket::bind("0.0.0.0:0").unwrap();
+        sender.send_to(b"probe", group).ok()?;
+        receiver.recv(&mut [0u8; 16]).ok()?;
+        Some(group)
+    }
+
+    #[test]
+    fn test_multicast_broadcast() {
+        let Some(group) = multicast_group() else {
+            eprintln!("Multicast loopback is unavailable, skip test");
+            return;
+        };
+        let control = start_server(ServerConfig {
+            multicast_addr: Some(group.to_string()),
+            ..Default::default()
+        });
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut conn = send_subscribe(control.local_addr, &udp, None);
+        match read_message(&mut conn) {
+            Message::Subscribed {
+                multicast_group, ..
+            } => assert_eq!(multicast_group, Some(group)),
+            msg => panic!("Unexpected message: {msg:?}"),
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let client = QuotesClientBuilder::new(&control.local_addr.to_string(), 0, ["AMD"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_handler(LiveQuotes(tx))
+            .unwrap();
+        let timestamps: Vec<u64> = (0..3)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        assert!(timestamps.windows(2).all(|w| w[0] < w[1]));
+
+        client.tx.send(ClientCmd::Stop).unwrap();
+        client.thread_handle.join().unwrap().unwrap();
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_ping_timeout_metric() {
+        let control = start_server(ServerConfig {
+            ping_wait_millis: 100,
+            ..Default::default()
+        });
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut conn = TcpStream::connect(control.local_addr).unwrap();
+        let req = Message::Tickers(TickerReqMessage {
+            port: udp.local_addr().unwrap().port(),
+            stripe_ports: Vec::new(),
+            tickers: vec!["AMD".to_string()],
+            token: None,
+            session: None,
+            filter: None,
+            bars: false,
+        });
+        conn.write_all(&pack_message_with_len(&req).unwrap())
+            .unwrap();
+
+        assert!(wait_until(|| control.metrics.snapshot().ping_timeouts == 1));
+        assert!(wait_until(
+            || control.metrics.snapshot().connected_clients == 0
+        ));
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_accept_keeps_socket_timeouts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = TcpSocketOptions {
+            read_timeout_millis: Some(300),
+            write_timeout_millis: Some(400),
+            ..Default::default()
+        };
+        for (options, read, write) in [
+            (options, Some(300), Some(400)),
+            (TcpSocketOptions::default(), None, None),
+        ] {
+            let _client = TcpStream::connect(addr).unwrap();
+            let (conn, _) = listener.accept().unwrap();
+            let stream = TcpHandler::accept(conn, None, &options).unwrap();
+            let tcp = stream.tcp();
+            assert_eq!(tcp.read_timeout().unwrap(), read.map(Duration::from_millis));
+            assert_eq!(
+                tcp.write_timeout().unwrap(),
+                write.map(Duration::from_millis)
+            );
+        }
+    }
+
+    #[test]
+    fn test_tls_handshake_in_client_thread() {
+        let control = start_server(
+            ServerConfig::default().tls("testdata/tls/server.pem", "testdata/tls/server.key"),
+        );
+        let stalled = TcpStream::connect(control.local_addr).unwrap();
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let stream = subscribe_tls(control.local_addr, &udp);
+
+        drop(stalled);
+        drop(stream);
+        stop_server(control);
+    }
+
+    #[test]
+    fn test_reap_finished_handlers() {
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let threads: Vec<thread::JoinHandle<Result<()>>> = vec![
+            thread::spawn(|| Ok(())),
+            thread::spawn(move || {
+                let _ = release_rx.recv();
+                Ok(())
+            }),
+            thread::spawn(|| Err(anyhow!("handler failure"))),
+        ];
+        let mut handlers: Vec<HanlerControl> = threads
+            .into_iter()
+            .enumerate()
+            .map(|(port, thread_handle)| HanlerControl {
+                tx: mpsc::channel().0,
+                thread_handle,
+                client_addr: SocketAddr::from(([127, 0, 0, 1], port as u16)),
+            })
+            .collect();
+        assert!(wait_until(|| {
+            [0, 2]
+                .iter()
+                .all(|&i| handlers[i].thread_handle.is_finished())
+        }));
+
+        reap_finished_handlers(&mut handlers);
+        assert_eq!(handlers.len(), 1);
+        assert_eq!(handlers[0].client_addr.port(), 1);
+
+        release_tx.send(()).unwrap();
+        assert!(wait_until(|| handlers[0].thread_handle.is_finished()));
+        reap_finished_handlers(&mut handlers);
+        assert!(handlers.is_empty());
+    }
+
+    #[test]
+    fn test_poisoned_generator_is_stopped() {
+        let generator = QuoteGenerator::new("generator_config.json", None).unwrap();
+        let origin = QuoteOrigin::Generator(Arc::new(Mutex::new(generator)));
+        assert!(!is_generator_stopped(&origin, None, &[], None));
+
+        let QuoteOrigin::Generator(shared) = origin.clone() else {
+            panic!("Unexpected origin");
+        };
+        let res = thread::spawn(move || {
+            let _generator = shared.lock().unwrap();
+            panic!("generator failure");
+        })
+        .join();
+        assert!(res.is_err());
+        assert!(is_generator_stopped(&origin, None, &[], None));
+    }
+
+    #[test]
+    fn test_close_on_panic() {
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let (events_tx, events_rx) = mpsc::sync_channel(10);
+        let session = ClientSession::new(addr, events_tx, None);
+        assert!(
+            session
+                .close_on_panic(HANDLER_SUBSYSTEM, Ok(Ok(())))
+                .is_ok()
+        );
+        assert!(events_rx.try_recv().is_err());
+
+        let res = panic::catch_unwind(|| -> Result<()> { panic!("boom") });
+        assert!(session.close_on_panic(HANDLER_SUBSYSTEM, res).is_err());
+        assert_eq!(
+            events_rx.try_recv().unwrap(),
+            ServerEvent::ThreadPanicked {
+                addr,
+                thread: HANDLER_SUBSYSTEM.to_string(),
+                message: "boom".to_string(),
+            }
+        );
+        assert!(matches!(
+            events_rx.try_recv().unwrap(),
+            ServerEvent::ClientDisconnected { .. }
+        ));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert!(matches!(
+            parse_addr("localhost"),
+            Err(ServerError::Config(_))
+        ));
+        assert!(matches!(
+            tls_config(Some(&"server.crt".to_string()), None),
+            Err(ServerError::Config(_))
+        ));
+        assert!(matches!(
+            tls_config(
+                Some(&"missing.crt".to_string()),
+                Some(&"missing.key".to_string())
+            ),
+            Err(ServerError::Tls(_))
+        ));
     }
 }
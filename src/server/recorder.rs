@@ -0,0 +1,265 @@
+use crate::quote::{QuoteCallback, StockQuote};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Имя файла индекса сегментов в каталоге записи
+pub const INDEX_FILE_NAME: &str = "index.ndjson";
+
+/// Емкость очереди котировок, ожидающих записи в фоновом потоке
+pub const RECORD_QUEUE_CAPACITY: usize = 4096;
+
+/// Записанная котировка. Одна строка NDJSON в файле сегмента
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedQuote {
+    /// Время генерации котировки, мс с начала эпохи Unix
+    pub unix_millis: u64,
+    /// Котировка
+    pub quote: StockQuote,
+}
+
+/// Описание завершенного сегмента. Одна строка NDJSON в файле индекса
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SegmentInfo {
+    /// Имя файла сегмента в каталоге записи
+    pub file: String,
+    /// Количество котировок в сегменте
+    pub quotes: u64,
+    /// Время первой котировки, мс с начала эпохи Unix
+    pub first_unix_millis: u64,
+    /// Время последней котировки, мс с начала эпохи Unix
+    pub last_unix_millis: u64,
+}
+
+struct Segment {
+    writer: LineWriter<File>,
+    info: SegmentInfo,
+}
+
+/// Запись всех сгенерированных котировок в сегменты NDJSON.
+/// Новый сегмент начинается, когда в текущем набралось `max_segment_quotes` котировок,
+/// завершенные сегменты добавляются в индекс
+pub struct QuoteRecorder {
+    dir: PathBuf,
+    max_segment_quotes: u64,
+    segment: Option<Segment>,
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl QuoteRecorder {
+    /// Создает запись в каталоге dir. Каталог создается, если его нет
+    pub fn new(dir: &Path, max_segment_quotes: u64) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        log::info!("Record quotes to {}", dir.display());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_segment_quotes: max_segment_quotes.max(1),
+            segment: None,
+        })
+    }
+
+    fn open_segment(&self, unix_millis: u64) -> Result<Segment> {
+        let mut file_name = format!("quotes-{unix_millis}.ndjson");
+        let mut suffix = 1;
+        while self.dir.join(&file_name).exists() {
+            file_name = format!("quotes-{unix_millis}-{suffix}.ndjson");
+            suffix += 1;
+        }
+        let file = File::create(self.dir.join(&file_name))?;
+        log::debug!("New record segment: {file_name}");
+        Ok(Segment {
+            writer: LineWriter::new(file),
+            info: SegmentInfo {
+                file: file_name,
+                quotes: 0,
+                first_unix_millis: unix_millis,
+                last_unix_millis: unix_millis,
+            },
+        })
+    }
+
+    fn close_segment(&mut self) -> Result<()> {
+        let Some(mut segment) = self.segment.take() else {
+            return Ok(());
+        };
+        segment.writer.flush()?;
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE_NAME))?;
+        writeln!(index, "{}", serde_json::to_string(&segment.info)?)?;
+        Ok(())
+    }
+
+    fn record(&mut self, record: &RecordedQuote) -> Result<()> {
+        if self.segment.is_none() {
+            self.segment = Some(self.open_segment(record.unix_millis)?);
+        }
+        let Some(segment) = self.segment.as_mut() else {
+            return Ok(());
+        };
+
+        writeln!(segment.writer, "{}", serde_json::to_string(record)?)?;
+        segment.info.quotes += 1;
+        segment.info.last_unix_millis = record.unix_millis;
+
+        if segment.info.quotes >= self.max_segment_quotes {
+            self.close_segment()?;
+        }
+        Ok(())
+    }
+}
+
+impl QuoteCallback for QuoteRecorder {
+    fn on_quote(&mut self, quote: &StockQuote) {
+        let record = RecordedQuote {
+            unix_millis: unix_millis(),
+            quote: quote.clone(),
+        };
+        if let Err(e) = self.record(&record) {
+            log::error!("Can't record quote: {e}");
+        }
+    }
+}
+
+/// Запись котировок в фоновом потоке: генератор только ставит котировку в очередь
+/// и не ждет диск. При переполнении очереди котировка не записывается и учитывается
+/// в `dropped`. Поток закрывает сегмент и завершается, когда удалены все копии
+#[derive(Clone)]
+pub struct BackgroundRecorder {
+    tx: mpsc::SyncSender<RecordedQuote>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl BackgroundRecorder {
+    /// Запускает поток записи recorder с очередью емкостью capacity
+    pub fn start(mut recorder: QuoteRecorder, capacity: usize) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<RecordedQuote>(capacity.max(1));
+        thread::spawn(move || {
+            for record in rx {
+                if let Err(e) = recorder.record(&record) {
+                    log::error!("Can't record quote: {e}");
+                }
+            }
+        });
+        Self {
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Количество котировок, не записанных из-за переполнения очереди
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl QuoteCallback for BackgroundRecorder {
+    fn on_quote(&mut self, quote: &StockQuote) {
+        let record = RecordedQuote {
+            unix_millis: unix_millis(),
+            quote: quote.clone(),
+        };
+        match self.tx.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    log::warn!("Record queue is full, quotes are not recorded");
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => log::debug!("Record thread is stopped"),
+        }
+    }
+}
+
+impl Drop for QuoteRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.close_segment() {
+            log::error!("Can't close record segment: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_segments_and_index() {
+        let dir = tempdir().unwrap();
+        let mut recorder = QuoteRecorder::new(dir.path(), 2).unwrap();
+        for timestamp in 1..=3 {
+            recorder.on_quote(&StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 10,
+                timestamp,
+            });
+        }
+        drop(recorder);
+
+        let index = std::fs::read_to_string(dir.path().join(INDEX_FILE_NAME)).unwrap();
+        let segments: Vec<SegmentInfo> = index
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].quotes, 2);
+        assert_eq!(segments[1].quotes, 1);
+
+        let last = std::fs::read_to_string(dir.path().join(&segments[1].file)).unwrap();
+        let record: RecordedQuote = serde_json::from_str(last.trim()).unwrap();
+        assert_eq!(record.quote.timestamp, 3);
+    }
+
+    #[test]
+    fn test_background_recorder() {
+        let dir = tempdir().unwrap();
+        let recorder = QuoteRecorder::new(dir.path(), 2).unwrap();
+        let mut background = BackgroundRecorder::start(recorder, RECORD_QUEUE_CAPACITY);
+        let mut copy = background.clone();
+        for timestamp in 1..=4 {
+            let quote = StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 10,
+                timestamp,
+            };
+            if timestamp % 2 == 0 {
+                copy.on_quote(&quote);
+            } else {
+                background.on_quote(&quote);
+            }
+        }
+        assert_eq!(background.dropped(), 0);
+        drop(copy);
+        drop(background);
+
+        let index_path = dir.path().join(INDEX_FILE_NAME);
+        let started = std::time::Instant::now();
+        let mut index = String::new();
+        while index.lines().count() < 2 && started.elapsed().as_secs() < 5 {
+            thread::sleep(std::time::Duration::from_millis(10));
+            index = std::fs::read_to_string(&index_path).unwrap_or_default();
+        }
+        let quotes: u64 = index
+            .lines()
+            .map(|line| serde_json::from_str::<SegmentInfo>(line).unwrap().quotes)
+            .sum();
+        assert_eq!(quotes, 4);
+    }
+}
@@ -0,0 +1,321 @@
+use super::config::UpstreamConfig;
+use super::source::QuoteSource;
+use crate::client::quotes_client::ReconnectPolicy;
+use crate::protocol::*;
+use crate::quote::StockQuote;
+use crate::utils::bind_udp;
+use anyhow::{Result, bail};
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+const RELAY_POLL_MILLIS: u64 = 10;
+const PING_PERIOD_MILLIS: u64 = 1000;
+const UPSTREAM_TIMEOUT_MILLIS: u64 = 10000;
+const WAIT_SUBSCRIBED_MILLIS: u64 = 5000;
+
+struct Upstream {
+    _control: TcpStream,
+    socket: UdpSocket,
+    udp_addr: Option<SocketAddr>,
+    last_ping: Instant,
+    last_datagram: Instant,
+}
+
+impl Upstream {
+    fn subscribe(config: &UpstreamConfig) -> Result<(Self, Vec<String>)> {
+        let mut control = TcpStream::connect(&config.addr)?;
+        control.set_read_timeout(Some(Duration::from_millis(WAIT_SUBSCRIBED_MILLIS)))?;
+        let socket = bind_udp(SocketAddr::new(control.local_addr()?.ip(), 0))?;
+        socket.set_nonblocking(true)?;
+
+        let req = Message::Tickers(TickerReqMessage {
+            port: socket.local_addr()?.port(),
+            stripe_ports: Vec::new(),
+            tickers: config.tickers.clone(),
+            token: config.token.clone(),
+            session: None,
+            filter: None,
+            bars: false,
+        });
+        control.write_all(&pack_message_with_len(&req)?)?;
+        control.flush()?;
+
+        let (unknown_tickers, tickers) = match read_message_with_len(&mut control)? {
+            Message::Subscribed {
+                multicast_group: Some(group),
+                ..
+            } => bail!("Upstream server publishes quotes to multicast group {group}"),
+            Message::Subscribed {
+                unknown_tickers,
+                tickers,
+                ..
+            } => (unknown_tickers, tickers),
+            Message::Error { code } => bail!("Upstream server rejected connection: {code:?}"),
+            msg => bail!("Unexpected response of upstream server: {msg:?}"),
+        };
+        if !unknown_tickers.is_empty() {
+            log::warn!("Upstream server doesn't know tickers: {unknown_tickers:?}");
+        }
+        if tickers.is_empty() {
+            bail!("Upstream server doesn't know any of requested tickers");
+        }
+        log::info!("Relay quotes of {} from {}", tickers.join(","), config.addr);
+
+        let upstream = Self {
+            _control: control,
+            socket,
+            udp_addr: None,
+            last_ping: Instant::now(),
+            last_datagram: Instant::now(),
+        };
+        Ok((upstream, tickers))
+    }
+
+    fn ping(&mut self) -> Result<()> {
+        let Some(addr) = self.udp_addr else {
+            return Ok(());
+        };
+        if self.last_ping.elapsed() < Duration::from_millis(PING_PERIOD_MILLIS) {
+            return Ok(());
+        }
+        self.socket
+            .send_to(&postcard::to_stdvec(&Message::Ping)?, addr)?;
+        self.last_ping = Instant::now();
+        Ok(())
+    }
+
+    fn recv(&mut self, quotes: &mut Vec<StockQuote>, bad_datagrams: &mut u64) -> Result<()> {
+        let mut recv_buf = [0u8; MAX_SIZE_DATAGRAM];
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut recv_buf) {
+                Ok(val) => val,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => continue,
+                Err(e) => bail!("Can't receive from upstream server: {e}"),
+            };
+            self.last_datagram = Instant::now();
+            let msg = match postcard::from_bytes::<Message>(&recv_buf[..len]) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    *bad_datagrams += 1;
+                    log::warn!(
+                        "Can't decode datagram from {addr}: {e}, bad datagrams: {bad_datagrams}"
+                    );
+                    continue;
+                }
+            };
+            match msg {
+                Message::Quote(resp) => {
+                    self.udp_addr = Some(addr);
+                    quotes.push(resp.quote);
+                }
+                Message::Pong | Message::ReplayQuote(_) => {}
+                Message::Shutdown => bail!("Upstream server is shutting down"),
+                msg => log::warn!("Unexpected datagram from upstream server: {msg:?}"),
+            }
+        }
+
+        if self.last_datagram.elapsed() > Duration::from_millis(UPSTREAM_TIMEOUT_MILLIS) {
+            bail!("Upstream server doesn't respond during {UPSTREAM_TIMEOUT_MILLIS} ms");
+        }
+        self.ping()
+    }
+}
+
+/// Источник котировок, который подписывается на вышестоящий сервер по клиентскому протоколу.
+/// Позволяет строить дерево серверов для раздачи котировок большому числу клиентов.
+/// При потере вышестоящего сервера переподключается к нему с растущей задержкой
+pub struct UpstreamRelay {
+    config: UpstreamConfig,
+    tickers: Vec<String>,
+    upstream: Option<Upstream>,
+    policy: ReconnectPolicy,
+    attempt: u32,
+    reconnect_at: Instant,
+    bad_datagrams: u64,
+}
+
+impl UpstreamRelay {
+    /// Подключается к вышестоящему серверу и подписывается на тикеры из настроек.
+    /// Тикеры, которых нет на вышестоящем сервере, не ретранслируются
+    pub fn connect(config: &UpstreamConfig) -> Result<Self> {
+        let (upstream, tickers) = Upstream::subscribe(config)?;
+        Ok(Self {
+            config: config.clone(),
+            tickers,
+            upstream: Some(upstream),
+            policy: ReconnectPolicy::default(),
+            attempt: 0,
+            reconnect_at: Instant::now(),
+            bad_datagrams: 0,
+        })
+    }
+
+    /// Задержки переподключения к вышестоящему серверу
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Количество датаграмм вышестоящего сервера, которые не удалось декодировать
+    pub fn bad_datagrams(&self) -> u64 {
+        self.bad_datagrams
+    }
+
+    fn reconnect(&mut self) {
+        if Instant::now() < self.reconnect_at {
+            return;
+        }
+        match Upstream::subscribe(&self.config) {
+            Ok((upstream, tickers)) => {
+                if tickers != self.tickers {
+                    log::warn!("Upstream server relays tickers {tickers:?} after reconnection");
+                }
+                log::info!("Reconnected to upstream server {}", self.config.addr);
+                self.upstream = Some(upstream);
+                self.attempt = 0;
+            }
+            Err(e) => {
+                let delay = self.policy.delay(self.attempt);
+                self.attempt = self.attempt.saturating_add(1);
+                self.reconnect_at = Instant::now() + delay;
+                log::warn!(
+                    "Can't reconnect to upstream server {}: {e}, retry in {} ms",
+                    self.config.addr,
+                    delay.as_millis()
+                );
+            }
+        }
+    }
+}
+
+impl QuoteSource for UpstreamRelay {
+    fn tickers(&self) -> Vec<String> {
+        self.tickers.clone()
+    }
+
+    fn poll_millis(&self) -> u64 {
+        RELAY_POLL_MILLIS
+    }
+
+    fn poll(&mut self) -> Result<Vec<StockQuote>> {
+        let mut quotes = Vec::new();
+        let Some(upstream) = self.upstream.as_mut() else {
+            self.reconnect();
+            return Ok(quotes);
+        };
+        if let Err(e) = upstream.recv(&mut quotes, &mut self.bad_datagrams) {
+            log::warn!("Lost upstream server {}: {e}", self.config.addr);
+            self.upstream = None;
+            self.reconnect_at = Instant::now() + self.policy.delay(0);
+            self.attempt = 1;
+        }
+        Ok(quotes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServerBuilder;
+    use std::thread;
+
+    fn test_quote(ticker: &str, timestamp: u64) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price: 100.0,
+            volume: 10,
+            timestamp,
+        }
+    }
+
+    fn poll_until<F: Fn(&UpstreamRelay, &[StockQuote]) -> bool>(
+        relay: &mut UpstreamRelay,
+        done: F,
+    ) -> Vec<StockQuote> {
+        let mut quotes = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(relay, &quotes) && Instant::now() < deadline {
+            quotes.extend(relay.poll().unwrap());
+            thread::sleep(Duration::from_millis(RELAY_POLL_MILLIS));
+        }
+        quotes
+    }
+
+    #[test]
+    fn test_relay_skips_bad_datagrams() {
+        let quotes = (1..=50).map(|ts| test_quote("AMD", ts)).collect();
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .with_quotes(quotes)
+            .with_interval_millis(10)
+            .start()
+            .unwrap();
+        let config = UpstreamConfig {
+            addr: server.addr().to_string(),
+            tickers: vec!["AMD".to_string()],
+            token: None,
+        };
+        let mut relay = UpstreamRelay::connect(&config).unwrap();
+        let relay_addr = relay
+            .upstream
+            .as_ref()
+            .unwrap()
+            .socket
+            .local_addr()
+            .unwrap();
+        let garbage = UdpSocket::bind("127.0.0.1:0").unwrap();
+        garbage.send_to(&[u8::MAX; 8], relay_addr).unwrap();
+
+        let received = poll_until(&mut relay, |_, quotes| quotes.len() == 50);
+        assert_eq!(relay.bad_datagrams(), 1);
+        assert!(relay.upstream.is_some());
+        assert_eq!(received.len(), 50);
+        assert_eq!(received.last().unwrap().timestamp, 50);
+    }
+
+    #[test]
+    fn test_relay_reconnects() {
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD"])
+            .with_quotes(vec![test_quote("AMD", 1)])
+            .start()
+            .unwrap();
+        let config = UpstreamConfig {
+            addr: server.addr().to_string(),
+            tickers: vec!["AMD".to_string()],
+            token: None,
+        };
+        let mut relay = UpstreamRelay::connect(&config)
+            .unwrap()
+            .with_reconnect_policy(ReconnectPolicy {
+                initial_delay_millis: 10,
+                max_delay_millis: 10,
+                max_retries: None,
+            });
+        poll_until(&mut relay, |_, quotes| !quotes.is_empty());
+
+        let relay_addr = relay
+            .upstream
+            .as_ref()
+            .unwrap()
+            .socket
+            .local_addr()
+            .unwrap();
+        let upstream = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let shutdown = postcard::to_stdvec(&Message::Shutdown).unwrap();
+        upstream.send_to(&shutdown, relay_addr).unwrap();
+        poll_until(&mut relay, |relay, _| relay.upstream.is_none());
+        assert!(relay.upstream.is_none());
+
+        let received = poll_until(&mut relay, |_, quotes| !quotes.is_empty());
+        assert_eq!(
+            server.wait_subscriptions(2, Duration::from_secs(1)).len(),
+            2
+        );
+        assert!(relay.upstream.is_some());
+        assert_eq!(received[0].timestamp, 1);
+        assert_eq!(relay.tickers(), vec!["AMD".to_string()]);
+    }
+}
@@ -0,0 +1,214 @@
+use super::quotes_server::{ControlCmd, cmd_from_channel};
+use super::recorder::{INDEX_FILE_NAME, RecordedQuote, SegmentInfo};
+use crate::quote::QuoteCallback;
+use crate::stats::LoopStats;
+use crate::timer::Timer;
+use anyhow::{Result, bail};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Instant;
+
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const PLAY_MILLIS: u64 = 10;
+
+const WAIT_CMD_EVENT: &str = "cmd";
+const PLAY_EVENT: &str = "play";
+
+/// Последовательное чтение котировок, записанных `QuoteRecorder`.
+/// Сегменты читаются в порядке индекса
+pub struct QuoteTape {
+    dir: PathBuf,
+    segments: VecDeque<SegmentInfo>,
+    lines: Option<Lines<BufReader<File>>>,
+}
+
+impl QuoteTape {
+    /// Открывает запись в каталоге dir
+    pub fn open(dir: &Path) -> Result<Self> {
+        let index = std::fs::read_to_string(dir.join(INDEX_FILE_NAME))?;
+        let mut segments = VecDeque::new();
+        for line in index.lines().filter(|line| !line.trim().is_empty()) {
+            segments.push_back(serde_json::from_str::<SegmentInfo>(line)?);
+        }
+        if segments.is_empty() {
+            bail!("Record in {} is empty", dir.display());
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segments,
+            lines: None,
+        })
+    }
+
+    /// Следующая котировка записи или None, если запись закончилась
+    pub fn next_quote(&mut self) -> Result<Option<RecordedQuote>> {
+        loop {
+            if let Some(lines) = self.lines.as_mut() {
+                match lines.next() {
+                    Some(line) => {
+                        let line = line?;
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        return Ok(Some(serde_json::from_str(&line)?));
+                    }
+                    None => self.lines = None,
+                }
+            }
+
+            let Some(segment) = self.segments.pop_front() else {
+                return Ok(None);
+            };
+            let file = File::open(self.dir.join(&segment.file))?;
+            self.lines = Some(BufReader::new(file).lines());
+        }
+    }
+}
+
+pub(super) struct ReplayPlayerControl {
+    pub(super) tx: mpsc::Sender<ControlCmd>,
+    pub(super) thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+/// Воспроизводит запись с исходными интервалами между котировками,
+/// ускоренными в speed раз, и передает котировки обработчикам
+pub(super) struct ReplayPlayer {
+    tape: QuoteTape,
+    speed: f64,
+    callbacks: Vec<Box<dyn QuoteCallback>>,
+}
+
+impl ReplayPlayer {
+    pub(super) fn new(
+        dir: &Path,
+        speed: f64,
+        callbacks: Vec<Box<dyn QuoteCallback>>,
+    ) -> Result<Self> {
+        if speed <= 0.0 {
+            bail!("Replay speed must be positive: {speed}");
+        }
+        let tape = QuoteTape::open(dir)?;
+        log::info!("Replay quotes from {} with speed x{speed}", dir.display());
+        Ok(Self {
+            tape,
+            speed,
+            callbacks,
+        })
+    }
+
+    pub(super) fn start(mut self, loop_stats: Arc<LoopStats>) -> ReplayPlayerControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::with_stats(loop_stats);
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(PLAY_EVENT, PLAY_MILLIS);
+
+            let started_at = Instant::now();
+            let mut next = self.tape.next_quote()?;
+            let first_unix_millis = next.as_ref().map_or(0, |record| record.unix_millis);
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    if let ControlCmd::Stop = cmd_from_channel(&rx) {
+                        break;
+                    }
+                }
+
+                if next.is_some() && timer.is_expired_event(PLAY_EVENT)? {
+                    timer.reset_event(PLAY_EVENT)?;
+                    let elapsed_millis = started_at.elapsed().as_millis() as f64 * self.speed;
+                    while let Some(record) = next.as_ref() {
+                        let offset_millis = record.unix_millis.saturating_sub(first_unix_millis);
+                        if offset_millis as f64 > elapsed_millis {
+                            break;
+                        }
+                        for callback in self.callbacks.iter_mut() {
+                            callback.on_quote(&record.quote);
+                        }
+                        next = self.tape.next_quote()?;
+                    }
+                    if next.is_none() {
+                        log::info!("Replay is finished");
+                    }
+                }
+            }
+
+            log::info!("Replay player is stopped");
+            Ok(())
+        });
+        ReplayPlayerControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cache::LastValueCache;
+    use super::super::recorder::QuoteRecorder;
+    use super::*;
+    use crate::quote::{QuoteCallback, StockQuote};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_tape_reads_all_segments() {
+        let dir = tempdir().unwrap();
+        let mut recorder = QuoteRecorder::new(dir.path(), 2).unwrap();
+        for timestamp in 1..=5 {
+            recorder.on_quote(&StockQuote {
+                ticker: "AMD".to_string(),
+                price: 1.0,
+                volume: 10,
+                timestamp,
+            });
+        }
+        drop(recorder);
+
+        let mut tape = QuoteTape::open(dir.path()).unwrap();
+        let mut timestamps = Vec::new();
+        while let Some(record) = tape.next_quote().unwrap() {
+            timestamps.push(record.quote.timestamp);
+        }
+        assert_eq!(timestamps, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_player_feeds_callbacks() {
+        let dir = tempdir().unwrap();
+        let mut recorder = QuoteRecorder::new(dir.path(), 10).unwrap();
+        for (ticker, timestamp) in [("AMD", 1), ("INT", 2), ("AMD", 3)] {
+            recorder.on_quote(&StockQuote {
+                ticker: ticker.to_string(),
+                price: 1.0,
+                volume: 10,
+                timestamp,
+            });
+        }
+        drop(recorder);
+
+        let cache = LastValueCache::default();
+        let player = ReplayPlayer::new(dir.path(), 1000.0, vec![Box::new(cache.clone())]).unwrap();
+        let control = player.start(Arc::new(LoopStats::default()));
+        let tickers = vec!["AMD".to_string(), "INT".to_string()];
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while cache.get(&tickers).len() < 2 && Instant::now() < deadline {
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        control.tx.send(ControlCmd::Stop).unwrap();
+        control.thread_handle.join().unwrap().unwrap();
+
+        let timestamps: Vec<u64> = cache
+            .get(&tickers)
+            .iter()
+            .map(|quote| quote.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![3, 2]);
+    }
+}
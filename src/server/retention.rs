@@ -0,0 +1,125 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct RetainedSubscription {
+    tickers: Vec<String>,
+    expires_at: Instant,
+}
+
+/// Подписки отключившихся клиентов, которые хранятся в течение grace периода
+/// и восстанавливаются при повторном подключении с тем же токеном сессии
+pub(super) struct RetainedSubscriptions {
+    grace: Duration,
+    subscriptions: Mutex<HashMap<String, RetainedSubscription>>,
+}
+
+impl RetainedSubscriptions {
+    pub(super) fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            subscriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(super) fn retain(&self, session: String, tickers: Vec<String>) {
+        if self.grace.is_zero() {
+            return;
+        }
+        log::debug!("Retain subscription of session {session}");
+        self.subscriptions.lock().unwrap().insert(
+            session,
+            RetainedSubscription {
+                tickers,
+                expires_at: Instant::now() + self.grace,
+            },
+        );
+    }
+
+    pub(super) fn take(&self, session: &str) -> Option<Vec<String>> {
+        let subscription = self.subscriptions.lock().unwrap().remove(session)?;
+        if subscription.expires_at < Instant::now() {
+            return None;
+        }
+        Some(subscription.tickers)
+    }
+
+    /// Загружает подписки, сохраненные до перезапуска сервера. Срок их хранения
+    /// отсчитывается заново. Если файла нет, ничего не загружается
+    pub(super) fn restore(&self, path: &Path) -> Result<usize> {
+        if !path.exists() {
+            return Ok(0);
+        }
+        let json_str = std::fs::read_to_string(path)?;
+        let saved: HashMap<String, Vec<String>> = serde_json::from_str(&json_str)?;
+        let count = saved.len();
+        for (session, tickers) in saved {
+            self.retain(session, tickers);
+        }
+        Ok(count)
+    }
+
+    /// Сохраняет неистекшие подписки в файл для восстановления после перезапуска
+    pub(super) fn persist(&self, path: &Path) -> Result<usize> {
+        self.purge_expired();
+        let saved: HashMap<String, Vec<String>> = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(session, subscription)| (session.clone(), subscription.tickers.clone()))
+            .collect();
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string(&saved)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(saved.len())
+    }
+
+    pub(super) fn purge_expired(&self) {
+        let now = Instant::now();
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .retain(|_, subscription| subscription.expires_at >= now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retained_subscriptions() {
+        let retained = RetainedSubscriptions::new(Duration::from_secs(60));
+        retained.retain("abc".to_string(), vec!["AMD".to_string()]);
+        assert!(retained.take("xyz").is_none());
+        assert_eq!(retained.take("abc"), Some(vec!["AMD".to_string()]));
+        assert!(retained.take("abc").is_none());
+
+        let retained = RetainedSubscriptions::new(Duration::ZERO);
+        retained.retain("abc".to_string(), vec!["AMD".to_string()]);
+        assert!(retained.take("abc").is_none());
+    }
+
+    #[test]
+    fn test_persist_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sessions.json");
+        let retained = RetainedSubscriptions::new(Duration::from_secs(60));
+        assert_eq!(retained.restore(&path).unwrap(), 0);
+        retained.retain(
+            "abc".to_string(),
+            vec!["AMD".to_string(), "INT".to_string()],
+        );
+        assert_eq!(retained.persist(&path).unwrap(), 1);
+
+        let restored = RetainedSubscriptions::new(Duration::from_secs(60));
+        assert_eq!(restored.restore(&path).unwrap(), 1);
+        assert_eq!(
+            restored.take("abc"),
+            Some(vec!["AMD".to_string(), "INT".to_string()])
+        );
+    }
+}
@@ -0,0 +1,754 @@
+use super::bandwidth::{BandwidthShare, ClientCap};
+use super::config::BackpressurePolicy;
+use crate::protocol::{BarRespMessage, DeltaFilter, DeltaState, Message, QuoteRespMessage};
+use crate::quote::{Bar, StockQuote};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_WOULD_BLOCK_RETRIES: u32 = 3;
+const MAX_DIRECT_QUEUE_LEN: usize = 1024;
+
+#[derive(Default)]
+pub(super) struct FlushReport {
+    pub(super) sent: u64,
+    pub(super) bytes: u64,
+    pub(super) dropped: u64,
+    pub(super) error: Option<anyhow::Error>,
+    pub(super) blocked: bool,
+    pub(super) throttled: bool,
+    pub(super) capped: bool,
+    pub(super) disconnect: bool,
+}
+
+/// Канал доставки сообщений потока котировок клиенту
+pub(super) trait Transport {
+    /// Кодирует сообщение для отправки клиенту
+    fn encode(&self, msg: &Message) -> Result<Vec<u8>>;
+
+    /// Отправляет закодированное сообщение. Ошибка WouldBlock — буфер отправки заполнен
+    fn send(&mut self, data: Vec<u8>) -> Result<usize>;
+
+    /// Клиенту есть куда отправлять сообщения
+    fn is_ready(&self) -> bool;
+
+    /// Порты клиента, между которыми распределяются датаграммы
+    fn set_ports(&mut self, _ports: Vec<u16>) {}
+
+    /// Сокет, на который клиент присылает пинги. None — живость клиента
+    /// следит управляющее соединение
+    fn ping_socket(&self) -> Option<&UdpSocket> {
+        None
+    }
+
+    /// Уведомляет клиента об остановке сервера, если управляющее соединение этого не делает
+    fn send_shutdown(&mut self) {}
+}
+
+/// Доставка датаграммами udp, распределяемыми по портам клиента
+pub(super) struct UdpTransport {
+    socket: UdpSocket,
+    client_ip_addr: IpAddr,
+    ports: Vec<u16>,
+    next_port_idx: usize,
+}
+
+impl UdpTransport {
+    pub(super) fn new(socket: UdpSocket, client_ip_addr: IpAddr) -> Result<Self> {
+        Ok(Self {
+            client_ip_addr: peer_ip(client_ip_addr, socket.local_addr()?),
+            socket,
+            ports: Vec::new(),
+            next_port_idx: 0,
+        })
+    }
+
+    fn client_addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.ports
+            .iter()
+            .map(|port| SocketAddr::new(self.client_ip_addr, *port))
+    }
+}
+
+impl Transport for UdpTransport {
+    fn encode(&self, msg: &Message) -> Result<Vec<u8>> {
+        Ok(postcard::to_stdvec(msg)?)
+    }
+
+    fn send(&mut self, data: Vec<u8>) -> Result<usize> {
+        let port = self.ports[self.next_port_idx];
+        let len = self
+            .socket
+            .send_to(&data, SocketAddr::new(self.client_ip_addr, port))?;
+        self.next_port_idx = (self.next_port_idx + 1) % self.ports.len();
+        Ok(len)
+    }
+
+    fn is_ready(&self) -> bool {
+        !self.ports.is_empty()
+    }
+
+    fn set_ports(&mut self, ports: Vec<u16>) {
+        self.ports = ports;
+        self.next_port_idx = 0;
+    }
+
+    fn ping_socket(&self) -> Option<&UdpSocket> {
+        Some(&self.socket)
+    }
+
+    fn send_shutdown(&mut self) {
+        let bin_msg = match postcard::to_stdvec(&Message::Shutdown) {
+            Ok(val) => val,
+            Err(e) => {
+                log::error!("Can't serialize shutdown message: {e}");
+                return;
+            }
+        };
+        for addr in self.client_addrs() {
+            if let Err(e) = self.socket.send_to(&bin_msg, addr) {
+                log::warn!("Can't send shutdown message to {addr}: {e}");
+            }
+        }
+    }
+}
+
+pub(super) struct QuotesSender {
+    policy: BackpressurePolicy,
+    queue: VecDeque<StockQuote>,
+    direct: VecDeque<Message>,
+    failures: u32,
+    conflation: Option<Duration>,
+    conflated: HashMap<String, StockQuote>,
+    last_enqueued: HashMap<String, Instant>,
+    filter: Option<DeltaFilter>,
+    delta_states: HashMap<String, DeltaState>,
+    bandwidth: Option<BandwidthShare>,
+    client_cap: Option<ClientCap>,
+    bytes_sent: u64,
+    next_seq: HashMap<String, u64>,
+    throttled: bool,
+    degraded: bool,
+    degraded_dropped: u64,
+}
+
+/// Адрес клиента в семействе адресов сокета local_addr.
+/// Dual-stack сокет отправляет на IPv4 адреса в виде IPv4-mapped IPv6
+fn peer_ip(client_ip_addr: IpAddr, local_addr: SocketAddr) -> IpAddr {
+    match (client_ip_addr, local_addr) {
+        (IpAddr::V4(ip), SocketAddr::V6(_)) => IpAddr::V6(ip.to_ipv6_mapped()),
+        (IpAddr::V6(ip), SocketAddr::V4(_)) => ip.to_canonical(),
+        (ip, _) => ip,
+    }
+}
+
+fn is_would_block(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::WouldBlock)
+}
+
+impl QuotesSender {
+    pub(super) fn new(policy: BackpressurePolicy) -> Self {
+        Self {
+            policy,
+            queue: VecDeque::new(),
+            direct: VecDeque::new(),
+            failures: 0,
+            conflation: None,
+            conflated: HashMap::new(),
+            last_enqueued: HashMap::new(),
+            filter: None,
+            delta_states: HashMap::new(),
+            bandwidth: None,
+            client_cap: None,
+            bytes_sent: 0,
+            next_seq: HashMap::new(),
+            throttled: false,
+            degraded: false,
+            degraded_dropped: 0,
+        }
+    }
+
+    pub(super) fn set_filter(&mut self, filter: Option<DeltaFilter>) {
+        self.filter = filter;
+    }
+
+    /// Не чаще одной котировки по тикеру за interval. Промежуточные котировки
+    /// заменяются последней
+    pub(super) fn with_conflation(mut self, interval: Duration) -> Self {
+        if !interval.is_zero() {
+            self.conflation = Some(interval);
+        }
+        self
+    }
+
+    /// Отправлять котировки в пределах доли общего лимита трафика.
+    /// Пока доля исчерпана, котировки в очереди заменяются последними по тикеру
+    pub(super) fn with_bandwidth(mut self, share: BandwidthShare) -> Self {
+        self.bandwidth = Some(share);
+        self
+    }
+
+    /// Отправлять клиенту не больше bytes_per_sec байт в секунду независимо от общего лимита.
+    /// Пока лимит исчерпан, котировки в очереди заменяются последними по тикеру
+    pub(super) fn with_client_cap(mut self, bytes_per_sec: u64) -> Self {
+        if bytes_per_sec > 0 {
+            self.client_cap = Some(ClientCap::new(bytes_per_sec));
+        }
+        self
+    }
+
+    /// Количество байт, отправленных клиенту
+    pub(super) fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Режим перегрузки сервера: котировки в очереди заменяются последними по тикеру
+    pub(super) fn set_degraded(&mut self, degraded: bool) {
+        self.degraded = degraded;
+    }
+
+    pub(super) fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Количество котировок, замененных в режиме перегрузки с прошлого вызова
+    pub(super) fn take_degraded_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.degraded_dropped)
+    }
+
+    /// Ставит котировку в очередь отправки. Возвращает количество выброшенных котировок
+    pub(super) fn push(&mut self, quote: StockQuote) -> u64 {
+        self.push_at(quote, Instant::now())
+    }
+
+    fn push_at(&mut self, quote: StockQuote, now: Instant) -> u64 {
+        if let Some(filter) = self.filter.as_ref() {
+            let state = self.delta_states.entry(quote.ticker.clone()).or_default();
+            if !filter.passes(state, &quote) {
+                return 0;
+            }
+        }
+        let Some(interval) = self.conflation else {
+            return self.enqueue(quote);
+        };
+        let superseded = self.conflated.remove(&quote.ticker).map_or(0, |_| 1);
+        let is_due = self
+            .last_enqueued
+            .get(&quote.ticker)
+            .is_none_or(|at| now.duration_since(*at) >= interval);
+        if !is_due {
+            self.conflated.insert(quote.ticker.clone(), quote);
+            return superseded;
+        }
+        self.last_enqueued.insert(quote.ticker.clone(), now);
+        superseded + self.enqueue(quote)
+    }
+
+    fn release_conflated(&mut self, now: Instant) -> u64 {
+        let Some(interval) = self.conflation else {
+            return 0;
+        };
+        let due: Vec<String> = self
+            .conflated
+            .keys()
+            .filter(|ticker| {
+                self.last_enqueued
+                    .get(*ticker)
+                    .is_none_or(|at| now.duration_since(*at) >= interval)
+            })
+            .cloned()
+            .collect();
+        let mut dropped = 0;
+        for ticker in due {
+            if let Some(quote) = self.conflated.remove(&ticker) {
+                self.last_enqueued.insert(ticker, now);
+                dropped += self.enqueue(quote);
+            }
+        }
+        dropped
+    }
+
+    fn enqueue(&mut self, quote: StockQuote) -> u64 {
+        if self.throttled || self.degraded {
+            let pending = self
+                .queue
+                .iter_mut()
+                .find(|pending| pending.ticker == quote.ticker);
+            if let Some(pending) = pending {
+                *pending = quote;
+                if !self.throttled {
+                    self.degraded_dropped += 1;
+                }
+                return 1;
+            }
+        }
+        match self.policy {
+            BackpressurePolicy::DropOldest { queue_len } => {
+                self.queue.push_back(quote);
+                let mut dropped = 0;
+                while self.queue.len() > queue_len.max(1) {
+                    self.queue.pop_front();
+                    dropped += 1;
+                }
+                dropped
+            }
+            BackpressurePolicy::ConflateLatest => {
+                match self
+                    .queue
+                    .iter_mut()
+                    .find(|pending| pending.ticker == quote.ticker)
+                {
+                    Some(pending) => {
+                        *pending = quote;
+                        1
+                    }
+                    None => {
+                        self.queue.push_back(quote);
+                        0
+                    }
+                }
+            }
+            BackpressurePolicy::Disconnect { .. } => {
+                self.queue.push_back(quote);
+                0
+            }
+        }
+    }
+
+    /// Отправляет котировку. None — лимит клиента или его доля общего лимита трафика исчерпаны
+    fn send<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        quote: &StockQuote,
+        report: &mut FlushReport,
+    ) -> Result<Option<usize>> {
+        let seq = self.next_seq.get(&quote.ticker).copied().unwrap_or(1);
+        let msg = Message::Quote(QuoteRespMessage {
+            quote: quote.clone(),
+            seq,
+        });
+        let len = self.send_message(transport, &msg, report)?;
+        if len.is_some() {
+            self.next_seq.insert(quote.ticker.clone(), seq + 1);
+        }
+        Ok(len)
+    }
+
+    /// Отправляет сообщение в пределах лимита клиента и его доли общего лимита трафика
+    fn send_message<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        msg: &Message,
+        report: &mut FlushReport,
+    ) -> Result<Option<usize>> {
+        let bin_msg = transport.encode(msg)?;
+        let now = Instant::now();
+        if self
+            .client_cap
+            .as_mut()
+            .is_some_and(|cap| !cap.allows(bin_msg.len(), now))
+        {
+            report.capped = true;
+            return Ok(None);
+        }
+        if self
+            .bandwidth
+            .as_ref()
+            .is_some_and(|share| !share.try_consume(bin_msg.len()))
+        {
+            return Ok(None);
+        }
+        let len = transport.send(bin_msg)?;
+        if let Some(cap) = self.client_cap.as_mut() {
+            cap.consume(len);
+        }
+        self.bytes_sent += len as u64;
+        Ok(Some(len))
+    }
+
+    fn send_failed(&mut self, report: &mut FlushReport) {
+        if let BackpressurePolicy::Disconnect { max_failures } = self.policy {
+            self.queue.pop_front();
+            self.failures += 1;
+            report.dropped += 1;
+            report.disconnect = self.failures >= max_failures;
+        }
+    }
+
+    /// Ставит свечу в очередь отправки перед котировками. Возвращает количество
+    /// выброшенных сообщений
+    pub(super) fn push_bar(&mut self, bar: Bar) -> u64 {
+        self.push_direct(Message::Bar(BarRespMessage { bar }))
+    }
+
+    /// Ставит котировку из истории в очередь отправки перед котировками.
+    /// Возвращает количество выброшенных сообщений
+    pub(super) fn push_replay(&mut self, quote: StockQuote) -> u64 {
+        self.push_direct(Message::ReplayQuote(QuoteRespMessage { quote, seq: 0 }))
+    }
+
+    fn push_direct(&mut self, msg: Message) -> u64 {
+        self.direct.push_back(msg);
+        let mut dropped = 0;
+        while self.direct.len() > MAX_DIRECT_QUEUE_LEN {
+            self.direct.pop_front();
+            dropped += 1;
+        }
+        dropped
+    }
+
+    /// Отправляет свечи и котировки из истории. false — отправка прервана,
+    /// котировки из очереди ждут следующего вызова
+    fn flush_direct<T: Transport>(&mut self, transport: &mut T, report: &mut FlushReport) -> bool {
+        let mut retries = 0;
+        while let Some(msg) = self.direct.pop_front() {
+            match self.send_message(transport, &msg, report) {
+                Ok(None) => {
+                    self.direct.push_front(msg);
+                    report.throttled = true;
+                    return false;
+                }
+                Ok(Some(len)) => {
+                    report.sent += 1;
+                    report.bytes += len as u64;
+                }
+                Err(e) if is_would_block(&e) => {
+                    self.direct.push_front(msg);
+                    if retries < MAX_WOULD_BLOCK_RETRIES {
+                        retries += 1;
+                        thread::yield_now();
+                        continue;
+                    }
+                    report.blocked = true;
+                    return false;
+                }
+                Err(e) => {
+                    report.dropped += 1;
+                    report.error = Some(e);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Отправляет котировки из очереди, пока отправка не завершится ошибкой.
+    /// Если буфер сокета заполнен, отправка повторяется MAX_WOULD_BLOCK_RETRIES раз,
+    /// после чего оставшиеся котировки ждут следующего вызова
+    pub(super) fn flush<T: Transport>(&mut self, transport: &mut T) -> FlushReport {
+        let mut report = FlushReport::default();
+        if !transport.is_ready() {
+            return report;
+        }
+        report.dropped = self.release_conflated(Instant::now());
+        if !self.flush_direct(transport, &mut report) {
+            self.throttled = report.throttled;
+            return report;
+        }
+
+        let mut retries = 0;
+        while let Some(quote) = self.queue.front().cloned() {
+            match self.send(transport, &quote, &mut report) {
+                Ok(None) => {
+                    report.throttled = true;
+                    break;
+                }
+                Ok(Some(len)) => {
+                    self.queue.pop_front();
+                    self.delta_states
+                        .entry(quote.ticker)
+                        .or_default()
+                        .sent(quote.price);
+                    self.failures = 0;
+                    report.sent += 1;
+                    report.bytes += len as u64;
+                }
+                Err(e) if is_would_block(&e) => {
+                    if retries < MAX_WOULD_BLOCK_RETRIES {
+                        retries += 1;
+                        thread::yield_now();
+                        continue;
+                    }
+                    self.send_failed(&mut report);
+                    report.blocked = true;
+                    break;
+                }
+                Err(e) => {
+                    self.send_failed(&mut report);
+                    report.error = Some(e);
+                    break;
+                }
+            }
+        }
+        self.throttled = report.throttled;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::bandwidth::BandwidthBudget;
+    use std::sync::Arc;
+
+    fn quote(ticker: &str, timestamp: u64) -> StockQuote {
+        StockQuote {
+            ticker: ticker.to_string(),
+            price: 1.0,
+            volume: 1,
+            timestamp,
+        }
+    }
+
+    fn local_addr() -> SocketAddr {
+        "127.0.0.1:34254".parse().unwrap()
+    }
+
+    #[derive(Default)]
+    struct Collected(Vec<Message>);
+
+    impl Collected {
+        fn timestamps(&self) -> Vec<u64> {
+            self.0
+                .iter()
+                .filter_map(|msg| match msg {
+                    Message::Quote(resp) => Some(resp.quote.timestamp),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    impl Transport for Collected {
+        fn encode(&self, msg: &Message) -> Result<Vec<u8>> {
+            Ok(postcard::to_stdvec(msg)?)
+        }
+
+        fn send(&mut self, data: Vec<u8>) -> Result<usize> {
+            self.0.push(postcard::from_bytes(&data)?);
+            Ok(data.len())
+        }
+
+        fn is_ready(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::DropOldest { queue_len: 2 });
+        assert_eq!(sender.push(quote("AMD", 1)), 0);
+        assert_eq!(sender.push(quote("AMD", 2)), 0);
+        assert_eq!(sender.push(quote("AMD", 3)), 1);
+        let timestamps: Vec<u64> = sender.queue.iter().map(|quote| quote.timestamp).collect();
+        assert_eq!(timestamps, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_conflate_latest() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::ConflateLatest);
+        assert_eq!(sender.push(quote("AMD", 1)), 0);
+        assert_eq!(sender.push(quote("INT", 2)), 0);
+        assert_eq!(sender.push(quote("AMD", 3)), 1);
+        let timestamps: Vec<u64> = sender.queue.iter().map(|quote| quote.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 2]);
+    }
+
+    #[test]
+    fn test_peer_ip() {
+        let v4 = IpAddr::from([127, 0, 0, 1]);
+        let mapped: IpAddr = "::ffff:127.0.0.1".parse().unwrap();
+        let v6: IpAddr = "::1".parse().unwrap();
+        let dual_stack: SocketAddr = "[::]:34254".parse().unwrap();
+
+        assert_eq!(peer_ip(v4, dual_stack), mapped);
+        assert_eq!(peer_ip(v6, dual_stack), v6);
+        assert_eq!(peer_ip(mapped, local_addr()), v4);
+        assert_eq!(peer_ip(v4, local_addr()), v4);
+    }
+
+    #[test]
+    fn test_conflation() {
+        let interval = Duration::from_millis(100);
+        let mut sender = QuotesSender::new(BackpressurePolicy::default()).with_conflation(interval);
+        let start = Instant::now();
+
+        assert_eq!(sender.push_at(quote("AMD", 1), start), 0);
+        assert_eq!(sender.push_at(quote("AMD", 2), start), 0);
+        assert_eq!(sender.push_at(quote("AMD", 3), start), 1);
+        assert_eq!(sender.push_at(quote("INT", 4), start), 0);
+        let timestamps: Vec<u64> = sender.queue.iter().map(|quote| quote.timestamp).collect();
+        assert_eq!(timestamps, vec![1, 4]);
+
+        assert_eq!(sender.release_conflated(start + interval / 2), 0);
+        assert_eq!(sender.queue.len(), 2);
+        assert_eq!(sender.release_conflated(start + interval), 0);
+        let timestamps: Vec<u64> = sender.queue.iter().map(|quote| quote.timestamp).collect();
+        assert_eq!(timestamps, vec![1, 4, 3]);
+        assert!(sender.conflated.is_empty());
+    }
+
+    #[test]
+    fn test_throttled_conflation() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::default());
+        assert_eq!(sender.push(quote("AMD", 1)), 0);
+        assert_eq!(sender.push(quote("AMD", 2)), 0);
+
+        sender.throttled = true;
+        assert_eq!(sender.push(quote("AMD", 3)), 1);
+        assert_eq!(sender.push(quote("INT", 4)), 0);
+        let timestamps: Vec<u64> = sender.queue.iter().map(|quote| quote.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 2, 4]);
+    }
+
+    #[test]
+    fn test_degraded_conflation() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::default());
+        sender.set_degraded(true);
+        assert_eq!(sender.push(quote("AMD", 1)), 0);
+        assert_eq!(sender.push(quote("INT", 2)), 0);
+        assert_eq!(sender.push(quote("AMD", 3)), 1);
+        let timestamps: Vec<u64> = sender.queue.iter().map(|quote| quote.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 2]);
+        assert_eq!(sender.take_degraded_dropped(), 1);
+        assert_eq!(sender.take_degraded_dropped(), 0);
+    }
+
+    #[test]
+    fn test_delta_filter_accumulates_volume() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::default());
+        sender.set_filter(Some(DeltaFilter {
+            min_price_change_percent: None,
+            min_volume: Some(3),
+        }));
+        let mut transport = Collected::default();
+        for timestamp in 1..=6 {
+            sender.push(quote("AMD", timestamp));
+            sender.flush(&mut transport);
+        }
+        assert_eq!(transport.timestamps(), vec![1, 4]);
+    }
+
+    fn replay_len() -> usize {
+        postcard::to_stdvec(&Message::ReplayQuote(QuoteRespMessage {
+            quote: quote("AMD", 1),
+            seq: 0,
+        }))
+        .unwrap()
+        .len()
+    }
+
+    #[test]
+    fn test_replay_client_cap() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::default())
+            .with_client_cap(2 * replay_len() as u64);
+        let mut transport = Collected::default();
+        for timestamp in 1..=3 {
+            assert_eq!(sender.push_replay(quote("AMD", timestamp)), 0);
+        }
+        sender.push(quote("AMD", 4));
+
+        let report = sender.flush(&mut transport);
+        assert_eq!(report.sent, 2);
+        assert!(report.capped);
+        assert!(report.throttled);
+        assert_eq!(sender.direct.len(), 1);
+        assert_eq!(sender.queue.len(), 1);
+        let replayed: Vec<u64> = transport
+            .0
+            .iter()
+            .map(|msg| match msg {
+                Message::ReplayQuote(resp) => resp.quote.timestamp,
+                msg => panic!("Unexpected message: {msg:?}"),
+            })
+            .collect();
+        assert_eq!(replayed, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_replay_bandwidth() {
+        let budget = Arc::new(BandwidthBudget::new(replay_len() as u64));
+        let mut sender =
+            QuotesSender::new(BackpressurePolicy::default()).with_bandwidth(budget.share());
+        let mut transport = Collected::default();
+        sender.push_replay(quote("AMD", 1));
+        sender.push_replay(quote("AMD", 2));
+        sender.push(quote("AMD", 3));
+
+        let report = sender.flush(&mut transport);
+        assert_eq!(report.sent, 1);
+        assert!(report.throttled);
+        assert!(!report.capped);
+        assert_eq!(transport.0.len(), 1);
+        assert!(transport.timestamps().is_empty());
+    }
+
+    #[test]
+    fn test_bars_client_cap() {
+        let bar = Bar::open(&quote("AMD", 1), 0, 1000);
+        let bar_len = postcard::to_stdvec(&Message::Bar(BarRespMessage { bar: bar.clone() }))
+            .unwrap()
+            .len();
+        let mut sender =
+            QuotesSender::new(BackpressurePolicy::default()).with_client_cap(bar_len as u64);
+        let mut transport = Collected::default();
+        assert_eq!(sender.push_bar(bar.clone()), 0);
+        assert_eq!(sender.push_bar(bar), 0);
+
+        let report = sender.flush(&mut transport);
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.bytes, bar_len as u64);
+        assert!(report.capped);
+        assert!(matches!(transport.0[..], [Message::Bar(_)]));
+        assert_eq!(sender.direct.len(), 1);
+    }
+
+    #[test]
+    fn test_direct_queue_len() {
+        let mut sender = QuotesSender::new(BackpressurePolicy::default());
+        for timestamp in 0..MAX_DIRECT_QUEUE_LEN as u64 {
+            assert_eq!(sender.push_replay(quote("AMD", timestamp)), 0);
+        }
+        assert_eq!(sender.push_replay(quote("AMD", 0)), 1);
+        assert_eq!(sender.direct.len(), MAX_DIRECT_QUEUE_LEN);
+    }
+
+    #[test]
+    fn test_client_cap() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let len = postcard::to_stdvec(&Message::Quote(QuoteRespMessage {
+            quote: quote("AMD", 1),
+            seq: 1,
+        }))
+        .unwrap()
+        .len();
+        let mut sender =
+            QuotesSender::new(BackpressurePolicy::default()).with_client_cap(2 * len as u64);
+        let mut transport = UdpTransport::new(socket, IpAddr::from([127, 0, 0, 1])).unwrap();
+        transport.set_ports(vec![receiver.local_addr().unwrap().port()]);
+        for timestamp in 1..=3 {
+            sender.push(quote("AMD", timestamp));
+        }
+
+        let report = sender.flush(&mut transport);
+        assert_eq!(report.sent, 2);
+        assert!(report.capped);
+        assert!(report.throttled);
+        assert_eq!(sender.bytes_sent(), 2 * len as u64);
+        let mut recv_buf = [0u8; 128];
+        for expected_seq in 1..=2 {
+            let len = receiver.recv(&mut recv_buf).unwrap();
+            match postcard::from_bytes::<Message>(&recv_buf[..len]).unwrap() {
+                Message::Quote(resp) => assert_eq!(resp.seq, expected_seq),
+                msg => panic!("Unexpected message: {msg:?}"),
+            }
+        }
+        assert_eq!(sender.push(quote("AMD", 4)), 1);
+        assert_eq!(sender.queue.len(), 1);
+    }
+}
@@ -0,0 +1,216 @@
+use super::quotes_server::ClientInfo;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+const MAX_SESSION_EVENTS: usize = 1000;
+
+/// Событие в сессии клиента
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// Клиент подключился
+    Connected,
+    /// Получено сообщение по управляющему каналу
+    Message {
+        /// Содержимое сообщения
+        message: String,
+    },
+    /// Изменилась подписка клиента
+    Subscription {
+        /// Тикеры подписки
+        tickers: Vec<String>,
+    },
+    /// Получен пинг от клиента
+    Ping,
+    /// Ошибка при обслуживании клиента
+    Error {
+        /// Описание ошибки
+        description: String,
+    },
+    /// Сессия завершена
+    Closed {
+        /// Причина завершения
+        reason: String,
+    },
+}
+
+/// Событие сервера для приложений, в которые встроен сервер
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    /// Клиент подключился
+    ClientConnected {
+        /// Адрес управляющего соединения клиента
+        addr: SocketAddr,
+    },
+    /// Клиент отключился
+    ClientDisconnected {
+        /// Адрес управляющего соединения клиента
+        addr: SocketAddr,
+        /// Причина отключения
+        reason: String,
+        /// Итоговая статистика клиента
+        summary: ClientInfo,
+    },
+    /// Изменилась подписка клиента
+    SubscriptionChanged {
+        /// Адрес управляющего соединения клиента
+        addr: SocketAddr,
+        /// Тикеры подписки
+        tickers: Vec<String>,
+    },
+    /// Ошибка при обслуживании клиента
+    StreamError {
+        /// Адрес управляющего соединения клиента
+        addr: SocketAddr,
+        /// Описание ошибки
+        description: String,
+    },
+    /// Поток обслуживания клиента аварийно завершился. Клиент отключается,
+    /// остальные клиенты продолжают получать котировки
+    ThreadPanicked {
+        /// Адрес управляющего соединения клиента
+        addr: SocketAddr,
+        /// Подсистема потока
+        thread: String,
+        /// Сообщение паники
+        message: String,
+    },
+}
+
+impl ServerEvent {
+    pub(super) fn from_session(addr: SocketAddr, event: &SessionEvent) -> Option<Self> {
+        match event {
+            SessionEvent::Connected => Some(Self::ClientConnected { addr }),
+            SessionEvent::Subscription { tickers } => Some(Self::SubscriptionChanged {
+                addr,
+                tickers: tickers.clone(),
+            }),
+            SessionEvent::Error { description } => Some(Self::StreamError {
+                addr,
+                description: description.clone(),
+            }),
+            SessionEvent::Message { .. } | SessionEvent::Ping | SessionEvent::Closed { .. } => None,
+        }
+    }
+}
+
+/// Запись журнала сессии
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionEntry {
+    /// Время события от начала сессии, мс
+    pub millis: u64,
+    /// Событие
+    pub event: SessionEvent,
+}
+
+/// Полная запись сессии клиента для разбора проблем
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionTranscript {
+    /// Информация о клиенте и счетчики доставки
+    pub client: ClientInfo,
+    /// События сессии в порядке возникновения
+    pub events: Vec<SessionEntry>,
+    /// Количество ранних событий, не поместившихся в журнал
+    pub dropped_events: u64,
+}
+
+pub(super) struct SessionLog {
+    started: Instant,
+    events: VecDeque<SessionEntry>,
+    dropped_events: u64,
+}
+
+impl SessionLog {
+    pub(super) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            events: VecDeque::new(),
+            dropped_events: 0,
+        }
+    }
+
+    pub(super) fn record(&mut self, event: SessionEvent) {
+        if self.events.len() == MAX_SESSION_EVENTS {
+            self.events.pop_front();
+            self.dropped_events += 1;
+        }
+        self.events.push_back(SessionEntry {
+            millis: self.started.elapsed().as_millis() as u64,
+            event,
+        });
+    }
+
+    pub(super) fn transcript(&self, client: ClientInfo) -> SessionTranscript {
+        SessionTranscript {
+            client,
+            events: self.events.iter().cloned().collect(),
+            dropped_events: self.dropped_events,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_session_log_is_bounded() {
+        let mut log = SessionLog::new();
+        log.record(SessionEvent::Connected);
+        for _ in 0..MAX_SESSION_EVENTS {
+            log.record(SessionEvent::Ping);
+        }
+
+        let transcript = log.transcript(ClientInfo {
+            addr: "127.0.0.1:5000".parse().unwrap(),
+            tickers: Vec::new(),
+            quotes_sent: 0,
+            bytes_sent: 0,
+            send_errors: 0,
+            quotes_dropped: 0,
+            bandwidth_capped: 0,
+            pings: 0,
+            connected: Duration::ZERO,
+        });
+        assert_eq!(transcript.events.len(), MAX_SESSION_EVENTS);
+        assert_eq!(transcript.dropped_events, 1);
+        assert_eq!(transcript.events[0].event, SessionEvent::Ping);
+
+        let json = serde_json::to_value(&transcript).unwrap();
+        assert_eq!(json["events"][0]["event"]["type"], "ping");
+    }
+
+    #[test]
+    fn test_server_event_from_session() {
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        assert_eq!(
+            ServerEvent::from_session(addr, &SessionEvent::Connected),
+            Some(ServerEvent::ClientConnected { addr })
+        );
+        assert_eq!(
+            ServerEvent::from_session(
+                addr,
+                &SessionEvent::Error {
+                    description: "Send quote error".to_string()
+                }
+            ),
+            Some(ServerEvent::StreamError {
+                addr,
+                description: "Send quote error".to_string()
+            })
+        );
+        assert_eq!(
+            ServerEvent::from_session(
+                addr,
+                &SessionEvent::Closed {
+                    reason: "kicked".to_string()
+                }
+            ),
+            None
+        );
+        assert_eq!(ServerEvent::from_session(addr, &SessionEvent::Ping), None);
+    }
+}
@@ -0,0 +1,70 @@
+use super::quotes_server::{ControlCmd, cmd_from_channel};
+use crate::quote::QuoteGenerator;
+use crate::stats::LoopStats;
+use crate::timer::Timer;
+use anyhow::Result;
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const GENERATE_MILLIS: u64 = 1000;
+
+const WAIT_CMD_EVENT: &str = "cmd";
+const GENERATE_EVENT: &str = "generate";
+
+pub(super) struct GeneratorShardControl {
+    pub(super) tx: mpsc::Sender<ControlCmd>,
+    pub(super) thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+/// Часть генератора, которая в своем потоке генерирует котировки по своим тикерам.
+/// Котировки передаются обработчикам генератора
+pub(super) struct GeneratorShard {
+    generator: QuoteGenerator,
+    tickers: Vec<String>,
+    loop_stats: Arc<LoopStats>,
+}
+
+impl GeneratorShard {
+    pub(super) fn new(generator: QuoteGenerator, loop_stats: Arc<LoopStats>) -> Self {
+        let tickers = generator.ticker_names();
+        Self {
+            generator,
+            tickers,
+            loop_stats,
+        }
+    }
+
+    pub(super) fn start(mut self) -> GeneratorShardControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::with_stats(self.loop_stats.clone());
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(GENERATE_EVENT, GENERATE_MILLIS);
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    if let ControlCmd::Stop = cmd_from_channel(&rx) {
+                        break;
+                    }
+                }
+
+                if timer.is_expired_event(GENERATE_EVENT)? {
+                    timer.reset_event(GENERATE_EVENT)?;
+                    for ticker in self.tickers.iter() {
+                        self.generator.generate_quote(ticker);
+                    }
+                }
+            }
+
+            log::info!("Generator shard is stopped");
+            Ok(())
+        });
+        GeneratorShardControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use super::quotes_server::{ControlCmd, cmd_from_channel};
+use crate::quote::{QuoteCallback, QuoteGenerator, StockQuote};
+use crate::stats::LoopStats;
+use crate::timer::Timer;
+use anyhow::Result;
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+const HANDLE_CMD_PERIOD_MILLIS: u64 = 300;
+const GENERATOR_POLL_MILLIS: u64 = 1000;
+
+const WAIT_CMD_EVENT: &str = "cmd";
+const POLL_EVENT: &str = "poll";
+
+/// Источник котировок для сервера: генератор, воспроизведение записи,
+/// подключение к бирже или тестовые данные
+pub trait QuoteSource: Send {
+    /// Тикеры, котировки которых выдает источник. Подписка на другие тикеры отклоняется
+    fn tickers(&self) -> Vec<String>;
+
+    /// Период опроса источника, мс
+    fn poll_millis(&self) -> u64;
+
+    /// Котировки, появившиеся с прошлого опроса. Ошибка останавливает сервер
+    fn poll(&mut self) -> Result<Vec<StockQuote>>;
+}
+
+impl QuoteSource for QuoteGenerator {
+    fn tickers(&self) -> Vec<String> {
+        self.ticker_names()
+    }
+
+    fn poll_millis(&self) -> u64 {
+        GENERATOR_POLL_MILLIS
+    }
+
+    fn poll(&mut self) -> Result<Vec<StockQuote>> {
+        Ok(self
+            .ticker_names()
+            .iter()
+            .filter_map(|ticker| self.generate_quote(ticker))
+            .collect())
+    }
+}
+
+pub(super) struct SourcePollerControl {
+    pub(super) tx: mpsc::Sender<ControlCmd>,
+    pub(super) thread_handle: thread::JoinHandle<Result<()>>,
+}
+
+/// Опрашивает источник котировок в своем потоке и передает котировки обработчикам
+pub(super) struct SourcePoller {
+    source: Box<dyn QuoteSource>,
+    callbacks: Vec<Box<dyn QuoteCallback>>,
+}
+
+impl SourcePoller {
+    pub(super) fn new(
+        source: Box<dyn QuoteSource>,
+        callbacks: Vec<Box<dyn QuoteCallback>>,
+    ) -> Self {
+        Self { source, callbacks }
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        for quote in self.source.poll()? {
+            for callback in self.callbacks.iter_mut() {
+                callback.on_quote(&quote);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn start(mut self, loop_stats: Arc<LoopStats>) -> SourcePollerControl {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut timer = Timer::with_stats(loop_stats);
+            timer.add_event(WAIT_CMD_EVENT, HANDLE_CMD_PERIOD_MILLIS);
+            timer.add_event(POLL_EVENT, self.source.poll_millis());
+
+            loop {
+                timer.sleep();
+                if timer.is_expired_event(WAIT_CMD_EVENT)? {
+                    timer.reset_event(WAIT_CMD_EVENT)?;
+                    if let ControlCmd::Stop = cmd_from_channel(&rx) {
+                        break;
+                    }
+                }
+
+                if timer.is_expired_event(POLL_EVENT)? {
+                    timer.reset_event(POLL_EVENT)?;
+                    self.poll()
+                        .inspect_err(|e| log::error!("Can't poll quote source: {e}"))?;
+                }
+            }
+
+            log::info!("Quote source poller is stopped");
+            Ok(())
+        });
+        SourcePollerControl {
+            tx,
+            thread_handle: handle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::sync::Mutex;
+
+    struct Fixture {
+        batches: Vec<Vec<StockQuote>>,
+    }
+
+    impl QuoteSource for Fixture {
+        fn tickers(&self) -> Vec<String> {
+            vec!["AMD".to_string()]
+        }
+
+        fn poll_millis(&self) -> u64 {
+            10
+        }
+
+        fn poll(&mut self) -> Result<Vec<StockQuote>> {
+            match self.batches.pop() {
+                Some(batch) => Ok(batch),
+                None => bail!("Feed is closed"),
+            }
+        }
+    }
+
+    struct Collect(Arc<Mutex<Vec<u64>>>);
+
+    impl QuoteCallback for Collect {
+        fn on_quote(&mut self, quote: &StockQuote) {
+            self.0.lock().unwrap().push(quote.timestamp);
+        }
+    }
+
+    #[test]
+    fn test_poller_passes_quotes_to_callbacks() {
+        let quote = |timestamp: u64| StockQuote {
+            ticker: "AMD".to_string(),
+            timestamp,
+            ..Default::default()
+        };
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut poller = SourcePoller::new(
+            Box::new(Fixture {
+                batches: vec![vec![quote(3)], vec![quote(1), quote(2)]],
+            }),
+            vec![Box::new(Collect(received.clone()))],
+        );
+
+        poller.poll().unwrap();
+        poller.poll().unwrap();
+        assert!(poller.poll().is_err());
+        assert_eq!(*received.lock().unwrap(), vec![1, 2, 3]);
+    }
+}
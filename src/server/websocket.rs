@@ -0,0 +1,307 @@
+use super::channel::{ControlChannel, RecvError};
+use super::config::TcpSocketOptions;
+use super::quotes_server::{QuotesStream, QuotesStreamControl};
+use super::sender::Transport;
+use crate::protocol::{ErrorCode, Message, SubscriptionMode, TickerReqMessage};
+use crate::quote::StockQuote;
+use crate::tls::ControlStream;
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tungstenite::WebSocket;
+use tungstenite::protocol::WebSocketConfig;
+
+const HANDSHAKE_TIMEOUT_MILLIS: u64 = 1000;
+const MAX_WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Запрос клиента WebSocket. Передается текстовым сообщением JSON
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum WsRequest {
+    /// Подписка на котировки
+    Subscribe {
+        tickers: Vec<String>,
+        #[serde(default)]
+        token: Option<String>,
+        #[serde(default)]
+        mode: SubscriptionMode,
+    },
+}
+
+/// Сообщение сервера клиенту WebSocket. Передается текстовым сообщением JSON
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum WsResponse {
+    /// Подтверждение подписки
+    Subscribed { unknown_tickers: Vec<String> },
+    /// Котировка
+    Quote { quote: StockQuote },
+    /// Котировка из истории, отправленная при подписке до живых котировок
+    Replay { quote: StockQuote },
+    /// Ошибка, после которой сервер закрывает соединение
+    Error { code: ErrorCode },
+    /// Сервер останавливается
+    Shutdown,
+}
+
+/// Сообщение протокола в виде ответа клиенту WebSocket
+fn ws_response(msg: &Message) -> Result<WsResponse> {
+    Ok(match msg {
+        Message::Quote(resp) => WsResponse::Quote {
+            quote: resp.quote.clone(),
+        },
+        Message::ReplayQuote(resp) => WsResponse::Replay {
+            quote: resp.quote.clone(),
+        },
+        Message::Subscribed {
+            unknown_tickers, ..
+        }
+        | Message::SubscriptionChanged {
+            unknown_tickers, ..
+        } => WsResponse::Subscribed {
+            unknown_tickers: unknown_tickers.clone(),
+        },
+        Message::Error { code } => WsResponse::Error { code: *code },
+        Message::Shutdown => WsResponse::Shutdown,
+        msg => bail!("{msg:?} can't be sent over WebSocket"),
+    })
+}
+
+/// Соединение с клиентом WebSocket поверх TCP или TLS
+pub(super) struct WsConnection {
+    socket: WebSocket<ControlStream>,
+}
+
+fn is_would_block(e: &tungstenite::Error) -> bool {
+    matches!(e, tungstenite::Error::Io(e) if e.kind() == ErrorKind::WouldBlock)
+}
+
+impl WsConnection {
+    /// Выполняет рукопожатие WebSocket, после чего соединение становится неблокирующим
+    /// с таймаутами из options
+    pub(super) fn accept(
+        conn: TcpStream,
+        tls: Option<&Arc<rustls::ServerConfig>>,
+        options: &TcpSocketOptions,
+    ) -> Result<Self> {
+        conn.set_read_timeout(Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MILLIS)))?;
+        conn.set_write_timeout(Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MILLIS)))?;
+        let config = WebSocketConfig::default().max_write_buffer_size(MAX_WRITE_BUFFER_SIZE);
+        let socket =
+            tungstenite::accept_with_config(ControlStream::accept(conn, tls)?, Some(config))
+                .map_err(|e| anyhow!("WebSocket handshake failed: {e}"))?;
+        let tcp = socket.get_ref().tcp();
+        options.restore_timeouts(tcp)?;
+        tcp.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    /// Следующий запрос клиента или None, если запросов нет
+    pub(super) fn recv(&mut self) -> Result<Option<WsRequest>> {
+        loop {
+            match self.socket.read() {
+                Ok(tungstenite::Message::Text(text)) => {
+                    return Ok(Some(serde_json::from_str(&text)?));
+                }
+                Ok(tungstenite::Message::Binary(_)) => bail!("Binary messages are not supported"),
+                Ok(tungstenite::Message::Close(_)) => bail!("Connection is closed"),
+                Ok(_) => {}
+                Err(e) if is_would_block(&e) => {
+                    self.flush()?;
+                    return Ok(None);
+                }
+                Err(e) => bail!("{e}"),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.socket.flush() {
+            Err(e) if !is_would_block(&e) => bail!("{e}"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Отправляет текстовое сообщение. Возвращает длину сообщения.
+    /// Ошибка WouldBlock — буфер отправки переполнен и сообщение не принято
+    fn send(&mut self, text: String) -> Result<usize> {
+        let len = text.len();
+        match self.socket.write(tungstenite::Message::text(text)) {
+            Ok(()) => {}
+            Err(tungstenite::Error::WriteBufferFull(_)) => {
+                return Err(std::io::Error::from(ErrorKind::WouldBlock).into());
+            }
+            Err(e) if is_would_block(&e) => {}
+            Err(e) => bail!("{e}"),
+        }
+        self.flush()?;
+        Ok(len)
+    }
+
+    /// Закрывает соединение, не дожидаясь ответа клиента
+    fn close(&mut self) {
+        let _ = self.socket.close(None);
+        let _ = self.flush();
+    }
+}
+
+/// Управляющий канал клиента WebSocket. Запросы JSON переводятся в сообщения
+/// родного протокола: первый запрос подписывает, следующие меняют подписку по режиму
+pub(super) struct WsChannel {
+    conn: Arc<Mutex<WsConnection>>,
+    subscribed: bool,
+}
+
+impl WsChannel {
+    pub(super) fn new(conn: WsConnection) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(conn)),
+            subscribed: false,
+        }
+    }
+}
+
+/// Запрос клиента WebSocket в виде сообщения протокола. subscribed — подписка уже есть
+fn protocol_request(req: WsRequest, subscribed: bool) -> Message {
+    let WsRequest::Subscribe {
+        tickers,
+        token,
+        mode,
+    } = req;
+    if subscribed {
+        return Message::ChangeSubscription { mode, tickers };
+    }
+    let mut subscription = Vec::new();
+    mode.apply(&mut subscription, &tickers);
+    Message::Tickers(TickerReqMessage {
+        port: 0,
+        stripe_ports: Vec::new(),
+        tickers: subscription,
+        token,
+        session: None,
+        filter: None,
+        bars: false,
+    })
+}
+
+impl ControlChannel for WsChannel {
+    fn recv(&mut self) -> Result<Option<Message>, RecvError> {
+        let req = self.conn.lock().unwrap().recv().map_err(|e| {
+            match e.downcast::<serde_json::Error>() {
+                Ok(e) => RecvError::Malformed {
+                    code: ErrorCode::DecodeFailure,
+                    reason: "decode failure",
+                    description: e.to_string(),
+                },
+                Err(e) => RecvError::Connection(e),
+            }
+        })?;
+        Ok(req.map(|req| {
+            let msg = protocol_request(req, self.subscribed);
+            self.subscribed = true;
+            msg
+        }))
+    }
+
+    fn send(&mut self, msg: &Message) -> Result<()> {
+        let text = serde_json::to_string(&ws_response(msg)?)?;
+        self.conn.lock().unwrap().send(text)?;
+        Ok(())
+    }
+
+    fn start_stream(&self, stream: QuotesStream) -> Option<QuotesStreamControl> {
+        let transport = WsTransport {
+            conn: self.conn.clone(),
+        };
+        Some(stream.start(move |_| Ok(transport)))
+    }
+
+    fn close(&mut self) {
+        self.conn.lock().unwrap().close();
+    }
+}
+
+/// Доставка котировок клиенту WebSocket через его соединение
+struct WsTransport {
+    conn: Arc<Mutex<WsConnection>>,
+}
+
+impl Transport for WsTransport {
+    fn encode(&self, msg: &Message) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&ws_response(msg)?)?)
+    }
+
+    fn send(&mut self, data: Vec<u8>) -> Result<usize> {
+        self.conn.lock().unwrap().send(String::from_utf8(data)?)
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ws_messages() {
+        let req: WsRequest =
+            serde_json::from_str(r#"{"type": "subscribe", "tickers": ["AMD"], "mode": "Add"}"#)
+                .unwrap();
+        assert_eq!(
+            req,
+            WsRequest::Subscribe {
+                tickers: vec!["AMD".to_string()],
+                token: None,
+                mode: SubscriptionMode::Add,
+            }
+        );
+
+        let resp = serde_json::to_value(WsResponse::Error {
+            code: ErrorCode::Unauthorized,
+        })
+        .unwrap();
+        assert_eq!(resp["type"], "error");
+        assert_eq!(resp["code"], "Unauthorized");
+    }
+
+    #[test]
+    fn test_ws_requests_map_to_protocol() {
+        let req = |mode| WsRequest::Subscribe {
+            tickers: vec!["AMD".to_string()],
+            token: Some("secret".to_string()),
+            mode,
+        };
+        match protocol_request(req(SubscriptionMode::Add), false) {
+            Message::Tickers(req) => {
+                assert_eq!(req.tickers, vec!["AMD".to_string()]);
+                assert_eq!(req.token.as_deref(), Some("secret"));
+            }
+            msg => panic!("Unexpected message: {msg:?}"),
+        }
+        assert!(matches!(
+            protocol_request(req(SubscriptionMode::Remove), true),
+            Message::ChangeSubscription {
+                mode: SubscriptionMode::Remove,
+                ..
+            }
+        ));
+
+        let ack = Message::SubscriptionChanged {
+            mode: SubscriptionMode::Remove,
+            unknown_tickers: vec!["XXX".to_string()],
+            tickers: Vec::new(),
+        };
+        assert_eq!(
+            ws_response(&ack).unwrap(),
+            WsResponse::Subscribed {
+                unknown_tickers: vec!["XXX".to_string()]
+            }
+        );
+        assert!(ws_response(&Message::Ping).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+/// Накопленная статистика циклов опроса одной подсистемы.
+/// Может разделяться между несколькими потоками одной подсистемы
+pub struct LoopStats {
+    iterations: AtomicU64,
+    busy_micros: AtomicU64,
+    total_micros: AtomicU64,
+    last_micros: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+/// Снимок статистики циклов опроса
+pub struct LoopStatsSnapshot {
+    /// Количество итераций цикла
+    pub iterations: u64,
+    /// Среднее количество пробуждений потока в секунду
+    pub wakeups_per_sec: f64,
+    /// Доля времени, которую поток занят работой, а не сном
+    pub busy_ratio: f64,
+}
+
+impl Display for LoopStatsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "iterations: {}, wakeups/s: {:.1}, busy: {:.2}%",
+            self.iterations,
+            self.wakeups_per_sec,
+            self.busy_ratio * 100.0
+        )
+    }
+}
+
+impl LoopStats {
+    /// Учитывает одну итерацию цикла: время работы и полное время итерации
+    pub fn record(&self, busy: Duration, total: Duration) {
+        self.iterations.fetch_add(1, Ordering::Relaxed);
+        self.busy_micros
+            .fetch_add(busy.as_micros() as u64, Ordering::Relaxed);
+        self.total_micros
+            .fetch_add(total.as_micros() as u64, Ordering::Relaxed);
+        self.last_micros
+            .store(monotonic_micros(), Ordering::Relaxed);
+    }
+
+    /// Время с последней итерации цикла или None, если цикл еще не выполнялся
+    pub fn since_last_iteration(&self) -> Option<Duration> {
+        let last = self.last_micros.load(Ordering::Relaxed);
+        if last == 0 {
+            return None;
+        }
+        Some(Duration::from_micros(
+            monotonic_micros().saturating_sub(last),
+        ))
+    }
+
+    /// Суммарное время работы и полное время итераций, мкс
+    pub fn micros(&self) -> (u64, u64) {
+        (
+            self.busy_micros.load(Ordering::Relaxed),
+            self.total_micros.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Текущий снимок статистики
+    pub fn snapshot(&self) -> LoopStatsSnapshot {
+        let iterations = self.iterations.load(Ordering::Relaxed);
+        let busy_micros = self.busy_micros.load(Ordering::Relaxed);
+        let total_micros = self.total_micros.load(Ordering::Relaxed);
+        if total_micros == 0 {
+            return LoopStatsSnapshot {
+                iterations,
+                ..Default::default()
+            };
+        }
+
+        LoopStatsSnapshot {
+            iterations,
+            wakeups_per_sec: iterations as f64 * 1_000_000.0 / total_micros as f64,
+            busy_ratio: busy_micros as f64 / total_micros as f64,
+        }
+    }
+}
+
+/// Монотонное время с первого обращения, мкс. Не меняется при переводе системных часов.
+/// Значение всегда больше нуля: ноль означает, что итераций еще не было
+fn monotonic_micros() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64 + 1
+}
+
+#[derive(Default)]
+/// Реестр статистики фоновых потоков, сгруппированной по подсистемам
+pub struct ThreadStats {
+    subsystems: Mutex<BTreeMap<String, Arc<LoopStats>>>,
+}
+
+impl ThreadStats {
+    /// Возвращает статистику подсистемы, создавая её при первом обращении
+    pub fn subsystem(&self, name: &str) -> Arc<LoopStats> {
+        self.subsystems
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Снимки статистики всех подсистем
+    pub fn snapshot(&self) -> Vec<(String, LoopStatsSnapshot)> {
+        self.subsystems
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, stats)| (name.clone(), stats.snapshot()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-6;
+
+    #[test]
+    fn test_loop_stats() {
+        let stats = LoopStats::default();
+        assert_eq!(stats.snapshot(), LoopStatsSnapshot::default());
+        assert!(stats.since_last_iteration().is_none());
+
+        stats.record(Duration::from_millis(1), Duration::from_millis(10));
+        stats.record(Duration::from_millis(3), Duration::from_millis(10));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.iterations, 2);
+        assert!((snapshot.wakeups_per_sec - 100.0).abs() < EPSILON);
+        assert!((snapshot.busy_ratio - 0.2).abs() < EPSILON);
+        assert!(stats.since_last_iteration().unwrap() < Duration::from_secs(1));
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(stats.since_last_iteration().unwrap() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_thread_stats() {
+        let stats = ThreadStats::default();
+        stats
+            .subsystem("stream")
+            .record(Duration::from_millis(1), Duration::from_millis(10));
+        stats
+            .subsystem("stream")
+            .record(Duration::from_millis(1), Duration::from_millis(10));
+        stats.subsystem("handler");
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].0, "handler");
+        assert_eq!(snapshot[1].0, "stream");
+        assert_eq!(snapshot[1].1.iterations, 2);
+    }
+}
@@ -0,0 +1,428 @@
+use crate::protocol::{
+    ErrorCode, Message, QuoteRespMessage, SubscriptionMode, expand_tickers, pack_message_with_len,
+    read_message_with_len,
+};
+use crate::quote::StockQuote;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_MILLIS: u64 = 5;
+
+/// Построитель тестового сервера котировок
+#[derive(Debug, Clone, Default)]
+pub struct MockServerBuilder {
+    tickers: Vec<String>,
+    quotes: Vec<StockQuote>,
+    interval: Duration,
+    history: HashMap<String, Vec<StockQuote>>,
+    rejection: Option<ErrorCode>,
+}
+
+impl MockServerBuilder {
+    /// Тикеры, которые знает сервер. Если не заданы, сервер принимает любые тикеры
+    /// без шаблонов
+    pub fn with_tickers<I>(mut self, tickers: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.tickers = tickers
+            .into_iter()
+            .map(|ticker| ticker.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Котировки, которые сервер отправляет каждому клиенту после подписки по порядку.
+    /// Котировки тикеров вне подписки клиента пропускаются
+    pub fn with_quotes(mut self, quotes: Vec<StockQuote>) -> Self {
+        self.quotes = quotes;
+        self
+    }
+
+    /// Пауза между котировками. По умолчанию котировки отправляются без пауз
+    pub fn with_interval_millis(mut self, millis: u64) -> Self {
+        self.interval = Duration::from_millis(millis);
+        self
+    }
+
+    /// Котировки, которые сервер возвращает на запрос истории тикера
+    pub fn with_history(mut self, ticker: &str, quotes: Vec<StockQuote>) -> Self {
+        self.history.insert(ticker.to_string(), quotes);
+        self
+    }
+
+    /// Отвечать на каждую подписку ошибкой code и закрывать соединение
+    pub fn with_rejection(mut self, code: ErrorCode) -> Self {
+        self.rejection = Some(code);
+        self
+    }
+
+    /// Запускает сервер на случайных портах loopback
+    pub fn start(self) -> Result<MockQuoteServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let udp = UdpSocket::bind("127.0.0.1:0")?;
+        udp.set_read_timeout(Some(Duration::from_millis(POLL_MILLIS)))?;
+        let shared = Arc::new(Shared {
+            config: self,
+            udp,
+            subscriptions: Mutex::new(Vec::new()),
+            subscribed: Condvar::new(),
+            stopped: AtomicBool::new(false),
+            sessions: AtomicU64::new(0),
+        });
+
+        let accept_shared = shared.clone();
+        let accept_handle = thread::spawn(move || accept_clients(listener, accept_shared));
+        let pong_shared = shared.clone();
+        let pong_handle = thread::spawn(move || answer_pings(&pong_shared));
+        log::info!("Mock quote server listens at {addr}");
+        Ok(MockQuoteServer {
+            addr,
+            shared,
+            handles: vec![accept_handle, pong_handle],
+        })
+    }
+}
+
+/// Запрос подписки, принятый тестовым сервером
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockSubscription {
+    /// Как тикеры запроса меняют подписку. Запрос котировок заменяет подписку
+    pub mode: SubscriptionMode,
+    /// Запрошенные тикеры и шаблоны
+    pub tickers: Vec<String>,
+}
+
+struct Shared {
+    config: MockServerBuilder,
+    udp: UdpSocket,
+    subscriptions: Mutex<Vec<MockSubscription>>,
+    subscribed: Condvar,
+    stopped: AtomicBool,
+    sessions: AtomicU64,
+}
+
+impl Shared {
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, mode: SubscriptionMode, tickers: &[String]) {
+        self.subscriptions.lock().unwrap().push(MockSubscription {
+            mode,
+            tickers: tickers.to_vec(),
+        });
+        self.subscribed.notify_all();
+    }
+
+    fn expand(&self, requested: &[String]) -> (Vec<String>, Vec<String>) {
+        if self.config.tickers.is_empty() {
+            return (requested.to_vec(), Vec::new());
+        }
+        expand_tickers(requested, &self.config.tickers)
+    }
+}
+
+/// Сервер котировок в том же процессе для тестов клиентского кода. Говорит
+/// по настоящему протоколу без TLS, отправляет заданные котировки и запоминает
+/// запросы подписки клиентов. Останавливается при удалении
+pub struct MockQuoteServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl MockQuoteServer {
+    /// Адрес управляющего канала для подключения клиентов
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Запросы подписки, принятые сервером, по порядку
+    pub fn subscriptions(&self) -> Vec<MockSubscription> {
+        self.shared.subscriptions.lock().unwrap().clone()
+    }
+
+    /// Ждет, пока сервер примет не меньше count запросов подписки, но не дольше timeout.
+    /// Возвращает принятые запросы
+    pub fn wait_subscriptions(&self, count: usize, timeout: Duration) -> Vec<MockSubscription> {
+        let subscriptions = self.shared.subscriptions.lock().unwrap();
+        let (subscriptions, _) = self
+            .shared
+            .subscribed
+            .wait_timeout_while(subscriptions, timeout, |subscriptions| {
+                subscriptions.len() < count
+            })
+            .unwrap();
+        subscriptions.clone()
+    }
+}
+
+impl Drop for MockQuoteServer {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Relaxed);
+        for handle in self.handles.drain(..) {
+            if handle.join().is_err() {
+                log::error!("Can't join mock server thread");
+            }
+        }
+    }
+}
+
+fn accept_clients(listener: TcpListener, shared: Arc<Shared>) {
+    let mut clients = Vec::new();
+    while !shared.is_stopped() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let shared = shared.clone();
+                clients.push(thread::spawn(move || {
+                    if let Err(e) = serve_client(stream, &shared) {
+                        log::warn!("Mock server client error: {e}");
+                    }
+                }));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(POLL_MILLIS));
+            }
+            Err(e) => {
+                log::error!("Mock server can't accept client: {e}");
+                break;
+            }
+        }
+    }
+    for client in clients {
+        if client.join().is_err() {
+            log::error!("Can't join mock server client thread");
+        }
+    }
+}
+
+fn answer_pings(shared: &Shared) {
+    let mut buf = [0u8; 64];
+    while !shared.is_stopped() {
+        let Ok((len, addr)) = shared.udp.recv_from(&mut buf) else {
+            continue;
+        };
+        if let Ok(Message::Ping) = postcard::from_bytes::<Message>(&buf[..len]) {
+            send_datagram(&shared.udp, addr, &Message::Pong);
+        }
+    }
+}
+
+fn send_datagram(udp: &UdpSocket, addr: SocketAddr, msg: &Message) {
+    let res = postcard::to_stdvec(msg)
+        .map_err(anyhow::Error::from)
+        .and_then(|datagram| Ok(udp.send_to(&datagram, addr)?));
+    if let Err(e) = res {
+        log::warn!("Mock server can't send datagram to {addr}: {e}");
+    }
+}
+
+#[derive(Default)]
+struct MockClient {
+    target: Option<SocketAddr>,
+    tickers: Vec<String>,
+    paused: bool,
+    seqs: HashMap<String, u64>,
+    next_quote: usize,
+    next_send: Option<Instant>,
+}
+
+fn serve_client(stream: TcpStream, shared: &Shared) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    let peer = stream.peer_addr()?;
+    let mut reader = stream.try_clone()?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(msg) = read_message_with_len::<Message, _>(&mut reader) {
+            if tx.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut writer = stream;
+    let mut client = MockClient::default();
+    loop {
+        if shared.is_stopped() {
+            if let Some(target) = client.target {
+                send_datagram(&shared.udp, target, &Message::Shutdown);
+            }
+            break;
+        }
+        match rx.recv_timeout(Duration::from_millis(POLL_MILLIS)) {
+            Ok(msg) => {
+                if !handle_message(msg, peer, &mut client, &mut writer, shared)? {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        send_due_quotes(&mut client, shared);
+    }
+    let _ = writer.shutdown(std::net::Shutdown::Both);
+    Ok(())
+}
+
+fn handle_message(
+    msg: Message,
+    peer: SocketAddr,
+    client: &mut MockClient,
+    writer: &mut TcpStream,
+    shared: &Shared,
+) -> Result<bool> {
+    let reply = match msg {
+        Message::Tickers(req) => {
+            client
+                .target
+                .get_or_insert(SocketAddr::new(peer.ip(), req.port));
+            shared.record(SubscriptionMode::Replace, &req.tickers);
+            if let Some(code) = shared.config.rejection {
+                writer.write_all(&pack_message_with_len(&Message::Error { code })?)?;
+                return Ok(false);
+            }
+            let (tickers, unknown_tickers) = shared.expand(&req.tickers);
+            client.tickers = tickers.clone();
+            let session = format!(
+                "mock-{}",
+                shared.sessions.fetch_add(1, Ordering::Relaxed) + 1
+            );
+            Message::Subscribed {
+                unknown_tickers,
+                tickers,
+                multicast_group: None,
+                session,
+                resumed: false,
+            }
+        }
+        Message::ChangeSubscription { mode, tickers } => {
+            shared.record(mode, &tickers);
+            let (tickers, unknown_tickers) = shared.expand(&tickers);
+            mode.apply(&mut client.tickers, &tickers);
+            Message::SubscriptionChanged {
+                mode,
+                unknown_tickers,
+                tickers,
+            }
+        }
+        Message::HistoryReq { ticker, last_n } => {
+            let history = shared.config.history.get(&ticker);
+            let quotes = history.map(Vec::as_slice).unwrap_or_default();
+            let skip = quotes.len().saturating_sub(last_n as usize);
+            Message::History {
+                ticker,
+                quotes: quotes[skip..].to_vec(),
+            }
+        }
+        Message::Pause => {
+            client.paused = true;
+            return Ok(true);
+        }
+        Message::Resume => {
+            client.paused = false;
+            return Ok(true);
+        }
+        Message::Unsubscribe => return Ok(false),
+        _ => Message::Error {
+            code: ErrorCode::UnexpectedMessage,
+        },
+    };
+    writer.write_all(&pack_message_with_len(&reply)?)?;
+    writer.flush()?;
+    Ok(!matches!(reply, Message::Error { .. }))
+}
+
+fn send_due_quotes(client: &mut MockClient, shared: &Shared) {
+    let Some(target) = client.target else {
+        return;
+    };
+    let quotes = &shared.config.quotes;
+    while !client.paused && client.next_quote < quotes.len() {
+        let now = Instant::now();
+        if client.next_send.is_some_and(|next_send| now < next_send) {
+            return;
+        }
+        let quote = &quotes[client.next_quote];
+        client.next_quote += 1;
+        if !client.tickers.contains(&quote.ticker) {
+            continue;
+        }
+        let seq = client.seqs.entry(quote.ticker.clone()).or_default();
+        *seq += 1;
+        let msg = Message::Quote(QuoteRespMessage {
+            quote: quote.clone(),
+            seq: *seq,
+        });
+        send_datagram(&shared.udp, target, &msg);
+        client.next_send = Some(now + shared.config.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::error::ClientError;
+    use crate::client::quotes_client::{ClientCmd, QuotesClientBuilder};
+
+    #[test]
+    fn test_mock_quote_server() {
+        let quote = |ticker: &str, timestamp: u64| StockQuote {
+            ticker: ticker.to_string(),
+            price: 10.0,
+            volume: 1,
+            timestamp,
+        };
+        let server = MockServerBuilder::default()
+            .with_tickers(["AMD", "INT"])
+            .with_quotes(vec![quote("AMD", 1), quote("GAZ", 2), quote("AMD", 3)])
+            .start()
+            .unwrap();
+        let control = QuotesClientBuilder::new(&server.addr().to_string(), 0, ["AMD", "GAZ"])
+            .with_poll_millis(10)
+            .build()
+            .unwrap()
+            .start_receive_channel(10)
+            .unwrap();
+
+        let quotes = control.quotes.as_ref().unwrap();
+        let timestamps: Vec<u64> = (0..2)
+            .map(|_| {
+                quotes
+                    .recv_timeout(Duration::from_secs(5))
+                    .unwrap()
+                    .timestamp
+            })
+            .collect();
+        assert_eq!(timestamps, vec![1, 3]);
+        assert_eq!(*control.acked_tickers.lock().unwrap(), vec!["AMD"]);
+        let subscriptions = server.wait_subscriptions(1, Duration::from_secs(1));
+        assert_eq!(subscriptions[0].tickers, vec!["AMD", "GAZ"]);
+
+        control.tx.send(ClientCmd::Stop).unwrap();
+        assert!(control.thread_handle.join().unwrap().is_ok());
+
+        let rejecting = MockServerBuilder::default()
+            .with_rejection(ErrorCode::Unauthorized)
+            .start()
+            .unwrap();
+        let res = QuotesClientBuilder::new(&rejecting.addr().to_string(), 0, ["AMD"])
+            .build()
+            .unwrap()
+            .start_receive_channel(10);
+        assert!(matches!(
+            res,
+            Err(ClientError::Rejected(ErrorCode::Unauthorized))
+        ));
+    }
+}
@@ -1,7 +1,9 @@
+use crate::stats::LoopStats;
 use anyhow::{Result, bail};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const TICK_MILLIS: u64 = 10;
 
@@ -19,9 +21,14 @@ impl Event {
     }
 
     fn tick(&mut self) {
-        if self.counter < (self.bound / TICK_MILLIS) {
-            self.counter += 1;
-        }
+        self.tick_by(1);
+    }
+
+    fn tick_by(&mut self, ticks: u64) {
+        self.counter = self
+            .counter
+            .saturating_add(ticks)
+            .min(self.bound / TICK_MILLIS);
     }
 
     fn is_expired(&self) -> bool {
@@ -37,17 +44,51 @@ impl Event {
 /// Используется для мониторинга событий с разными временными окнами
 pub struct Timer {
     events: HashMap<String, Event>,
+    stats: Option<Arc<LoopStats>>,
+    last_wake: Option<Instant>,
+    last_advance: Option<Instant>,
 }
 
 impl Timer {
+    /// Таймер, который учитывает итерации цикла и время работы потока между сном
+    pub fn with_stats(stats: Arc<LoopStats>) -> Self {
+        Self {
+            stats: Some(stats),
+            ..Default::default()
+        }
+    }
+
     /// Усыпляет поток на 10 мс и увеличивает счетчик всех подписанных событий
     pub fn sleep(&mut self) {
+        let sleep_start = Instant::now();
         thread::sleep(Duration::from_millis(TICK_MILLIS));
+        if let Some(stats) = self.stats.as_ref() {
+            let wake = Instant::now();
+            if let Some(last_wake) = self.last_wake {
+                stats.record(sleep_start - last_wake, wake - last_wake);
+            }
+            self.last_wake = Some(wake);
+        }
         for (_, event) in self.events.iter_mut() {
             event.tick();
         }
     }
 
+    /// Увеличивает счетчики событий на число тиков, прошедших с прошлого вызова, без сна.
+    /// Для циклов, которые опрашивают сокеты непрерывно
+    pub fn advance(&mut self) {
+        let now = Instant::now();
+        let last_advance = *self.last_advance.get_or_insert(now);
+        let ticks = (now - last_advance).as_millis() as u64 / TICK_MILLIS;
+        if ticks == 0 {
+            return;
+        }
+        self.last_advance = Some(last_advance + Duration::from_millis(ticks * TICK_MILLIS));
+        for (_, event) in self.events.iter_mut() {
+            event.tick_by(ticks);
+        }
+    }
+
     /// Подписывает событие на мониторинг
     pub fn add_event(&mut self, event_name: &str, bound_millis: u64) {
         self.events
@@ -114,4 +155,15 @@ mod tests {
         assert_eq!(timer.is_expired_event("A").unwrap(), false);
         assert_eq!(timer.is_expired_event("B").unwrap(), false);
     }
+
+    #[test]
+    fn test_advance() {
+        let mut timer = Timer::default();
+        timer.add_event("A", 20);
+        timer.advance();
+        assert_eq!(timer.is_expired_event("A").unwrap(), false);
+        thread::sleep(Duration::from_millis(25));
+        timer.advance();
+        assert_eq!(timer.is_expired_event("A").unwrap(), true);
+    }
 }
@@ -0,0 +1,122 @@
+use anyhow::{Result, anyhow};
+use rustls::StreamOwned;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+/// Настройки TLS сервера из сертификата и закрытого ключа в формате PEM
+pub fn server_config(cert_path: &str, key_path: &str) -> Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key_path)?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+/// Настройки TLS клиента, доверяющего сертификатам удостоверяющих центров из файла PEM
+pub fn client_config(ca_path: &str) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_path)? {
+        roots.add(cert?)?;
+    }
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Управляющее TCP соединение, открытое или защищенное TLS
+pub enum ControlStream {
+    /// Соединение без шифрования
+    Plain(TcpStream),
+    /// TLS соединение на стороне сервера
+    Server(Box<StreamOwned<ServerConnection, TcpStream>>),
+    /// TLS соединение на стороне клиента
+    Client(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl ControlStream {
+    /// Соединение, принятое сервером. Если заданы настройки TLS, соединение оборачивается в TLS
+    pub fn accept(conn: TcpStream, tls: Option<&Arc<ServerConfig>>) -> Result<Self> {
+        match tls {
+            Some(config) => {
+                let tls_conn = ServerConnection::new(config.clone())?;
+                Ok(Self::Server(Box::new(StreamOwned::new(tls_conn, conn))))
+            }
+            None => Ok(Self::Plain(conn)),
+        }
+    }
+
+    /// Соединение клиента с сервером. server_name - имя или ip-адрес сервера в его сертификате
+    pub fn connect(conn: TcpStream, tls: Option<(&Arc<ClientConfig>, &str)>) -> Result<Self> {
+        match tls {
+            Some((config, server_name)) => {
+                let server_name = ServerName::try_from(server_name.to_string())
+                    .map_err(|e| anyhow!("Wrong server name {server_name}: {e}"))?;
+                let tls_conn = ClientConnection::new(config.clone(), server_name)?;
+                Ok(Self::Client(Box::new(StreamOwned::new(tls_conn, conn))))
+            }
+            None => Ok(Self::Plain(conn)),
+        }
+    }
+
+    /// Завершает рукопожатие TLS. Соединение должно быть блокирующим,
+    /// время ожидания ограничивают таймауты сокета
+    pub fn complete_handshake(&mut self) -> Result<()> {
+        match self {
+            Self::Plain(_) => {}
+            Self::Server(stream) => {
+                while stream.conn.is_handshaking() {
+                    stream.conn.complete_io(&mut stream.sock)?;
+                }
+            }
+            Self::Client(stream) => {
+                while stream.conn.is_handshaking() {
+                    stream.conn.complete_io(&mut stream.sock)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Нижележащее TCP соединение
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            Self::Plain(conn) => conn,
+            Self::Server(stream) => stream.get_ref(),
+            Self::Client(stream) => stream.get_ref(),
+        }
+    }
+}
+
+impl Read for ControlStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(conn) => conn.read(buf),
+            Self::Server(stream) => stream.read(buf),
+            Self::Client(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ControlStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(conn) => conn.write(buf),
+            Self::Server(stream) => stream.write(buf),
+            Self::Client(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(conn) => conn.flush(),
+            Self::Server(stream) => stream.flush(),
+            Self::Client(stream) => stream.flush(),
+        }
+    }
+}
@@ -1,6 +1,13 @@
 use anyhow::{Result, bail};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::collections::VecDeque;
 use std::io::{ErrorKind, Read};
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+
+const LISTEN_BACKLOG: i32 = 128;
+const READ_CHUNK_LEN: usize = 4096;
+#[cfg(target_os = "linux")]
+const SD_LISTEN_FDS_START: i32 = 3;
 
 #[derive(Default)]
 
@@ -10,21 +17,25 @@ pub struct StreamReader {
 }
 
 impl StreamReader {
-    /// Читает в буфер все данные, доступные в потоке
+    /// Читает в буфер все данные, доступные в потоке.
+    /// Возвращает ошибку, если поток закрыт другой стороной и новых данных нет
     pub fn read_from_stream<T: Read>(&mut self, stream: &mut T) -> Result<()> {
-        let mut buf = vec![0u8; 512];
-
-        match stream.read(&mut buf) {
-            Ok(len) => {
-                for i in 0..len {
-                    self.buf.push_back(buf[i]);
+        let mut buf = [0u8; READ_CHUNK_LEN];
+        let mut received = false;
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) if received => return Ok(()),
+                Ok(0) => bail!("Connection is closed"),
+                Ok(len) => {
+                    self.buf.extend(&buf[..len]);
+                    received = true;
                 }
-                return Ok(());
+                Err(e) => match e.kind() {
+                    ErrorKind::Interrupted => {}
+                    ErrorKind::WouldBlock | ErrorKind::UnexpectedEof => return Ok(()),
+                    _ => bail!("{e}"),
+                },
             }
-            Err(e) => match e.kind() {
-                ErrorKind::WouldBlock | ErrorKind::UnexpectedEof => return Ok(()),
-                _ => bail!("{e}"),
-            },
         }
     }
 
@@ -42,10 +53,96 @@ impl StreamReader {
     }
 }
 
+/// Открывает TCP сокет на прослушивание. Сокет с IPv6 адресом
+/// принимает и IPv4 соединения (dual-stack)
+pub fn bind_tcp_listener(addr: SocketAddr) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    Ok(socket.into())
+}
+
+/// Открывает UDP сокет. Сокет с IPv6 адресом работает и с IPv4 адресами (dual-stack)
+pub fn bind_udp(addr: SocketAddr) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+/// Сокет, переданный systemd при активации через сокет (LISTEN_PID, LISTEN_FDS).
+/// Возвращает None, если процесс запущен без активации
+#[cfg(target_os = "linux")]
+pub fn systemd_listener() -> Result<Option<TcpListener>> {
+    let Ok(pid) = std::env::var("LISTEN_PID") else {
+        return Ok(None);
+    };
+    if pid.parse::<u32>()? != std::process::id() {
+        return Ok(None);
+    }
+    let fds = std::env::var("LISTEN_FDS")?.parse::<i32>()?;
+    if fds < 1 {
+        return Ok(None);
+    }
+    if fds > 1 {
+        bail!("Only one socket is supported for activation, got {fds}");
+    }
+    listener_from_fd(SD_LISTEN_FDS_START).map(Some)
+}
+
+/// Принимает во владение дескриптор прослушивающего TCP сокета.
+/// Дескриптор другого типа остается открытым и не используется
+#[cfg(target_os = "linux")]
+fn listener_from_fd(fd: std::os::fd::RawFd) -> Result<TcpListener> {
+    use socket2::SockRef;
+    use std::os::fd::{BorrowedFd, FromRawFd};
+
+    // SAFETY: LISTEN_PID совпадает с нашим процессом, значит по протоколу sd_listen_fds
+    // systemd передал открытый дескриптор fd, и он остается открытым во время проверки
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    let socket = SockRef::from(&borrowed);
+    if socket.r#type()? != Type::STREAM || !socket.is_listener()? {
+        bail!("Descriptor {fd} is not a listening stream socket");
+    }
+    if socket.local_addr()?.as_socket().is_none() {
+        bail!("Descriptor {fd} is not an IP socket");
+    }
+    // SAFETY: fd — открытый прослушивающий TCP сокет, который больше никто в процессе
+    // не использует: его передал systemd, и владение переходит к TcpListener
+    Ok(unsafe { TcpListener::from_raw_fd(fd) })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Cursor;
+    #[cfg(target_os = "linux")]
+    use std::os::fd::{AsRawFd, IntoRawFd};
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_listener_from_fd() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = listener_from_fd(listener.into_raw_fd()).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(listener_from_fd(udp.as_raw_fd()).is_err());
+        udp.local_addr().unwrap();
+
+        let conn = std::net::TcpStream::connect(addr).unwrap();
+        assert!(listener_from_fd(conn.as_raw_fd()).is_err());
+        conn.peer_addr().unwrap();
+
+        let file = std::fs::File::open("Cargo.toml").unwrap();
+        assert!(listener_from_fd(file.as_raw_fd()).is_err());
+    }
 
     #[test]
     fn test_stream_reader() {
@@ -59,5 +156,12 @@ mod tests {
         assert!(rem.is_none());
         let chunk = reader.extract_chunk(1).unwrap();
         assert_eq!(vec![3], chunk);
+        assert!(reader.read_from_stream(&mut stream).is_err());
+
+        let buf = vec![7u8; 3 * READ_CHUNK_LEN];
+        reader
+            .read_from_stream(&mut Cursor::new(buf.clone()))
+            .unwrap();
+        assert_eq!(reader.extract_chunk(buf.len()), Some(buf));
     }
 }